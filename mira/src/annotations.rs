@@ -45,7 +45,7 @@ impl Display for AnnotationReceiver {
     }
 }
 
-pub trait Annotation: Display + Debug + Any {
+pub trait Annotation: Display + Debug + Any + Send + Sync {
     fn get_name(&self) -> &'static str;
     #[allow(unused_variables)]
     fn is_valid_for(&self, thing: AnnotationReceiver, annotations: &Annotations) -> bool {
@@ -151,6 +151,12 @@ impl Annotations {
         self.0.is_empty()
     }
 
+    /// Adds an annotation that doesn't come from source code, like the calling convention of an
+    /// `extern "C" fn`
+    pub fn push<T: ClonableAnnotation>(&mut self, annotation: T, loc: Location) {
+        self.0.push((Box::new(annotation), loc));
+    }
+
     pub fn push_annotation(
         &mut self,
         name: &str,
@@ -3,6 +3,7 @@ use std::{
     collections::{HashMap, HashSet},
     io::Write,
     path::Path,
+    str::FromStr,
     sync::Arc,
 };
 
@@ -56,6 +57,7 @@ pub(super) struct DefaultTypes<'ctx> {
     pub ptr: PointerType<'ctx>,
     // { ptr, isize }
     pub fat_ptr: StructType<'ctx>,
+    pub f16: FloatType<'ctx>,
     pub f32: FloatType<'ctx>,
     pub f64: FloatType<'ctx>,
     // {}
@@ -83,8 +85,8 @@ pub struct CodegenContext<'ctx> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Optimizations {
-    /// No Optimizations (Like clangs -O1)
+pub enum OptLevel {
+    /// No Optimizations (Like clangs -O0)
     None,
     /// low optimizations (Like clangs -O1)
     Low,
@@ -98,10 +100,52 @@ pub enum Optimizations {
     ExtremelySmall,
 }
 
+impl OptLevel {
+    /// The level the target machine generates code at. llvm has no size levels for code
+    /// generation, so the size levels use the default one.
+    pub fn to_llvm_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::None => OptimizationLevel::None,
+            OptLevel::Low => OptimizationLevel::Less,
+            OptLevel::Normal | OptLevel::Small | OptLevel::ExtremelySmall => {
+                OptimizationLevel::Default
+            }
+            OptLevel::High => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = ();
+
+    /// Parses the level of an `-O<level>` flag, e.g. `2` for `-O2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::None),
+            "1" => Ok(OptLevel::Low),
+            "2" => Ok(OptLevel::Normal),
+            "3" => Ok(OptLevel::High),
+            "s" => Ok(OptLevel::Small),
+            "z" => Ok(OptLevel::ExtremelySmall),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether a build is a debug or a release build. Decides if `debug_assert`s are compiled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildMode {
+    Debug,
+    Release,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct CodegenConfig<'a> {
-    pub optimizations: Optimizations,
+    pub optimizations: OptLevel,
+    pub build_mode: BuildMode,
     pub runtime_safety: bool,
+    /// Whether to emit dwarf debug info (line tables, variables and types) into the object
+    pub emit_debug_info: bool,
     pub target: Target,
     pub cpu_features: &'a str,
     pub reloc_mode: RelocMode,
@@ -120,17 +164,21 @@ impl<'a> CodegenConfig<'a> {
     pub fn new(target: Target) -> Self {
         Self {
             target,
-            optimizations: Optimizations::Normal,
+            optimizations: OptLevel::Normal,
+            build_mode: BuildMode::Debug,
             runtime_safety: true,
+            emit_debug_info: true,
             cpu_features: "",
             reloc_mode: RelocMode::Default,
         }
     }
 
-    fn new_with_opt(target: Target, optimizations: Optimizations, runtime_safety: bool) -> Self {
+    fn new_with_opt(target: Target, optimizations: OptLevel, runtime_safety: bool) -> Self {
         Self {
             optimizations,
             runtime_safety,
+            build_mode: BuildMode::Release,
+            emit_debug_info: false,
             ..Self::new(target)
         }
     }
@@ -139,19 +187,19 @@ impl<'a> CodegenConfig<'a> {
         Self::new(target)
     }
     pub fn new_release_fast(target: Target) -> Self {
-        Self::new_with_opt(target, Optimizations::High, false)
+        Self::new_with_opt(target, OptLevel::High, false)
     }
     pub fn new_release_safe(target: Target) -> Self {
-        Self::new_with_opt(target, Optimizations::High, true)
+        Self::new_with_opt(target, OptLevel::High, true)
     }
     pub fn new_release_small(target: Target) -> Self {
-        Self::new_with_opt(target, Optimizations::Small, false)
+        Self::new_with_opt(target, OptLevel::Small, false)
     }
     pub fn new_release_tiny(target: Target) -> Self {
-        Self::new_with_opt(target, Optimizations::ExtremelySmall, false)
+        Self::new_with_opt(target, OptLevel::ExtremelySmall, false)
     }
 
-    setter!(cpu_features: &'a str, reloc_mode: RelocMode, runtime_safety: bool, optimizations: Optimizations, target: Target);
+    setter!(cpu_features: &'a str, reloc_mode: RelocMode, runtime_safety: bool, emit_debug_info: bool, optimizations: OptLevel, build_mode: BuildMode, target: Target);
 }
 
 impl<'a> CodegenContext<'a> {
@@ -167,12 +215,12 @@ impl<'a> CodegenContext<'a> {
 
     pub fn optimize(&self) -> Result<(), LLVMString> {
         let passes = match self.config.optimizations {
-            Optimizations::None => "default<O0>",
-            Optimizations::Low => "default<O1>",
-            Optimizations::Normal => "default<O2>",
-            Optimizations::High => "default<O3>",
-            Optimizations::Small => "default<Os>",
-            Optimizations::ExtremelySmall => "default<Oz>",
+            OptLevel::None => "default<O0>",
+            OptLevel::Low => "default<O1>",
+            OptLevel::Normal => "default<O2>",
+            OptLevel::High => "default<O3>",
+            OptLevel::Small => "default<Os>",
+            OptLevel::ExtremelySmall => "default<Oz>",
         };
         self.run_passes(passes)
     }
@@ -230,7 +278,7 @@ impl<'a> CodegenContext<'a> {
             &triple,
             config.target.arch.to_llvm_cpu(),
             &config.cpu_features,
-            OptimizationLevel::Aggressive,
+            config.optimizations.to_llvm_level(),
             RelocMode::PIC,
             CodeModel::Default,
         ) else {
@@ -249,6 +297,7 @@ impl<'a> CodegenContext<'a> {
             i16: context.i16_type(),
             i32: context.i32_type(),
             i64: context.i64_type(),
+            f16: context.f16_type(),
             f32: context.f32_type(),
             f64: context.f64_type(),
             bool: context.bool_type(),
@@ -264,7 +313,8 @@ impl<'a> CodegenContext<'a> {
             default_types,
             &ctx,
             &path,
-            config.optimizations != Optimizations::None,
+            config.optimizations != OptLevel::None,
+            config.emit_debug_info,
         );
 
         let builder = context.create_builder();
@@ -362,6 +412,9 @@ impl<'a> CodegenContext<'a> {
                         CallConvAnnotation::C => {
                             func.set_call_conventions(LLVMCallConv::LLVMCCallConv as u32)
                         }
+                        CallConvAnnotation::Stdcall => {
+                            func.set_call_conventions(LLVMCallConv::LLVMX86StdcallCallConv as u32)
+                        }
                         CallConvAnnotation::Fast => {
                             func.set_call_conventions(LLVMCallConv::LLVMFastCallConv as u32)
                         }
@@ -477,6 +530,9 @@ impl<'a> CodegenContext<'a> {
                         CallConvAnnotation::C => {
                             func.set_call_conventions(LLVMCallConv::LLVMCCallConv as u32)
                         }
+                        CallConvAnnotation::Stdcall => {
+                            func.set_call_conventions(LLVMCallConv::LLVMX86StdcallCallConv as u32)
+                        }
                         CallConvAnnotation::Fast => {
                             func.set_call_conventions(LLVMCallConv::LLVMFastCallConv as u32)
                         }
@@ -570,14 +626,14 @@ impl<'a> CodegenContext<'a> {
         drop(module_reader);
 
         let mut vtable_map = HashMap::new();
-        let trait_reader = ctx.traits.read();
         for (ty, traits) in vtables.drain() {
+            let vtable = ctx
+                .vtable(&ty, &traits)
+                .expect("vtables should only be attached to types implementing the traits");
             let mut typs = vec![default_types.isize.into()];
-            for trait_id in traits.iter() {
-                for _ in trait_reader[*trait_id].functions.iter() {
-                    // func pointer
-                    typs.push(default_types.ptr.into());
-                }
+            for _ in vtable.functions.iter() {
+                // func pointer
+                typs.push(default_types.ptr.into());
             }
             let vtable_ty = context.struct_type(&typs, false);
             let mut field_values = vec![default_types
@@ -591,26 +647,14 @@ impl<'a> CodegenContext<'a> {
                     false,
                 )
                 .into()];
-            for trait_id in traits.iter() {
-                match &ty {
-                    Type::Struct { struct_id, .. } => {
-                        for fn_id in struct_reader[*struct_id].trait_impl[trait_id]
-                            .iter()
-                            .copied()
-                        {
-                            field_values
-                                .push(functions[fn_id].as_global_value().as_pointer_value().into());
-                        }
-                    }
-                    _ => unreachable!(),
-                }
+            for fn_id in vtable.functions {
+                field_values.push(functions[fn_id].as_global_value().as_pointer_value().into());
             }
             let global = module.add_global(vtable_ty, Default::default(), "vtable");
             global.set_linkage(Linkage::LinkOnceODR);
             global.set_initializer(&vtable_ty.const_named_struct(&field_values));
             vtable_map.insert((ty, traits), global);
         }
-        drop(trait_reader);
         drop(struct_reader);
         drop(vtables);
 
@@ -810,6 +854,7 @@ fn collect_strings_for_expressions(
             }
             TypecheckedExpression::Range { lhs, rhs, .. }
             | TypecheckedExpression::StoreAssignment(_, lhs, rhs)
+            | TypecheckedExpression::MakeSlice(_, _, lhs, rhs)
             | TypecheckedExpression::Add(.., lhs, rhs)
             | TypecheckedExpression::Sub(.., lhs, rhs)
             | TypecheckedExpression::Mul(.., lhs, rhs)
@@ -836,6 +881,7 @@ fn collect_strings_for_expressions(
             | TypecheckedExpression::Dereference(.., lit)
             | TypecheckedExpression::Offset(.., lit, _)
             | TypecheckedExpression::OffsetNonPointer(.., lit, _)
+            | TypecheckedExpression::BoundsCheck(_, lit, _)
             | TypecheckedExpression::Literal(.., lit)
             | TypecheckedExpression::MakeUnsizedSlice(.., lit, _)
             | TypecheckedExpression::Pos(.., lit)
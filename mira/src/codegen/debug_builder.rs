@@ -130,6 +130,7 @@ impl<'ctx> DebugContext<'ctx> {
         tc_ctx: &TypecheckingContext,
         root_path: &Path,
         optimizations: bool,
+        emit_debug_info: bool,
     ) -> Self {
         module.add_basic_value_flag(
             "Debug Info Version",
@@ -158,7 +159,12 @@ impl<'ctx> DebugContext<'ctx> {
             "",
             0,
             "",
-            DWARFEmissionKind::Full,
+            // the debug info is still built without it, llvm just doesn't emit any of it
+            if emit_debug_info {
+                DWARFEmissionKind::Full
+            } else {
+                DWARFEmissionKind::None
+            },
             0,
             false,
             false,
@@ -343,6 +349,13 @@ impl<'ctx> DebugContext<'ctx> {
                         .unwrap()
                         .as_type()
                 }
+                Type::PrimitiveF16(0) => {
+                    break 'out self
+                        .builder
+                        .create_basic_type("f16", 0, BasicTypeEncoding::Float, DIFlags::PUBLIC)
+                        .unwrap()
+                        .as_type()
+                }
                 Type::PrimitiveF32(0) => {
                     break 'out self
                         .builder
@@ -2,10 +2,11 @@ use context::DefaultTypes;
 use core::panic;
 use debug_builder::DebugContext;
 use intrinsics::LLVMIntrinsics;
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, path::Path, sync::Arc};
 
 use crate::{
     globals::GlobalStr,
+    lang_items::FunctionLangItem,
     module::{ModuleId, TraitId},
     typechecking::{
         expression::{OffsetValue, TypecheckedExpression, TypedLiteral},
@@ -16,7 +17,7 @@ use crate::{
 };
 pub use inkwell::context::Context as InkwellContext;
 pub mod mangling;
-pub use context::{CodegenConfig, CodegenContext};
+pub use context::{BuildMode, CodegenConfig, CodegenContext, OptLevel};
 pub use error::CodegenError;
 pub use inkwell::support::LLVMString;
 use inkwell::{
@@ -40,6 +41,67 @@ mod debug_constants;
 mod error;
 mod intrinsics;
 
+/// The scopes `typecheck_function` returned for every function, indexed by the function id.
+pub type FunctionScopes = Vec<Vec<(Type, ScopeTypeMetadata)>>;
+
+/// Lowers all functions and statics of `ctx` into a new llvm module for `config.target` and
+/// writes it to `out_path` as an object file. `scopes` and `ext_scopes` are the scopes of the
+/// functions and external functions.
+pub fn emit_object(
+    ctx: Arc<TypecheckingContext>,
+    scopes: FunctionScopes,
+    ext_scopes: FunctionScopes,
+    config: CodegenConfig,
+    out_path: &Path,
+) -> Result<(), CodegenError> {
+    let context = Context::create();
+    let codegen_context = lower_module(&context, ctx, scopes, ext_scopes, config, out_path)?;
+    codegen_context.optimize()?;
+    codegen_context
+        .write_object_file(out_path)
+        .map_err(CodegenError::LLVMNative)
+}
+
+/// Lowers all functions and statics of `ctx` like [`emit_object`] and returns the textual llvm ir
+/// of the module. The ir is not optimized, so it reflects the typechecked ir as closely as
+/// possible.
+pub fn emit_ir_string(
+    ctx: Arc<TypecheckingContext>,
+    scopes: FunctionScopes,
+    ext_scopes: FunctionScopes,
+    config: CodegenConfig,
+) -> Result<String, CodegenError> {
+    let context = Context::create();
+    let path = Path::new("module.mr");
+    let codegen_context = lower_module(&context, ctx, scopes, ext_scopes, config, path)?;
+    let ir = codegen_context.module.print_to_string();
+    Ok(ir.to_string())
+}
+
+fn lower_module<'ctx>(
+    context: &'ctx Context,
+    ctx: Arc<TypecheckingContext>,
+    scopes: FunctionScopes,
+    ext_scopes: FunctionScopes,
+    config: CodegenConfig<'ctx>,
+    path: &Path,
+) -> Result<CodegenContext<'ctx>, CodegenError> {
+    let name = path
+        .file_stem()
+        .map(|v| v.to_string_lossy())
+        .unwrap_or_default();
+    let mut codegen_context = CodegenContext::new(context, ctx, &name, path.into(), config)?;
+    for (fn_id, scope) in scopes.into_iter().enumerate() {
+        codegen_context.compile_fn(fn_id, scope, false)?;
+    }
+    for (fn_id, scope) in ext_scopes.into_iter().enumerate() {
+        codegen_context.compile_fn(fn_id, scope, true)?;
+    }
+    codegen_context.finalize_debug_info();
+    codegen_context.check()?;
+    Ok(codegen_context)
+}
+
 impl<'ctx, 'me> CodegenContext<'ctx> {
     pub fn make_function_codegen_context(
         &'me mut self,
@@ -68,6 +130,7 @@ impl<'ctx, 'me> CodegenContext<'ctx> {
             module: &self.module,
             retaddr: self.retaddr,
             current_block: bb,
+            build_mode: self.config.build_mode,
         }
     }
 }
@@ -85,6 +148,20 @@ impl<'ctx> FunctionCodegenContext<'ctx, '_> {
         }
         Ok(())
     }
+
+    /// builds a call to the `panic` lang item, which never returns
+    pub fn build_panic(&self) -> Result<(), BuilderError> {
+        let panic_fn = match self.tc_ctx.lang_items.read().panic {
+            Some(FunctionLangItem::Internal(id)) => self.functions[id],
+            Some(FunctionLangItem::External(id)) => self.external_functions[id],
+            None => unreachable!("typechecking should have checked for the panic lang item"),
+        };
+        self.builder
+            .build_direct_call(panic_fn, &[], "")?
+            .set_call_convention(panic_fn.get_call_conventions());
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
 }
 
 pub struct FunctionCodegenContext<'ctx, 'codegen> {
@@ -108,6 +185,7 @@ pub struct FunctionCodegenContext<'ctx, 'codegen> {
     retaddr: FunctionValue<'ctx>,
     module: &'codegen Module<'ctx>,
     current_block: BasicBlock<'ctx>,
+    build_mode: BuildMode,
 }
 
 impl<'ctx> FunctionCodegenContext<'ctx, '_> {
@@ -236,6 +314,7 @@ impl<'ctx> Type {
             Type::PrimitiveU32(_) | Type::PrimitiveI32(_) => default_types.i32.into(),
             Type::PrimitiveU64(_) | Type::PrimitiveI64(_) => default_types.i64.into(),
             Type::PrimitiveUSize(_) | Type::PrimitiveISize(_) => default_types.isize.into(),
+            Type::PrimitiveF16(_) => default_types.f16.into(),
             Type::PrimitiveF32(_) => default_types.f32.into(),
             Type::PrimitiveF64(_) => default_types.f64.into(),
             Type::PrimitiveBool(_) => default_types.bool.into(),
@@ -513,6 +592,7 @@ impl<'ctx> TypedLiteral {
             }
             TypedLiteral::F64(v) => default_types.f64.const_float(*v).into(),
             TypedLiteral::F32(v) => default_types.f32.const_float(*v as f64).into(),
+            TypedLiteral::F16(v) => default_types.f16.const_float(*v as f64).into(),
             TypedLiteral::U8(v) => default_types.i8.const_int(*v as u64, false).into(),
             TypedLiteral::U16(v) => default_types.i16.const_int(*v as u64, false).into(),
             TypedLiteral::U32(v) => default_types.i32.const_int(*v as u64, false).into(),
@@ -550,7 +630,7 @@ macro_rules! f_s_u {
                 .builder
                 .$uint($($uint_val,)* $lhs.into_int_value(), $rhs.into_int_value(), "")?
                 .into(),
-            Type::PrimitiveF32(0) | Type::PrimitiveF64(0) => $ctx
+            Type::PrimitiveF16(0) | Type::PrimitiveF32(0) | Type::PrimitiveF64(0) => $ctx
                 .builder
                 .$float($($float_val,)* $lhs.into_float_value(), $rhs.into_float_value(), "")?
                 .into(),
@@ -575,7 +655,7 @@ macro_rules! f_s_u {
                 .builder
                 .$uint($lhs.into_int_value(), $rhs.into_int_value(), "")?
                 .into(),
-            Type::PrimitiveF32(0) | Type::PrimitiveF64(0) => $ctx
+            Type::PrimitiveF16(0) | Type::PrimitiveF32(0) | Type::PrimitiveF64(0) => $ctx
                 .builder
                 .$float($lhs.into_float_value(), $rhs.into_float_value(), "")?
                 .into(),
@@ -707,6 +787,7 @@ fn build_deref<'a>(
         | Type::PrimitiveU32(_)
         | Type::PrimitiveU64(_)
         | Type::PrimitiveUSize(_)
+        | Type::PrimitiveF16(_)
         | Type::PrimitiveF32(_)
         | Type::PrimitiveF64(_)
         | Type::PrimitiveBool(_) => Ok(ctx.builder.build_load(
@@ -814,6 +895,7 @@ fn build_ptr_store(
         | Type::PrimitiveU32(_)
         | Type::PrimitiveU64(_)
         | Type::PrimitiveUSize(_)
+        | Type::PrimitiveF16(_)
         | Type::PrimitiveF32(_)
         | Type::PrimitiveF64(_)
         | Type::PrimitiveBool(_) => {
@@ -1194,7 +1276,7 @@ impl TypecheckedExpression {
                 );
                 Ok(())
             }
-            TypecheckedExpression::IntrinsicCall(_, dst, intrinsic, _) => {
+            TypecheckedExpression::IntrinsicCall(_, dst, intrinsic, args) => {
                 match intrinsic {
                     Intrinsic::Unreachable => {
                         ctx.builder.build_unreachable()?;
@@ -1212,6 +1294,25 @@ impl TypecheckedExpression {
                             .build_call(ctx.module, ctx.builder, &[], &[])?;
                         ctx.push_value(*dst, ctx.default_types.empty_struct.const_zero().into());
                     }
+                    Intrinsic::Assert | Intrinsic::DebugAssert => {
+                        if *intrinsic == Intrinsic::Assert || ctx.build_mode == BuildMode::Debug {
+                            let cond = args[0].fn_ctx_to_basic_value(ctx).into_int_value();
+                            let ok_basic_block =
+                                ctx.context.append_basic_block(ctx.current_fn, "assert-ok");
+                            let failed_basic_block = ctx
+                                .context
+                                .append_basic_block(ctx.current_fn, "assert-failed");
+                            ctx.builder.build_conditional_branch(
+                                cond,
+                                ok_basic_block,
+                                failed_basic_block,
+                            )?;
+                            ctx.goto(failed_basic_block);
+                            ctx.build_panic()?;
+                            ctx.goto(ok_basic_block);
+                        }
+                        ctx.push_value(*dst, ctx.default_types.empty_struct.const_zero().into());
+                    }
                     Intrinsic::ReturnAddress => {
                         let ret = ctx.builder.build_direct_call(
                             ctx.retaddr,
@@ -1234,14 +1335,93 @@ impl TypecheckedExpression {
                     Intrinsic::SizeOfVal => todo!(),
                     Intrinsic::Location => {}
                     Intrinsic::Offset => todo!(),
-                    Intrinsic::GetMetadata => todo!(),
+                    Intrinsic::GetMetadata => {
+                        // fat pointers are a { ptr, metadata } struct, thin pointers have none
+                        let value = args[0].fn_ctx_to_basic_value(ctx);
+                        let metadata = if value.is_struct_value() {
+                            ctx.builder
+                                .build_extract_value(value.into_struct_value(), 1, "")?
+                                .into_int_value()
+                        } else {
+                            ctx.default_types.isize.const_zero()
+                        };
+                        ctx.push_value(*dst, metadata.into());
+                    }
                     Intrinsic::WithMetadata => todo!(),
                     Intrinsic::TypeName => todo!(),
                     Intrinsic::Read => todo!(),
                     Intrinsic::Write => todo!(),
                     Intrinsic::Select => todo!(),
-                    Intrinsic::VolatileRead => todo!(),
-                    Intrinsic::VolatileWrite => todo!(),
+                    Intrinsic::VolatileRead => {
+                        let ptr = args[0].fn_ctx_to_basic_value(ctx).into_pointer_value();
+                        let llvm_ty = ctx.tc_scope[*dst].0.to_llvm_basic_type(
+                            &ctx.default_types,
+                            ctx.structs,
+                            ctx.context,
+                        );
+                        let value = ctx.builder.build_load(llvm_ty, ptr, "")?;
+                        value
+                            .as_instruction_value()
+                            .expect("a load should always be an instruction")
+                            .set_volatile(true)
+                            .expect("a load can always be volatile");
+                        ctx.push_value(*dst, value);
+                    }
+                    Intrinsic::VolatileWrite => {
+                        let ptr = args[0].fn_ctx_to_basic_value(ctx).into_pointer_value();
+                        let value = args[1].fn_ctx_to_basic_value(ctx);
+                        ctx.builder
+                            .build_store(ptr, value)?
+                            .set_volatile(true)
+                            .expect("a store can always be volatile");
+                        ctx.push_value(*dst, ctx.default_types.empty_struct.const_zero().into());
+                    }
+                    Intrinsic::PtrAdd | Intrinsic::PtrOffset => {
+                        let ptr = args[0].fn_ctx_to_basic_value(ctx).into_pointer_value();
+                        let offset = ctx.builder.build_int_cast_sign_flag(
+                            args[1].fn_ctx_to_basic_value(ctx).into_int_value(),
+                            ctx.default_types.isize,
+                            *intrinsic == Intrinsic::PtrOffset,
+                            "",
+                        )?;
+                        let element_ty = ctx.tc_scope[*dst]
+                            .0
+                            .clone()
+                            .deref()
+                            .expect("the typechecker ensures ptr_add returns a reference")
+                            .to_llvm_basic_type(&ctx.default_types, ctx.structs, ctx.context);
+                        // not inbounds, the pointer may leave the allocation it points into
+                        let value =
+                            unsafe { ctx.builder.build_gep(element_ty, ptr, &[offset], "") }?;
+                        ctx.push_value(*dst, value.into());
+                    }
+                    Intrinsic::Null => {
+                        // a null fat pointer also has its metadata zeroed
+                        let value = ctx.tc_scope[*dst]
+                            .0
+                            .to_llvm_basic_type(&ctx.default_types, ctx.structs, ctx.context)
+                            .const_zero();
+                        ctx.push_value(*dst, value);
+                    }
+                    Intrinsic::IsNull => {
+                        let ptr = match args[0].fn_ctx_to_basic_value(ctx) {
+                            BasicValueEnum::StructValue(fat_ptr) => {
+                                ctx.builder.build_extract_value(fat_ptr, 0, "")?
+                            }
+                            ptr => ptr,
+                        };
+                        let value = ctx.builder.build_is_null(ptr.into_pointer_value(), "")?;
+                        ctx.push_value(*dst, value.into());
+                    }
+                    Intrinsic::Transmute => {
+                        // the typechecker ensures both types have the same size, so the value can
+                        // be reinterpreted by going through memory
+                        let value = args[0].fn_ctx_to_basic_value(ctx);
+                        let ptr = ctx.builder.build_alloca(value.get_type(), "")?;
+                        ctx.builder.build_store(ptr, value)?;
+                        let value = build_deref(ptr, &ctx.tc_scope[*dst].0, ctx)?;
+                        ctx.push_value(*dst, value);
+                    }
                     Intrinsic::ByteSwap => todo!(),
                     Intrinsic::BitReverse => todo!(),
                     Intrinsic::CountLeadingZeros => todo!(),
@@ -1446,8 +1626,9 @@ impl TypecheckedExpression {
                 ctx.push_value(*dst, v);
                 Ok(())
             }
-            TypecheckedExpression::LAnd(..) => todo!(),
-            TypecheckedExpression::LOr(..) => todo!(),
+            TypecheckedExpression::LAnd(..) | TypecheckedExpression::LOr(..) => {
+                unreachable!("&& and || are lowered into branches by the typechecker")
+            }
             TypecheckedExpression::GreaterThanEq(_, dst, lhs, rhs) => {
                 let typ = lhs
                     .to_primitive_type(&ctx.tc_scope, ctx.tc_ctx)
@@ -1492,16 +1673,27 @@ impl TypecheckedExpression {
                     .expect("tc should have errored if you try to compare 2 non-number values");
                 let lhs = lhs.fn_ctx_to_basic_value(ctx);
                 let rhs = rhs.fn_ctx_to_basic_value(ctx);
-                let v = f_s_u!(
-                    ctx,
-                    typ,
-                    lhs,
-                    rhs,
-                    build_int_compare(IntPredicate::EQ),
-                    build_int_compare(IntPredicate::EQ),
-                    build_float_compare(FloatPredicate::UEQ),
-                    "tc should have errored if you try to compare 2 non-number values"
-                );
+                let v = if typ == Type::PrimitiveBool(0) {
+                    ctx.builder
+                        .build_int_compare(
+                            IntPredicate::EQ,
+                            lhs.into_int_value(),
+                            rhs.into_int_value(),
+                            "",
+                        )?
+                        .into()
+                } else {
+                    f_s_u!(
+                        ctx,
+                        typ,
+                        lhs,
+                        rhs,
+                        build_int_compare(IntPredicate::EQ),
+                        build_int_compare(IntPredicate::EQ),
+                        build_float_compare(FloatPredicate::UEQ),
+                        "tc should have errored if you try to compare 2 non-number values"
+                    )
+                };
                 ctx.push_value(*dst, v);
                 Ok(())
             }
@@ -1511,16 +1703,27 @@ impl TypecheckedExpression {
                     .expect("tc should have errored if you try to compare 2 non-number values");
                 let lhs = lhs.fn_ctx_to_basic_value(ctx);
                 let rhs = rhs.fn_ctx_to_basic_value(ctx);
-                let v = f_s_u!(
-                    ctx,
-                    typ,
-                    lhs,
-                    rhs,
-                    build_int_compare(IntPredicate::NE),
-                    build_int_compare(IntPredicate::NE),
-                    build_float_compare(FloatPredicate::UNE),
-                    "tc should have errored if you try to compare 2 non-number values"
-                );
+                let v = if typ == Type::PrimitiveBool(0) {
+                    ctx.builder
+                        .build_int_compare(
+                            IntPredicate::NE,
+                            lhs.into_int_value(),
+                            rhs.into_int_value(),
+                            "",
+                        )?
+                        .into()
+                } else {
+                    f_s_u!(
+                        ctx,
+                        typ,
+                        lhs,
+                        rhs,
+                        build_int_compare(IntPredicate::NE),
+                        build_int_compare(IntPredicate::NE),
+                        build_float_compare(FloatPredicate::UNE),
+                        "tc should have errored if you try to compare 2 non-number values"
+                    )
+                };
                 ctx.push_value(*dst, v);
                 Ok(())
             }
@@ -1591,6 +1794,20 @@ impl TypecheckedExpression {
 
                 Ok(())
             }
+            TypecheckedExpression::MakeSlice(_, dst, ptr, size) => {
+                let fat_ptr = ctx.builder.build_insert_value(
+                    ctx.default_types.fat_ptr.get_poison(),
+                    ptr.fn_ctx_to_basic_value(ctx),
+                    0,
+                    "",
+                )?;
+                let fat_ptr = ctx
+                    .builder
+                    .build_insert_value(fat_ptr, size.fn_ctx_to_basic_value(ctx), 1, "")?
+                    .into_struct_value();
+                ctx.push_value(*dst, fat_ptr.into());
+                Ok(())
+            }
             TypecheckedExpression::Dereference(_, dst, rhs) => {
                 if ctx.tc_scope[*dst].1.stack_allocated {
                     let ty = ctx.tc_scope[*dst].0.to_llvm_basic_type(
@@ -1733,6 +1950,47 @@ impl TypecheckedExpression {
                     _ => unreachable!("cannot take offset of {ty:?}"),
                 }
             }
+            TypecheckedExpression::BoundsCheck(_, array, offset) => {
+                let ty = array.to_type(&ctx.tc_scope, ctx.tc_ctx);
+                let len = match ty.as_ref() {
+                    Type::SizedArray {
+                        number_elements,
+                        num_references: 1,
+                        ..
+                    } => ctx
+                        .default_types
+                        .isize
+                        .const_int(*number_elements as u64, false),
+                    Type::UnsizedArray {
+                        num_references: 1, ..
+                    } => ctx
+                        .builder
+                        .build_extract_value(
+                            array.fn_ctx_to_basic_value(ctx).into_struct_value(),
+                            1,
+                            "",
+                        )?
+                        .into_int_value(),
+                    _ => unreachable!("cannot bounds check {ty:?}"),
+                };
+                let offset = match offset {
+                    OffsetValue::Dynamic(id) => ctx.get_value(*id).into_int_value(),
+                    OffsetValue::Static(v) => ctx.default_types.isize.const_int(*v as u64, false),
+                };
+                let in_bounds =
+                    ctx.builder
+                        .build_int_compare(IntPredicate::ULT, offset, len, "")?;
+                let ok_basic_block = ctx.context.append_basic_block(ctx.current_fn, "in-bounds");
+                let oob_basic_block = ctx
+                    .context
+                    .append_basic_block(ctx.current_fn, "out-of-bounds");
+                ctx.builder
+                    .build_conditional_branch(in_bounds, ok_basic_block, oob_basic_block)?;
+                ctx.goto(oob_basic_block);
+                ctx.build_panic()?;
+                ctx.goto(ok_basic_block);
+                Ok(())
+            }
             TypecheckedExpression::OffsetNonPointer(_, dst, src, offset_value) => {
                 let src = src.fn_ctx_to_basic_value(ctx);
                 if src.is_array_value() {
@@ -1939,23 +2197,18 @@ impl TypecheckedExpression {
                     ctx.push_value(*dst, value.into());
                     return Ok(());
                 }
-                // f32 -> f64
-                if src_ty == Type::PrimitiveF32(0) && *dst_ty == Type::PrimitiveF64(0) {
-                    let value = ctx.builder.build_float_ext(
-                        src_value.into_float_value(),
-                        ctx.default_types.f64,
-                        "",
-                    )?;
-                    ctx.push_value(*dst, value.into());
-                    return Ok(());
-                }
-                // f64 -> f32
-                if src_ty == Type::PrimitiveBool(0) && *dst_ty == Type::PrimitiveU8(0) {
-                    let value = ctx.builder.build_float_trunc(
-                        src_value.into_float_value(),
-                        ctx.default_types.f32,
-                        "",
-                    )?;
+                // f16 -> f32, f32 -> f16, f64 -> f32, ...
+                if src_ty.is_float() && dst_ty.is_float() {
+                    let ty = dst_ty
+                        .to_llvm_basic_type(&ctx.default_types, ctx.structs, ctx.context)
+                        .into_float_type();
+                    let value = if src_ty.get_bitwidth(0) < dst_ty.get_bitwidth(0) {
+                        ctx.builder
+                            .build_float_ext(src_value.into_float_value(), ty, "")?
+                    } else {
+                        ctx.builder
+                            .build_float_trunc(src_value.into_float_value(), ty, "")?
+                    };
                     ctx.push_value(*dst, value.into());
                     return Ok(());
                 }
@@ -2010,6 +2263,7 @@ impl TypecheckedExpression {
                     Ok(())
                 } else {
                     let ty = match dst_ty {
+                        Type::PrimitiveF16(0) => ctx.default_types.f16,
                         Type::PrimitiveF32(0) => ctx.default_types.f32,
                         Type::PrimitiveF64(0) => ctx.default_types.f64,
                         _ => unreachable!("not a float type: {:?}", dst_ty),
@@ -2036,3 +2290,280 @@ impl TypecheckedExpression {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        target::Target,
+        typechecking::{test::get_context, typechecking::typecheck_function, TypecheckingError},
+    };
+    use inkwell::targets::{InitializationConfig, Target as LLVMTarget};
+
+    const TARGET: &str = "x86_64-linux";
+
+    /// the llvm we link against might have been built without the target
+    fn target_available() -> bool {
+        LLVMTarget::initialize_all(&InitializationConfig::default());
+        let (triple, _) = Target::parse(TARGET)
+            .expect("invalid target")
+            .to_llvm_triple();
+        LLVMTarget::from_triple(&triple).is_ok()
+    }
+
+    fn typecheck(source: &str) -> (Arc<TypecheckingContext>, FunctionScopes, FunctionScopes) {
+        let (module_context, context) = get_context(source, TARGET);
+        // the test sources don't define the lang items
+        let errs = context
+            .resolve_types(module_context.clone())
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(errs.len(), 0, "unexpected errors: {errs:?}");
+
+        let num_functions = context.functions.read().len();
+        let num_ext_functions = context.external_functions.read().len();
+        let check = |is_external, i| {
+            typecheck_function(&context, &module_context, i, is_external, &mut Vec::new())
+                .unwrap_or_else(|errs| panic!("unexpected errors: {errs:?}"))
+        };
+        let scopes = (0..num_functions).map(|i| check(false, i)).collect();
+        let ext_scopes = (0..num_ext_functions).map(|i| check(true, i)).collect();
+        (context, scopes, ext_scopes)
+    }
+
+    #[test]
+    fn test_emit_object() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck("fn answer() -> i32 { return 42; }");
+        let path = std::env::temp_dir().join(format!("mira-emit-object-{}.o", std::process::id()));
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        emit_object(ctx, scopes, ext_scopes, config, &path).expect("failed to emit the object");
+
+        let len = std::fs::metadata(&path).map(|v| v.len());
+        _ = std::fs::remove_file(&path);
+        assert!(len.expect("the object file was not written") > 0);
+    }
+
+    #[test]
+    fn test_emit_ir_string() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck("fn answer() -> i32 { return 42; }");
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        assert!(ir.contains("answer"), "function missing in the ir:\n{ir}");
+        assert!(ir.contains("ret i32"), "return missing in the ir:\n{ir}");
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck(
+            "fn side_effect() -> bool = true;
+fn f(a: bool) -> bool = a && side_effect();
+fn g(a: bool) -> bool = a || side_effect();",
+        );
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        // the call to `side_effect` has to be in the block that is only entered when `a` doesn't
+        // decide the result
+        for (name, block) in [("1f17h", "\nthen:"), ("1g17h", "\nelse:")] {
+            let function = ir
+                .split("\ndefine ")
+                .find(|v| v.lines().next().is_some_and(|v| v.contains(name)))
+                .unwrap_or_else(|| panic!("{name} missing in the ir:\n{ir}"));
+            let branch = function
+                .find("br i1 ")
+                .unwrap_or_else(|| panic!("{name} doesn't branch:\n{function}"));
+            let rhs_block = function
+                .find(block)
+                .unwrap_or_else(|| panic!("{name} has no {block:?} block:\n{function}"));
+            let call = function
+                .find("side_effect")
+                .unwrap_or_else(|| panic!("{name} doesn't call side_effect:\n{function}"));
+            assert!(
+                branch < rhs_block && rhs_block < call,
+                "the right side of {name} isn't guarded:\n{function}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_external_function_declaration() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck(
+            "extern fn puts(s: &u8) -> i32;\nfn print(s: &u8) -> i32 { return puts(s); }",
+        );
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        assert!(
+            ir.contains("declare i32 @puts(ptr"),
+            "missing declaration in the ir:\n{ir}"
+        );
+        assert!(
+            ir.contains("call i32 @puts("),
+            "missing call in the ir:\n{ir}"
+        );
+    }
+
+    #[test]
+    fn test_variadic_external_function() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck(
+            "extern fn printf(fmt: &u8, ...) -> i32;\nfn print(s: &u8) -> i32 { return printf(s, s, 12); }",
+        );
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        assert!(
+            ir.contains("declare i32 @printf(ptr, ...)"),
+            "missing variadic declaration in the ir:\n{ir}"
+        );
+    }
+
+    #[test]
+    fn test_extern_abi() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) =
+            typecheck("extern \"stdcall\" fn ext_stdcall(a: i32);\nextern \"fast\" fn ext_fast();");
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        assert!(
+            ir.contains("declare x86_stdcallcc void @ext_stdcall(i32"),
+            "missing calling convention in the ir:\n{ir}"
+        );
+        assert!(
+            ir.contains("declare fastcc void @ext_fast("),
+            "missing calling convention in the ir:\n{ir}"
+        );
+    }
+
+    #[test]
+    fn test_debug_assert() {
+        if !target_available() {
+            return;
+        }
+        let source = "@intrinsic(\"assert\") fn assert(cond: bool) { while (true) {} }
+@intrinsic(\"debug_assert\") fn debug_assert(cond: bool) { while (true) {} }
+@lang(\"panic\") fn panic() -> ! { while (true) {} }
+fn check(a: bool) { debug_assert(a); }
+fn check_release(a: bool) { assert(a); }";
+        let target = Target::parse(TARGET).expect("invalid target");
+        let ir_for = |build_mode: BuildMode| {
+            let (ctx, scopes, ext_scopes) = typecheck(source);
+            let config = CodegenConfig::new_debug(target).build_mode(build_mode);
+            emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir")
+        };
+        let ir = ir_for(BuildMode::Debug);
+        assert_eq!(
+            ir.matches("assert-failed:").count(),
+            2,
+            "missing assertion in the ir:\n{ir}"
+        );
+        let ir = ir_for(BuildMode::Release);
+        assert_eq!(
+            ir.matches("assert-failed:").count(),
+            1,
+            "debug_assert was not elided in the ir:\n{ir}"
+        );
+    }
+
+    #[test]
+    fn test_opt_level() {
+        if !target_available() {
+            return;
+        }
+        let target = Target::parse(TARGET).expect("invalid target");
+        let ir_at = |opt_level: OptLevel| {
+            let (ctx, scopes, ext_scopes) =
+                typecheck("fn triple(a: i32) -> i32 = a * 3;\nfn f() -> i32 = triple(2) + 4;");
+            let config = CodegenConfig::new_debug(target).optimizations(opt_level);
+            let context = Context::create();
+            let codegen_context = lower_module(
+                &context,
+                ctx,
+                scopes,
+                ext_scopes,
+                config,
+                Path::new("module.mr"),
+            )
+            .expect("failed to lower the module");
+            codegen_context
+                .optimize()
+                .expect("failed to optimize the module");
+            codegen_context.module.print_to_string().to_string()
+        };
+        let ir = ir_at(OptLevel::None);
+        assert!(!ir.contains("ret i32 10"), "folded the call at -O0:\n{ir}");
+        let ir = ir_at(OptLevel::Normal);
+        assert!(
+            ir.contains("ret i32 10"),
+            "did not fold the call at -O2:\n{ir}"
+        );
+    }
+
+    #[test]
+    fn test_debug_info() {
+        if !target_available() {
+            return;
+        }
+        let target = Target::parse(TARGET).expect("invalid target");
+        let object_with = |emit_debug_info: bool| {
+            let (ctx, scopes, ext_scopes) =
+                typecheck("fn answer() -> i32 { let a = 42; return a; }");
+            let path = std::env::temp_dir().join(format!(
+                "mira-debug-info-{emit_debug_info}-{}.o",
+                std::process::id()
+            ));
+            let config = CodegenConfig::new_debug(target).emit_debug_info(emit_debug_info);
+            emit_object(ctx, scopes, ext_scopes, config, &path).expect("failed to emit the object");
+            let object = std::fs::read(&path);
+            _ = std::fs::remove_file(&path);
+            object.expect("the object file was not written")
+        };
+        let has_line_table = |object: &[u8]| {
+            object
+                .windows(b".debug_line".len())
+                .any(|v| v == b".debug_line")
+        };
+        assert!(
+            has_line_table(&object_with(true)),
+            "missing .debug_line section"
+        );
+        assert!(
+            !has_line_table(&object_with(false)),
+            "unexpected .debug_line section"
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero_check() {
+        if !target_available() {
+            return;
+        }
+        let (ctx, scopes, ext_scopes) = typecheck(
+            "@lang(\"panic\") fn panic() -> ! { while (true) {} }\nfn f(x: i32) -> i32 = 1 / x;",
+        );
+        let config = CodegenConfig::new_debug(Target::parse(TARGET).expect("invalid target"));
+        let ir = emit_ir_string(ctx, scopes, ext_scopes, config).expect("failed to emit the ir");
+        assert!(
+            ir.contains("sdiv i32 1"),
+            "missing division in the ir:\n{ir}"
+        );
+        assert!(
+            ir.contains("assert-failed:"),
+            "missing division by zero check in the ir:\n{ir}"
+        );
+    }
+}
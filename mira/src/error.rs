@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     fmt::{Debug, Display, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use thiserror::Error;
@@ -91,6 +93,68 @@ pub enum MiraError {
     },
 }
 
+impl MiraError {
+    /// The location the error occurred at, if it is about a specific place in the source code
+    pub fn get_loc(&self) -> Option<&Location> {
+        match self {
+            Self::Parsing { inner } => Some(inner.get_loc()),
+            Self::Tokenization { inner } => Some(inner.get_loc()),
+            Self::ProgramForming { inner } => Some(inner.get_loc()),
+            Self::Typechecking { inner } => inner.get_loc(),
+            Self::Codegen { .. }
+            | Self::Generic { .. }
+            | Self::IO { .. }
+            | Self::Linking { .. } => None,
+        }
+    }
+
+    /// Renders the error together with the offending line of the source code (see
+    /// [`SourceSnippet`]) if the source of the file the error occurred in is in `sources`.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        match self
+            .get_loc()
+            .and_then(|location| Some((location, sources.get(&location.file)?)))
+        {
+            Some((location, source)) => SourceSnippet {
+                error: self,
+                location,
+                source,
+            }
+            .to_string(),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// The source code of every file of a program, used to render errors
+#[derive(Debug, Default)]
+pub struct SourceMap(HashMap<Arc<Path>, Arc<str>>);
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, file: Arc<Path>, source: Arc<str>) {
+        self.0.insert(file, source);
+    }
+
+    /// Reads `file` from disk unless its source is already known. Files that can't be read are
+    /// skipped, their errors are then rendered without the source code.
+    pub fn load(&mut self, file: &Arc<Path>) {
+        if self.0.contains_key(file) {
+            return;
+        }
+        if let Ok(source) = std::fs::read_to_string(file) {
+            self.0.insert(file.clone(), source.into());
+        }
+    }
+
+    pub fn get(&self, file: &Path) -> Option<&str> {
+        self.0.get(file).map(|v| &**v)
+    }
+}
+
 #[derive(Clone, Debug, Error)]
 pub enum ProgrammingLangResolveError {
     #[error("{0}: Could not find file at `{1}`")]
@@ -113,14 +177,22 @@ pub enum ParsingError {
     DuplicateAsmReplacer { loc: Location, replacer: GlobalStr },
     #[error("{0}: {1} is an invalid function attribute")]
     InvalidFunctionAttribute(Location, GlobalStr),
+    #[error("{0}: {1} can't be derived")]
+    InvalidDerive(Location, GlobalStr),
     #[error("{0}: {1} is an invalid intrinsic")]
     InvalidIntrinsic(Location, GlobalStr),
     #[error("{0}: {1} is an invalid calling convention")]
     InvalidCallConv(Location, GlobalStr),
     #[error("{loc}: Expected a type, but found {found:?}")]
     ExpectedType { loc: Location, found: TokenType },
+    #[error("{loc}: Exceeded the maximum nesting depth")]
+    RecursionLimitExceeded { loc: Location },
+    #[error("{loc}: A type can't have more than 255 references")]
+    TooManyReferences { loc: Location },
     #[error("{loc}: Expected a function call")]
     ExpectedFunctionCall { loc: Location },
+    #[error("{loc}: Comparison operators cannot be chained, use parentheses or `&&`")]
+    ChainedComparison { loc: Location },
     #[error("{loc}: Expected `,` or `)`, but found {found:?}")]
     ExpectedFunctionArgument { loc: Location, found: TokenType },
     #[error("{loc}: Expected `,` or `]`, but found {found:?}")]
@@ -135,6 +207,11 @@ pub enum ParsingError {
     ExpectedExpression { loc: Location, found: TokenType },
     #[error("{loc}: Expected an identifier, but found {found:?}")]
     ExpectedIdentifier { loc: Location, found: TokenType },
+    #[error("{loc}: Expected an identifier, but found the keyword `{keyword}` (use `` `{keyword}` `` to use it as an identifier)")]
+    KeywordAsIdentifier {
+        loc: Location,
+        keyword: &'static str,
+    },
     #[error("{loc}: Incorrect Tokenization (this was an error of the compiler! report it!)")]
     InvalidTokenization { loc: Location },
     #[error("{loc}: Expected {expected:?} but found {found:?}")]
@@ -178,6 +255,12 @@ pub enum ParsingError {
     },
     #[error("{loc}: Unknown annotation `{name}`")]
     UnknownAnnotation { loc: Location, name: String },
+    #[error("{loc}: Expected a trait bound, but found {found:?}")]
+    ExpectedTraitBound { loc: Location, found: TokenType },
+    #[error("{loc}: `{name}` is not a generic parameter")]
+    UnknownGenericParameter { loc: Location, name: GlobalStr },
+    #[error("{loc}: `{name}` is bounded more than once in this where clause")]
+    DuplicateWhereBound { loc: Location, name: GlobalStr },
 }
 
 impl ParsingError {
@@ -186,20 +269,25 @@ impl ParsingError {
             Self::InvalidIntrinsic(loc, ..)
             | Self::InvalidCallConv(loc, ..)
             | Self::InvalidFunctionAttribute(loc, ..)
+            | Self::InvalidDerive(loc, ..)
             | Self::ExpectedElementForPub { loc, .. }
             | Self::OutputNotStartingWithEqual { loc, .. }
             | Self::InputStartingWithInvalidChar { loc, .. }
             | Self::DuplicateAsmReplacer { loc, .. }
             | Self::ExpectedType { loc, .. }
+            | Self::RecursionLimitExceeded { loc }
+            | Self::TooManyReferences { loc }
             | Self::AnnotationDoesNotGoOn { loc, .. }
             | Self::ExpectedExpression { loc, .. }
             | Self::ExpectedIdentifier { loc, .. }
+            | Self::KeywordAsIdentifier { loc, .. }
             | Self::ExpectedArrayElement { loc, .. }
             | Self::ExpectedObjectElement { loc, .. }
             | Self::ExpectedFunctionArgument { loc, .. }
             | Self::ExpectedFunctionArgumentExpression { loc, .. }
             | Self::ExpectedFunctionBody { loc, .. }
             | Self::ExpectedFunctionCall { loc }
+            | Self::ChainedComparison { loc }
             | Self::InvalidTokenization { loc }
             | Self::ExpressionAtTopLevel { loc }
             | Self::ExpectedAnnotationStatement { loc }
@@ -207,6 +295,9 @@ impl ParsingError {
             | Self::ExpectedArbitrary { loc, .. }
             | Self::FunctionAlreadyDefined { loc, .. }
             | Self::UnknownAnnotation { loc, .. }
+            | Self::UnknownGenericParameter { loc, .. }
+            | Self::ExpectedTraitBound { loc, .. }
+            | Self::DuplicateWhereBound { loc, .. }
             | Self::ExpectedStatement { loc, .. }
             | Self::InvalidKeyword { loc, .. } => loc,
             Self::ModuleResolution(err) => match err {
@@ -224,34 +315,63 @@ pub enum TokenizationError {
     UnknownTokenError { loc: Location, character: char },
     #[error("{loc}: Could not parse the number")]
     InvalidNumberError { loc: Location },
-    #[error("{loc}: Expected `\"`, but found nothing")]
-    UnclosedString { loc: Location },
+    #[error("{loc}: unterminated string literal")]
+    UnterminatedString { loc: Location },
+    #[error("{loc}: unterminated char literal, expected a `'` after the character")]
+    UnterminatedChar { loc: Location },
+    #[error("{loc}: char literals have to be ascii, but found `{character}`")]
+    NonAsciiChar { loc: Location, character: char },
     #[error("{0}: Invalid number type")]
     InvalidNumberType(Location),
     #[error("{loc}: unclosed macro invocation (Expected a `{bracket}`))")]
     UnclosedMacro { loc: Location, bracket: char },
     #[error("{loc}: expected a bracket (`(`, `[` or `{{`), but found {character}")]
     MacroExpectedBracket { loc: Location, character: char },
+    #[error("{loc}: unmatched `}}` in format string, use `}}}}` to insert a `}}`")]
+    UnmatchedFormatBrace { loc: Location },
+    #[error("{loc}: too many errors, stopped tokenizing")]
+    TooManyErrors { loc: Location },
+    #[error("{loc}: cannot edit the bytes {start}..{end}, the range is out of bounds or not on a char boundary")]
+    InvalidEdit {
+        loc: Location,
+        start: usize,
+        end: usize,
+    },
 }
 
 impl TokenizationError {
     pub fn invalid_number(loc: Location) -> Self {
         Self::InvalidNumberError { loc }
     }
-    pub fn unclosed_string(loc: Location) -> Self {
-        Self::UnclosedString { loc }
+    pub fn unterminated_string(loc: Location) -> Self {
+        Self::UnterminatedString { loc }
     }
     pub fn unknown_token(loc: Location, character: char) -> Self {
         Self::UnknownTokenError { loc, character }
     }
 
+    /// Returns a displayable version of this error that also prints the line of `source` the
+    /// error occurred on
+    pub fn with_source<'a>(&'a self, source: &'a str) -> SourceSnippet<'a, Self> {
+        SourceSnippet {
+            error: self,
+            location: self.get_loc(),
+            source,
+        }
+    }
+
     pub fn get_loc(&self) -> &Location {
         match self {
-            Self::UnclosedString { loc }
+            Self::UnterminatedString { loc }
+            | Self::UnterminatedChar { loc }
+            | Self::NonAsciiChar { loc, .. }
             | Self::InvalidNumberError { loc }
             | Self::InvalidNumberType(loc)
             | Self::UnclosedMacro { loc, .. }
             | Self::MacroExpectedBracket { loc, .. }
+            | Self::UnmatchedFormatBrace { loc }
+            | Self::TooManyErrors { loc }
+            | Self::InvalidEdit { loc, .. }
             | Self::UnknownTokenError { loc, .. } => &loc,
         }
     }
@@ -263,7 +383,7 @@ pub enum ProgramFormingError {
     NoCodeOutsideOfFunctions(Location),
     #[error("{0}: There are no anonymous functions at global level allowed")]
     AnonymousFunctionAtGlobalLevel(Location),
-    #[error("{0}: global-level let or const expects you to pass a literal")]
+    #[error("{0}: associated consts expect you to pass a literal")]
     GlobalValueNoLiteral(Location),
     #[error("{0}: global-level const expects you to pass a type")]
     GlobalValueNoType(Location),
@@ -271,6 +391,37 @@ pub enum ProgramFormingError {
     IdentNotDefined(Location, GlobalStr),
     #[error("{0}: `{1}` is already defined in the current module")]
     IdentAlreadyDefined(Location, GlobalStr),
+    #[error("{0}: `{1}` is imported, only definitions of the current module can be exported")]
+    ExportOfImport(Location, GlobalStr),
+}
+
+impl ProgramFormingError {
+    pub fn get_loc(&self) -> &Location {
+        match self {
+            Self::NoCodeOutsideOfFunctions(loc)
+            | Self::AnonymousFunctionAtGlobalLevel(loc)
+            | Self::GlobalValueNoLiteral(loc)
+            | Self::GlobalValueNoType(loc)
+            | Self::IdentNotDefined(loc, _)
+            | Self::IdentAlreadyDefined(loc, _)
+            | Self::ExportOfImport(loc, _) => loc,
+        }
+    }
+}
+
+/// Displays the paths of modules that import each other, e.g. `a.mr -> b.mr -> a.mr`
+pub struct ModuleCycle<'a>(pub &'a [Arc<Path>]);
+
+impl Display for ModuleCycle<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in 0..self.0.len() {
+            if i != 0 {
+                f.write_str(" -> ")?;
+            }
+            Display::fmt(&self.0[i].display(), f)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct FunctionList<'a>(pub &'a [Type]);
@@ -287,3 +438,64 @@ impl Display for FunctionList<'_> {
         f.write_char(')')
     }
 }
+
+/// Displays an error followed by the offending line of the source code and a caret pointing at
+/// the column of the error:
+///
+/// ```text
+/// file.mr:1:8: Unknown token `\`
+///   |
+///   |     let \a = 12;
+///   |         ^
+/// ```
+pub struct SourceSnippet<'a, E: Display> {
+    pub error: &'a E,
+    pub location: &'a Location,
+    pub source: &'a str,
+}
+
+impl<E: Display> Display for SourceSnippet<'_, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.error, f)?;
+        let Some(line) = self.source.lines().nth(self.location.line as usize) else {
+            return Ok(());
+        };
+        f.write_str("\n  |\n  | ")?;
+        f.write_str(line)?;
+        f.write_str("\n  | ")?;
+        // keep tabs so the caret lines up with the line above
+        for c in line.chars().take(self.location.column as usize) {
+            f.write_char(if c == '\t' { '\t' } else { ' ' })?;
+        }
+        f.write_char('^')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use crate::tokenizer::Tokenizer;
+
+    use super::{MiraError, SourceMap};
+
+    #[test]
+    fn test_render() {
+        let source = "fn main() {\n    let \\a = 12;\n}";
+        let file: Arc<Path> = Path::new("main.mr").into();
+        let mut tokenizer = Tokenizer::new(source, file.clone());
+        let errs = tokenizer
+            .scan_tokens()
+            .expect_err("expected a tokenization error");
+        assert_eq!(errs.len(), 1, "unexpected errors: {errs:?}");
+        let err: MiraError = errs.into_iter().next().unwrap().into();
+
+        let mut sources = SourceMap::new();
+        assert_eq!(err.render(&sources), err.to_string());
+        sources.insert(file, source.into());
+        assert_eq!(
+            err.render(&sources),
+            "main.mr:1:8: Unknown token `\\`\n  |\n  |     let \\a = 12;\n  |         ^"
+        );
+    }
+}
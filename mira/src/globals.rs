@@ -1,80 +1,106 @@
 use std::boxed::Box;
-use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Write};
 use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+
+use parking_lot::RwLock;
 
 struct GlobalValue<T> {
-    refs: usize,
+    /// the number of handles to this value. Changed under the shared lock, so that cloning and
+    /// dropping a handle don't block each other.
+    refs: AtomicUsize,
     value: T,
 }
 
 struct Slab<T> {
     entries: Vec<Option<T>>,
-    last_free: usize,
+    free: Vec<usize>,
 }
 
 impl<T> Slab<T> {
     pub const fn new() -> Self {
         Self {
             entries: Vec::new(),
-            last_free: 0,
+            free: Vec::new(),
         }
     }
 
     pub fn push(&mut self, value: T) -> usize {
-        if self.last_free >= self.entries.len() {
+        if let Some(entry) = self.free.pop() {
+            self.entries[entry] = Some(value);
+            entry
+        } else {
             self.entries.push(Some(value));
-            self.last_free = self.entries.len();
             self.entries.len() - 1
-        } else {
-            self.entries[self.last_free] = Some(value);
-            let entry = self.last_free;
-            for i in (self.last_free + 1)..self.entries.len() {
-                if self.entries[i].is_none() {
-                    self.last_free = i;
-                    return entry;
-                }
-            }
-            self.last_free = self.entries.len();
-            return entry;
         }
     }
 
     /// trys to remove a value from the slab and returns it if it found one
     pub fn remove(&mut self, index: usize) -> Option<T> {
-        if index >= self.entries.len() {
-            None
-        } else if index < self.last_free {
-            self.last_free = index;
-            self.entries[index].take()
-        } else {
-            self.entries[index].take()
-        }
+        let value = self.entries.get_mut(index)?.take()?;
+        self.free.push(index);
+        Some(value)
     }
 
     pub fn get(&self, index: usize) -> Option<&T> {
         self.entries.get(index).map(Option::as_ref).flatten()
     }
-    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
-        self.entries.get_mut(index).map(Option::as_mut).flatten()
-    }
 }
 
-type GlobalStrs = Slab<GlobalValue<Box<str>>>;
-//type GlobalPaths = Slab<GlobalValue<Box<Path>>>;
+struct GlobalStrs {
+    values: Slab<GlobalValue<Box<str>>>,
+    /// the slab index of every interned string, so that interning doesn't search the slab
+    indices: HashMap<Box<str>, usize>,
+}
+
+impl GlobalStrs {
+    /// Returns a new handle to `value` if it is interned already
+    fn acquire(&self, value: &str) -> Option<GlobalStr> {
+        let idx = *self.indices.get(value)?;
+        self.values.get(idx)?.refs.fetch_add(1, Ordering::Relaxed);
+        Some(GlobalStr(idx))
+    }
 
-thread_local! {
-    static STRINGS: RefCell<GlobalStrs> = RefCell::new({
-        let mut slab = Slab::new();
-        slab.push(GlobalValue {
-            refs: usize::MAX,
-            value: "".to_string().into_boxed_str()
+    fn insert(&mut self, value: Box<str>) -> GlobalStr {
+        let idx = self.values.push(GlobalValue {
+            refs: AtomicUsize::new(1),
+            value: value.clone(),
         });
-        slab
-    });
-    //static PATHS: RefCell<GlobalPaths> = RefCell::new(Slab::new());
+        self.indices.insert(value, idx);
+        GlobalStr(idx)
+    }
+}
+//type GlobalPaths = Slab<GlobalValue<Box<Path>>>;
+
+// shared between all threads, so that a GlobalStr created while parsing resolves to the same
+// string on the threads typechecking the module. Only interning a new string and dropping the
+// last handle of one take the write lock. Reads use `read_recursive`, as formatting a value may
+// format another GlobalStr while the lock is still held.
+static STRINGS: LazyLock<RwLock<GlobalStrs>> = LazyLock::new(|| {
+    let mut strings = GlobalStrs {
+        values: Slab::new(),
+        indices: HashMap::new(),
+    };
+    strings.insert("".into());
+    RwLock::new(strings)
+});
+//static PATHS: RefCell<GlobalPaths> = RefCell::new(Slab::new());
+
+fn with_strings<T>(func: impl FnOnce(&GlobalStrs) -> T) -> T {
+    func(&STRINGS.read_recursive())
 }
 
+fn with_strings_mut<T>(func: impl FnOnce(&mut GlobalStrs) -> T) -> T {
+    func(&mut STRINGS.write())
+}
+
+/// An interned string. Every distinct string is stored once in a table shared between all threads
+/// and a `GlobalStr` is a reference-counted handle to its entry, which makes cloning and comparing
+/// cheap. There is no separate owning string type: `GlobalStr::new`, `GlobalStr::new_boxed` and
+/// the `From` impls all look up the same table, so the same text always results in the same
+/// handle, no matter which constructor created it. `GlobalStr::ZERO` is the empty string.
 #[derive(Eq)]
 pub struct GlobalStr(usize);
 
@@ -86,8 +112,8 @@ impl PartialEq for GlobalStr {
 
 impl PartialEq<&str> for GlobalStr {
     fn eq(&self, other: &&str) -> bool {
-        STRINGS.with_borrow(|strings: &GlobalStrs| {
-            if let Some(v) = strings.get(self.0) {
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
                 (*v.value).eq(*other)
             } else {
                 false
@@ -98,9 +124,9 @@ impl PartialEq<&str> for GlobalStr {
 
 impl Clone for GlobalStr {
     fn clone(&self) -> Self {
-        STRINGS.with_borrow_mut(|strings: &mut GlobalStrs| {
-            if let Some(v) = strings.get_mut(self.0) {
-                v.refs += 1;
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
+                v.refs.fetch_add(1, Ordering::Relaxed);
             } else {
                 panic!("tried to clone dropped GlobalStr");
             }
@@ -114,12 +140,27 @@ impl Drop for GlobalStr {
         if self.0 == 0 {
             return;
         }
-        STRINGS.with_borrow_mut(|strings: &mut GlobalStrs| {
-            if let Some(v) = strings.get_mut(self.0) {
-                if v.refs == 0 {
-                    strings.remove(self.0);
-                } else {
-                    v.refs -= 1;
+        let last = with_strings(|strings: &GlobalStrs| {
+            let Some(v) = strings.values.get(self.0) else {
+                return false;
+            };
+            v.refs
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |refs| {
+                    (refs > 1).then(|| refs - 1)
+                })
+                .is_err()
+        });
+        if !last {
+            return;
+        }
+        with_strings_mut(|strings: &mut GlobalStrs| {
+            let Some(v) = strings.values.get(self.0) else {
+                return;
+            };
+            // `GlobalStr::new` may have handed out another handle while we waited for the lock
+            if v.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+                if let Some(v) = strings.values.remove(self.0) {
+                    strings.indices.remove(&v.value);
                 }
             }
         });
@@ -127,42 +168,53 @@ impl Drop for GlobalStr {
 }
 
 impl GlobalStr {
+    /// The empty string, which is interned from the start and never freed
     pub const ZERO: GlobalStr = Self(0);
 
+    /// Interns `value`, returning the handle of the existing entry if the string was interned
+    /// before
     pub fn new(value: &str) -> Self {
-        STRINGS.with_borrow_mut(|strings: &mut GlobalStrs| {
-            for (idx, v) in strings.entries.iter_mut().enumerate() {
-                if let Some(v) = v {
-                    if (*v.value).eq(value) {
-                        v.refs += 1;
-                        return Self(idx);
-                    }
-                }
-            }
-            Self(strings.push(GlobalValue {
-                refs: 0,
-                value: value.into(),
-            }))
+        if let Some(v) = with_strings(|strings: &GlobalStrs| strings.acquire(value)) {
+            return v;
+        }
+        with_strings_mut(|strings: &mut GlobalStrs| {
+            // another thread may have interned it in the meantime
+            strings
+                .acquire(value)
+                .unwrap_or_else(|| strings.insert(value.into()))
         })
     }
 
+    /// Like [`GlobalStr::new`], but reuses the allocation of `value` if it has to be interned
     pub fn new_boxed(value: Box<str>) -> Self {
-        STRINGS.with_borrow_mut(|strings: &mut GlobalStrs| {
-            for (idx, v) in strings.entries.iter_mut().enumerate() {
-                if let Some(v) = v {
-                    if (*v.value).eq(&*value) {
-                        v.refs += 1;
-                        return Self(idx);
-                    }
-                }
-            }
-            Self(strings.push(GlobalValue { refs: 0, value }))
+        if let Some(v) = with_strings(|strings: &GlobalStrs| strings.acquire(&value)) {
+            return v;
+        }
+        with_strings_mut(|strings: &mut GlobalStrs| match strings.acquire(&value) {
+            Some(v) => v,
+            None => strings.insert(value),
+        })
+    }
+
+    /// Returns how many strings are interned right now and their combined length in bytes. The
+    /// table is shared between all threads, so this counts the strings of everything that's
+    /// being compiled.
+    pub fn interned_totals() -> (usize, usize) {
+        with_strings(|strings: &GlobalStrs| {
+            strings
+                .values
+                .entries
+                .iter()
+                .flatten()
+                .fold((0, 0), |(count, bytes), v| {
+                    (count + 1, bytes + v.value.len())
+                })
         })
     }
 
     pub fn with<T>(&self, mut func: impl FnMut(&str) -> T) -> T {
-        STRINGS.with_borrow(|strings: &GlobalStrs| {
-            if let Some(v) = strings.get(self.0) {
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
                 func(&v.value)
             } else {
                 func("")
@@ -189,8 +241,8 @@ impl From<String> for GlobalStr {
 
 impl Debug for GlobalStr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        STRINGS.with_borrow(|strings: &GlobalStrs| {
-            if let Some(v) = strings.get(self.0) {
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
                 Debug::fmt(&*v.value, f)
             } else {
                 f.write_str("GlobalStr<missing #")?;
@@ -203,8 +255,8 @@ impl Debug for GlobalStr {
 
 impl Display for GlobalStr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        STRINGS.with_borrow(|strings: &GlobalStrs| {
-            if let Some(v) = strings.get(self.0) {
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
                 f.write_str(&*v.value)
             } else {
                 f.write_str("GlobalStr<missing #")?;
@@ -217,8 +269,8 @@ impl Display for GlobalStr {
 
 impl Hash for GlobalStr {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        STRINGS.with_borrow(|strings: &GlobalStrs| {
-            if let Some(v) = strings.get(self.0) {
+        with_strings(|strings: &GlobalStrs| {
+            if let Some(v) = strings.values.get(self.0) {
                 v.value.hash(state);
             } else {
                 "".hash(state)
@@ -335,3 +387,57 @@ impl Hash for GlobalStr {
 //        })
 //    }
 //}
+
+#[cfg(test)]
+mod test {
+    use super::GlobalStr;
+
+    #[test]
+    fn test_interning() {
+        let a = GlobalStr::new("interned!");
+        let handles = [
+            GlobalStr::from("interned!"),
+            GlobalStr::new_boxed("interned!".into()),
+            GlobalStr::from(String::from("interned!")),
+            GlobalStr::from(Box::<str>::from("interned!")),
+            a.clone(),
+        ];
+        for handle in &handles {
+            assert_eq!(*handle, a);
+            assert_eq!(handle.0, a.0);
+        }
+        assert_ne!(GlobalStr::new("not interned!"), a);
+        assert!(a == "interned!");
+
+        // the empty string is always the first entry
+        assert_eq!(GlobalStr::new("").0, GlobalStr::ZERO.0);
+        assert_eq!(GlobalStr::ZERO.clone().to_string(), "");
+
+        let (count, bytes) = GlobalStr::interned_totals();
+        assert!(count >= 2, "{count}");
+        assert!(bytes >= "interned!".len(), "{bytes}");
+    }
+
+    #[test]
+    fn test_interning_threads() {
+        let kept = GlobalStr::new("kept across threads");
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let kept = &kept;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        let shared = GlobalStr::new(&format!("shared {}", i % 8));
+                        let own = GlobalStr::from(format!("thread {thread} {i}"));
+                        assert_eq!(shared.clone(), shared);
+                        assert!(own == format!("thread {thread} {i}").as_str());
+                        assert_eq!(GlobalStr::new("kept across threads"), *kept);
+                    }
+                });
+            }
+        });
+        assert!(kept == "kept across threads");
+        let a = GlobalStr::new("shared 3");
+        assert_eq!(GlobalStr::new("shared 3").0, a.0);
+        assert!(a == "shared 3");
+    }
+}
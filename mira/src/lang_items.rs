@@ -89,7 +89,7 @@ macro_rules! lang_item_def {
             #[allow(unreachable_code)]
             pub fn push_struct(&mut self, id: StructId, lang_item: &str, loc: Location) -> Result<bool, LangItemAssignmentError> {
                 match lang_item {
-                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($ty), loc)),)*
+                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($lang_item), loc)),)*
                     $(stringify!($lang_item) => lang_item_def!(expect_struct $ty, self, $lang_item, id, loc),)*
                     _ => return Ok(false),
                 }
@@ -101,7 +101,7 @@ macro_rules! lang_item_def {
             #[allow(unreachable_code)]
             pub fn push_trait(&mut self, id: TraitId, lang_item: &str, loc: Location) -> Result<bool, LangItemAssignmentError> {
                 match lang_item {
-                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($ty), loc)),)*
+                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($lang_item), loc)),)*
                     $(stringify!($lang_item) => lang_item_def!(expect_trait $ty, self, $lang_item, id, loc),)*
                     _ => return Ok(false),
                 }
@@ -113,7 +113,7 @@ macro_rules! lang_item_def {
             #[allow(unreachable_code)]
             pub fn push_static(&mut self, id: StaticId, lang_item: &str, loc: Location) -> Result<bool, LangItemAssignmentError> {
                 match lang_item {
-                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($ty), loc)),)*
+                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($lang_item), loc)),)*
                     $(stringify!($lang_item) => lang_item_def!(expect_static $ty, self, $lang_item, id, loc),)*
                     _ => return Ok(false),
                 }
@@ -123,7 +123,7 @@ macro_rules! lang_item_def {
             #[allow(unreachable_code)]
             fn internal_push_fn(&mut self, id: FunctionLangItem, lang_item: &str, loc: Location) -> Result<bool, LangItemAssignmentError> {
                 match lang_item {
-                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($ty), loc)),)*
+                    $(stringify!($lang_item) if self.$lang_item.is_some() => return Err(LangItemAssignmentError::Redefinition(stringify!($lang_item), loc)),)*
                     $(stringify!($lang_item) => lang_item_def!(expect_function $ty, self, $lang_item, id, loc),)*
                     _ => return Ok(false),
                 }
@@ -142,9 +142,50 @@ macro_rules! lang_item_def {
                 self.internal_push_fn(FunctionLangItem::Internal(id), lang_item, loc)
             }
 
+            /// Returns the struct registered as the lang item `lang_item`, or None if it wasn't
+            /// registered or isn't a struct lang item.
+            pub fn get_struct(&self, lang_item: &str) -> Option<StructId> {
+                match lang_item {
+                    $(stringify!($lang_item) => lang_item_def!(get Struct $ty, self.$lang_item),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the trait registered as the lang item `lang_item`, or None if it wasn't
+            /// registered or isn't a trait lang item.
+            pub fn get_trait(&self, lang_item: &str) -> Option<TraitId> {
+                match lang_item {
+                    $(stringify!($lang_item) => lang_item_def!(get Trait $ty, self.$lang_item),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the static registered as the lang item `lang_item`, or None if it wasn't
+            /// registered or isn't a static lang item.
+            pub fn get_static(&self, lang_item: &str) -> Option<StaticId> {
+                match lang_item {
+                    $(stringify!($lang_item) => lang_item_def!(get Static $ty, self.$lang_item),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the function registered as the lang item `lang_item`, or None if it wasn't
+            /// registered or isn't a function lang item.
+            pub fn get_function(&self, lang_item: &str) -> Option<FunctionLangItem> {
+                match lang_item {
+                    $(stringify!($lang_item) => lang_item_def!(get Function $ty, self.$lang_item),)*
+                    _ => None,
+                }
+            }
         }
     };
 
+    (get Struct Struct, $value: expr) => { $value };
+    (get Trait Trait, $value: expr) => { $value };
+    (get Static Static, $value: expr) => { $value };
+    (get Function Function, $value: expr) => { $value };
+    (get $expected_ty: ident $ty: ident, $value: expr) => { None };
+
     (underlying_typ Trait) => { TraitId };
     (underlying_typ Struct) => { StructId };
     (underlying_typ Function) => { FunctionLangItem };
@@ -411,6 +452,9 @@ lang_item_def! {
     usize => Struct, // done
     range => Struct,
 
+    // called when a runtime check, like the bounds check of an index, fails. fn() -> !
+    panic => Function,
+
     // let allocator: dyn lang_item!("allocator_trait");
     allocator => Static, // done
 
@@ -539,6 +583,11 @@ impl LangItems {
         }
     }
 
+    fn panic(&self) -> LangItemFunction {
+        // fn panic() -> !;
+        LangItemFunction::new(Vec::new(), Type::PrimitiveNever)
+    }
+
     fn copy_trait(&self) -> LangItemTrait {
         // trait Copy {}
         LangItemTrait { funcs: Vec::new() }
@@ -563,6 +612,8 @@ impl LangItems {
         check_langitem!(required self.clone_trait: Trait; trait_reader errors context);
         check_langitem!(required self.copy_trait: Trait; trait_reader errors context);
         check_langitem!(required self.allocator: Static; static_reader errors context);
+        // only required when something is runtime checked, see Scopes::bounds_check
+        check_langitem!(self.panic: Function; static_reader errors context);
         check_langitem!(self.bool: Struct; struct_reader errors context);
         check_langitem!(self.f32: Struct; struct_reader errors context);
         check_langitem!(self.f64: Struct; struct_reader errors context);
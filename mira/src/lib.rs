@@ -14,6 +14,7 @@ pub mod module;
 pub mod parser;
 pub(crate) mod std_annotations;
 pub mod target;
+pub mod timings;
 pub mod tokenizer;
 pub mod tokenstream;
 pub mod typechecking;
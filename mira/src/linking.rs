@@ -8,19 +8,17 @@ use std::{
 };
 
 use inkwell::{context::Context, object_file};
-use parking_lot::RwLock;
 use thiserror::Error;
 
 use crate::{
     codegen::{CodegenConfig, CodegenContext, CodegenError},
     error::MiraError,
-    module::{Module, ModuleContext},
-    parser::ParserQueueEntry,
-    tokenizer::Tokenizer,
+    module::ModuleContext,
+    timings::PassTimings,
     typechecking::{
         ir_displayer::TCContextDisplay,
-        typechecking::{typecheck_function, typecheck_static},
-        TypecheckingContext,
+        typechecking::{typecheck_functions, typecheck_static},
+        TypecheckingContext, TypecheckingWarning,
     },
 };
 
@@ -232,7 +230,11 @@ pub struct FullCompilationOptions<'a> {
     pub additional_linker_directories: &'a [PathBuf],
     /// Set to true if you want to print what is being currently done to stdout.
     pub verbose: bool,
-    /// Set to true if you want the binary to have debug info
+    /// Set to true if you want to print the time spent in each pass to stderr once the pipeline
+    /// finished, even if it failed.
+    pub time_passes: bool,
+    /// Set to true if you want the binary to have debug info. Overrides
+    /// `codegen_opts.emit_debug_info`.
     pub with_debug_info: bool,
     /// Writer to write the intermediate representation to (this DOES NOT look good, use for
     /// debugging purposes only. Expects user to have knowledge of compiler internals)
@@ -245,9 +247,25 @@ pub struct FullCompilationOptions<'a> {
     pub asm_writer: Option<Box<dyn Write>>,
 }
 
-/// Runs the pipeline to turn a source file into an executable or shared object.
+/// Runs the pipeline to turn a source file into an executable or shared object. The warnings of
+/// the typechecker are pushed to `warnings`, it is up to the caller to report them.
 pub fn run_full_compilation_pipeline(
+    opts: FullCompilationOptions,
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<(), Vec<MiraError>> {
+    let time_passes = opts.time_passes;
+    let mut timings = PassTimings::new();
+    let result = run_pipeline(opts, warnings, &mut timings);
+    if time_passes {
+        eprint!("{timings}");
+    }
+    result
+}
+
+fn run_pipeline(
     mut opts: FullCompilationOptions,
+    warnings: &mut Vec<TypecheckingWarning>,
+    timings: &mut PassTimings,
 ) -> Result<(), Vec<MiraError>> {
     macro_rules! vprintln {
         (nn $($t:tt)*) => {
@@ -276,10 +294,15 @@ pub fn run_full_compilation_pipeline(
             println!("Runtime Safety: Disabled");
         }
         println!("Optimizations: {:?}", opts.codegen_opts.optimizations);
+        println!("Build Mode: {:?}", opts.codegen_opts.build_mode);
         println!("Relocation Mode: {:?}", opts.codegen_opts.reloc_mode);
+        if opts.with_debug_info {
+            println!("Debug Info: Enabled");
+        } else {
+            println!("Debug Info: Disabled");
+        }
         println!("Assuming cpu features: {}", opts.codegen_opts.cpu_features);
     }
-    let now = Instant::now();
     vprintln!("Parsing...");
     let mut read = String::new();
     let source = opts
@@ -296,12 +319,12 @@ pub fn run_full_compilation_pipeline(
         opts.debug_file.clone(),
         source,
         opts.verbose,
-    );
-    vprintln!("Parsing took {:?}", Instant::now().duration_since(now));
-    let module_context = module_context?;
+        timings,
+    )?;
     let now = Instant::now();
     vprintln!("Type Resolution...");
-    let typechecking_context = TypecheckingContext::new(module_context.clone());
+    let typechecking_context =
+        TypecheckingContext::new(module_context.clone(), opts.codegen_opts.target);
     let errs = typechecking_context.resolve_imports(module_context.clone());
     if errs.len() > 0 {
         return Err(errs.into_iter().map(Into::into).collect());
@@ -313,48 +336,34 @@ pub fn run_full_compilation_pipeline(
 
     vprintln!("Typechecking...");
 
-    let num_functions = { typechecking_context.functions.read().len() };
-    let num_ext_functions = { typechecking_context.external_functions.read().len() };
     let num_statics = { typechecking_context.statics.read().len() };
-
-    let mut errs = Vec::new();
-    let mut scopes_fns = Vec::with_capacity(num_functions);
-    let mut scopes_ext_fns = Vec::with_capacity(num_ext_functions);
-
-    for i in 0..num_functions {
-        vprintln!(nn "\rTypechecking function #{i}");
-        match typecheck_function(&typechecking_context, &module_context, i, false) {
-            Ok(v) => scopes_fns.push(v),
-            Err(e) => errs.extend(e),
-        }
-    }
-    vprintln!();
-
-    for i in 0..num_ext_functions {
-        vprintln!(nn "\rTypechecking external function #{i}");
-        match typecheck_function(&typechecking_context, &module_context, i, true) {
-            Ok(v) => scopes_ext_fns.push(v),
-            Err(e) => errs.extend(e),
-        }
-    }
-    vprintln!();
+    let num_threads = std::thread::available_parallelism().map_or(1, Into::into);
+
+    vprintln!("Typechecking functions on {num_threads} threads");
+    let (scopes_fns, scopes_ext_fns, mut errs) = match typecheck_functions(
+        &typechecking_context,
+        &module_context,
+        num_threads,
+        warnings,
+    ) {
+        Ok((scopes_fns, scopes_ext_fns)) => (scopes_fns, scopes_ext_fns, Vec::new()),
+        Err(errs) => (Vec::new(), Vec::new(), errs),
+    };
 
     for i in 0..num_statics {
         vprintln!(nn "\rTypechecking static #{i}");
         typecheck_static(&typechecking_context, &module_context, i, &mut errs);
     }
 
-    vprintln!(
-        "\nType Resolution and Typechecking took {:?}",
-        Instant::now().duration_since(now)
-    );
-
-    let now = Instant::now();
+    vprintln!();
+    timings.record("typecheck", now.elapsed());
 
     if errs.len() > 0 {
         return Err(errs.into_iter().map(Into::into).collect());
     }
 
+    warnings.extend(typechecking_context.unused_imports(&module_context));
+
     let mut errs = Vec::new();
 
     match opts.ir_writer.as_mut().map(|v| {
@@ -368,6 +377,7 @@ pub fn run_full_compilation_pipeline(
     }
 
     vprintln!("Codegen...");
+    let now = Instant::now();
     let num_fns = { typechecking_context.functions.read().len() };
     let num_ext_fns = { typechecking_context.external_functions.read().len() };
     let context = Context::create();
@@ -382,7 +392,7 @@ pub fn run_full_compilation_pipeline(
         typechecking_context.clone(),
         &debug_filename,
         opts.debug_file.clone(),
-        opts.codegen_opts,
+        opts.codegen_opts.emit_debug_info(opts.with_debug_info),
     )
     .expect("failed to create the llvm context");
     for fn_id in 0..num_fns {
@@ -405,7 +415,7 @@ pub fn run_full_compilation_pipeline(
         errs.push(CodegenError::LLVMNative(e).into());
     }
 
-    vprintln!("Codegen took {:?}", Instant::now().duration_since(now));
+    timings.record("codegen", now.elapsed());
 
     // emit llvm ir even in the case there are errors. LLVM errors are often more-or-less cryptic, and
     // seeing what junk the compiler generated will probably help in diagnosing them.
@@ -485,11 +495,11 @@ pub fn run_full_compilation_pipeline(
             create_dynamic_library: opts.shared_object,
         })
         .map_err(|v| vec![v.into()])?;
-    vprintln!("Linking took {:?}", Instant::now().duration_since(now));
+    timings.record("link", now.elapsed());
     Ok(())
 }
 
-/// Parses a string of text into a module
+/// Parses a string of text and every file it imports into modules, see [`ModuleContext::load`]
 ///
 /// `file` - The file the source came from. Used to evaluate relative imports
 /// `root_directory` - The path the import `@root/` points to
@@ -501,83 +511,7 @@ pub fn parse_all(
     debug_file: Arc<Path>,
     source: &str,
     verbose: bool,
+    timings: &mut PassTimings,
 ) -> Result<Arc<ModuleContext>, Vec<MiraError>> {
-    let mut errors = vec![];
-    macro_rules! vprintln {
-        ($($t:tt)*) => {
-            if verbose { println!($($t)*); }
-        };
-    }
-
-    if debug_file != file {
-        vprintln!("Tokenizing {file:?} as {debug_file:?}");
-    } else {
-        vprintln!("Tokenizing {file:?}");
-    }
-    let mut tokenizer = Tokenizer::new(source.as_ref(), debug_file.clone());
-    if let Err(errs) = tokenizer.scan_tokens() {
-        errors.extend(
-            errs.into_iter()
-                .map(|inner| MiraError::Tokenization { inner }),
-        );
-    }
-
-    let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
-        file: debug_file,
-        root: root_directory.clone(),
-    }]));
-    let mut current_parser = tokenizer.to_parser(modules.clone(), root_directory);
-    current_parser.file = file;
-
-    let module_context = Arc::new(ModuleContext::default());
-
-    loop {
-        vprintln!("Parsing {:?}", current_parser.file);
-        let (statements, parsing_errors) = current_parser.parse_all();
-        errors.extend(
-            parsing_errors
-                .into_iter()
-                .map(|inner| MiraError::Parsing { inner }),
-        );
-        let (path, root) = {
-            let module = &modules.read()[module_context.modules.read().len()];
-            (module.file.clone(), module.root.clone())
-        };
-        let mut module = Module::new(module_context.clone(), current_parser.imports, path, root);
-        if let Err(errs) = module.push_all(statements, module_context.modules.read().len()) {
-            errors.extend(
-                errs.into_iter()
-                    .map(|inner| MiraError::ProgramForming { inner }),
-            );
-        }
-        let mut writer = module_context.modules.write();
-        writer.push(module);
-
-        let read_modules = modules.read();
-        if read_modules.len() > writer.len() {
-            let entry = read_modules[writer.len()].clone();
-            drop(read_modules);
-            drop(writer);
-            let mut tokenizer = Tokenizer::new(
-                &std::fs::read_to_string(&entry.file).expect("failed to read module file"),
-                entry.file,
-            );
-            vprintln!("Tokenizing {:?}", tokenizer.file);
-            if let Err(errs) = tokenizer.scan_tokens() {
-                errors.extend(
-                    errs.into_iter()
-                        .map(|inner| MiraError::Tokenization { inner }),
-                );
-            }
-            current_parser = tokenizer.to_parser(modules.clone(), entry.root);
-        } else {
-            break;
-        }
-    }
-
-    if errors.len() > 0 {
-        Err(errors)
-    } else {
-        Ok(module_context)
-    }
+    ModuleContext::load(file, root_directory, debug_file, source, verbose, timings)
 }
@@ -1,12 +1,22 @@
 use parking_lot::RwLock;
-use std::{collections::HashMap, fmt::Debug, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    path::Path,
+    sync::Arc,
+};
 
 use crate::{
     annotations::Annotations,
-    error::ProgramFormingError,
+    error::{MiraError, ProgramFormingError},
     globals::GlobalStr,
-    parser::{Expression, FunctionContract, Generic, LiteralValue, Statement, Trait, TypeRef},
-    tokenizer::Location,
+    parser::{
+        AssociatedConst, Expression, FunctionContract, Generic, LiteralValue, ParserQueueEntry,
+        Statement, Trait, TypeRef,
+    },
+    std_annotations::derive::Derive,
+    timings::PassTimings,
+    tokenizer::{Location, Tokenizer},
 };
 
 pub type ModuleId = usize;
@@ -16,7 +26,7 @@ pub type FunctionId = usize;
 pub type StaticId = usize;
 pub type ExternalFunctionId = usize;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ModuleScopeValue {
     Function(FunctionId),
     ExternalFunction(ExternalFunctionId),
@@ -26,24 +36,77 @@ pub enum ModuleScopeValue {
     Trait(TraitId),
 }
 
+type BakedImpl = (
+    HashMap<GlobalStr, FunctionId>,
+    Vec<(GlobalStr, TypeRef, LiteralValue, Location)>,
+);
+
+/// Sorts the functions of an impl by their location, so their ids don't depend on the iteration
+/// order of the `HashMap`
+fn in_source_order(
+    functions: HashMap<GlobalStr, (FunctionContract, Statement)>,
+) -> Vec<(GlobalStr, (FunctionContract, Statement))> {
+    let mut functions = functions.into_iter().collect::<Vec<_>>();
+    functions.sort_by_key(|(_, (contract, _))| (contract.location.line, contract.location.column));
+    functions
+}
+
 #[derive(Debug)]
 pub struct BakedStruct {
     pub name: GlobalStr,
     pub elements: Vec<(GlobalStr, TypeRef)>,
     pub location: Location,
     pub global_impl: HashMap<GlobalStr, FunctionId>,
-    pub impls: Vec<(GlobalStr, HashMap<GlobalStr, FunctionId>, Location)>,
+    pub impls: Vec<(
+        GlobalStr,
+        HashMap<GlobalStr, FunctionId>,
+        Vec<(GlobalStr, TypeRef, LiteralValue, Location)>,
+        Location,
+    )>,
     pub annotations: Annotations,
     pub module_id: ModuleId,
     pub generics: Vec<Generic>,
 }
 
+/// How many of each item a [`ModuleContext`] or
+/// [`TypecheckingContext`](crate::typechecking::TypecheckingContext) holds, see their `stats`.
+/// Printing it lists one count per line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ContextStats {
+    pub modules: usize,
+    pub functions: usize,
+    pub external_functions: usize,
+    pub structs: usize,
+    pub traits: usize,
+    pub statics: usize,
+    /// see [`GlobalStr::interned_totals`]
+    pub interned_strings: usize,
+    pub interned_string_bytes: usize,
+}
+
+impl Display for ContextStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "modules: {}", self.modules)?;
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "external functions: {}", self.external_functions)?;
+        writeln!(f, "structs: {}", self.structs)?;
+        writeln!(f, "traits: {}", self.traits)?;
+        writeln!(f, "statics: {}", self.statics)?;
+        writeln!(
+            f,
+            "interned strings: {} ({} bytes)",
+            self.interned_strings, self.interned_string_bytes
+        )
+    }
+}
+
 #[derive(Default)]
 pub struct ModuleContext {
     pub modules: RwLock<Vec<Module>>,
     pub functions: RwLock<Vec<(FunctionContract, Statement, ModuleId)>>,
     pub external_functions: RwLock<Vec<(FunctionContract, Option<Statement>, ModuleId)>>,
-    pub statics: RwLock<Vec<(TypeRef, LiteralValue, ModuleId, Location, Annotations)>>, // TODO: const-eval for statics
+    /// the values of statics are evaluated at compile time, so they can call `const fn`s
+    pub statics: RwLock<Vec<(TypeRef, Expression, ModuleId, Location, Annotations)>>,
     pub structs: RwLock<Vec<BakedStruct>>,
     pub traits: RwLock<Vec<Trait>>,
 }
@@ -59,6 +122,110 @@ impl Debug for ModuleContext {
     }
 }
 
+impl ModuleContext {
+    pub fn stats(&self) -> ContextStats {
+        let (interned_strings, interned_string_bytes) = GlobalStr::interned_totals();
+        ContextStats {
+            modules: self.modules.read().len(),
+            functions: self.functions.read().len(),
+            external_functions: self.external_functions.read().len(),
+            structs: self.structs.read().len(),
+            traits: self.traits.read().len(),
+            statics: self.statics.read().len(),
+            interned_strings,
+            interned_string_bytes,
+        }
+    }
+
+    /// Tokenizes and parses `source` and every file it imports (directly or through other
+    /// modules) and forms a module out of each of them. The module with the id `0` is the one of
+    /// `source`, every other module gets the id of its position in the queue of the parser.
+    /// Files are only loaded once, even if they import each other.
+    ///
+    /// `file` - The file the source came from. Used to evaluate relative imports
+    /// `root_directory` - The path the import `@root/` points to
+    /// `debug_file` - The file that will appear in locations and debug info
+    /// `source` - The source that will be parsed
+    /// `timings` - Records the time spent tokenizing, parsing and forming the modules
+    pub fn load(
+        file: Arc<Path>,
+        root_directory: Arc<Path>,
+        debug_file: Arc<Path>,
+        source: &str,
+        verbose: bool,
+        timings: &mut PassTimings,
+    ) -> Result<Arc<Self>, Vec<MiraError>> {
+        let mut errors = vec![];
+        macro_rules! vprintln {
+            ($($t:tt)*) => {
+                if verbose { println!($($t)*); }
+            };
+        }
+
+        if debug_file != file {
+            vprintln!("Tokenizing {file:?} as {debug_file:?}");
+        } else {
+            vprintln!("Tokenizing {file:?}");
+        }
+        let mut tokenizer = Tokenizer::new(source, debug_file.clone());
+        if let Err(errs) = timings.time("tokenize", || tokenizer.scan_tokens()) {
+            errors.extend(errs.into_iter().map(MiraError::from));
+        }
+
+        // `use` statements push every file that wasn't seen yet onto this queue, which prevents
+        // loading files multiple times when imports are cyclic.
+        let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
+            file: debug_file,
+            root: root_directory.clone(),
+        }]));
+        let mut current_parser = tokenizer.to_parser(modules.clone(), root_directory);
+        current_parser.file = file;
+
+        let module_context = Arc::new(ModuleContext::default());
+
+        loop {
+            vprintln!("Parsing {:?}", current_parser.file);
+            let (statements, parsing_errors) = timings.time("parse", || current_parser.parse_all());
+            errors.extend(parsing_errors.into_iter().map(MiraError::from));
+            let module_id = module_context.modules.read().len();
+            let (path, root) = {
+                let module = &modules.read()[module_id];
+                (module.file.clone(), module.root.clone())
+            };
+            let mut module =
+                Module::new(module_context.clone(), current_parser.imports, path, root);
+            if let Err(errs) =
+                timings.time("module-form", || module.push_all(statements, module_id))
+            {
+                errors.extend(errs.into_iter().map(MiraError::from));
+            }
+            module_context.modules.write().push(module);
+
+            let Some(entry) = modules.read().get(module_id + 1).cloned() else {
+                break;
+            };
+            // an unreadable file still gets an (empty) module, so the ids of the modules keep
+            // matching their position in the queue.
+            let source = std::fs::read_to_string(&entry.file).unwrap_or_else(|e| {
+                errors.push(e.into());
+                String::new()
+            });
+            let mut tokenizer = Tokenizer::new(&source, entry.file);
+            vprintln!("Tokenizing {:?}", tokenizer.file);
+            if let Err(errs) = timings.time("tokenize", || tokenizer.scan_tokens()) {
+                errors.extend(errs.into_iter().map(MiraError::from));
+            }
+            current_parser = tokenizer.to_parser(modules.clone(), entry.root);
+        }
+
+        if errors.len() > 0 {
+            Err(errors)
+        } else {
+            Ok(module_context)
+        }
+    }
+}
+
 pub struct Module {
     pub context: Arc<ModuleContext>,
     pub scope: HashMap<GlobalStr, ModuleScopeValue>,
@@ -122,13 +289,41 @@ impl Module {
         idx
     }
 
+    fn bake_impl(
+        &mut self,
+        functions: HashMap<GlobalStr, (FunctionContract, Statement)>,
+        consts: Vec<AssociatedConst>,
+        module_id: ModuleId,
+    ) -> Result<BakedImpl, ProgramFormingError> {
+        let mut baked_functions = HashMap::new();
+        for (name, (contract, body)) in in_source_order(functions) {
+            baked_functions.insert(name, self.push_fn(contract, body, module_id));
+        }
+        let mut baked_consts = Vec::with_capacity(consts.len());
+        for (name, typ, value, location) in consts {
+            let Expression::Literal(value, _) = value else {
+                return Err(ProgramFormingError::GlobalValueNoLiteral(
+                    value.loc().clone(),
+                ));
+            };
+            baked_consts.push((name, typ, value, location));
+        }
+        Ok((baked_functions, baked_consts))
+    }
+
     pub fn push_all(
         &mut self,
         statements: Vec<Statement>,
         module_id: ModuleId,
     ) -> Result<(), Vec<ProgramFormingError>> {
+        // standalone impls are pushed last, so they can come before the struct they implement
+        // the trait for
+        let (impls, statements): (Vec<_>, Vec<_>) = statements
+            .into_iter()
+            .partition(|statement| matches!(statement, Statement::Impl { .. }));
         let errors = statements
             .into_iter()
+            .chain(impls)
             .map(|statement| self.push_statement(statement, module_id))
             .filter_map(|el| el.err())
             .collect::<Vec<_>>();
@@ -185,7 +380,7 @@ impl Module {
                 elements,
                 location,
                 global_impl,
-                impls,
+                mut impls,
                 annotations,
                 generics,
             } => {
@@ -198,17 +393,18 @@ impl Module {
 
                 let mut baked_global_impl = HashMap::new();
                 let mut baked_impls = Vec::new();
+                for derive in annotations.get_annotations::<Derive>() {
+                    impls.extend(derive.implementations(&name, &elements));
+                }
 
-                for (name, (contract, body)) in global_impl {
+                for (name, (contract, body)) in in_source_order(global_impl) {
                     baked_global_impl.insert(name, self.push_fn(contract, body, module_id));
                 }
 
-                for (trait_name, implementation, loc) in impls {
-                    let mut baked_impl = HashMap::new();
-                    for (name, (contract, body)) in implementation {
-                        baked_impl.insert(name, self.push_fn(contract, body, module_id));
-                    }
-                    baked_impls.push((trait_name, baked_impl, loc));
+                for (trait_name, implementation, consts, loc) in impls {
+                    let (baked_impl, baked_consts) =
+                        self.bake_impl(implementation, consts, module_id)?;
+                    baked_impls.push((trait_name, baked_impl, baked_consts, loc));
                 }
 
                 let baked_struct = BakedStruct {
@@ -227,10 +423,29 @@ impl Module {
                 self.scope
                     .insert(name, ModuleScopeValue::Struct(writer.len() - 1));
             }
-            Statement::Var(_, _, None, location, _) => {
+            Statement::Impl {
+                trait_name,
+                struct_name,
+                functions,
+                consts,
+                location,
+            } => {
+                let Some(&ModuleScopeValue::Struct(struct_id)) = self.scope.get(&struct_name)
+                else {
+                    return Err(ProgramFormingError::IdentNotDefined(location, struct_name));
+                };
+                let (baked_impl, baked_consts) = self.bake_impl(functions, consts, module_id)?;
+                self.context.structs.write()[struct_id].impls.push((
+                    trait_name,
+                    baked_impl,
+                    baked_consts,
+                    location,
+                ));
+            }
+            Statement::Var(_, _, None, location, ..) => {
                 return Err(ProgramFormingError::GlobalValueNoType(location.clone()))
             }
-            Statement::Var(name, expr, Some(typ), location, annotations) => {
+            Statement::Var(name, expr, Some(typ), location, annotations, _) => {
                 if self.scope.contains_key(&name) || self.imports.contains_key(&name) {
                     return Err(ProgramFormingError::IdentAlreadyDefined(
                         location.clone(),
@@ -238,13 +453,8 @@ impl Module {
                     ));
                 }
 
-                let Expression::Literal(value, _) = expr else {
-                    return Err(ProgramFormingError::GlobalValueNoLiteral(
-                        expr.loc().clone(),
-                    ));
-                };
                 let mut writer = self.context.statics.write();
-                writer.push((typ, value, module_id, location, annotations));
+                writer.push((typ, expr, module_id, location, annotations));
                 self.scope
                     .insert(name, ModuleScopeValue::Static(writer.len() - 1));
             }
@@ -271,7 +481,11 @@ impl Module {
                     .insert(name, ModuleScopeValue::ExternalFunction(writer.len() - 1));
             }
             Statement::Export(key, exported_key, loc) => {
-                if !self.scope.contains_key(&key) && !self.imports.contains_key(&key) {
+                // only functions, structs, traits, statics and external functions are in the scope
+                if !self.scope.contains_key(&key) {
+                    if self.imports.contains_key(&key) {
+                        return Err(ProgramFormingError::ExportOfImport(loc, key));
+                    }
                     return Err(ProgramFormingError::IdentNotDefined(loc, key));
                 }
                 self.exports.insert(exported_key, key);
@@ -287,3 +501,390 @@ impl Module {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use parking_lot::RwLock;
+    use std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use crate::{
+        error::{MiraError, ProgramFormingError},
+        globals::GlobalStr,
+        parser::{ParserQueueEntry, Statement},
+        target::Target,
+        tokenizer::Tokenizer,
+        typechecking::{
+            typechecking::{retypecheck_modules, typecheck_functions},
+            TypecheckingContext, TypecheckingError,
+        },
+    };
+
+    use super::{ContextStats, ModuleContext, ModuleScopeValue, PassTimings};
+
+    fn target() -> Target {
+        Target::parse("x86_64-linux-gnu").expect("invalid target")
+    }
+
+    /// The temporary directory [`load_files`] writes the files of the test `name` into
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mira_{name}_{}", std::process::id()))
+    }
+
+    /// Writes `files` into a temporary directory and loads the first one
+    fn load_files(
+        name: &str,
+        files: &[(&str, &str)],
+    ) -> Result<Arc<ModuleContext>, Vec<MiraError>> {
+        load_files_timed(name, files, &mut PassTimings::new())
+    }
+
+    /// [`load_files`], recording the passes in `timings`
+    fn load_files_timed(
+        name: &str,
+        files: &[(&str, &str)],
+        timings: &mut PassTimings,
+    ) -> Result<Arc<ModuleContext>, Vec<MiraError>> {
+        let dir = test_dir(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        let file: Arc<Path> = dir.join(files[0].0).into();
+        let result = ModuleContext::load(
+            file.clone(),
+            dir.as_path().into(),
+            file,
+            files[0].1,
+            false,
+            timings,
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_load_imports() {
+        let mut timings = PassTimings::new();
+        let module_context = load_files_timed(
+            "load_imports",
+            &[
+                (
+                    "main.mr",
+                    "use \"./other\"::add;\nfn main() -> i32 { return add(1, 2); }\n",
+                ),
+                // imports main back, which must not load main a second time
+                (
+                    "other.mr",
+                    "use \"./main\" as main;\nfn add(a: i32, b: i32) -> i32 { return a + b; }\nexport add;\n",
+                ),
+            ],
+            &mut timings,
+        )
+        .expect("failed to load the modules");
+        let dir = test_dir("load_imports");
+
+        let modules = module_context.modules.read();
+        assert_eq!(modules.len(), 2);
+        assert_eq!(*modules[1].path, *dir.join("other.mr"));
+        assert_eq!(modules[1].imports[&GlobalStr::new("main")].1, 0);
+        let Some(ModuleScopeValue::Function(id)) =
+            modules[1].scope.get(&GlobalStr::new("add")).copied()
+        else {
+            panic!("`add` is not a function: {:?}", modules[1].scope);
+        };
+        assert_eq!(module_context.functions.read()[id].2, 1);
+        drop(modules);
+
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        // `add` can only be imported into `main` if it was exported by the loaded module
+        let errs = context.resolve_imports(module_context.clone());
+        assert!(errs.is_empty(), "{errs:?}");
+
+        for pass in ["tokenize", "parse", "module-form"] {
+            assert!(
+                timings.get(pass).is_some(),
+                "no timing for {pass}: {timings:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_exports() {
+        let module_context = load_files(
+            "export_struct",
+            &[(
+                "main.mr",
+                "struct Point { x: i32, y: i32 }\nexport Point;\n",
+            )],
+        )
+        .expect("failed to load the modules");
+        assert_eq!(
+            module_context.modules.read()[0].exports[&GlobalStr::new("Point")],
+            GlobalStr::new("Point")
+        );
+
+        let errs = load_files("export_unknown", &[("main.mr", "export nothing;\n")]).unwrap_err();
+        assert!(
+            matches!(
+                errs[..],
+                [MiraError::ProgramForming {
+                    inner: ProgramFormingError::IdentNotDefined(..)
+                }]
+            ),
+            "{errs:?}"
+        );
+
+        let errs = load_files(
+            "export_import",
+            &[
+                ("main.mr", "use \"./other\"::add;\nexport add;\n"),
+                (
+                    "other.mr",
+                    "fn add(a: i32, b: i32) -> i32 { return a + b; }\nexport add;\n",
+                ),
+            ],
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                errs[..],
+                [MiraError::ProgramForming {
+                    inner: ProgramFormingError::ExportOfImport(..)
+                }]
+            ),
+            "{errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_retypecheck_modules() {
+        let module_context = load_files(
+            "retypecheck",
+            &[
+                (
+                    "main.mr",
+                    "use \"./a\"::one;\nuse \"./b\"::two;\nfn main() -> i32 { return one() + two(); }\n",
+                ),
+                ("a.mr", "fn one() -> i32 { return 1; }\nexport one;\n"),
+                (
+                    "b.mr",
+                    "fn two() -> i32 { return 2; }\nfn three() -> i32 { return 3; }\nexport two;\n",
+                ),
+            ],
+        )
+        .expect("failed to load the modules");
+        let module_id = |name: &str| {
+            let modules = module_context.modules.read();
+            (0..modules.len())
+                .find(|&id| modules[id].path.ends_with(name))
+                .unwrap()
+        };
+        let (main, a, b) = (module_id("main.mr"), module_id("a.mr"), module_id("b.mr"));
+
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        assert!(context.resolve_imports(module_context.clone()).is_empty());
+        context.resolve_types(module_context.clone());
+        assert_eq!(
+            context.modules.read()[main].dependencies,
+            HashSet::from([a, b])
+        );
+        assert!(context.modules.read()[a].dependencies.is_empty());
+
+        let mut warnings = Vec::new();
+        let mut scopes = typecheck_functions(&context, &module_context, 2, &mut warnings)
+            .unwrap_or_else(|errs| panic!("unexpected errors: {errs:?}"));
+        assert_eq!(context.invalidated_modules(&[b]), HashSet::from([main, b]));
+        assert_eq!(context.invalidated_modules(&[main]), HashSet::from([main]));
+
+        let function_id = |name: &str| {
+            let functions = module_context.functions.read();
+            (0..functions.len())
+                .find(|&id| functions[id].0.name == Some(GlobalStr::new(name)))
+                .unwrap()
+        };
+        let body_of = |source: &str| {
+            let file: Arc<Path> = Path::new("a.mr").into();
+            let mut tokenizer = Tokenizer::new(source, file.clone());
+            tokenizer.scan_tokens().expect("failed to tokenize");
+            let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
+                file: file.clone(),
+                root: file.clone(),
+            }]));
+            let (mut statements, errs) = tokenizer.to_parser(modules, file).parse_all();
+            assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+            let Some(Statement::Function(_, body)) = statements.pop() else {
+                panic!("expected a function");
+            };
+            *body
+        };
+        // the typechecked bodies are only replaced for the functions that are typechecked again
+        let body_ptrs = || {
+            context
+                .functions
+                .read()
+                .iter()
+                .map(|(_, body)| body.as_ptr())
+                .collect::<Vec<_>>()
+        };
+        let (one, two, three) = (function_id("one"), function_id("two"), function_id("three"));
+
+        // `a` is a leaf, so only `one` and `main`, which depends on it, are typechecked again
+        let before = body_ptrs();
+        let num_typechecked = retypecheck_modules(
+            &context,
+            &module_context,
+            vec![(one, body_of("fn one() -> i32 { return 11; }"))],
+            &mut scopes,
+            2,
+            &mut warnings,
+        )
+        .unwrap_or_else(|errs| panic!("unexpected errors: {errs:?}"));
+        assert_eq!(num_typechecked, 2);
+        assert_eq!(scopes.0.len(), 4);
+        let after = body_ptrs();
+        assert_eq!(before[two], after[two]);
+        assert_eq!(before[three], after[three]);
+        assert!(format!("{:?}", context.functions.read()[one].1).contains("I32(11)"));
+        assert!(context
+            .functions
+            .read()
+            .iter()
+            .all(|(_, body)| !body.is_empty()));
+
+        // a body that fails to typecheck leaves everything as it was before the edit
+        let num_scopes = scopes.0.iter().map(Vec::len).collect::<Vec<_>>();
+        let errs = retypecheck_modules(
+            &context,
+            &module_context,
+            vec![(one, body_of("fn one() -> i32 { return true; }"))],
+            &mut scopes,
+            2,
+            &mut warnings,
+        )
+        .unwrap_err();
+        assert!(!errs.is_empty());
+        assert_eq!(body_ptrs(), after);
+        assert!(format!("{:?}", context.functions.read()[one].1).contains("I32(11)"));
+        assert!(format!("{:?}", module_context.functions.read()[one].1).contains("11"));
+        assert_eq!(
+            scopes.0.iter().map(Vec::len).collect::<Vec<_>>(),
+            num_scopes
+        );
+    }
+
+    #[test]
+    fn test_module_dependencies() {
+        let module_context = load_files(
+            "module_dependencies",
+            &[
+                ("main.mr", "use \"./a\" as a;\nuse \"./b\" as b;\n"),
+                ("a.mr", "use \"./b\" as b;\n"),
+                ("b.mr", ""),
+            ],
+        )
+        .expect("failed to load the modules");
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        let dependencies = context.module_dependencies();
+        assert_eq!(dependencies.len(), 3);
+        assert_eq!(dependencies[&0], HashSet::from([1, 2]));
+        assert_eq!(dependencies[&1], HashSet::from([2]));
+        assert!(dependencies[&2].is_empty());
+        assert_eq!(context.find_import_cycle(), None);
+
+        let module_context = load_files(
+            "module_dependencies_cycle",
+            &[
+                ("main.mr", "use \"./a\" as a;\n"),
+                ("a.mr", "use \"./b\" as b;\n"),
+                ("b.mr", "use \"./a\" as a;\n"),
+            ],
+        )
+        .expect("failed to load the modules");
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        assert_eq!(context.module_dependencies()[&2], HashSet::from([1]));
+        assert_eq!(context.find_import_cycle(), Some(vec![1, 2, 1]));
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let module_context = load_files(
+            "import_cycle",
+            &[
+                ("main.mr", "use \"./a\" as a;\n"),
+                ("a.mr", "use \"./b\" as b;\n"),
+                ("b.mr", "use \"./c\" as c;\n"),
+                ("c.mr", "\nuse \"./a\" as a;\n"),
+            ],
+        )
+        .expect("failed to load the modules");
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        let Err(TypecheckingError::ImportCycle { location, modules }) =
+            context.check_import_cycles(&module_context)
+        else {
+            panic!("the import cycle wasn't found");
+        };
+        let names = modules
+            .iter()
+            .map(|v| v.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.mr", "b.mr", "c.mr", "a.mr"]);
+        assert!(location.file.ends_with("a.mr"));
+        let error = context.check_import_cycles(&module_context).unwrap_err();
+        let message = error.to_string();
+        assert_eq!(message.matches(" -> ").count(), 3, "{message}");
+        assert!(message.ends_with("a.mr"), "{message}");
+
+        let module_context =
+            load_files("no_import_cycle", &[("main.mr", "")]).expect("failed to load the modules");
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        assert!(context.check_import_cycles(&module_context).is_ok());
+    }
+
+    #[test]
+    fn test_stats() {
+        let module_context = load_files(
+            "stats",
+            &[
+                (
+                    "main.mr",
+                    "use \"./other\"::add;\ntrait T {}\nstruct A { a: i32 }\nlet b: i32 = 2;\nfn main() -> i32 { return add(1, 2); }\nextern fn exit(code: i32);\n",
+                ),
+                (
+                    "other.mr",
+                    "fn add(a: i32, b: i32) -> i32 { return a + b; }\nexport add;\n",
+                ),
+            ],
+        )
+        .expect("failed to load the modules");
+        let stats = module_context.stats();
+        assert_eq!(
+            stats,
+            ContextStats {
+                modules: 2,
+                functions: 2,
+                external_functions: 1,
+                structs: 1,
+                traits: 1,
+                statics: 1,
+                ..stats
+            }
+        );
+        assert!(stats.interned_strings > 0 && stats.interned_string_bytes > 0);
+        assert!(stats.to_string().starts_with("modules: 2\nfunctions: 2\n"));
+
+        let context = TypecheckingContext::new(module_context.clone(), target());
+        let typechecking_stats = context.stats();
+        assert_eq!(
+            typechecking_stats,
+            ContextStats {
+                interned_strings: typechecking_stats.interned_strings,
+                interned_string_bytes: typechecking_stats.interned_string_bytes,
+                ..stats
+            }
+        );
+    }
+}
@@ -8,8 +8,10 @@ use crate::{
     tokenizer::{Location, Token, TokenType},
 };
 pub use expression::{BinaryOp, Expression, LiteralValue, Path, PathWithoutGenerics, UnaryOp};
-pub use statement::{Argument, BakableFunction, FunctionContract, Statement, Trait};
-pub use types::{Generic, Implementation, Struct, TypeRef, RESERVED_TYPE_NAMES};
+pub use statement::{
+    Argument, AssociatedConst, BakableFunction, FunctionContract, Statement, Trait,
+};
+pub use types::{ArrayLength, Generic, Implementation, Struct, TypeRef, RESERVED_TYPE_NAMES};
 mod expression;
 mod module_resolution;
 mod statement;
@@ -57,6 +59,14 @@ impl Display for Annotation {
 }
 */
 
+/// The default for [`Parser::max_depth`]. Every level of nesting costs stack in the parser and
+/// again in the typechecker. In debug builds that is up to ~46 KiB per level in the parser (a
+/// parenthesized expression) and ~68 KiB in the typechecker; release builds need ~8.5 KiB and
+/// ~2.5 KiB. At 32 levels the parser fits into the 2 MiB stack of a spawned thread, and the
+/// typechecker runs on threads with larger stacks (see
+/// [`crate::typechecking::typechecking::TYPECHECKING_STACK_SIZE`]).
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
 #[derive(Clone, Debug)]
 pub struct ParserQueueEntry {
     pub file: Arc<std::path::Path>,
@@ -78,6 +88,11 @@ pub struct Parser {
     /// a map of idents => imports. if the size of the vec is 0, the identifier refers to the
     /// module itself. otherwise, it refers to something in it.
     pub imports: HashMap<GlobalStr, (Location, usize, Vec<GlobalStr>)>,
+    /// how deeply types, expressions and blocks (including `if`, `while` and `for`) can be nested
+    /// before parsing fails with `ParsingError::RecursionLimitExceeded`, so that deeply nested
+    /// input doesn't overflow the stack.
+    pub max_depth: usize,
+    depth: usize,
 }
 
 impl Parser {
@@ -86,7 +101,7 @@ impl Parser {
     }
 
     pub fn is_at_end(&self) -> bool {
-        if self.current >= self.tokens.len() - 1 {
+        if self.current >= self.tokens.len().saturating_sub(1) {
             return true;
         }
         assert_ne!(
@@ -106,9 +121,17 @@ impl Parser {
     }
 
     fn peekpeek(&self) -> &Token {
-        self.tokens
-            .get(self.current + 1)
-            .unwrap_or(&self.tokens[self.tokens.len() - 1])
+        self.peek_nth(1)
+    }
+
+    /// returns the token `n` tokens after the next one (`peek_nth(0)` is `peek()`), or the eof
+    /// token if there aren't that many tokens left.
+    fn peek_nth(&self, n: usize) -> &Token {
+        let eof = &self.tokens[self.tokens.len() - 1];
+        match self.current.checked_add(n) {
+            Some(idx) if idx < self.tokens.len() - 1 => &self.tokens[idx],
+            _ => eof,
+        }
     }
 
     fn check(&self, typ: TokenType) -> bool {
@@ -123,7 +146,7 @@ impl Parser {
         if !self.is_at_end() {
             self.current += 1;
         }
-        &self.tokens[self.current - 1]
+        self.current()
     }
 
     fn current(&self) -> &Token {
@@ -168,28 +191,728 @@ impl Parser {
         false
     }
 
-    // gets to the next sensical expression/type boundary
-    pub fn bail(&mut self) {
-        self.advance();
+    /// Runs `f` one nesting level deeper, failing if that would go past `max_depth`.
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ParsingError>,
+    ) -> Result<T, ParsingError> {
+        if self.depth >= self.max_depth {
+            return Err(ParsingError::RecursionLimitExceeded {
+                loc: self.peek().location.clone(),
+            });
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
 
+    /// Skips to the start of the next statement after an error, so that the statements after it
+    /// can still be parsed. Stops before a keyword that starts a statement, or after a `;` or an
+    /// unmatched `}`. Everything inside of `{}` that starts after the error is skipped. This never
+    /// goes past the eof token.
+    pub fn bail(&mut self) {
+        let mut depth = 0usize;
         while !self.is_at_end() {
-            if self.current().typ == TokenType::Semicolon {
-                break;
-            }
-
             match self.peek().typ {
-                TokenType::Struct
-                | TokenType::Fn
-                | TokenType::If
-                | TokenType::While
-                | TokenType::For
+                TokenType::CurlyLeft => depth += 1,
+                TokenType::CurlyRight if depth > 0 => depth -= 1,
+                TokenType::CurlyRight | TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                TokenType::Fn
+                | TokenType::Const
+                | TokenType::Struct
                 | TokenType::Trait
+                | TokenType::Impl
                 | TokenType::Let
-                | TokenType::Return => break,
+                | TokenType::Var
+                | TokenType::Use
+                | TokenType::Export
+                | TokenType::Extern
+                | TokenType::Pub
+                | TokenType::AnnotationIntroducer
+                    if depth == 0 =>
+                {
+                    break
+                }
                 _ => (),
             }
-
             self.advance();
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        std_annotations::{callconv::CallConvAnnotation, derive::Derive, ext_vararg::ExternVarArg},
+        tokenizer::Tokenizer,
+    };
+
+    fn get_parser(source: &str) -> Parser {
+        let file: Arc<std::path::Path> = std::path::Path::new("test").into();
+        let mut tokenizer = Tokenizer::new(source, file.clone());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
+            file: file.clone(),
+            root: file.clone(),
+        }]));
+        tokenizer.to_parser(modules, file)
+    }
+
+    fn function_names(statements: &[Statement]) -> Vec<String> {
+        statements
+            .iter()
+            .filter_map(|v| match v {
+                Statement::Function(contract, _) => contract.name.as_ref().map(|v| v.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bail_recovers() {
+        let (statements, errs) =
+            get_parser("struct A { x: }\nfn main() {}\nfn f( {}\nfn g() {}").parse_all();
+        assert_eq!(errs.len(), 2, "unexpected errors: {errs:?}");
+        assert_eq!(function_names(&statements), ["main", "g"]);
+    }
+
+    #[test]
+    fn test_bail_skips_garbage() {
+        let (statements, errs) =
+            get_parser("fn a() {}\n) 1 + * garbage; ]] \"text\" { fn c() } 12\nfn b() {}")
+                .parse_all();
+        assert!(!errs.is_empty());
+        assert_eq!(function_names(&statements), ["a", "b"]);
+
+        // the error is right before the next statement
+        let (statements, errs) = get_parser("let a = 1\nfn b() {}").parse_all();
+        assert_eq!(errs.len(), 1, "unexpected errors: {errs:?}");
+        assert_eq!(function_names(&statements), ["b"]);
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let nested_type = format!("{}i32{}", "[".repeat(10_000), "]".repeat(10_000));
+        let mut parser = get_parser(&nested_type);
+        assert!(matches!(
+            TypeRef::parse(&mut parser),
+            Err(ParsingError::RecursionLimitExceeded { .. })
+        ));
+
+        let mut parser = get_parser("[[[i32]]]");
+        assert_eq!(
+            TypeRef::parse(&mut parser).unwrap().to_string(),
+            "[[[i32]]]"
+        );
+        let mut parser = get_parser("[[[i32]]]");
+        parser.max_depth = 3;
+        assert!(matches!(
+            TypeRef::parse(&mut parser),
+            Err(ParsingError::RecursionLimitExceeded { .. })
+        ));
+
+        let parse_expr = |source: &str| get_parser(source).parse_expression();
+        let nested_expr = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(matches!(
+            parse_expr(&nested_expr),
+            Err(ParsingError::RecursionLimitExceeded { .. })
+        ));
+        assert!(matches!(
+            parse_expr(&format!("{}1", "-".repeat(10_000))),
+            Err(ParsingError::RecursionLimitExceeded { .. })
+        ));
+        // the outermost expression is one level
+        let depth = DEFAULT_MAX_DEPTH - 1;
+        parse_expr(&format!("{}1{}", "(".repeat(depth), ")".repeat(depth))).unwrap();
+        parse_expr(&format!("{}1{}", "f(".repeat(depth), ")".repeat(depth))).unwrap();
+
+        // blocks and the statements that contain other statements are nested as well
+        let parse_stmt = |source: &str| get_parser(source).parse_statement(false);
+        let depth = DEFAULT_MAX_DEPTH;
+        parse_stmt(&format!("{}{}", "{".repeat(depth), "}".repeat(depth))).unwrap();
+        for nested in ["{", "if (true) ", "while (true) ", "for (i in 0..1) "] {
+            let source = format!("{}return;{}", nested.repeat(10_000), "}".repeat(10_000));
+            assert!(
+                matches!(
+                    parse_stmt(&source),
+                    Err(ParsingError::RecursionLimitExceeded { .. })
+                ),
+                "{nested}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_depth_fits_the_stack() {
+        // the parser has to stay within the 2 MiB stack of a spawned thread at the limit, even
+        // for the constructs that take up the most stack per level
+        let depth = DEFAULT_MAX_DEPTH - 1;
+        std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(move || {
+                for (open, close) in [("(", ")"), ("f(", ")"), (".[", "]"), ("-", "")] {
+                    let source = format!("{}1{}", open.repeat(depth), close.repeat(depth));
+                    if let Err(e) = get_parser(&source).parse_expression() {
+                        panic!("{open}: {e:?}");
+                    }
+                }
+                let source = format!(
+                    "{}return;{}",
+                    "{ if (a) ".repeat(depth / 2),
+                    "}".repeat(depth / 2)
+                );
+                get_parser(&source).parse_statement(false).unwrap();
+            })
+            .expect("failed to spawn the thread")
+            .join()
+            .expect("failed to parse at the nesting limit");
+    }
+
+    #[test]
+    fn test_nested_array_types() {
+        // as deep as the parser allows, alternating between sized and unsized arrays
+        let depth = DEFAULT_MAX_DEPTH - 1;
+        let mut source = "i32".to_string();
+        for i in 0..depth {
+            source = if i % 2 == 0 {
+                format!("[{source}; {i}]")
+            } else {
+                format!("&[{source}]")
+            };
+        }
+
+        let typ = TypeRef::parse(&mut get_parser(&source)).unwrap();
+        assert_eq!(typ.to_string(), source);
+        assert_eq!(
+            TypeRef::parse(&mut get_parser(&typ.to_string())).unwrap(),
+            typ
+        );
+
+        let mut current = &typ;
+        for i in (0..depth).rev() {
+            current = match current {
+                TypeRef::SizedArray {
+                    child,
+                    number_elements: ArrayLength::Literal(n),
+                    num_references: 0,
+                    ..
+                } if i % 2 == 0 && *n == i => child,
+                TypeRef::UnsizedArray {
+                    child,
+                    num_references: 1,
+                    ..
+                } if i % 2 == 1 => child,
+                _ => panic!("unexpected type at level {i}: {current}"),
+            };
+        }
+        assert_eq!(current.to_string(), "i32");
+    }
+
+    #[test]
+    fn test_array_length_eq() {
+        let parse = |source: &str| TypeRef::parse(&mut get_parser(source)).unwrap();
+        // the locations differ, the expressions don't
+        assert_eq!(parse("[u8; size(2) + 1]"), parse("[u8;   size( 2 )+1]"));
+        assert_eq!(
+            parse("[u8; a::b(.{ x: 1.5 })]"),
+            parse("[u8; a::b(.{x: 1.5})]")
+        );
+        assert_ne!(parse("[u8; size(2) + 1]"), parse("[u8; size(3) + 1]"));
+        assert_ne!(parse("[u8; size(2) + 1]"), parse("[u8; 1 + size(2)]"));
+        assert_ne!(parse("[u8; (a + b) * c]"), parse("[u8; a + b * c]"));
+        assert_ne!(parse("[u8; 3]"), parse("[u8; size()]"));
+    }
+
+    #[test]
+    fn test_too_many_references() {
+        let mut parser = get_parser(&format!("{}i32", "&".repeat(300)));
+        assert!(matches!(
+            TypeRef::parse(&mut parser),
+            Err(ParsingError::TooManyReferences { .. })
+        ));
+        let mut parser = get_parser(&format!("{}i32", "&&".repeat(128)));
+        assert!(matches!(
+            TypeRef::parse(&mut parser),
+            Err(ParsingError::TooManyReferences { .. })
+        ));
+
+        let mut parser = get_parser(&format!("{}&i32", "&&".repeat(127)));
+        assert!(matches!(
+            TypeRef::parse(&mut parser),
+            Ok(TypeRef::Reference {
+                num_references: 255,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_derive() {
+        let (statements, errs) = get_parser("@derive(Eq, Clone) struct A { a: i32 }").parse_all();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let Statement::Struct { annotations, .. } = &statements[0] else {
+            panic!("expected a struct, found {}", statements[0]);
+        };
+        let derive = annotations.get_first_annotation::<Derive>().unwrap();
+        assert_eq!(derive.to_string(), "@derive(Eq, Clone)");
+
+        let (_, errs) = get_parser("@derive(Debug) struct A {}").parse_all();
+        assert!(
+            matches!(&errs[..], [ParsingError::InvalidDerive(_, name)] if *name == "Debug"),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_bail_at_eof() {
+        let (statements, errs) = get_parser("fn main() {}\nlet").parse_all();
+        assert_eq!(errs.len(), 1, "unexpected errors: {errs:?}");
+        assert_eq!(function_names(&statements), ["main"]);
+
+        let mut parser = get_parser("");
+        parser.bail();
+        parser.bail();
+        assert!(parser.is_at_end());
+        assert_eq!(parser.peek().typ, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_peek_nth() {
+        let mut parser = get_parser("let a = 12;");
+        assert_eq!(parser.peek_nth(0).typ, parser.peek().typ);
+        assert_eq!(parser.peek_nth(1).typ, TokenType::IdentifierLiteral);
+        assert_eq!(parser.peek_nth(4).typ, TokenType::Semicolon);
+        assert_eq!(parser.peek_nth(5).typ, TokenType::Eof);
+        assert_eq!(parser.peek_nth(usize::MAX).typ, TokenType::Eof);
+
+        parser.advance();
+        assert_eq!(parser.peek_nth(0).typ, parser.peek().typ);
+        assert_eq!(parser.peek_nth(1).typ, TokenType::Equal);
+        assert_eq!(parser.peek_nth(4).typ, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        const CASES: &[(&str, &str)] = &[
+            ("1 + 2 * 3", "(1 + (2 * 3))"),
+            ("1 * 2 + 3", "((1 * 2) + 3)"),
+            ("a - b - c", "((a - b) - c)"),
+            ("a / b % c", "((a / b) % c)"),
+            ("(a + b) * c", "((a + b) * c)"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c", "((a && b) || c)"),
+            ("a || b || c", "((a || b) || c)"),
+            ("a == b && c < d", "((a == b) && (c < d))"),
+            ("a + 1 <= b * 2", "((a + 1) <= (b * 2))"),
+            ("(a >= b) != c", "((a >= b) != c)"),
+            ("!a && -b < c", "((!a) && ((-b) < c))"),
+            ("-(a + b)", "(-(a + b))"),
+            ("a << 2 + 1", "(a << (2 + 1))"),
+            ("a >> 1 << 2", "((a >> 1) << 2)"),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a & b | c", "((a & b) | c)"),
+            ("a & 1 == b << 1", "((a & 1) == (b << 1))"),
+            ("a | b || c", "((a | b) || c)"),
+            ("~a & b", "((~a) & b)"),
+        ];
+
+        for (source, expected) in CASES {
+            let expr = get_parser(source)
+                .parse_expression()
+                .unwrap_or_else(|e| panic!("failed to parse `{source}`: {e:?}"));
+            assert_eq!(expr.to_string(), *expected, "while parsing `{source}`");
+        }
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        for source in ["a < b < c", "a >= b != c", "a == b == c", "a < b | c < d"] {
+            let res = get_parser(source).parse_expression();
+            assert!(
+                matches!(res, Err(ParsingError::ChainedComparison { .. })),
+                "unexpected result while parsing `{source}`: {res:?}"
+            );
+        }
+        for source in ["(a < b) == c", "a < b && b < c"] {
+            if let Err(e) = get_parser(source).parse_expression() {
+                panic!("failed to parse `{source}`: {e:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_expression_display_roundtrip() {
+        const CASES: &[(&str, &str)] = &[
+            ("1 + 2 * 3", "(1 + (2 * 3))"),
+            ("-a * !b", "((-a) * (!b))"),
+            ("&&a", "(&(&a))"),
+            ("*a.b", "(*a.b)"),
+            ("a.b.c", "a.b.c"),
+            ("a.b(1, c)", "a.b(1, c)"),
+            ("(a.b)(1)", "(a.b)(1)"),
+            ("a.b(f())", "a.b(f())"),
+            ("f(g(1), 2)(3)", "f(g(1), 2)(3)"),
+            ("a[b][1 + 2]", "a[b][(1 + 2)]"),
+            ("a[1..b]", "a[(1..b)]"),
+            ("a[1..=2]", "a[(1..=2)]"),
+            ("a as &u8 as usize", "((a as &u8) as usize)"),
+            ("a = b += 1", "a = b += 1"),
+            ("[1, 2.5, 3.0f32, 4i64]", "[1, 2.5, 3.0f32, 4i64]"),
+            (".(a, \"s\\n\", true)", ".(a, \"s\\n\", true)"),
+            (".{ a: 1 }", ".{ a: 1, }"),
+            ("S { a: b[0] }", "S { a: b[0], }"),
+            ("a::b::c(void)", "a::b::c(void)"),
+        ];
+
+        for (source, expected) in CASES {
+            let expr = get_parser(source)
+                .parse_expression()
+                .unwrap_or_else(|e| panic!("failed to parse `{source}`: {e:?}"));
+            let displayed = expr.to_string();
+            assert_eq!(displayed, *expected, "while parsing `{source}`");
+            let reparsed = get_parser(&displayed)
+                .parse_expression()
+                .unwrap_or_else(|e| panic!("failed to parse `{displayed}`: {e:?}"));
+            assert_eq!(
+                reparsed.to_string(),
+                displayed,
+                "while reparsing `{displayed}`"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        const CASES: &[(&str, &str)] = &[
+            ("a = b + 1", "a = (b + 1)"),
+            ("a += b * 2", "a += (b * 2)"),
+            ("a -= b - c", "a -= (b - c)"),
+            ("a *= 2", "a *= 2"),
+            ("a /= 2", "a /= 2"),
+            ("a %= 2", "a %= 2"),
+            ("a &= b", "a &= b"),
+            ("a |= b", "a |= b"),
+            ("a ^= b", "a ^= b"),
+            ("a <<= 1", "a <<= 1"),
+            ("a >>= 1", "a >>= 1"),
+            ("a <= 1", "(a <= 1)"),
+        ];
+
+        for (source, expected) in CASES {
+            let expr = get_parser(source)
+                .parse_expression()
+                .unwrap_or_else(|e| panic!("failed to parse `{source}`: {e:?}"));
+            assert_eq!(expr.to_string(), *expected, "while parsing `{source}`");
+        }
+    }
+
+    #[test]
+    fn test_format_strings() {
+        let expr = get_parser(r#"f"x = {x}, y = {{{y + 1}}}""#)
+            .parse_expression()
+            .expect("failed to parse the format string");
+        assert_eq!(expr.to_string(), r#".("x = ", x, ", y = {", (y + 1), "}")"#);
+        let Expression::Literal(LiteralValue::Tuple(parts), _) = &expr else {
+            panic!("expected a tuple, found {expr:?}");
+        };
+        assert!(matches!(
+            parts[0].1,
+            Expression::Literal(LiteralValue::String(_), _)
+        ));
+        assert!(matches!(parts[3].1, Expression::Binary { .. }));
+
+        let expr = get_parser(r#"f"{a}""#)
+            .parse_expression()
+            .expect("failed to parse the format string");
+        assert_eq!(expr.to_string(), ".(a)");
+
+        let err = get_parser(r#"f"{}""#)
+            .parse_expression()
+            .expect_err("empty embedded expressions should be rejected");
+        assert!(matches!(
+            err,
+            ParsingError::ExpectedExpression {
+                found: TokenType::CurlyRight,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_associated_consts() {
+        let (statements, errs) = get_parser(
+            "trait Shape { const SIDES: usize; fn area(self: &Self) -> i32; }
+struct Square { size: i32; impl Shape { const SIDES: usize = 4; fn area(self: &Self) -> i32 { return 0; } } }",
+        )
+        .parse_all();
+        assert_eq!(errs.len(), 0, "unexpected errors: {errs:?}");
+
+        let Statement::Trait(r#trait) = &statements[0] else {
+            panic!("expected a trait, found {:?}", statements[0]);
+        };
+        assert_eq!(r#trait.consts.len(), 1);
+        assert_eq!(r#trait.consts[0].0, GlobalStr::new("SIDES"));
+        assert_eq!(r#trait.consts[0].1.to_string(), "usize");
+        assert!(r#trait.to_string().contains("    const SIDES: usize;\n"));
+
+        let Statement::Struct { impls, .. } = &statements[1] else {
+            panic!("expected a struct, found {:?}", statements[1]);
+        };
+        let (_, functions, consts, _) = &impls[0];
+        assert_eq!(functions.len(), 1);
+        assert_eq!(consts.len(), 1);
+        assert_eq!(consts[0].0, GlobalStr::new("SIDES"));
+        assert!(matches!(consts[0].2, Expression::Literal(..)));
+
+        // trait consts can't have a value
+        let (_, errs) = get_parser("trait Shape { const SIDES: usize = 4; }").parse_all();
+        assert!(
+            matches!(
+                errs[0],
+                ParsingError::ExpectedArbitrary {
+                    expected: TokenType::Semicolon,
+                    found: TokenType::Equal,
+                    ..
+                }
+            ),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let mut parser = get_parser("1 + 2 * 3;");
+        let expr = parser.parse_expression().unwrap();
+        let Expression::Binary {
+            operator: BinaryOp::Plus,
+            left_side,
+            right_side,
+            ..
+        } = &expr
+        else {
+            panic!("expected an addition, found {expr}");
+        };
+        assert!(matches!(
+            **left_side,
+            Expression::Literal(LiteralValue::UInt(1, _), _)
+        ));
+        let Expression::Binary {
+            operator: BinaryOp::Multiply,
+            left_side,
+            right_side,
+            ..
+        } = &**right_side
+        else {
+            panic!("expected a multiplication, found {right_side}");
+        };
+        assert!(matches!(
+            (&**left_side, &**right_side),
+            (
+                Expression::Literal(LiteralValue::UInt(2, _), _),
+                Expression::Literal(LiteralValue::UInt(3, _), _)
+            )
+        ));
+        // the semicolon is left for the caller
+        assert_eq!(parser.peek().typ, TokenType::Semicolon);
+    }
+
+    #[test]
+    fn test_multiple_bounds() {
+        let (statements, errs) = get_parser("fn f<T: A + B + c::C>(a: T) {}").parse_all();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let Statement::Function(contract, _) = &statements[0] else {
+            panic!("expected a function, found {}", statements[0]);
+        };
+        let bounds = contract.generics[0]
+            .bounds
+            .iter()
+            .map(|(path, _)| path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(bounds, ["A", "B", "c::C"]);
+
+        for source in [
+            "fn f<T: A +>() {}",
+            "fn f<T: A + B +, U>() {}",
+            "fn f<T:>() {}",
+        ] {
+            let (_, errs) = get_parser(source).parse_all();
+            assert!(
+                matches!(errs[..], [ParsingError::ExpectedTraitBound { .. }, ..]),
+                "unexpected errors for {source}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_where_clauses() {
+        fn bounds(source: &str) -> Vec<(GlobalStr, Vec<PathWithoutGenerics>)> {
+            let (statements, errs) = get_parser(source).parse_all();
+            assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+            let generics = match &statements[0] {
+                Statement::Struct { generics, .. } => generics,
+                Statement::Function(contract, _) => &contract.generics,
+                stmt => panic!("unexpected statement {stmt}"),
+            };
+            generics
+                .iter()
+                .map(|generic| {
+                    let bounds = generic.bounds.iter().map(|(path, _)| path.clone());
+                    (generic.name.clone(), bounds.collect())
+                })
+                .collect()
+        }
+
+        assert_eq!(
+            bounds("struct A<T: X + Y, U> { a: T }"),
+            bounds("struct A<T, U> where T: X + Y, { a: T }")
+        );
+        assert_eq!(
+            bounds("fn f<T: X, U: Y + Z>(a: T) -> T { return a; }"),
+            bounds("fn f<T, U: Y>(a: T) -> T where T: X, U: Z { return a; }")
+        );
+
+        let (_, errs) = get_parser("fn f<T>() where U: X {}").parse_all();
+        assert!(
+            matches!(&errs[..], [ParsingError::UnknownGenericParameter { name, .. }] if *name == "U"),
+            "unexpected errors: {errs:?}"
+        );
+        let (_, errs) = get_parser("fn f<T>() where T: X, T: Y {}").parse_all();
+        assert!(
+            matches!(errs[..], [ParsingError::DuplicateWhereBound { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_var() {
+        let mut parser = get_parser("let a = 1; var b: i32 = 2;");
+        assert_eq!(
+            parser.parse_statement(false).unwrap().to_string(),
+            "(var-assign a 1)"
+        );
+        assert_eq!(
+            parser.parse_statement(false).unwrap().to_string(),
+            "(var-assign-mut b i32 2)"
+        );
+
+        let (_, errs) = get_parser("var a: i32 = 1;").parse_all();
+        assert!(
+            matches!(
+                errs[..],
+                [ParsingError::InvalidKeyword { keyword: "var", .. }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_indexing() {
+        let mut parser = get_parser("let a = b[1][c];");
+        assert_eq!(
+            parser.parse_statement(false).unwrap().to_string(),
+            "(var-assign a b[1][c])"
+        );
+        let mut parser = get_parser("let a = b[1..c];");
+        assert_eq!(
+            parser.parse_statement(false).unwrap().to_string(),
+            "(var-assign a b[(1..c)])"
+        );
+    }
+
+    #[test]
+    fn test_extern_abi() {
+        let (statements, errs) =
+            get_parser("extern \"C\" fn a();\nextern \"stdcall\" fn b(x: i32);\nextern fn c();")
+                .parse_all();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let callconvs = statements
+            .iter()
+            .map(|v| match v {
+                Statement::ExternalFunction(contract, _) => contract
+                    .annotations
+                    .get_first_annotation::<CallConvAnnotation>()
+                    .copied(),
+                stmt => panic!("expected an external function, found {stmt}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            callconvs,
+            [
+                Some(CallConvAnnotation::C),
+                Some(CallConvAnnotation::Stdcall),
+                None
+            ]
+        );
+
+        for abi in ["rust", "naked", "Inline"] {
+            let (_, errs) = get_parser(&format!("extern \"{abi}\" fn a();")).parse_all();
+            assert!(
+                matches!(&errs[..], [ParsingError::InvalidCallConv(_, name)] if *name == abi),
+                "unexpected errors: {errs:?}"
+            );
+        }
+        let (_, errs) = get_parser("@callconv(c)\nextern \"C\" fn a();").parse_all();
+        assert!(
+            matches!(errs[..], [ParsingError::AnnotationDoesNotGoOn { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_extern_varargs() {
+        let (statements, errs) =
+            get_parser("extern fn printf(fmt: &u8, ...) -> i32;\nextern fn f(...,);").parse_all();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let Statement::ExternalFunction(contract, _) = &statements[0] else {
+            panic!("expected an external function, found {}", statements[0]);
+        };
+        assert_eq!(contract.arguments.len(), 1);
+        assert!(contract
+            .annotations
+            .get_first_annotation::<ExternVarArg>()
+            .is_some());
+
+        for source in [
+            "fn printf(fmt: &u8, ...) {}",
+            "extern fn printf(..., fmt: &u8);",
+        ] {
+            let (_, errs) = get_parser(source).parse_all();
+            assert!(!errs.is_empty(), "expected an error for {source}");
+        }
+    }
+
+    #[test]
+    fn test_keyword_as_identifier() {
+        let (_, errs) = get_parser("let struct = 1;").parse_all();
+        match &errs[..] {
+            [ParsingError::KeywordAsIdentifier { keyword, loc }, ..] => {
+                assert_eq!(*keyword, "struct");
+                assert_eq!(loc.to_string(), "test:0:4");
+            }
+            errs => panic!("unexpected errors: {errs:?}"),
+        }
+        let (_, errs) = get_parser("let `struct` = 1;").parse_all();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let (statements, errs) = get_parser("").parse_all();
+        assert!(statements.is_empty());
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        // a parser without any tokens gets an eof token
+        let file: Arc<std::path::Path> = std::path::Path::new("test").into();
+        let mut parser = Parser::new(Vec::new(), Default::default(), file.clone(), file);
+        assert_eq!(parser.tokens.len(), 1);
+        assert!(parser.is_at_end());
+        let (statements, errs) = parser.parse_all();
+        assert!(statements.is_empty());
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+    }
+}
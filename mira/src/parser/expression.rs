@@ -180,7 +180,8 @@ impl Display for LiteralValue {
             LiteralValue::Dynamic(d) => Display::fmt(d, f),
             LiteralValue::UInt(v, typ) => f.write_fmt(format_args!("{}{}", *v, *typ)),
             LiteralValue::SInt(v, typ) => f.write_fmt(format_args!("{}{}", *v, *typ)),
-            LiteralValue::Float(v, typ) => f.write_fmt(format_args!("{}{}", *v, *typ)),
+            // debug formatting keeps the `.0` of whole numbers, so they stay floats
+            LiteralValue::Float(v, typ) => f.write_fmt(format_args!("{:?}{}", *v, *typ)),
             LiteralValue::String(v) => Debug::fmt(v, f),
             LiteralValue::Array(v) => {
                 f.write_char('[')?;
@@ -241,6 +242,46 @@ impl Display for LiteralValue {
     }
 }
 
+/// Literals are compared structurally, the locations of their elements are ignored. Anonymous
+/// functions are only equal once they are baked into the same function.
+impl PartialEq for LiteralValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Array(a), Self::Array(b)) => a == b,
+            (Self::Struct(a, a_name), Self::Struct(b, b_name)) => {
+                a_name == b_name && fields_eq(a, b)
+            }
+            (Self::AnonymousStruct(a), Self::AnonymousStruct(b)) => fields_eq(a, b),
+            (Self::Tuple(a), Self::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.1 == b.1)
+            }
+            // comparing the bits keeps NaN equal to itself
+            (Self::Float(a, a_typ), Self::Float(b, b_typ)) => {
+                a.to_bits() == b.to_bits() && a_typ == b_typ
+            }
+            (Self::SInt(a, a_typ), Self::SInt(b, b_typ)) => a == b && a_typ == b_typ,
+            (Self::UInt(a, a_typ), Self::UInt(b, b_typ)) => a == b && a_typ == b_typ,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Dynamic(a), Self::Dynamic(b)) => a == b,
+            (Self::BakedAnonymousFunction(a), Self::BakedAnonymousFunction(b)) => a == b,
+            (Self::Void, Self::Void) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LiteralValue {}
+
+fn fields_eq(
+    a: &HashMap<GlobalStr, (Location, Expression)>,
+    b: &HashMap<GlobalStr, (Location, Expression)>,
+) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(name, (_, a))| b.get(name).is_some_and(|(_, b)| a == b))
+}
+
 impl LiteralValue {
     // NOTE: **ONLY** FOR ERROR MESSAGES!!!!!
     pub fn type_name(&self) -> &'static str {
@@ -281,6 +322,58 @@ pub enum BinaryOp {
     NotEquals,
 }
 
+impl BinaryOp {
+    /// How tightly the operator binds, higher binds tighter (`a || b && c` is `a || (b && c)`).
+    /// All binary operators are left-associative.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::LogicalOr => 0,
+            Self::LogicalAnd => 1,
+            Self::Equals
+            | Self::NotEquals
+            | Self::LessThan
+            | Self::LessThanEq
+            | Self::GreaterThan
+            | Self::GreaterThanEq => 2,
+            Self::BitwiseOr => 3,
+            Self::BitwiseXor => 4,
+            Self::BitwiseAnd => 5,
+            Self::LShift | Self::RShift => 6,
+            Self::Plus | Self::Minus => 7,
+            Self::Multiply | Self::Divide | Self::Modulo => 8,
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == Self::Equals.precedence()
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Divide => "/",
+            Self::Multiply => "*",
+            Self::Modulo => "%",
+            Self::LessThan => "<",
+            Self::LessThanEq => "<=",
+            Self::GreaterThan => ">",
+            Self::GreaterThanEq => ">=",
+            Self::LShift => "<<",
+            Self::RShift => ">>",
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+            Self::BitwiseAnd => "&",
+            Self::BitwiseOr => "|",
+            Self::BitwiseXor => "^",
+            Self::Equals => "==",
+            Self::NotEquals => "!=",
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UnaryOp {
     Plus,
@@ -291,6 +384,33 @@ pub enum UnaryOp {
     Dereference,
 }
 
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::BitwiseNot => "~",
+            Self::LogicalNot => "!",
+            Self::Reference => "&",
+            Self::Dereference => "*",
+        })
+    }
+}
+
+fn display_arguments(
+    f: &mut std::fmt::Formatter<'_>,
+    arguments: &[Expression],
+) -> std::fmt::Result {
+    f.write_char('(')?;
+    for (i, arg) in arguments.iter().enumerate() {
+        if i != 0 {
+            f.write_str(", ")?;
+        }
+        Display::fmt(arg, f)?;
+    }
+    f.write_char(')')
+}
+
 #[derive(Debug, Clone)]
 pub enum Expression {
     Literal(LiteralValue, Location),
@@ -326,6 +446,8 @@ pub enum Expression {
     Assignment {
         left_side: Box<Expression>,
         right_side: Box<Expression>,
+        /// the operator of a compound assignment, `Plus` for `a += b`
+        operator: Option<BinaryOp>,
         loc: Location,
     },
     Range {
@@ -349,6 +471,156 @@ pub enum Expression {
     },
 }
 
+/// Expressions are compared structurally, their locations are ignored
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(a, _), Self::Literal(b, _)) => a == b,
+            (
+                Self::Unary {
+                    operator: a_op,
+                    right_side: a,
+                    ..
+                },
+                Self::Unary {
+                    operator: b_op,
+                    right_side: b,
+                    ..
+                },
+            ) => a_op == b_op && a == b,
+            (
+                Self::Binary {
+                    operator: a_op,
+                    right_side: a_rhs,
+                    left_side: a_lhs,
+                    ..
+                },
+                Self::Binary {
+                    operator: b_op,
+                    right_side: b_rhs,
+                    left_side: b_lhs,
+                    ..
+                },
+            ) => a_op == b_op && a_lhs == b_lhs && a_rhs == b_rhs,
+            (
+                Self::FunctionCall {
+                    identifier: a,
+                    arguments: a_args,
+                },
+                Self::FunctionCall {
+                    identifier: b,
+                    arguments: b_args,
+                },
+            ) => a == b && a_args == b_args,
+            (
+                Self::MemberCall {
+                    identifier: a,
+                    lhs: a_lhs,
+                    arguments: a_args,
+                },
+                Self::MemberCall {
+                    identifier: b,
+                    lhs: b_lhs,
+                    arguments: b_args,
+                },
+            ) => a == b && a_lhs == b_lhs && a_args == b_args,
+            (
+                Self::Indexing {
+                    left_side: a_lhs,
+                    right_side: a_rhs,
+                },
+                Self::Indexing {
+                    left_side: b_lhs,
+                    right_side: b_rhs,
+                },
+            ) => a_lhs == b_lhs && a_rhs == b_rhs,
+            (
+                Self::MemberAccess {
+                    left_side: a,
+                    index: a_index,
+                    ..
+                },
+                Self::MemberAccess {
+                    left_side: b,
+                    index: b_index,
+                    ..
+                },
+            ) => a == b && a_index == b_index,
+            (
+                Self::Assignment {
+                    left_side: a_lhs,
+                    right_side: a_rhs,
+                    operator: a_op,
+                    ..
+                },
+                Self::Assignment {
+                    left_side: b_lhs,
+                    right_side: b_rhs,
+                    operator: b_op,
+                    ..
+                },
+            ) => a_op == b_op && a_lhs == b_lhs && a_rhs == b_rhs,
+            (
+                Self::Range {
+                    left_side: a_lhs,
+                    right_side: a_rhs,
+                    inclusive: a_inclusive,
+                    ..
+                },
+                Self::Range {
+                    left_side: b_lhs,
+                    right_side: b_rhs,
+                    inclusive: b_inclusive,
+                    ..
+                },
+            ) => a_inclusive == b_inclusive && a_lhs == b_lhs && a_rhs == b_rhs,
+            (
+                Self::TypeCast {
+                    left_side: a,
+                    new_type: a_typ,
+                    ..
+                },
+                Self::TypeCast {
+                    left_side: b,
+                    new_type: b_typ,
+                    ..
+                },
+            ) => a == b && a_typ == b_typ,
+            (
+                Self::Asm {
+                    asm: a_asm,
+                    volatile: a_volatile,
+                    output: a_output,
+                    registers: a_registers,
+                    inputs: a_inputs,
+                    ..
+                },
+                Self::Asm {
+                    asm: b_asm,
+                    volatile: b_volatile,
+                    output: b_output,
+                    registers: b_registers,
+                    inputs: b_inputs,
+                    ..
+                },
+            ) => {
+                a_asm == b_asm
+                    && a_volatile == b_volatile
+                    && a_output == b_output
+                    && a_registers == b_registers
+                    && a_inputs.len() == b_inputs.len()
+                    && a_inputs.iter().zip(b_inputs).all(|(a, b)| a.1 == b.1)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Expression {}
+
+/// Displays the expression as source code that parses back into the same expression. Binary,
+/// unary, range and cast expressions are always wrapped in parentheses, which makes their
+/// precedence visible.
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -358,112 +630,71 @@ impl Display for Expression {
                 right_side,
                 left_side,
                 ..
-            } => {
-                f.write_char('(')?;
-                operator.fmt(f)?;
-                f.write_char(' ')?;
-                Display::fmt(left_side, f)?;
-                f.write_char(' ')?;
-                Display::fmt(right_side, f)?;
-                f.write_char(')')
-            }
+            } => f.write_fmt(format_args!("({left_side} {operator} {right_side})")),
             Expression::Unary {
                 operator,
                 right_side,
                 ..
-            } => {
-                f.write_char('(')?;
-                operator.fmt(f)?;
-                f.write_char(' ')?;
-                Display::fmt(right_side, f)?;
-                f.write_char(')')
-            }
+            } => f.write_fmt(format_args!("({operator}{right_side})")),
             Expression::FunctionCall {
                 identifier,
                 arguments,
-                ..
             } => {
-                f.write_str("(call ")?;
-                Display::fmt(identifier, f)?;
-                for i in 0..arguments.len() {
-                    f.write_char(' ')?;
-                    Display::fmt(&arguments[i], f)?;
+                // `(a.b)(c)` calls the field b, while `a.b(c)` calls the method b
+                if matches!(**identifier, Expression::MemberAccess { .. }) {
+                    f.write_fmt(format_args!("({identifier})"))?;
+                } else {
+                    Display::fmt(identifier, f)?;
                 }
-                f.write_char(')')
+                display_arguments(f, arguments)
             }
             Expression::Indexing {
                 left_side,
                 right_side,
-            } => {
-                f.write_str("(index ")?;
-                Display::fmt(left_side, f)?;
-                f.write_char(' ')?;
-                Display::fmt(right_side, f)?;
-                f.write_char(')')
-            }
+            } => f.write_fmt(format_args!("{left_side}[{right_side}]")),
             Expression::MemberAccess {
                 left_side, index, ..
             } => {
-                f.write_str("(member ")?;
                 Display::fmt(left_side, f)?;
                 for value in index {
-                    f.write_char(' ')?;
+                    f.write_char('.')?;
                     Display::fmt(value, f)?;
                 }
-
-                f.write_char(')')
+                Ok(())
             }
             Expression::Assignment {
                 left_side,
                 right_side,
+                operator: None,
                 ..
-            } => {
-                f.write_str("(assignment ")?;
-                Display::fmt(left_side, f)?;
-                f.write_char(' ')?;
-                Display::fmt(right_side, f)?;
-                f.write_char(')')
-            }
+            } => f.write_fmt(format_args!("{left_side} = {right_side}")),
+            Expression::Assignment {
+                left_side,
+                right_side,
+                operator: Some(operator),
+                ..
+            } => f.write_fmt(format_args!("{left_side} {operator}= {right_side}")),
             Expression::Range {
                 left_side,
                 right_side,
                 inclusive,
                 loc: _,
             } => {
-                f.write_str("(range ")?;
-                if *inclusive {
-                    f.write_str("inclusive ")?;
-                }
-                Display::fmt(left_side, f)?;
-                f.write_char(' ')?;
-                Display::fmt(right_side, f)?;
-                f.write_char(')')
+                let operator = if *inclusive { "..=" } else { ".." };
+                f.write_fmt(format_args!("({left_side}{operator}{right_side})"))
             }
             Expression::TypeCast {
                 left_side,
                 new_type,
                 loc: _,
-            } => {
-                f.write_str("(type-cast ")?;
-                Display::fmt(left_side, f)?;
-                f.write_str(" -> ")?;
-                Display::fmt(new_type, f)?;
-                f.write_char(')')
-            }
+            } => f.write_fmt(format_args!("({left_side} as {new_type})")),
             Expression::MemberCall {
                 identifier,
                 lhs,
                 arguments,
             } => {
-                f.write_str("(member-call ")?;
-                Display::fmt(lhs, f)?;
-                f.write_char(' ')?;
-                Display::fmt(identifier, f)?;
-                for arg in arguments {
-                    f.write_char(' ')?;
-                    Display::fmt(arg, f)?;
-                }
-                f.write_char(')')
+                f.write_fmt(format_args!("{lhs}.{identifier}"))?;
+                display_arguments(f, arguments)
             }
             Self::Asm {
                 asm,
@@ -611,17 +842,6 @@ impl Expression {
     }
 }
 
-macro_rules! assign_set {
-    ($expr: expr, $right: expr, $operator: expr, $loc: expr) => {
-        let loc = $loc;
-        $expr = Expression::Assignment {
-            left_side: Box::new($expr.clone()),
-            right_side: Box::new(Expression::binary($operator, loc.clone(), $expr, $right)),
-            loc,
-        }
-    };
-}
-
 // asm expression
 impl Parser {
     /// Parses [<.0>] "<.1>" (<.2>)
@@ -775,35 +995,65 @@ impl Parser {
 }
 
 impl Parser {
+    /// Parses a single expression, stopping before the first token that can't continue it (e.g.
+    /// a `;`, which is left for the caller to consume)
     pub fn parse_expression(&mut self) -> Result<Expression, ParsingError> {
         if self.peek().typ == TokenType::Asm {
             return self.parse_asm();
         }
-        self.comparison()
+        self.nested(|parser| parser.binary_expression(0))
     }
 
-    fn comparison(&mut self) -> Result<Expression, ParsingError> {
-        let mut expr = self.pipe_operator()?;
+    /// returns the binary operator `binary_expression` can parse at the current position and the
+    /// number of tokens it is made of
+    fn peek_binary_operator(&self) -> Option<(BinaryOp, usize)> {
+        match (self.peek().typ, self.peekpeek().typ) {
+            (TokenType::LogicalOr, _) => Some((BinaryOp::LogicalOr, 1)),
+            (TokenType::LogicalAnd, _) => Some((BinaryOp::LogicalAnd, 1)),
+            (TokenType::EqualEqual, _) => Some((BinaryOp::Equals, 1)),
+            (TokenType::NotEquals, _) => Some((BinaryOp::NotEquals, 1)),
+            (TokenType::LessThan, TokenType::Equal) => Some((BinaryOp::LessThanEq, 2)),
+            (TokenType::LessThan, TokenType::LessThan) => Some((BinaryOp::LShift, 2)),
+            (TokenType::LessThan, _) => Some((BinaryOp::LessThan, 1)),
+            (TokenType::GreaterThan, TokenType::Equal) => Some((BinaryOp::GreaterThanEq, 2)),
+            (TokenType::GreaterThan, TokenType::GreaterThan) => Some((BinaryOp::RShift, 2)),
+            (TokenType::GreaterThan, _) => Some((BinaryOp::GreaterThan, 1)),
+            (TokenType::BitwiseOr, _) => Some((BinaryOp::BitwiseOr, 1)),
+            (TokenType::BitwiseXor, _) => Some((BinaryOp::BitwiseXor, 1)),
+            (TokenType::Ampersand, _) => Some((BinaryOp::BitwiseAnd, 1)),
+            _ => None,
+        }
+    }
 
-        loop {
+    /// precedence climbing over the logical, comparison, bitwise and shift operators. Only
+    /// operators with a precedence of at least `min_precedence` are consumed.
+    ///
+    /// Pipes and ranges bind tighter than comparisons but looser than the bitwise operators, so
+    /// they are parsed as the operands of the comparisons: `x & 1 |> f() == 0` is `f(x & 1) == 0`.
+    fn binary_expression(&mut self, min_precedence: u8) -> Result<Expression, ParsingError> {
+        let mut expr = if min_precedence > BinaryOp::Equals.precedence() {
+            self.term()?
+        } else {
+            self.pipe_operator()?
+        };
+
+        // if `expr` is a comparison that was parsed by this loop, which can't be compared again
+        let mut is_comparison = false;
+        while let Some((op, num_tokens)) = self.peek_binary_operator() {
+            if op.precedence() < min_precedence {
+                break;
+            }
             let loc = self.peek().location.clone();
-            let op = match self.peek().typ {
-                TokenType::EqualEqual => BinaryOp::Equals,
-                TokenType::NotEquals => BinaryOp::NotEquals,
-                TokenType::LogicalAnd => BinaryOp::LogicalAnd,
-                TokenType::LogicalOr => BinaryOp::LogicalOr,
-                TokenType::LessThan if self.peekpeek().typ == TokenType::Equal => {
-                    BinaryOp::LessThanEq
-                }
-                TokenType::LessThan => BinaryOp::LessThan,
-                TokenType::GreaterThan if self.peekpeek().typ == TokenType::Equal => {
-                    BinaryOp::GreaterThanEq
-                }
-                TokenType::GreaterThan => BinaryOp::GreaterThan,
-                _ => break,
-            };
-            self.advance();
-            let right = self.pipe_operator()?;
+            // `a < b < c` would compare the bool `a < b` to c
+            if is_comparison && op.is_comparison() {
+                return Err(ParsingError::ChainedComparison { loc });
+            }
+            is_comparison = op.is_comparison();
+            for _ in 0..num_tokens {
+                self.advance();
+            }
+            // + 1 makes the operator left-associative: a - b - c => (a - b) - c
+            let right = self.binary_expression(op.precedence() + 1)?;
             expr = Expression::binary(op, loc, expr, right);
         }
 
@@ -835,7 +1085,7 @@ impl Parser {
     }
 
     fn range(&mut self) -> Result<Expression, ParsingError> {
-        let mut expr = self.term()?;
+        let mut expr = self.binary_expression(BinaryOp::BitwiseOr.precedence())?;
 
         while self.matches(&[TokenType::Range, TokenType::RangeInclusive]) {
             let inclusive = self.current().typ == TokenType::RangeInclusive;
@@ -855,32 +1105,15 @@ impl Parser {
     fn term(&mut self) -> Result<Expression, ParsingError> {
         let mut expr = self.factor()?;
 
-        while self.matches(&[
-            TokenType::Minus,
-            TokenType::Plus,
-            TokenType::PlusAssign,
-            TokenType::MinusAssign,
-        ]) {
-            let mut operator = self.current().clone();
-            let right = self.factor()?;
-
-            match operator.typ {
-                TokenType::PlusAssign => {
-                    operator.typ = TokenType::Plus;
-                    assign_set!(expr, right, BinaryOp::Plus, operator.location);
-                }
-                TokenType::MinusAssign => {
-                    operator.typ = TokenType::Minus;
-                    assign_set!(expr, right, BinaryOp::Minus, operator.location);
-                }
-                TokenType::Plus => {
-                    expr = Expression::binary(BinaryOp::Plus, operator.location, expr, right)
-                }
-                TokenType::Minus => {
-                    expr = Expression::binary(BinaryOp::Minus, operator.location, expr, right)
-                }
+        while self.matches(&[TokenType::Minus, TokenType::Plus]) {
+            let op = match self.current().typ {
+                TokenType::Plus => BinaryOp::Plus,
+                TokenType::Minus => BinaryOp::Minus,
                 _ => unreachable!(),
-            }
+            };
+            let loc = self.current().location.clone();
+            let right = self.factor()?;
+            expr = Expression::binary(op, loc, expr, right);
         }
 
         Ok(expr)
@@ -890,27 +1123,15 @@ impl Parser {
         let mut expr = self.unary()?;
 
         loop {
-            let op = match (self.peek().typ, self.peekpeek().typ) {
-                (TokenType::Divide, _) => BinaryOp::Divide,
-                (TokenType::Modulo, _) => BinaryOp::Modulo,
-                (TokenType::Asterix, _) => BinaryOp::Multiply,
-                (TokenType::Ampersand, _) => BinaryOp::BitwiseAnd,
-                (TokenType::BitwiseOr, _) => BinaryOp::BitwiseOr,
-                (TokenType::BitwiseXor, _) => BinaryOp::BitwiseXor,
-                (TokenType::LessThan, TokenType::LessThan) => BinaryOp::LShift,
-                (TokenType::GreaterThan, TokenType::GreaterThan) => BinaryOp::RShift,
+            let op = match self.peek().typ {
+                TokenType::Divide => BinaryOp::Divide,
+                TokenType::Modulo => BinaryOp::Modulo,
+                TokenType::Asterix => BinaryOp::Multiply,
                 _ => break,
             };
             let loc = self.advance().location.clone();
-            if op == BinaryOp::LShift || op == BinaryOp::RShift {
-                self.advance();
-            }
             let right = self.unary()?;
-            if self.match_tok(TokenType::Equal) {
-                assign_set!(expr, right, op, loc);
-            } else {
-                expr = Expression::binary(op, loc, expr, right);
-            }
+            expr = Expression::binary(op, loc, expr, right);
         }
 
         Ok(expr)
@@ -931,24 +1152,55 @@ impl Parser {
                 _ => unreachable!(),
             };
             let loc = self.current().location.clone();
-            return Ok(Expression::unary(operator, loc, self.unary()?));
+            return Ok(Expression::unary(operator, loc, self.nested(Self::unary)?));
         }
 
         self.assignment()
     }
 
+    /// returns the binary operator of the (compound) assignment at the current position, `None`
+    /// for `=`, and the number of tokens it is made of
+    fn peek_assignment_operator(&self) -> Option<(Option<BinaryOp>, usize)> {
+        let op = match self.peek().typ {
+            TokenType::Equal => None,
+            TokenType::PlusAssign => Some(BinaryOp::Plus),
+            TokenType::MinusAssign => Some(BinaryOp::Minus),
+            TokenType::MultiplyAssign => Some(BinaryOp::Multiply),
+            TokenType::DivideAssign => Some(BinaryOp::Divide),
+            TokenType::ModuloAssign => Some(BinaryOp::Modulo),
+            TokenType::BitwiseAndAssign => Some(BinaryOp::BitwiseAnd),
+            TokenType::BitwiseOrAssign => Some(BinaryOp::BitwiseOr),
+            TokenType::BitwiseXorAssign => Some(BinaryOp::BitwiseXor),
+            // `<<=` and `>>=` are tokenized as `<` `<` `=` like `<<` and `<=` are
+            typ @ (TokenType::LessThan | TokenType::GreaterThan)
+                if self.peek_nth(1).typ == typ && self.peek_nth(2).typ == TokenType::Equal =>
+            {
+                return match typ {
+                    TokenType::LessThan => Some((Some(BinaryOp::LShift), 3)),
+                    _ => Some((Some(BinaryOp::RShift), 3)),
+                };
+            }
+            _ => return None,
+        };
+        Some((op, 1))
+    }
+
     fn assignment(&mut self) -> Result<Expression, ParsingError> {
         let expr = self.type_cast()?;
-        if self.match_tok(TokenType::Equal) {
-            let loc = self.current().location.clone();
-            let value = self.parse_expression()?;
-            return Ok(Expression::Assignment {
-                left_side: Box::new(expr),
-                right_side: Box::new(value),
-                loc,
-            });
+        let Some((op, num_tokens)) = self.peek_assignment_operator() else {
+            return Ok(expr);
+        };
+        let loc = self.peek().location.clone();
+        for _ in 0..num_tokens {
+            self.advance();
         }
-        Ok(expr)
+        let value = self.parse_expression()?;
+        Ok(Expression::Assignment {
+            left_side: Box::new(expr),
+            right_side: Box::new(value),
+            operator: op,
+            loc,
+        })
     }
 
     fn type_cast(&mut self) -> Result<Expression, ParsingError> {
@@ -1003,7 +1255,9 @@ impl Parser {
 
         while self.matches(&[TokenType::BracketLeft, TokenType::ParenLeft, TokenType::Dot]) {
             if self.current().typ == TokenType::ParenLeft {
-                // function call
+                // function call, `(a.b)(c)` calls the field b instead of the method b
+                let parenthesized_callee =
+                    self.tokens[self.current.saturating_sub(2)].typ == TokenType::ParenRight;
                 let mut arguments: Vec<Expression> = vec![];
                 loop {
                     if self.peek().typ == TokenType::ParenRight {
@@ -1031,7 +1285,7 @@ impl Parser {
                     }
                 }
 
-                if self.tokens[self.current.saturating_sub(2)].typ == TokenType::ParenRight {
+                if parenthesized_callee {
                     expr = Expression::FunctionCall {
                         identifier: Box::new(expr),
                         arguments,
@@ -1168,6 +1422,10 @@ impl Parser {
             }
         }
 
+        if self.peek().typ == TokenType::FormatStringStart {
+            return self.parse_format_string();
+        }
+
         if let Some(lit) = self.peek().to_literal_value() {
             return Ok(Expression::Literal(lit, self.advance().location.clone()));
         }
@@ -1185,6 +1443,26 @@ impl Parser {
         });
     }
 
+    /// Parses a format string into a tuple of its string fragments and embedded expressions, e.g.
+    /// `f"x = {x}!"` turns into `.("x = ", x, "!")`.
+    fn parse_format_string(&mut self) -> Result<Expression, ParsingError> {
+        let loc = self.advance().location.clone();
+        let mut parts = Vec::new();
+        while !self.match_tok(TokenType::FormatStringEnd) {
+            if self.match_tok(TokenType::FormatStringFragment) {
+                let fragment = self.current();
+                let location = fragment.location.clone();
+                let value = LiteralValue::String(fragment.string_literal()?.clone());
+                parts.push((location.clone(), Expression::Literal(value, location)));
+                continue;
+            }
+            self.expect_tok(TokenType::CurlyLeft)?;
+            parts.push((self.peek().location.clone(), self.parse_expression()?));
+            self.expect_tok(TokenType::CurlyRight)?;
+        }
+        Ok(Expression::Literal(LiteralValue::Tuple(parts), loc))
+    }
+
     fn try_array(&mut self) -> Option<Result<Expression, ParsingError>> {
         if self.match_tok(TokenType::BracketLeft) {
             let loc = self.current().location.clone();
@@ -1270,12 +1548,25 @@ impl Parser {
         }
     }
 
+    /// Appends an eof token to `tokens` if they don't end in one already, so the parser can
+    /// always fall back to it.
     pub fn new(
-        tokens: Vec<Token>,
+        mut tokens: Vec<Token>,
         modules: Arc<RwLock<Vec<ParserQueueEntry>>>,
         file: Arc<std::path::Path>,
         root_directory: Arc<std::path::Path>,
     ) -> Self {
+        if tokens.last().map(|v| v.typ) != Some(TokenType::Eof) {
+            let location = tokens
+                .last()
+                .map(|v| v.location.clone())
+                .unwrap_or_else(|| Location::new(file.clone(), 0, 0));
+            tokens.push(Token {
+                typ: TokenType::Eof,
+                literal: None,
+                location,
+            });
+        }
         Self {
             tokens,
             current: 0,
@@ -1284,6 +1575,8 @@ impl Parser {
             modules,
             file,
             root_directory,
+            max_depth: super::DEFAULT_MAX_DEPTH,
+            depth: 0,
         }
     }
 }
@@ -9,6 +9,7 @@ use crate::{
     globals::GlobalStr,
     module::{FunctionId, Module, ModuleId, StaticId, StructId, TraitId},
     parser::{module_resolution::resolve_module, ParserQueueEntry},
+    std_annotations::{callconv::CallConvAnnotation, const_fn::ConstFn, ext_vararg::ExternVarArg},
     tokenizer::{Literal, Location, Token, TokenType},
 };
 
@@ -23,10 +24,15 @@ pub enum BakableFunction {
     BakedFunction(FunctionId),
 }
 
+/// An associated const in a trait implementation: name, type, value, location
+pub type AssociatedConst = (GlobalStr, TypeRef, Expression, Location);
+
 #[derive(Clone, Debug)]
 pub struct Trait {
     pub name: GlobalStr,
     pub functions: Vec<(GlobalStr, Vec<Argument>, TypeRef, Annotations, Location)>,
+    /// associated consts, `const NAME: Type;`
+    pub consts: Vec<(GlobalStr, TypeRef, Location)>,
     pub location: Location,
     pub annotations: Annotations,
     pub module_id: ModuleId,
@@ -40,6 +46,14 @@ impl Display for Trait {
         Display::fmt(&self.name, f)?;
         f.write_str("{\n")?;
 
+        for (name, typ, _) in self.consts.iter() {
+            f.write_str("    const ")?;
+            Display::fmt(name, f)?;
+            f.write_str(": ")?;
+            Display::fmt(typ, f)?;
+            f.write_str(";\n")?;
+        }
+
         for (name, args, return_type, annotations, _) in self.functions.iter() {
             Display::fmt(annotations, f)?;
             f.write_str("    fn ")?;
@@ -87,12 +101,14 @@ pub enum Statement {
     },
     Return(Option<Expression>, Location),
     Block(Box<[Statement]>, Location, Annotations),
+    /// `let` (immutable) or `var` (mutable, the last field) binding
     Var(
         GlobalStr,
         Expression,
         Option<TypeRef>,
         Location,
         Annotations,
+        bool,
     ),
     Expression(Expression),
     Function(FunctionContract, Box<Statement>),
@@ -105,12 +121,21 @@ pub enum Statement {
         impls: Vec<(
             GlobalStr,
             HashMap<GlobalStr, (FunctionContract, Statement)>,
+            Vec<AssociatedConst>,
             Location,
         )>,
         generics: Vec<Generic>,
         annotations: Annotations,
     },
     Trait(Trait),
+    /// `impl <trait> for <struct> { ... }` outside of the struct's definition
+    Impl {
+        trait_name: GlobalStr,
+        struct_name: GlobalStr,
+        functions: HashMap<GlobalStr, (FunctionContract, Statement)>,
+        consts: Vec<AssociatedConst>,
+        location: Location,
+    },
     /// key (the name of the thing in the module), export key (the name during import), location
     Export(GlobalStr, GlobalStr, Location),
     ModuleAsm(Location, String),
@@ -135,7 +160,8 @@ impl Statement {
             | Self::If { location, .. }
             | Self::Return(_, location)
             | Self::Struct { location, .. }
-            | Self::Var(_, _, _, location, _)
+            | Self::Impl { location, .. }
+            | Self::Var(_, _, _, location, ..)
             | Self::BakedFunction(_, location)
             | Self::BakedExternalFunction(_, location)
             | Self::BakedStruct(_, location)
@@ -158,8 +184,8 @@ impl Statement {
             | Self::ModuleAsm(..)
             | Self::Trait { .. } => (),
             Self::ExternalFunction(..) => unreachable!("function in a non-top-level scope"),
-            Self::Struct { location, .. } => {
-                panic!("{location}: use Module::push_statement to bake a struct")
+            Self::Struct { location, .. } | Self::Impl { location, .. } => {
+                panic!("{location}: use Module::push_statement to bake a struct or impl")
             }
             Self::Function(..) => unreachable!("function in a non-top-level scope"),
             Self::Block(statements, ..) => statements
@@ -208,11 +234,20 @@ impl Display for Statement {
             Self::BakedTrait(id, _) => f.write_fmt(format_args!("(module-trait {id:08x})")),
 
             Self::Trait(r#trait) => Display::fmt(&r#trait, f),
-            Self::Var(left_hand, right_hand, None, ..) => {
-                f.write_fmt(format_args!("(var-assign {left_hand} {right_hand})"))
-            }
-            Self::Var(left_hand, right_hand, Some(typ), ..) => {
-                f.write_fmt(format_args!("(var-assign {left_hand} {typ} {right_hand})"))
+            Self::Var(left_hand, right_hand, typ, .., mutable) => {
+                f.write_str(if *mutable {
+                    "(var-assign-mut "
+                } else {
+                    "(var-assign "
+                })?;
+                Display::fmt(left_hand, f)?;
+                if let Some(typ) = typ {
+                    f.write_char(' ')?;
+                    Display::fmt(typ, f)?;
+                }
+                f.write_char(' ')?;
+                Display::fmt(right_hand, f)?;
+                f.write_char(')')
             }
             Self::Block(stmts, _, annotations) => {
                 Display::fmt(annotations, f)?;
@@ -306,6 +341,26 @@ impl Display for Statement {
 
                 f.write_str("}")
             }
+            Self::Impl {
+                trait_name,
+                struct_name,
+                functions,
+                consts,
+                ..
+            } => {
+                f.write_fmt(format_args!("impl {trait_name} for {struct_name} {{\n"))?;
+                for (name, typ, value, _) in consts {
+                    f.write_fmt(format_args!("    const {name}: {typ} = {value};\n"))?;
+                }
+                for (contract, body) in functions.values() {
+                    f.write_str("    ")?;
+                    display_contract(f, contract, false)?;
+                    f.write_char(' ')?;
+                    Display::fmt(body, f)?;
+                    f.write_str(")\n")?;
+                }
+                f.write_char('}')
+            }
             Self::Function(contract, body) => {
                 display_contract(f, contract, false)?;
                 f.write_char(' ')?;
@@ -409,9 +464,15 @@ impl Parser {
         let mut errors = vec![];
 
         while !self.is_at_end() {
+            let start = self.current;
             match self.parse_statement_part(true) {
                 Err(error) => {
                     errors.push(error);
+                    // the statement didn't get past its first token, skip it so we don't parse it
+                    // again
+                    if self.current == start {
+                        self.advance();
+                    }
                     self.bail();
                 }
                 Ok(Some(statement)) => statements.push(statement),
@@ -458,11 +519,13 @@ impl Parser {
                 TokenType::AnnotationIntroducer
                 | TokenType::Extern
                 | TokenType::Fn
+                | TokenType::Const
                 | TokenType::CurlyLeft
                 | TokenType::Struct
                 | TokenType::For
                 | TokenType::While
                 | TokenType::Let
+                | TokenType::Var
                 | TokenType::Trait
                 | TokenType::Pub
                 | TokenType::If => false,
@@ -479,11 +542,14 @@ impl Parser {
         let maybe_statement = match self.peek().typ {
             TokenType::Extern if !is_global => invalid_kw!("external value/function"),
             TokenType::Fn if !is_global => invalid_kw!("function"),
+            TokenType::Const if !is_global => invalid_kw!("const function"),
             TokenType::Struct if !is_global => invalid_kw!("struct definition"),
             TokenType::Use if !is_global => invalid_kw!("use"),
             TokenType::Export if !is_global => invalid_kw!("export"),
             TokenType::Trait if !is_global => invalid_kw!("trait"),
+            TokenType::Impl if !is_global => invalid_kw!("impl"),
             TokenType::Pub if !is_global => invalid_kw!("pub"),
+            TokenType::Var if is_global => invalid_kw!("var"),
 
             TokenType::Return if is_global => invalid_kw!("return"),
             TokenType::CurlyLeft if is_global => invalid_kw!("code block"),
@@ -493,12 +559,15 @@ impl Parser {
 
             TokenType::Asm if is_global => self.parse_global_asm().map(Some),
             TokenType::Trait => self.parse_trait().map(Some),
-            TokenType::Let => self.parse_let_stmt(is_global).map(Some),
-            TokenType::CurlyLeft => self.parse_block_stmt().map(Some),
+            TokenType::Impl => self.parse_impl().map(Some),
+            TokenType::Let | TokenType::Var => self.parse_let_stmt(is_global).map(Some),
+            // statements nested in these count towards the nesting limit like nested
+            // expressions do
+            TokenType::CurlyLeft => self.nested(Self::parse_block_stmt).map(Some),
             TokenType::Return => self.parse_return_stmt().map(Some),
-            TokenType::If => self.parse_if_stmt().map(Some),
-            TokenType::While => self.parse_while_stmt().map(Some),
-            TokenType::For => self.parse_for_stmt().map(Some),
+            TokenType::If => self.nested(Self::parse_if_stmt).map(Some),
+            TokenType::While => self.nested(Self::parse_while_stmt).map(Some),
+            TokenType::For => self.nested(Self::parse_for_stmt).map(Some),
             TokenType::Struct => self.parse_struct().map(Some),
             TokenType::Fn => self
                 .parse_callable(false)
@@ -509,6 +578,7 @@ impl Parser {
                     Ok(Statement::Function(contract, Box::new(body)))
                 })
                 .map(Some),
+            TokenType::Const => self.parse_const_fn().map(Some),
             TokenType::AnnotationIntroducer => {
                 self.parse_annotation()?;
                 Ok(None)
@@ -540,6 +610,18 @@ impl Parser {
         Ok(maybe_statement)
     }
 
+    /// `const fn <name>(...) -> <type> <body>`, which is a function with the `@const_fn()`
+    /// annotation
+    fn parse_const_fn(&mut self) -> Result<Statement, ParsingError> {
+        let loc = self.advance().location.clone(); // skip `const`
+        self.current_annotations.push(ConstFn, loc);
+        let (contract, body) = self.parse_callable(false)?;
+        contract
+            .annotations
+            .are_annotations_valid_for(AnnotationReceiver::Function)?;
+        Ok(Statement::Function(contract, Box::new(body)))
+    }
+
     fn parse_pub(&mut self) -> Result<Statement, ParsingError> {
         let loc = self.advance().location.clone();
         let stmt = match self.peek().typ {
@@ -549,7 +631,7 @@ impl Parser {
                     .are_annotations_valid_for(AnnotationReceiver::Function)?;
                 Ok(Statement::Function(contract, Box::new(body)))
             })?,
-
+            TokenType::Const => self.parse_const_fn()?,
             TokenType::Let => self.parse_let_stmt(true)?,
             TokenType::Struct => self.parse_struct()?,
             TokenType::Extern => self.parse_external()?,
@@ -570,7 +652,7 @@ impl Parser {
                 c.location.clone(),
             ),
             Statement::Trait(Trait { name, location, .. })
-            | Statement::Var(name, .., location, _)
+            | Statement::Var(name, _, _, location, ..)
             | Statement::Struct { name, location, .. } => (name.clone(), location.clone()),
             _ => unreachable!(),
         };
@@ -793,6 +875,7 @@ impl Parser {
         let annotations = std::mem::take(&mut self.current_annotations);
         annotations.are_annotations_valid_for(crate::annotations::AnnotationReceiver::Trait)?;
         let mut functions = Vec::new();
+        let mut consts = Vec::new();
 
         while !self.match_tok(TokenType::CurlyRight) {
             if self.match_tok(TokenType::AnnotationIntroducer) {
@@ -800,6 +883,17 @@ impl Parser {
                 continue;
             }
 
+            if self.match_tok(TokenType::Const) {
+                // const <name>: <type>;
+                let location = self.current().location.clone();
+                let name = self.expect_identifier()?;
+                self.expect_tok(TokenType::Colon)?;
+                let typ = TypeRef::parse(self)?;
+                self.expect_tok(TokenType::Semicolon)?;
+                consts.push((name, typ, location));
+                continue;
+            }
+
             self.expect_tok(TokenType::Fn)?;
             let func = self.parse_trait_fn()?;
             functions.push(func);
@@ -808,6 +902,7 @@ impl Parser {
         Ok(Statement::Trait(Trait {
             name,
             functions,
+            consts,
             location,
             annotations,
             module_id: 0,
@@ -831,9 +926,10 @@ impl Parser {
     }
 
     fn parse_let_stmt(&mut self, is_static: bool) -> Result<Statement, ParsingError> {
-        // let <identifier>;
         // let <identifier> = <expr>;
-        let location = self.advance().location.clone(); // skip `let`
+        // var <identifier>: <type> = <expr>;
+        let location = self.peek().location.clone();
+        let mutable = self.advance().typ == TokenType::Var; // skip `let` or `var`
 
         let annotations = std::mem::take(&mut self.current_annotations);
         annotations.are_annotations_valid_for(if is_static {
@@ -854,7 +950,14 @@ impl Parser {
 
         let expr = self.parse_expression()?;
         self.consume_semicolon()?;
-        Ok(Statement::Var(name, expr, typ, location, annotations))
+        Ok(Statement::Var(
+            name,
+            expr,
+            typ,
+            location,
+            annotations,
+            mutable,
+        ))
     }
     fn parse_block_stmt(&mut self) -> Result<Statement, ParsingError> {
         let annotations = std::mem::take(&mut self.current_annotations);
@@ -971,19 +1074,13 @@ impl Parser {
         let location = self.advance().location.clone(); // skip over `struct`
         let name = self.expect_identifier()?;
 
-        let mut generics = vec![];
-        if self.match_tok(TokenType::LessThan) {
-            while !self.match_tok(TokenType::GreaterThan) {
-                if generics.len() > 1 {
-                    self.expect_tok(TokenType::Comma)?;
-
-                    if self.match_tok(TokenType::GreaterThan) {
-                        break;
-                    }
-                }
-
-                generics.push(Generic::parse(self)?);
-            }
+        let mut generics = if self.match_tok(TokenType::LessThan) {
+            self.parse_function_generics()?
+        } else {
+            vec![]
+        };
+        if self.match_tok(TokenType::Where) {
+            self.parse_where_clause(&mut generics)?;
         }
 
         let mut elements = vec![];
@@ -1044,35 +1141,8 @@ impl Parser {
                     TokenType::Impl => {
                         let loc = self.advance().location.clone();
                         let trait_name: GlobalStr = self.expect_identifier()?;
-                        let mut current_impl =
-                            HashMap::<GlobalStr, (FunctionContract, Statement)>::new();
-
-                        self.expect_tok(TokenType::CurlyLeft)?;
-                        while !self.match_tok(TokenType::CurlyRight) {
-                            if self.peek().typ != TokenType::Fn {
-                                return Err(ParsingError::StructImplRegionExpect {
-                                    loc: self.peek().location.clone(),
-                                    found: self.peek().typ,
-                                    is_trait_impl: true,
-                                });
-                            }
-                            let func = self.parse_callable(false)?;
-                            let name = func
-                                .0
-                                .name
-                                .as_ref()
-                                .cloned()
-                                .expect("non-anonymous function without name");
-                            if let Some(other_func) = current_impl.get(&name) {
-                                return Err(ParsingError::FunctionAlreadyDefined {
-                                    loc: func.0.location,
-                                    name,
-                                    first_func_loc: other_func.0.location.clone(),
-                                });
-                            }
-                            current_impl.insert(name, func);
-                        }
-                        impls.push((trait_name, current_impl, loc));
+                        let (current_impl, consts) = self.parse_impl_block()?;
+                        impls.push((trait_name, current_impl, consts, loc));
                     }
                     token @ _ => {
                         return Err(ParsingError::StructImplRegionExpect {
@@ -1096,7 +1166,84 @@ impl Parser {
         })
     }
 
+    /// Parses the body of a trait implementation, `{ <functions and associated consts> }`
+    fn parse_impl_block(
+        &mut self,
+    ) -> Result<
+        (
+            HashMap<GlobalStr, (FunctionContract, Statement)>,
+            Vec<AssociatedConst>,
+        ),
+        ParsingError,
+    > {
+        let mut functions = HashMap::<GlobalStr, (FunctionContract, Statement)>::new();
+        let mut consts = Vec::<AssociatedConst>::new();
+
+        self.expect_tok(TokenType::CurlyLeft)?;
+        while !self.match_tok(TokenType::CurlyRight) {
+            if self.match_tok(TokenType::Const) {
+                // const <name>: <type> = <value>;
+                let location = self.current().location.clone();
+                let name = self.expect_identifier()?;
+                self.expect_tok(TokenType::Colon)?;
+                let typ = TypeRef::parse(self)?;
+                self.expect_tok(TokenType::Equal)?;
+                let value = self.parse_expression()?;
+                self.expect_tok(TokenType::Semicolon)?;
+                consts.push((name, typ, value, location));
+                continue;
+            }
+            if self.peek().typ != TokenType::Fn {
+                return Err(ParsingError::StructImplRegionExpect {
+                    loc: self.peek().location.clone(),
+                    found: self.peek().typ,
+                    is_trait_impl: true,
+                });
+            }
+            let func = self.parse_callable(false)?;
+            let name = func
+                .0
+                .name
+                .as_ref()
+                .cloned()
+                .expect("non-anonymous function without name");
+            if let Some(other_func) = functions.get(&name) {
+                return Err(ParsingError::FunctionAlreadyDefined {
+                    loc: func.0.location,
+                    name,
+                    first_func_loc: other_func.0.location.clone(),
+                });
+            }
+            functions.insert(name, func);
+        }
+
+        Ok((functions, consts))
+    }
+
+    fn parse_impl(&mut self) -> Result<Statement, ParsingError> {
+        // impl <trait name> for <struct name> { <implementation> }
+        let location = self.advance().location.clone(); // skip `impl`
+        let trait_name = self.expect_identifier()?;
+        self.expect_tok(TokenType::For)?;
+        let struct_name = self.expect_identifier()?;
+        let (functions, consts) = self.parse_impl_block()?;
+
+        Ok(Statement::Impl {
+            trait_name,
+            struct_name,
+            functions,
+            consts,
+            location,
+        })
+    }
+
     pub fn expect_identifier(&mut self) -> Result<GlobalStr, ParsingError> {
+        if let Some(keyword) = self.peek().typ.keyword() {
+            return Err(ParsingError::KeywordAsIdentifier {
+                loc: self.peek().location.clone(),
+                keyword,
+            });
+        }
         if !self.match_tok(TokenType::IdentifierLiteral) {
             return Err(ParsingError::ExpectedIdentifier {
                 loc: self.peek().location.clone(),
@@ -1109,24 +1256,12 @@ impl Parser {
         let loc = self.peek().location.clone();
         assert_eq!(self.advance().typ, TokenType::AnnotationIntroducer);
 
-        let name = match self.peek().typ {
-            TokenType::If => GlobalStr::new("if"),
-            TokenType::While => GlobalStr::new("while"),
-            TokenType::For => GlobalStr::new("for"),
-            TokenType::Pub => GlobalStr::new("pub"),
-            TokenType::As => GlobalStr::new("as"),
-            TokenType::Else => GlobalStr::new("else"),
-            TokenType::Asm => GlobalStr::new("asm"),
-            TokenType::Volatile => GlobalStr::new("volatile"),
-            TokenType::Impl => GlobalStr::new("impl"),
-            TokenType::Fn => GlobalStr::new("fn"),
-            TokenType::In => GlobalStr::new("in"),
-            TokenType::Unsized => GlobalStr::new("unsized"),
-            TokenType::Struct => GlobalStr::new("struct"),
-            TokenType::Trait => GlobalStr::new("trait"),
-            TokenType::IdentifierLiteral => self.peek().string_literal()?.clone(),
-
-            _ => {
+        let name = match self.peek().typ.keyword() {
+            Some(keyword) => GlobalStr::new(keyword),
+            None if self.peek().typ == TokenType::IdentifierLiteral => {
+                self.peek().string_literal()?.clone()
+            }
+            None => {
                 return Err(ParsingError::ExpectedIdentifier {
                     loc: self.peek().location.clone(),
                     found: self.peek().typ,
@@ -1210,14 +1345,29 @@ arguments => list of argument seperated by comma, trailing comma allowed, the la
 impl Parser {
     pub fn parse_external(&mut self) -> Result<Statement, ParsingError> {
         let location = self.advance().location.clone();
-        self.parse_any_callable(false, false, false)
-            .and_then(|(mut contract, body)| {
-                contract
-                    .annotations
-                    .are_annotations_valid_for(AnnotationReceiver::ExternalFunction)?;
-                contract.location = location;
-                Ok(Statement::ExternalFunction(contract, body.map(Box::new)))
-            })
+        // extern "abi" fn
+        let mut invalid_abi = None;
+        if self.peek().typ == TokenType::StringLiteral {
+            let tok = self.advance();
+            let (abi, loc) = (tok.string_literal()?.clone(), tok.location.clone());
+            match abi.with(CallConvAnnotation::from_abi) {
+                Some(callconv) => self.current_annotations.push(callconv, loc),
+                None => invalid_abi = Some(ParsingError::InvalidCallConv(loc, abi)),
+            }
+        }
+        let function = self.parse_any_callable(false, false, false);
+        // the error is only reported after the function was parsed, so we don't have to recover
+        // from the middle of it
+        if let Some(error) = invalid_abi {
+            return Err(error);
+        }
+        function.and_then(|(mut contract, body)| {
+            contract
+                .annotations
+                .are_annotations_valid_for(AnnotationReceiver::ExternalFunction)?;
+            contract.location = location;
+            Ok(Statement::ExternalFunction(contract, body.map(Box::new)))
+        })
     }
 
     pub fn parse_callable(
@@ -1234,7 +1384,7 @@ impl Parser {
         needs_body: bool,
         can_have_generics: bool,
     ) -> Result<(FunctionContract, Option<Statement>), ParsingError> {
-        let annotations = std::mem::take(&mut self.current_annotations);
+        let mut annotations = std::mem::take(&mut self.current_annotations);
 
         let location = self.peek().location.clone();
         self.expect_tok(TokenType::Fn)?;
@@ -1245,7 +1395,7 @@ impl Parser {
             Some(self.expect_identifier()?.clone())
         };
 
-        let generics = if can_have_generics && self.match_tok(TokenType::LessThan) {
+        let mut generics = if can_have_generics && self.match_tok(TokenType::LessThan) {
             self.parse_function_generics()?
         } else {
             vec![]
@@ -1270,6 +1420,15 @@ impl Parser {
                 }
             }
 
+            // `...` for c varargs, which has to be the last argument of an external function
+            if !needs_body && self.peek().typ == TokenType::Ellipsis {
+                let loc = self.advance().location.clone();
+                annotations.push(ExternVarArg, loc);
+                self.match_tok(TokenType::Comma);
+                self.expect_tok(TokenType::ParenRight)?;
+                break;
+            }
+
             let name = self.expect_identifier()?.clone();
             self.expect_tok(TokenType::Colon)?;
 
@@ -1282,6 +1441,10 @@ impl Parser {
             TypeRef::Void(self.peek().location.clone(), 0)
         };
 
+        if self.match_tok(TokenType::Where) {
+            self.parse_where_clause(&mut generics)?;
+        }
+
         let body = if !needs_body && self.match_tok(TokenType::Semicolon) {
             None
         } else {
@@ -1337,4 +1500,29 @@ impl Parser {
 
         Ok(generics)
     }
+
+    /// Parses the bounds of a `where` clause (`where T: A + B, U: C`) and adds them to the bounds
+    /// of the matching generic.
+    pub fn parse_where_clause(&mut self, generics: &mut [Generic]) -> Result<(), ParsingError> {
+        let mut bounded = Vec::new();
+        loop {
+            let loc = self.peek().location.clone();
+            let name = self.expect_identifier()?;
+            let Some(generic) = generics.iter_mut().find(|generic| generic.name == name) else {
+                return Err(ParsingError::UnknownGenericParameter { loc, name });
+            };
+            if bounded.contains(&name) {
+                return Err(ParsingError::DuplicateWhereBound { loc, name });
+            }
+            self.expect_tok(TokenType::Colon)?;
+            generic.bounds.extend(Generic::parse_bounds(self)?);
+            bounded.push(name);
+
+            // for trailing commas
+            if !self.match_tok(TokenType::Comma) || self.peek().typ != TokenType::IdentifierLiteral
+            {
+                return Ok(());
+            }
+        }
+    }
 }
@@ -8,7 +8,7 @@ use crate::{
     tokenizer::TokenType,
 };
 
-use super::{expression::PathWithoutGenerics, Annotations, Parser, Path};
+use super::{expression::PathWithoutGenerics, Annotations, Expression, Parser, Path};
 
 pub static RESERVED_TYPE_NAMES: &[&'static str] = &[
     "str", "bool", "char", "void", "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32", "u64",
@@ -37,7 +37,7 @@ pub enum TypeRef {
     SizedArray {
         num_references: u8,
         child: Box<TypeRef>,
-        number_elements: usize,
+        number_elements: ArrayLength,
         loc: Location,
     },
     Function {
@@ -53,6 +53,35 @@ pub enum TypeRef {
     },
 }
 
+/// The length of a sized array type, `N` in `[T; N]`
+#[derive(Clone, Debug)]
+pub enum ArrayLength {
+    Literal(usize),
+    /// an expression that is evaluated at compile time, like a call to a `const fn`
+    Const(Box<Expression>),
+}
+
+impl Display for ArrayLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(v) => Display::fmt(v, f),
+            Self::Const(expr) => Display::fmt(expr, f),
+        }
+    }
+}
+
+impl PartialEq for ArrayLength {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(a), Self::Literal(b)) => a == b,
+            (Self::Const(a), Self::Const(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ArrayLength {}
+
 impl Display for TypeRef {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for _ in 0..self.get_ref_count() {
@@ -155,19 +184,27 @@ impl TypeRef {
     }
 
     pub fn parse(parser: &mut Parser) -> Result<Self, ParsingError> {
-        let mut num_references = 0;
+        parser.nested(Self::parse_nested)
+    }
+
+    fn parse_nested(parser: &mut Parser) -> Result<Self, ParsingError> {
+        let mut num_references: u8 = 0;
 
         while !parser.is_at_end() {
             // <type> = <subtype> | &<type>
             // <subtype> = [<sized-or-unsized>] | <identifier>
             // <sized-or-unsized> = <type>; <number> | <type>
 
-            if parser.match_tok(TokenType::Ampersand) {
-                num_references += 1;
-                continue;
-            }
-            if parser.match_tok(TokenType::LogicalAnd) {
-                num_references += 2;
+            let added_references = match parser.peek().typ {
+                TokenType::Ampersand => 1,
+                TokenType::LogicalAnd => 2,
+                _ => 0,
+            };
+            if added_references > 0 {
+                let loc = parser.advance().location.clone();
+                num_references = num_references
+                    .checked_add(added_references)
+                    .ok_or(ParsingError::TooManyReferences { loc })?;
                 continue;
             }
 
@@ -176,13 +213,21 @@ impl TypeRef {
                 let child = Box::new(Self::parse(parser)?);
                 if parser.match_tok(TokenType::Semicolon) {
                     // case [<type>; <amount>]
-                    let (lit, _) = parser.expect_tok(TokenType::UIntLiteral)?.uint_literal()?;
+                    let number_elements = if parser.peek().typ == TokenType::UIntLiteral
+                        && parser.peekpeek().typ == TokenType::BracketRight
+                    {
+                        let (lit, _) = parser.advance().uint_literal()?;
+                        ArrayLength::Literal(lit as usize)
+                    } else {
+                        // case [<type>; <const expression>]
+                        ArrayLength::Const(Box::new(parser.parse_expression()?))
+                    };
                     parser.expect_tok(TokenType::BracketRight)?;
 
                     return Ok(Self::SizedArray {
                         num_references,
                         child,
-                        number_elements: lit as usize,
+                        number_elements,
                         loc,
                     });
                 } else {
@@ -437,26 +482,38 @@ impl Generic {
     pub fn parse(parser: &mut Parser) -> Result<Self, ParsingError> {
         let sized = !parser.match_tok(TokenType::Unsized);
         let name = parser.expect_identifier()?;
+        let bounds = if parser.match_tok(TokenType::Colon) {
+            Self::parse_bounds(parser)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            sized,
+            name,
+            bounds,
+        })
+    }
+
+    /// Parses the traits after the `:` of a generic (`A + B`)
+    pub fn parse_bounds(
+        parser: &mut Parser,
+    ) -> Result<Vec<(PathWithoutGenerics, Location)>, ParsingError> {
         let mut bounds = Vec::new();
-        if !parser.match_tok(TokenType::Colon) {
-            return Ok(Self {
-                sized,
-                name,
-                bounds,
-            });
-        }
         while parser.peek().typ == TokenType::Plus || bounds.len() == 0 {
             if bounds.len() > 0 {
                 parser.expect_tok(TokenType::Plus)?;
             }
 
             let loc = parser.peek().location.clone();
+            // `T: A +` or `T:`
+            if parser.peek().typ != TokenType::IdentifierLiteral {
+                return Err(ParsingError::ExpectedTraitBound {
+                    loc,
+                    found: parser.peek().typ,
+                });
+            }
             bounds.push((PathWithoutGenerics::parse(parser)?, loc));
         }
-        Ok(Self {
-            sized,
-            name,
-            bounds,
-        })
+        Ok(bounds)
     }
 }
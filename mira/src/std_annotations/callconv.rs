@@ -3,6 +3,7 @@ use super::*;
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CallConvAnnotation {
     C,
+    Stdcall,
     Naked,
     Fast,
     Cold,
@@ -37,6 +38,7 @@ impl Display for CallConvAnnotation {
         f.write_char('(')?;
         match self {
             CallConvAnnotation::C => f.write_char('c')?,
+            CallConvAnnotation::Stdcall => f.write_str("stdcall")?,
             CallConvAnnotation::Naked => f.write_str("naked")?,
             CallConvAnnotation::Fast => f.write_str("fast")?,
             CallConvAnnotation::Cold => f.write_str("cold")?,
@@ -46,16 +48,31 @@ impl Display for CallConvAnnotation {
     }
 }
 
+impl CallConvAnnotation {
+    /// The calling convention `@callconv(name)` stands for
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "c" | "C" => Some(CallConvAnnotation::C),
+            "stdcall" | "Stdcall" => Some(CallConvAnnotation::Stdcall),
+            "naked" | "Naked" => Some(CallConvAnnotation::Naked),
+            "fast" | "Fast" => Some(CallConvAnnotation::Fast),
+            "cold" | "Cold" => Some(CallConvAnnotation::Cold),
+            "inline" | "Inline" => Some(CallConvAnnotation::Inline),
+            _ => None,
+        }
+    }
+
+    /// The calling convention for the abi string of `extern "abi" fn`. Only actual calling
+    /// conventions are accepted, `naked` and `inline` have to be specified with `@callconv`.
+    pub fn from_abi(abi: &str) -> Option<Self> {
+        Self::from_name(abi)
+            .filter(|v| !matches!(v, CallConvAnnotation::Naked | CallConvAnnotation::Inline))
+    }
+}
+
 pub fn parse(mut tokens: TokenStream) -> Result<CallConvAnnotation, ParsingError> {
     let (name, loc) = tokens.expect_remove_identifier()?;
     tokens.finish()?;
-    name.with(|v| match v {
-        "c" | "C" => Some(CallConvAnnotation::C),
-        "naked" | "Naked" => Some(CallConvAnnotation::Naked),
-        "fast" | "Fast" => Some(CallConvAnnotation::Fast),
-        "cold" | "Cold" => Some(CallConvAnnotation::Cold),
-        "inline" | "Inline" => Some(CallConvAnnotation::Inline),
-        _ => None,
-    })
-    .ok_or_else(|| ParsingError::InvalidIntrinsic(loc, name))
+    name.with(CallConvAnnotation::from_name)
+        .ok_or_else(|| ParsingError::InvalidCallConv(loc, name))
 }
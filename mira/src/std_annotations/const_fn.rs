@@ -0,0 +1,28 @@
+use super::*;
+
+/// Marks a function as `const fn`, which allows calling it in statics and array lengths. The
+/// parser adds it for the `const fn` syntax.
+#[derive(Debug, Clone)]
+pub struct ConstFn;
+impl Annotation for ConstFn {
+    fn get_name(&self) -> &'static str {
+        "const_fn"
+    }
+
+    fn is_valid_for(
+        &self,
+        thing: super::AnnotationReceiver,
+        annotations: &super::Annotations,
+    ) -> bool {
+        thing == AnnotationReceiver::Function && annotations.get_annotations::<Self>().count() < 2
+    }
+}
+impl Display for ConstFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("@{}()", self.get_name()))
+    }
+}
+pub fn parse(mut tokens: TokenStream) -> Result<ConstFn, ParsingError> {
+    tokens.finish()?;
+    Ok(ConstFn)
+}
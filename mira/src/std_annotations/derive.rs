@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use super::*;
+use crate::{
+    parser::{
+        Argument, AssociatedConst, BinaryOp, Expression, FunctionContract, LiteralValue, Path,
+        Statement, TypeRef,
+    },
+    tokenizer::Location,
+};
+
+/// A trait that can be implemented with `@derive`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DerivableTrait {
+    /// `fn eq(self: &Self, other: &Self) -> bool`, comparing every field with `==`
+    Eq,
+    /// `fn clone(self: &Self) -> Self`, copying every field
+    Clone,
+}
+
+impl DerivableTrait {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Eq => "Eq",
+            Self::Clone => "Clone",
+        }
+    }
+}
+
+/// Implements traits for a struct by going over its fields, e.g. `@derive(Eq, Clone)`. The traits
+/// have to be in scope under their name, like they would for an `impl` block.
+#[derive(Debug, Clone)]
+pub struct Derive(pub Vec<(DerivableTrait, Location)>);
+
+impl Annotation for Derive {
+    fn get_name(&self) -> &'static str {
+        "derive"
+    }
+
+    fn is_valid_for(&self, thing: AnnotationReceiver, _: &Annotations) -> bool {
+        thing == AnnotationReceiver::Struct
+    }
+}
+
+impl Display for Derive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("@derive(")?;
+        for (i, (derived_trait, _)) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(derived_trait.name())?;
+        }
+        f.write_char(')')
+    }
+}
+
+pub fn parse(mut tokens: TokenStream) -> Result<Derive, ParsingError> {
+    let mut traits = Vec::new();
+    let mut needs_comma = false;
+    while !tokens.is_at_end() {
+        if needs_comma {
+            tokens.expect_remove_token(TokenType::Comma)?;
+        } else {
+            needs_comma = true;
+        }
+        let (name, loc) = tokens.expect_remove_identifier()?;
+        let derived_trait = name
+            .with(|v| match v {
+                "Eq" => Some(DerivableTrait::Eq),
+                "Clone" => Some(DerivableTrait::Clone),
+                _ => None,
+            })
+            .ok_or_else(|| ParsingError::InvalidDerive(loc.clone(), name))?;
+        traits.push((derived_trait, loc));
+    }
+    Ok(Derive(traits))
+}
+
+type DerivedImpl = (
+    GlobalStr,
+    HashMap<GlobalStr, (FunctionContract, Statement)>,
+    Vec<AssociatedConst>,
+    Location,
+);
+
+impl Derive {
+    /// Generates the `impl` blocks of the derived traits for the struct `struct_name` with the
+    /// fields `elements`. Whether the fields support the traits is checked during typechecking.
+    pub fn implementations(
+        &self,
+        struct_name: &GlobalStr,
+        elements: &[(GlobalStr, TypeRef)],
+    ) -> Vec<DerivedImpl> {
+        self.0
+            .iter()
+            .map(|(derived_trait, loc)| {
+                let function = match derived_trait {
+                    DerivableTrait::Eq => derive_eq(elements, loc),
+                    DerivableTrait::Clone => derive_clone(struct_name, elements, loc),
+                };
+                let mut functions = HashMap::new();
+                functions.insert(function.0.name.clone().unwrap(), function);
+                (
+                    GlobalStr::new(derived_trait.name()),
+                    functions,
+                    Vec::new(),
+                    loc.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn type_ref(name: &str, num_references: u8, loc: &Location) -> TypeRef {
+    TypeRef::Reference {
+        num_references,
+        type_name: Path::new(GlobalStr::new(name), Vec::new()),
+        loc: loc.clone(),
+    }
+}
+
+fn self_arguments(names: &[&str], loc: &Location) -> Vec<Argument> {
+    names
+        .iter()
+        .map(|name| Argument::new(type_ref("Self", 1, loc), GlobalStr::new(name)))
+        .collect()
+}
+
+/// `<value>.<field>`
+fn field(value: &str, field: &GlobalStr, loc: &Location) -> Expression {
+    Expression::MemberAccess {
+        left_side: Box::new(Expression::Literal(
+            LiteralValue::Dynamic(Path::new(GlobalStr::new(value), Vec::new())),
+            loc.clone(),
+        )),
+        index: vec![field.clone()],
+        loc: loc.clone(),
+    }
+}
+
+fn contract(
+    name: &str,
+    arguments: Vec<Argument>,
+    return_type: TypeRef,
+    loc: &Location,
+) -> FunctionContract {
+    FunctionContract {
+        name: Some(GlobalStr::new(name)),
+        arguments,
+        return_type,
+        location: loc.clone(),
+        annotations: Annotations::default(),
+        generics: Vec::new(),
+    }
+}
+
+/// `fn eq(self: &Self, other: &Self) -> bool = self.a == other.a && self.b == other.b;`
+fn derive_eq(elements: &[(GlobalStr, TypeRef)], loc: &Location) -> (FunctionContract, Statement) {
+    let comparison = elements
+        .iter()
+        .map(|(name, _)| {
+            Expression::binary(
+                BinaryOp::Equals,
+                loc.clone(),
+                field("self", name, loc),
+                field("other", name, loc),
+            )
+        })
+        .reduce(|left, right| Expression::binary(BinaryOp::LogicalAnd, loc.clone(), left, right))
+        .unwrap_or_else(|| Expression::Literal(LiteralValue::Bool(true), loc.clone()));
+    (
+        contract(
+            "eq",
+            self_arguments(&["self", "other"], loc),
+            type_ref("bool", 0, loc),
+            loc,
+        ),
+        Statement::Return(Some(comparison), loc.clone()),
+    )
+}
+
+/// `fn clone(self: &Self) -> Self = Struct { a: self.a, b: self.b };`
+fn derive_clone(
+    struct_name: &GlobalStr,
+    elements: &[(GlobalStr, TypeRef)],
+    loc: &Location,
+) -> (FunctionContract, Statement) {
+    let fields = elements
+        .iter()
+        .map(|(name, _)| (name.clone(), (loc.clone(), field("self", name, loc))))
+        .collect();
+    let value = Expression::Literal(
+        LiteralValue::Struct(fields, Path::new(struct_name.clone(), Vec::new())),
+        loc.clone(),
+    );
+    (
+        contract(
+            "clone",
+            self_arguments(&["self"], loc),
+            type_ref("Self", 0, loc),
+            loc,
+        ),
+        Statement::Return(Some(value), loc.clone()),
+    )
+}
@@ -25,7 +25,10 @@ annotations!(
     alias,
     ext_vararg,
     callconv,
+    const_fn,
+    derive,
     function_attr,
     noinline,
-    section
+    section,
+    unchecked
 );
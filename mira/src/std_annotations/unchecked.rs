@@ -0,0 +1,28 @@
+use super::*;
+
+/// Disables the bounds checks of array indexing in the annotated function
+#[derive(Debug, Clone)]
+pub struct Unchecked;
+impl Annotation for Unchecked {
+    fn get_name(&self) -> &'static str {
+        "unchecked"
+    }
+
+    fn is_valid_for(
+        &self,
+        thing: super::AnnotationReceiver,
+        annotations: &super::Annotations,
+    ) -> bool {
+        (thing == AnnotationReceiver::Function || thing == AnnotationReceiver::ExternalFunction)
+            && annotations.get_annotations::<Self>().count() < 2
+    }
+}
+impl Display for Unchecked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("@{}()", self.get_name()))
+    }
+}
+pub fn parse(mut tokens: TokenStream) -> Result<Unchecked, ParsingError> {
+    tokens.finish()?;
+    Ok(Unchecked)
+}
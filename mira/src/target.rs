@@ -1,13 +1,18 @@
 use std::{
+    collections::HashMap,
     fmt::{Display, Write},
     str::FromStr,
+    sync::LazyLock,
 };
 
 use inkwell::targets::TargetTriple;
+use parking_lot::RwLock;
 use thiserror::Error;
 
+/// `Tag = "canonical name" | "alias" | ...`: the aliases are accepted by `from_str`, but
+/// `Display` always uses the canonical name.
 macro_rules! str_enum {
-    ($name:ident: $($tag:ident = $value:literal),* $(,)?) => {
+    ($name:ident: $($tag:ident = $value:literal $(| $alias:literal)*),* $(,)?) => {
         #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
         pub enum $name {
             $($tag),*
@@ -22,7 +27,7 @@ macro_rules! str_enum {
 
             fn from_str(s: &str) -> Result<Self, Self::Err> {
                 match s {
-                    $($value => Ok(Self::$tag),)*
+                    $($value $(| $alias)* => Ok(Self::$tag),)*
                     _ => Err(()),
                 }
             }
@@ -35,6 +40,26 @@ macro_rules! str_enum {
                 }
             }
         }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.to_str())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = String::deserialize(deserializer)?;
+                std::str::FromStr::from_str(&value).map_err(|_| {
+                    serde::de::Error::custom(format!(
+                        concat!("invalid ", stringify!($name), " `{}`"),
+                        value
+                    ))
+                })
+            }
+        }
     };
 }
 
@@ -43,18 +68,21 @@ Os:
     Freestanding = "freestanding",
     Other = "other",
     Linux = "linux",
+    Windows = "windows",
 }
 
 impl Os {
     pub fn exe_file_ext(&self) -> &str {
         match self {
-            _ => "",
+            Os::Windows => ".exe",
+            Os::Freestanding | Os::Other | Os::Linux => "",
         }
     }
 
     pub fn dynamic_lib_ext(&self) -> &str {
         match self {
-            _ => "so",
+            Os::Windows => ".dll",
+            Os::Freestanding | Os::Other | Os::Linux => ".so",
         }
     }
 
@@ -62,14 +90,15 @@ impl Os {
         match self {
             Os::Freestanding | Os::Other => "unknown",
             Os::Linux => "pc-linux",
+            Os::Windows => "pc-windows",
         }
     }
 }
 
 str_enum! {
 Arch:
-    X86_64 = "x86_64",
-    X86 = "x86",
+    X86_64 = "x86_64" | "amd64" | "x64",
+    X86 = "x86" | "i386" | "i686",
 }
 
 impl Arch {
@@ -85,6 +114,24 @@ impl Arch {
         }
     }
 
+    /// The size of a pointer, `usize` and `isize` in bytes. Every pointer width is derived from
+    /// this, so new archs only have to be added here.
+    pub fn ptr_size(&self) -> u64 {
+        match self {
+            Self::X86_64 => 8,
+            Self::X86 => 4,
+        }
+    }
+
+    /// Whether `f16` can be lowered to llvm's `half` for this arch. Passing and returning a `half`
+    /// on x86 requires SSE2, which 32-bit x86 can't assume.
+    pub fn supports_f16(&self) -> bool {
+        match self {
+            Self::X86_64 => true,
+            Self::X86 => false,
+        }
+    }
+
     pub fn is_x86(&self) -> bool {
         matches!(self, Self::X86 | Self::X86_64)
     }
@@ -151,7 +198,7 @@ impl std::fmt::Debug for Target {
     }
 }
 
-#[derive(Error, Clone, Copy, Debug)]
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TargetParsingError {
     #[error("Invalid Arch")]
     InvalidArch,
@@ -167,8 +214,25 @@ pub enum TargetParsingError {
     MissingOs,
 }
 
+/// Interned llvm triples, see [`Target::to_llvm`]
+static LLVM_TRIPLES: LazyLock<RwLock<HashMap<Target, &'static str>>> =
+    LazyLock::new(Default::default);
+
 impl Target {
-    pub fn to_llvm(&self) -> String {
+    /// Returns the llvm triple of this target. The triple is only built once per target and
+    /// leaked, so the string is shared by every later call. There is only a handful of targets,
+    /// so this leaks at most one small string for each of them.
+    pub fn to_llvm(&self) -> &'static str {
+        if let Some(triple) = LLVM_TRIPLES.read().get(self) {
+            return triple;
+        }
+        LLVM_TRIPLES
+            .write()
+            .entry(*self)
+            .or_insert_with(|| self.build_llvm_triple().leak())
+    }
+
+    fn build_llvm_triple(&self) -> String {
         let mut s = self.arch.to_llvm().to_string();
         s.push('-');
         s.push_str(self.os.to_llvm());
@@ -185,13 +249,84 @@ impl Target {
         Self::new(arch, os, Abi::None)
     }
 
-    pub fn to_llvm_triple(&self) -> (TargetTriple, String) {
+    /// The target to use if only an operating system was specified. Always picks x86_64, with the
+    /// gnu abi for linux and windows and no abi otherwise.
+    pub fn default_for_os(os: Os) -> Self {
+        let abi = match os {
+            Os::Linux | Os::Windows => Abi::Gnu,
+            Os::Freestanding | Os::Other => Abi::None,
+        };
+        Self::new(Arch::X86_64, os, abi)
+    }
+
+    /// Returns the llvm triple of this target and its string representation. The string is
+    /// interned (see [`Target::to_llvm`]), the `TargetTriple` is not, as it can't be shared
+    /// between threads.
+    pub fn to_llvm_triple(&self) -> (TargetTriple, &'static str) {
         let v = self.to_llvm();
-        (TargetTriple::create(&v), v)
+        (TargetTriple::create(v), v)
+    }
+
+    /// The width of a pointer, `usize` and `isize` in bits, see [`Arch::ptr_size`]
+    pub fn pointer_width_bits(&self) -> u32 {
+        self.arch.ptr_size() as u32 * 8
+    }
+
+    /// The target the compiler is running on, or `None` if its arch or operating system can't be
+    /// targeted yet.
+    pub fn host() -> Option<Self> {
+        let arch = if cfg!(target_arch = "x86") {
+            Arch::X86
+        } else if cfg!(target_arch = "x86_64") {
+            Arch::X86_64
+        } else {
+            return None;
+        };
+        let os = if cfg!(target_os = "linux") {
+            Os::Linux
+        } else {
+            return None;
+        };
+        let abi = if cfg!(target_env = "gnu") {
+            Abi::Gnu
+        } else {
+            Abi::None
+        };
+        Some(Self::new(arch, os, abi))
+    }
+
+    /// Parses a target of the form `arch-os` or `arch-os-abi`
+    pub fn parse(name: &str) -> Result<Self, TargetParsingError> {
+        Target::from_str(name)
     }
 
+    #[deprecated(note = "panics on invalid targets, use `Target::parse` instead")]
     pub fn from_name(name: &str) -> Self {
-        Target::from_str(name).expect("failed to parse target")
+        Target::parse(name).expect("failed to parse target")
+    }
+}
+
+/// Targets are (de)serialized as their triple, e.g. `"x86_64-linux-gnu"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Target {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Target {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Target::from_str(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl TryFrom<&str> for Target {
+    type Error = TargetParsingError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Target::from_str(value)
     }
 }
 
@@ -214,3 +349,124 @@ impl std::str::FromStr for Target {
         Ok(Self { arch, os, abi })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Target::parse("x86_64-linux"),
+            Ok(Target::new_simple(Arch::X86_64, Os::Linux))
+        );
+        assert_eq!(
+            Target::try_from("x86-freestanding-gnu"),
+            Ok(Target::new(Arch::X86, Os::Freestanding, Abi::Gnu))
+        );
+        assert_eq!(Target::parse(""), Err(TargetParsingError::MissingOs));
+        assert_eq!(Target::parse("x86_64"), Err(TargetParsingError::MissingOs));
+        assert_eq!(
+            Target::parse("riscv-linux"),
+            Err(TargetParsingError::InvalidArch)
+        );
+        assert_eq!(
+            Target::parse("x86-macos"),
+            Err(TargetParsingError::InvalidOs)
+        );
+        assert_eq!(
+            Target::parse("x86-linux-musl"),
+            Err(TargetParsingError::InvalidAbi)
+        );
+        assert_eq!(
+            Target::parse("x86-linux-gnu-extra"),
+            Err(TargetParsingError::TooManyArguments)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let target = Target::new(Arch::X86_64, Os::Linux, Abi::Gnu);
+        let json = serde_json::to_string(&target).unwrap();
+        assert_eq!(json, "\"x86_64-linux-gnu\"");
+        assert_eq!(serde_json::from_str::<Target>(&json).unwrap(), target);
+
+        assert_eq!(serde_json::to_string(&Arch::X86).unwrap(), "\"x86\"");
+        assert_eq!(
+            serde_json::to_string(&Os::Freestanding).unwrap(),
+            "\"freestanding\""
+        );
+        assert_eq!(serde_json::to_string(&Abi::None).unwrap(), "\"none\"");
+        assert_eq!(
+            serde_json::to_string(&Endianess::Little).unwrap(),
+            "\"little\""
+        );
+        assert_eq!(serde_json::from_str::<Os>("\"linux\"").unwrap(), Os::Linux);
+
+        assert!(serde_json::from_str::<Target>("\"riscv-linux\"").is_err());
+        assert!(serde_json::from_str::<Arch>("\"arm\"").is_err());
+    }
+
+    #[test]
+    fn test_host() {
+        let host = Target::host();
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            assert!(matches!(
+                host,
+                Some(Target {
+                    arch: Arch::X86_64,
+                    os: Os::Linux,
+                    ..
+                })
+            ));
+        } else if !cfg!(target_arch = "x86") {
+            assert_eq!(host, None);
+        }
+    }
+
+    #[test]
+    fn test_llvm_triple_cache() {
+        let target = Target::new(Arch::X86, Os::Linux, Abi::Gnu);
+        let first = target.to_llvm();
+        assert_eq!(first, "x86-pc-linux-gnu");
+        for _ in 0..100 {
+            // every call returns the same interned string
+            assert!(std::ptr::eq(target.to_llvm(), first));
+        }
+        assert_eq!(
+            Target::new_simple(Arch::X86_64, Os::Freestanding).to_llvm(),
+            "x86_64-unknown-none"
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert_eq!(Arch::from_str("amd64"), Ok(Arch::X86_64));
+        assert_eq!(Arch::from_str("i686"), Ok(Arch::X86));
+        let target = Target::parse("amd64-linux-gnu").unwrap();
+        assert_eq!(target, Target::new(Arch::X86_64, Os::Linux, Abi::Gnu));
+        assert_eq!(target.to_string(), "x86_64-linux-gnu");
+        assert_eq!(Arch::X86.to_string(), "x86");
+    }
+
+    #[test]
+    fn test_pointer_width() {
+        let width = |arch| Target::new_simple(arch, Os::Linux).pointer_width_bits();
+        assert_eq!(width(Arch::X86_64), 64);
+        assert_eq!(width(Arch::X86), 32);
+    }
+
+    #[test]
+    fn test_default_for_os() {
+        let default = |os| Target::default_for_os(os).to_string();
+        assert_eq!(default(Os::Linux), "x86_64-linux-gnu");
+        assert_eq!(default(Os::Windows), "x86_64-windows-gnu");
+        assert_eq!(
+            Target::default_for_os(Os::Windows).to_llvm(),
+            "x86_64-pc-windows-gnu"
+        );
+        assert_eq!(default(Os::Freestanding), "x86_64-freestanding");
+        assert_eq!(default(Os::Other), "x86_64-other");
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// The time spent in each pass of the compiler, printed by `--time-passes`.
+///
+/// Passes that run multiple times (e.g. tokenizing every imported file) are summed up, the passes
+/// are kept in the order they first ran in.
+#[derive(Debug, Default, Clone)]
+pub struct PassTimings(Vec<(&'static str, Duration)>);
+
+impl PassTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `duration` to the time spent in `pass`
+    pub fn record(&mut self, pass: &'static str, duration: Duration) {
+        match self.0.iter_mut().find(|(name, _)| *name == pass) {
+            Some((_, total)) => *total += duration,
+            None => self.0.push((pass, duration)),
+        }
+    }
+
+    /// Runs `f` and records the time it took as part of `pass`
+    pub fn time<T>(&mut self, pass: &'static str, f: impl FnOnce() -> T) -> T {
+        let now = Instant::now();
+        let value = f();
+        self.record(pass, now.elapsed());
+        value
+    }
+
+    pub fn get(&self, pass: &str) -> Option<Duration> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == pass)
+            .map(|(_, duration)| *duration)
+    }
+
+    pub fn total(&self) -> Duration {
+        self.0.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// Prints a table with the time of every pass, followed by the total time.
+impl Display for PassTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<16}{:>16}", "pass", "time")?;
+        for (pass, duration) in self.iter() {
+            writeln!(f, "{pass:<16}{:>16}", format!("{duration:.2?}"))?;
+        }
+        writeln!(f, "{:<16}{:>16}", "total", format!("{:.2?}", self.total()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::PassTimings;
+
+    #[test]
+    fn test_pass_timings() {
+        let mut timings = PassTimings::new();
+        timings.record("tokenize", Duration::from_millis(2));
+        assert_eq!(timings.time("parse", || 12), 12);
+        timings.record("tokenize", Duration::from_millis(3));
+
+        assert_eq!(timings.get("tokenize"), Some(Duration::from_millis(5)));
+        assert_eq!(timings.get("typecheck"), None);
+        assert_eq!(
+            timings.iter().map(|(pass, _)| pass).collect::<Vec<_>>(),
+            ["tokenize", "parse"]
+        );
+
+        let table = timings.to_string();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{table}");
+        assert!(lines[1].starts_with("tokenize") && lines[1].ends_with("5.00ms"));
+        assert!(lines[2].starts_with("parse"));
+        assert!(lines[3].starts_with("total"));
+    }
+}
@@ -1,9 +1,10 @@
 use parking_lot::RwLock;
 use std::{
     fmt::{Display, Write},
+    ops::Range,
     path::Path,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, LazyLock},
 };
 
 use crate::{
@@ -15,6 +16,8 @@ use crate::{
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TokenType {
     Let,                  // done, done
+    Var,                  // done, done
+    Where,                // done, done
     EqualEqual,           // done, done
     NotEquals,            // done, done
     LessThan,             // done, done
@@ -23,6 +26,9 @@ pub enum TokenType {
     LogicalAnd,           // done, done
     LogicalOr,            // done, done
     StringLiteral,        // done, done
+    FormatStringStart,    // done, done
+    FormatStringFragment, // done, done
+    FormatStringEnd,      // done, done
     FloatLiteral,         // done, done
     SIntLiteral,          // done, done
     UIntLiteral,          // done, done
@@ -64,13 +70,21 @@ pub enum TokenType {
     Unsized,              // done, done
     Range,                // done, done
     RangeInclusive,       // done, done
+    Ellipsis,             // done, done
     ReturnType,           // done, done
     Struct,               // done, done
     Trait,                // done, done
     Impl,                 // done, done
+    Const,                // done, done
     Comma,                // done, done
     PlusAssign,           // done, done
     MinusAssign,          // done, done
+    MultiplyAssign,       // done, done
+    DivideAssign,         // done, done
+    ModuloAssign,         // done, done
+    BitwiseAndAssign,     // done, done
+    BitwiseOrAssign,      // done, done
+    BitwiseXorAssign,     // done, done
     Dot,                  // done, done
     As,                   // done, done
     QuestionMark,         // done, done
@@ -79,8 +93,46 @@ pub enum TokenType {
     Eof,                  // done, done
 }
 
+/// Every keyword of the language and the token it is tokenized into. Keywords can't be used as
+/// identifiers unless they're escaped with backticks (`` `struct` ``).
+pub static KEYWORDS: &[(&str, TokenType)] = &[
+    ("let", TokenType::Let),
+    ("var", TokenType::Var),
+    ("as", TokenType::As),
+    ("fn", TokenType::Fn),
+    ("extern", TokenType::Extern),
+    ("return", TokenType::Return),
+    ("if", TokenType::If),
+    ("else", TokenType::Else),
+    ("asm", TokenType::Asm),
+    ("volatile", TokenType::Volatile),
+    ("while", TokenType::While),
+    ("for", TokenType::For),
+    ("pub", TokenType::Pub),
+    ("in", TokenType::In),
+    ("unsized", TokenType::Unsized),
+    ("struct", TokenType::Struct),
+    ("impl", TokenType::Impl),
+    ("const", TokenType::Const),
+    ("trait", TokenType::Trait),
+    ("use", TokenType::Use),
+    ("export", TokenType::Export),
+    ("where", TokenType::Where),
+];
+
+impl TokenType {
+    /// Returns the keyword this token is tokenized from, if it is a keyword
+    pub fn keyword(self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, typ)| *typ == self)
+            .map(|(keyword, _)| *keyword)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NumberType {
+    F16,
     F32,
     F64,
     I8,
@@ -99,6 +151,7 @@ pub enum NumberType {
 impl Display for NumberType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::F16 => f.write_str("f16"),
             Self::F32 => f.write_str("f32"),
             Self::F64 => f.write_str("f64"),
             Self::I8 => f.write_str("i8"),
@@ -121,6 +174,7 @@ impl FromStr for NumberType {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "f16" => Ok(Self::F16),
             "f32" => Ok(Self::F32),
             "f64" => Ok(Self::F64),
             "i8" => Ok(Self::I8),
@@ -154,10 +208,38 @@ pub struct Location {
     pub file: Arc<Path>,
 }
 
+static DUMMY_FILE: LazyLock<Arc<Path>> = LazyLock::new(|| Path::new("").into());
+
 impl Location {
     pub fn new(file: Arc<Path>, line: u32, column: u32) -> Self {
         Self { column, file, line }
     }
+
+    /// A location that doesn't point into any file, used as a placeholder for values that aren't
+    /// resolved yet.
+    pub fn dummy() -> Self {
+        Self::new(DUMMY_FILE.clone(), 0, 0)
+    }
+
+    /// Returns if this location is (a clone of) [Self::dummy]. A real location never is, even if
+    /// it is at the start of a file with an empty path.
+    pub fn is_dummy(&self) -> bool {
+        Arc::ptr_eq(&self.file, &DUMMY_FILE)
+    }
+}
+
+/// Locations are ordered by file, then line, then column, which sorts diagnostics into source
+/// order.
+impl Ord for Location {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.file, self.line, self.column).cmp(&(&other.file, other.line, other.column))
+    }
+}
+
+impl PartialOrd for Location {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl Display for Location {
@@ -228,15 +310,24 @@ impl Display for Token {
             TokenType::Export => f.write_str("export"),
             TokenType::If => f.write_str("if"),
             TokenType::Impl => f.write_str("impl"),
+            TokenType::Const => f.write_str("const"),
             TokenType::In => f.write_str("in"),
             TokenType::Unsized => f.write_str("unsized"),
             TokenType::LessThan => f.write_str("<"),
             TokenType::Let => f.write_str("let"),
+            TokenType::Var => f.write_str("var"),
+            TokenType::Where => f.write_str("where"),
             TokenType::LogicalAnd => f.write_str("&&"),
             TokenType::LogicalNot => f.write_str("!"),
             TokenType::LogicalOr => f.write_str("||"),
             TokenType::Minus => f.write_str("-"),
             TokenType::MinusAssign => f.write_str("-="),
+            TokenType::MultiplyAssign => f.write_str("*="),
+            TokenType::DivideAssign => f.write_str("/="),
+            TokenType::ModuloAssign => f.write_str("%="),
+            TokenType::BitwiseAndAssign => f.write_str("&="),
+            TokenType::BitwiseOrAssign => f.write_str("|="),
+            TokenType::BitwiseXorAssign => f.write_str("^="),
             TokenType::Modulo => f.write_str("%"),
             TokenType::Asterix => f.write_str("*"),
             TokenType::NotEquals => f.write_str("!="),
@@ -262,6 +353,7 @@ impl Display for Token {
             TokenType::QuestionMark => f.write_str("?"),
             TokenType::Range => f.write_str(".."),
             TokenType::RangeInclusive => f.write_str("..="),
+            TokenType::Ellipsis => f.write_str("..."),
             TokenType::Return => f.write_str("return"),
             TokenType::ReturnType => f.write_str("->"),
             TokenType::Semicolon => f.write_str(";"),
@@ -269,6 +361,12 @@ impl Display for Token {
                 Some(Literal::String(v)) => f.write_fmt(format_args!("string({v:?})")),
                 _ => f.write_str("string(malformed data)"),
             },
+            TokenType::FormatStringStart => f.write_str("f\""),
+            TokenType::FormatStringFragment => match &self.literal {
+                Some(Literal::String(v)) => f.write_fmt(format_args!("fragment({v:?})")),
+                _ => f.write_str("fragment(malformed data)"),
+            },
+            TokenType::FormatStringEnd => f.write_str("\""),
             TokenType::Struct => f.write_str("struct"),
             TokenType::Trait => f.write_str("trait"),
             TokenType::While => f.write_str("while"),
@@ -378,6 +476,18 @@ impl Token {
     }
 }
 
+/// How [`Tokenizer::apply_edit`] updated the tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retokenization {
+    /// Only the lines touched by the edit were tokenized again
+    Incremental,
+    /// The whole source was tokenized again
+    Full,
+}
+
+/// The amount of errors after which [`Tokenizer::scan_tokens`] gives up by default
+pub const DEFAULT_MAX_ERRORS: usize = 100;
+
 pub struct Tokenizer {
     source: Vec<char>,
     pub file: Arc<Path>,
@@ -386,6 +496,13 @@ pub struct Tokenizer {
     current: usize,
     line: u32,
     column: u32,
+    max_errors: usize,
+    /// the byte and char offset of the start of every line, built by the first
+    /// [`Tokenizer::apply_edit`] so that later edits don't have to walk the whole source
+    line_starts: Vec<(usize, usize)>,
+    /// whether the source contains anything [`Tokenizer::may_span_lines`] looks for, only valid
+    /// while `line_starts` is
+    spans_lines: bool,
 }
 
 impl Tokenizer {
@@ -398,23 +515,56 @@ impl Tokenizer {
             tokens: vec![],
             line: 0,
             column: 0,
+            max_errors: DEFAULT_MAX_ERRORS,
+            line_starts: Vec::new(),
+            spans_lines: false,
         }
     }
 
+    /// Sets the amount of errors after which scanning stops with a
+    /// [`TokenizationError::TooManyErrors`]. Defaults to [`DEFAULT_MAX_ERRORS`].
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Prepares the tokenizer to tokenize `source`, reusing the buffers of the previous source and
+    /// its tokens. The tokens of the previous source are discarded.
+    pub fn reset(&mut self, source: &str, file: Arc<Path>) {
+        self.source.clear();
+        self.source.extend(source.chars());
+        self.tokens.clear();
+        self.file = file;
+        self.start = 0;
+        self.current = 0;
+        self.line = 0;
+        self.column = 0;
+        self.line_starts.clear();
+    }
+
     pub fn scan_tokens(&mut self) -> Result<(), Vec<TokenizationError>> {
         let mut errors = vec![];
         while !self.is_at_end() {
             self.start = self.current;
             if let Err(e) = self.scan_token() {
                 errors.push(e);
+                if errors.len() >= self.max_errors {
+                    errors.push(TokenizationError::TooManyErrors {
+                        loc: loc!(self.file;self.line;self.column),
+                    });
+                    break;
+                }
             }
         }
 
-        self.tokens.push(Token {
-            typ: TokenType::Eof,
-            literal: None,
-            location: loc!(self.file;self.line + 1),
-        });
+        // scanning again after reaching the end must not add a second eof token
+        if self.tokens.last().map(|v| v.typ) != Some(TokenType::Eof) {
+            self.tokens.push(Token {
+                typ: TokenType::Eof,
+                literal: None,
+                location: loc!(self.file;self.line + 1),
+            });
+        }
 
         if errors.len() > 0 {
             Err(errors)
@@ -423,6 +573,165 @@ impl Tokenizer {
         }
     }
 
+    /// Replaces the bytes `range` of the source with `text` and updates the tokens, only
+    /// tokenizing the lines touched by the edit again. The tokens after the edit are moved to their
+    /// new lines.
+    ///
+    /// Falls back to tokenizing the whole source again if the tokens weren't scanned yet, the
+    /// edited lines fail to tokenize (e.g. because the edit opened a string) or the source
+    /// contains block comments, macro invocations or escaped newlines, as these can span multiple
+    /// lines. Fails without changing anything if `range` is out of bounds or doesn't start and
+    /// end on a char boundary.
+    pub fn apply_edit(
+        &mut self,
+        range: Range<usize>,
+        text: &str,
+    ) -> Result<Retokenization, Vec<TokenizationError>> {
+        if self.line_starts.is_empty() {
+            self.index_lines();
+        }
+        let bounds = (range.start <= range.end)
+            .then(|| (self.char_index(range.start), self.char_index(range.end)));
+        let Some((Some((first_line, start)), Some((last_old_line, end)))) = bounds else {
+            return Err(vec![TokenizationError::InvalidEdit {
+                loc: loc!(self.file;0),
+                start: range.start,
+                end: range.end,
+            }]);
+        };
+
+        let has_tokens = self.tokens.last().map(|v| v.typ) == Some(TokenType::Eof);
+        if !has_tokens || self.spans_lines {
+            return self.rescan(start..end, text);
+        }
+
+        // the lines touched by the edit, as they are after it
+        let region_start = self.line_starts[first_line as usize].1;
+        let region_end = self
+            .line_starts
+            .get(last_old_line as usize + 1)
+            .map_or(self.source.len(), |v| v.1);
+        let mut region = self.source[region_start..start].iter().collect::<String>();
+        region.push_str(text);
+        region.extend(&self.source[end..region_end]);
+        if Self::may_span_lines(&region) {
+            return self.rescan(start..end, text);
+        }
+        let mut tokenizer = Tokenizer::new(&region, self.file.clone());
+        tokenizer.line = first_line;
+        if tokenizer.scan_tokens().is_err() {
+            return self.rescan(start..end, text);
+        }
+        tokenizer.tokens.pop(); // eof
+
+        let last_line = first_line + text.matches('\n').count() as u32;
+        let eof = self
+            .tokens
+            .pop()
+            .expect("the tokens should end with an eof token");
+        let token_start = self
+            .tokens
+            .partition_point(|v| v.location.line < first_line);
+        let token_end = self
+            .tokens
+            .partition_point(|v| v.location.line <= last_old_line);
+        for token in &mut self.tokens[token_end..] {
+            token.location.line = token.location.line + last_line - last_old_line;
+        }
+        self.tokens.splice(token_start..token_end, tokenizer.tokens);
+
+        // the lines starting in `text` replace the ones starting in the removed range
+        let (mut byte, mut index) = (range.start, start);
+        let mut new_starts = Vec::new();
+        for c in text.chars() {
+            byte += c.len_utf8();
+            index += 1;
+            if c == '\n' {
+                new_starts.push((byte, index));
+            }
+        }
+        let text_chars = index - start;
+        for (byte, index) in &mut self.line_starts[last_old_line as usize + 1..] {
+            *byte = *byte - range.len() + text.len();
+            *index = *index - (end - start) + text_chars;
+        }
+        self.line_starts.splice(
+            first_line as usize + 1..last_old_line as usize + 1,
+            new_starts,
+        );
+        self.source.splice(start..end, text.chars());
+
+        self.current = self.source.len();
+        let newlines = self.line_starts.len() - 1;
+        self.line = (newlines - (self.source.last() == Some(&'\n')) as usize) as u32;
+        self.column = 0;
+        self.tokens.push(Token {
+            location: loc!(self.file;self.line + 1),
+            ..eof
+        });
+        Ok(Retokenization::Incremental)
+    }
+
+    /// Replaces the chars `range` of the source with `text` and tokenizes all of it again
+    fn rescan(
+        &mut self,
+        range: Range<usize>,
+        text: &str,
+    ) -> Result<Retokenization, Vec<TokenizationError>> {
+        let mut source = self.source[..range.start].iter().collect::<String>();
+        source.push_str(text);
+        source.extend(&self.source[range.end..]);
+        let file = self.file.clone();
+        self.reset(&source, file);
+        self.scan_tokens().map(|()| Retokenization::Full)
+    }
+
+    /// Fills `line_starts` and `spans_lines` for the current source
+    fn index_lines(&mut self) {
+        let mut byte = 0;
+        self.line_starts.clear();
+        self.line_starts.push((0, 0));
+        for (index, c) in self.source.iter().enumerate() {
+            byte += c.len_utf8();
+            if *c == '\n' {
+                self.line_starts.push((byte, index + 1));
+            }
+        }
+        self.spans_lines = Self::may_span_lines(&self.source.iter().collect::<String>());
+    }
+
+    /// Returns the line and the index into the source of the char starting at the byte `byte`,
+    /// only walking the line it is in. None if it's out of bounds or not on a char boundary.
+    fn char_index(&self, byte: usize) -> Option<(u32, usize)> {
+        let line = self.line_starts.partition_point(|v| v.0 <= byte) - 1;
+        let (mut offset, mut index) = self.line_starts[line];
+        while offset < byte {
+            offset += self.source.get(index)?.len_utf8();
+            index += 1;
+        }
+        (offset == byte).then_some((line as u32, index))
+    }
+
+    /// Returns true if `source` contains a block comment, a macro invocation or an escaped newline,
+    /// which continues a string on the next line
+    fn may_span_lines(source: &str) -> bool {
+        if source.contains("/*") || source.contains("\\\n") {
+            return true;
+        }
+        let mut chars = source.chars().peekable();
+        let mut last = ' ';
+        while let Some(c) = chars.next() {
+            if c == '!'
+                && Self::is_valid_identifier_char(last)
+                && matches!(chars.peek(), Some('(' | '[' | '{'))
+            {
+                return true;
+            }
+            last = c;
+        }
+        false
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -431,12 +740,13 @@ impl Tokenizer {
         if self.is_at_end() {
             return '\0';
         }
-        self.column += 1;
-        self.current += 1;
-        if self.current > 1 && self.source[self.current - 2] == '\n' {
+        if self.current > 0 && self.source[self.current - 1] == '\n' {
             self.line += 1;
             self.column = 0;
+        } else if self.current > 0 {
+            self.column += 1;
         }
+        self.current += 1;
         self.source[self.current - 1]
     }
 
@@ -458,6 +768,11 @@ impl Tokenizer {
     }
 
     fn scan_token(&mut self) -> Result<(), TokenizationError> {
+        if self.is_format_string_start() {
+            let mut tokens = self.parse_format_string()?;
+            self.tokens.append(&mut tokens);
+            return Ok(());
+        }
         let tok = self.int_scan_token()?;
         let Some(tok) = tok else { return Ok(()) };
         match tok.typ {
@@ -501,7 +816,13 @@ impl Tokenizer {
             '[' => token!(BracketLeft),
             ']' => token!(BracketRight),
             ',' => token!(Comma),
-            '.' if self.if_char_advance('.') => token!(Range, RangeInclusive, '='),
+            '.' if self.if_char_advance('.') => match self.peek() {
+                '.' => {
+                    self.advance();
+                    token!(Ellipsis)
+                }
+                _ => token!(Range, RangeInclusive, '='),
+            },
             '.' if self.peek().is_ascii_digit() => self.parse_number('.'),
             '.' => token!(Dot),
             '+' => token!(Plus, PlusAssign, '='),
@@ -509,9 +830,10 @@ impl Tokenizer {
             '-' if self.if_char_advance('=') => token!(MinusAssign),
             '-' if self.if_char_advance('>') => token!(ReturnType),
             '-' => token!(Minus),
+            '/' if self.if_char_advance('=') => token!(DivideAssign),
             '/' if self.peek() != '/' && self.peek() != '*' => token!(Divide),
-            '%' => token!(Modulo),
-            '*' => token!(Asterix),
+            '%' => token!(Modulo, ModuloAssign, '='),
+            '*' => token!(Asterix, MultiplyAssign, '='),
             '=' => token!(Equal, EqualEqual, '='),
             '<' => token!(LessThan),
             '>' => token!(GreaterThan),
@@ -519,11 +841,12 @@ impl Tokenizer {
             ';' => token!(Semicolon),
             '!' => token!(LogicalNot, NotEquals, '='),
             '~' => token!(BitwiseNot),
+            '&' if self.if_char_advance('=') => token!(BitwiseAndAssign),
             '&' => token!(Ampersand, LogicalAnd, '&'),
             '|' if self.if_char_advance('|') => token!(LogicalOr),
             '|' if self.if_char_advance('>') => token!(PipeOperator),
-            '|' => token!(BitwiseOr),
-            '^' => token!(BitwiseXor),
+            '|' => token!(BitwiseOr, BitwiseOrAssign, '='),
+            '^' => token!(BitwiseXor, BitwiseXorAssign, '='),
             ' ' | '\n' | '\r' | '\t' => {
                 while matches!(self.peek(), ' ' | '\n' | '\r' | '\t') {
                     self.advance();
@@ -531,7 +854,7 @@ impl Tokenizer {
                 return Ok(None);
             }
             '/' if self.if_char_advance('*') => {
-                loop {
+                while !self.is_at_end() {
                     if self.advance() == '*' && self.if_char_advance('/') {
                         break;
                     }
@@ -545,6 +868,7 @@ impl Tokenizer {
             '@' => token!(AnnotationIntroducer),
             ('0'..='9') => self.parse_number(c),
             '"' => self.parse_string('"'),
+            '\'' => self.parse_char(),
             '`' => {
                 let mut tok = self.parse_string('`')?;
                 tok.typ = TokenType::IdentifierLiteral;
@@ -555,7 +879,7 @@ impl Tokenizer {
             }
             _ => {
                 return Err(TokenizationError::unknown_token(
-                    loc!(self.file;self.line),
+                    loc!(self.file;self.line;self.column),
                     c,
                 ))
             }
@@ -606,18 +930,22 @@ impl Tokenizer {
                         return Err(err);
                     };
                     return match number_type {
-                        NumberType::F32 | NumberType::F64 if !allow_float => Err(err),
-                        NumberType::F32 | NumberType::F64 if is_negative => Ok(self
-                            .get_token_lit_loc(
+                        NumberType::F16 | NumberType::F32 | NumberType::F64 if !allow_float => {
+                            Err(err)
+                        }
+                        NumberType::F16 | NumberType::F32 | NumberType::F64 if is_negative => {
+                            Ok(self.get_token_lit_loc(
                                 TokenType::FloatLiteral,
                                 Literal::Float(-(value as f64), number_type),
                                 loc,
+                            ))
+                        }
+                        NumberType::F16 | NumberType::F32 | NumberType::F64 => Ok(self
+                            .get_token_lit_loc(
+                                TokenType::FloatLiteral,
+                                Literal::Float(value as f64, number_type),
+                                loc,
                             )),
-                        NumberType::F32 | NumberType::F64 => Ok(self.get_token_lit_loc(
-                            TokenType::FloatLiteral,
-                            Literal::Float(value as f64, number_type),
-                            loc,
-                        )),
                         NumberType::U8
                         | NumberType::U16
                         | NumberType::U32
@@ -633,7 +961,7 @@ impl Tokenizer {
                             loc,
                         )),
                         _ => Ok(self.get_token_lit_loc(
-                            TokenType::SIntLiteral,
+                            TokenType::UIntLiteral,
                             Literal::UInt(value, number_type),
                             loc,
                         )),
@@ -898,12 +1226,22 @@ impl Tokenizer {
             }
         }
         let number_type = match NumberType::from_str(&typ) {
-            Ok(v @ (NumberType::F32 | NumberType::F64)) => v,
-            Ok(v @ (NumberType::U8 | NumberType::U16 | NumberType::U32 | NumberType::U64))
-                if !is_negative && !is_float =>
-            {
+            Ok(v @ (NumberType::F16 | NumberType::F32 | NumberType::F64)) => {
+                // `7f32` is a float
+                is_float = true;
                 v
             }
+            Ok(
+                NumberType::U8
+                | NumberType::U16
+                | NumberType::U32
+                | NumberType::U64
+                | NumberType::Usize,
+            ) if is_negative => {
+                return Err(TokenizationError::InvalidNumberType(
+                    loc!(self.file;self.line;self.column - typ.len() as u32),
+                ))
+            }
             Ok(v) if !is_float => v,
             Err(_) if typ.len() < 1 => NumberType::None,
             _ => {
@@ -938,29 +1276,22 @@ impl Tokenizer {
         Ok(self.get_token_lit_loc(tok, lit, loc))
     }
 
+    /// Tokenizes a string up to the closing `string_char`. Strings end at the end of the line, an
+    /// unterminated string is reported at its opening quote.
     fn parse_string(&mut self, string_char: char) -> Result<Token, TokenizationError> {
-        let mut is_backslash = false;
         let mut str = String::new();
         let loc = loc!(self.file;self.line;self.column);
 
-        while !self.is_at_end() {
-            let c = self.advance();
-
-            if is_backslash {
-                is_backslash = false;
-                str.push(Self::escape_char_to_real_char(c));
-            } else if c == '\\' {
-                is_backslash = true;
-            } else if c == string_char || c == '\n' {
-                break;
-            } else {
-                str.push(c);
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(TokenizationError::unterminated_string(loc));
+            }
+            match self.advance() {
+                '\\' if self.is_at_end() => {}
+                '\\' => str.push(Self::escape_char_to_real_char(self.advance())),
+                c if c == string_char => break,
+                c => str.push(c),
             }
-        }
-        if self.cur_char() != string_char || self.source[self.current - 2] == '\\' {
-            return Err(TokenizationError::unclosed_string(
-                loc!(self.file;self.line+1),
-            ));
         }
 
         Ok(self.get_token_lit_loc(
@@ -970,6 +1301,135 @@ impl Tokenizer {
         ))
     }
 
+    /// Tokenizes a char literal (`'a'`, `'\\n'`) into the byte of the char as a `u8`.
+    fn parse_char(&mut self) -> Result<Token, TokenizationError> {
+        let loc = loc!(self.file;self.line;self.column);
+        if self.is_at_end() || self.peek() == '\n' || self.if_char_advance('\'') {
+            return Err(TokenizationError::UnterminatedChar { loc });
+        }
+        let c = match self.advance() {
+            '\\' if !self.is_at_end() && self.peek() != '\n' => {
+                Self::escape_char_to_real_char(self.advance())
+            }
+            c => c,
+        };
+        if !self.if_char_advance('\'') {
+            return Err(TokenizationError::UnterminatedChar { loc });
+        }
+        if !c.is_ascii() {
+            return Err(TokenizationError::NonAsciiChar { loc, character: c });
+        }
+
+        Ok(self.get_token_lit_loc(
+            TokenType::UIntLiteral,
+            Literal::UInt(c as u64, NumberType::U8),
+            loc,
+        ))
+    }
+
+    fn is_format_string_start(&self) -> bool {
+        self.peek() == 'f' && self.source.get(self.current + 1) == Some(&'"')
+    }
+
+    /// Tokenizes a format string (`f"x = {x}, y = {y}"`) into a `FormatStringStart` token,
+    /// followed by a `FormatStringFragment` for every piece of text and the tokens of every
+    /// embedded expression surrounded by `{` and `}`, and a `FormatStringEnd` token.
+    /// `{{` and `}}` are turned into literal braces.
+    fn parse_format_string(&mut self) -> Result<Vec<Token>, TokenizationError> {
+        self.advance();
+        let start = self.get_token(TokenType::FormatStringStart);
+        self.advance();
+        let quote_loc = loc!(self.file;self.line;self.column);
+        let mut fragment = String::new();
+        let mut fragment_loc = start.location.clone();
+        let mut tokens = vec![start];
+
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(TokenizationError::unterminated_string(quote_loc));
+            }
+            let c = self.advance();
+            let loc = loc!(self.file;self.line;self.column);
+            let c = match c {
+                '"' => break,
+                '\\' if self.is_at_end() => continue,
+                '\\' => Self::escape_char_to_real_char(self.advance()),
+                '{' if self.if_char_advance('{') => '{',
+                '}' if self.if_char_advance('}') => '}',
+                '}' => return Err(TokenizationError::UnmatchedFormatBrace { loc }),
+                '{' => {
+                    if !fragment.is_empty() {
+                        tokens.push(self.format_fragment(&mut fragment, fragment_loc.clone()));
+                    }
+                    tokens.push(self.get_token(TokenType::CurlyLeft));
+                    self.scan_format_argument(&mut tokens, &quote_loc)?;
+                    continue;
+                }
+                c => c,
+            };
+            if fragment.is_empty() {
+                fragment_loc = loc;
+            }
+            fragment.push(c);
+        }
+        if !fragment.is_empty() {
+            tokens.push(self.format_fragment(&mut fragment, fragment_loc));
+        }
+        tokens.push(self.get_token(TokenType::FormatStringEnd));
+        Ok(tokens)
+    }
+
+    fn format_fragment(&self, fragment: &mut String, loc: Location) -> Token {
+        let str = std::mem::take(fragment);
+        self.get_token_lit_loc(
+            TokenType::FormatStringFragment,
+            Literal::String(GlobalStr::new_boxed(str.into_boxed_str())),
+            loc,
+        )
+    }
+
+    /// Tokenizes an expression embedded into a format string up to and including the `}` that
+    /// closes it.
+    fn scan_format_argument(
+        &mut self,
+        tokens: &mut Vec<Token>,
+        quote_loc: &Location,
+    ) -> Result<(), TokenizationError> {
+        let mut depth = 0usize;
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(TokenizationError::unterminated_string(quote_loc.clone()));
+            }
+            if self.is_format_string_start() {
+                tokens.append(&mut self.parse_format_string()?);
+                continue;
+            }
+            if self.peek() == '}' && depth == 0 {
+                self.advance();
+                tokens.push(self.get_token(TokenType::CurlyRight));
+                return Ok(());
+            }
+            let Some(tok) = self.int_scan_token()? else {
+                continue;
+            };
+            match tok.typ {
+                TokenType::CurlyLeft => depth += 1,
+                TokenType::CurlyRight => depth -= 1,
+                TokenType::IdentifierLiteral if self.if_char_advance('!') => {
+                    let Some(Literal::String(ref name)) = tok.literal else {
+                        unreachable!(
+                            "TokenType::IdentifierLiteral should always have a string literal value"
+                        )
+                    };
+                    tokens.append(&mut self.do_macro(&tok.location, name)?);
+                    continue;
+                }
+                _ => (),
+            }
+            tokens.push(tok);
+        }
+    }
+
     fn escape_char_to_real_char(character: char) -> char {
         match character {
             'n' => '\n',
@@ -1010,7 +1470,7 @@ impl Tokenizer {
             _ => (),
         }
         Ok(Self::try_token_from_keyword(&identifier)
-            .map(|v| self.get_token(v))
+            .map(|v| Token::new(v, None, loc.line, loc.column, loc.file.clone()))
             .unwrap_or_else(|| {
                 self.get_token_lit_loc(
                     TokenType::IdentifierLiteral,
@@ -1053,6 +1513,10 @@ impl Tokenizer {
                     bracket: closing_bracket_type,
                 });
             }
+            if self.is_format_string_start() {
+                tokens.append(&mut self.parse_format_string()?);
+                continue;
+            }
             let Some(tok) = self.int_scan_token()? else {
                 continue;
             };
@@ -1078,28 +1542,10 @@ impl Tokenizer {
     }
 
     fn try_token_from_keyword(word: &str) -> Option<TokenType> {
-        match word {
-            "let" => Some(TokenType::Let),
-            "as" => Some(TokenType::As),
-            "fn" => Some(TokenType::Fn),
-            "extern" => Some(TokenType::Extern),
-            "return" => Some(TokenType::Return),
-            "if" => Some(TokenType::If),
-            "else" => Some(TokenType::Else),
-            "asm" => Some(TokenType::Asm),
-            "volatile" => Some(TokenType::Volatile),
-            "while" => Some(TokenType::While),
-            "for" => Some(TokenType::For),
-            "pub" => Some(TokenType::Pub),
-            "in" => Some(TokenType::In),
-            "unsized" => Some(TokenType::Unsized),
-            "struct" => Some(TokenType::Struct),
-            "impl" => Some(TokenType::Impl),
-            "trait" => Some(TokenType::Trait),
-            "use" => Some(TokenType::Use),
-            "export" => Some(TokenType::Export),
-            _ => None,
-        }
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == word)
+            .map(|(_, typ)| *typ)
     }
 
     fn get_token_lit_loc(&self, token: TokenType, literal: Literal, location: Location) -> Token {
@@ -1121,10 +1567,18 @@ impl Tokenizer {
         )
     }
 
-    pub fn get_tokens(&self) -> &[Token] {
+    /// The tokens scanned so far, ending in an [`Eof`](TokenType::Eof) token after
+    /// [`scan_tokens`](Self::scan_tokens). Unlike [`to_parser`](Self::to_parser), this leaves the
+    /// tokenizer intact, e.g. for syntax highlighting.
+    pub fn tokens(&self) -> &[Token] {
         &self.tokens
     }
 
+    #[deprecated(note = "renamed to `Tokenizer::tokens`")]
+    pub fn get_tokens(&self) -> &[Token] {
+        self.tokens()
+    }
+
     pub fn to_parser(self, modules: Arc<RwLock<Vec<ParserQueueEntry>>>, root: Arc<Path>) -> Parser {
         Parser::new(self.tokens, modules, self.file, root)
     }
@@ -1139,6 +1593,7 @@ mod test {
             match (&token.typ, &token.literal) {
                 (TokenType::IdentifierLiteral, Some(Literal::String(..)))
                 | (TokenType::StringLiteral, Some(Literal::String(..)))
+                | (TokenType::FormatStringFragment, Some(Literal::String(..)))
                 | (TokenType::SIntLiteral, Some(Literal::SInt(..)))
                 | (TokenType::UIntLiteral, Some(Literal::UInt(..)))
                 | (TokenType::FloatLiteral, Some(Literal::Float(..)))
@@ -1146,6 +1601,7 @@ mod test {
                 | (TokenType::VoidLiteral, None) => (),
                 (TokenType::IdentifierLiteral, _)
                 | (TokenType::StringLiteral, _)
+                | (TokenType::FormatStringFragment, _)
                 | (TokenType::SIntLiteral, _)
                 | (TokenType::UIntLiteral, _)
                 | (TokenType::FloatLiteral, _)
@@ -1161,7 +1617,7 @@ mod test {
     fn get_tokens(str: &str) -> (Vec<Token>, Vec<TokenizationError>) {
         let mut tokenizer = Tokenizer::new(str, Path::new("test").into());
         let errs = tokenizer.scan_tokens().err().unwrap_or_default();
-        check_tokens(tokenizer.get_tokens());
+        check_tokens(tokenizer.tokens());
         (tokenizer.tokens, errs)
     }
 
@@ -1244,7 +1700,68 @@ mod test {
             ],
         );
 
-        match_errs!("\"a\nb\nc\";"; TokenizationError::UnclosedString { loc: _ }, TokenizationError::UnclosedString { loc: _ });
+        match_errs!("\"a\nb\nc\";"; TokenizationError::UnterminatedString { loc: _ }, TokenizationError::UnterminatedString { loc: _ });
+        assert_token_eq(r#""\\""#, &[tok!(StringLiteral, String("\\"))]);
+
+        let (tokens, errs) = get_tokens("let a = \"abc");
+        assert_eq!(tokens.len(), 4);
+        match &errs[..] {
+            [TokenizationError::UnterminatedString { loc }] => {
+                assert_eq!(loc.to_string(), "test:0:8")
+            }
+            errs => panic!("expected an unterminated string, found {errs:?}"),
+        }
+    }
+
+    #[test]
+    fn test_chars() {
+        assert_token_eq(
+            r"'a' '\n' '\'' '\\'",
+            &[
+                tok!(UIntLiteral, UInt(97, U8)),
+                tok!(UIntLiteral, UInt(10, U8)),
+                tok!(UIntLiteral, UInt(39, U8)),
+                tok!(UIntLiteral, UInt(92, U8)),
+            ],
+        );
+
+        let (_, errs) = get_tokens("a 'a");
+        match &errs[..] {
+            [TokenizationError::UnterminatedChar { loc }] => {
+                assert_eq!(loc.to_string(), "test:0:2")
+            }
+            errs => panic!("expected an unterminated char, found {errs:?}"),
+        }
+        match_errs!("'ab'"; TokenizationError::UnterminatedChar { .. }, TokenizationError::UnterminatedChar { .. });
+        match_errs!("''"; TokenizationError::UnterminatedChar { .. });
+        match_errs!("'\\\n'"; TokenizationError::UnterminatedChar { .. }, TokenizationError::UnterminatedChar { .. });
+        match_errs!("'ä'"; TokenizationError::NonAsciiChar { character: 'ä', .. });
+    }
+
+    #[test]
+    fn test_dummy_location() {
+        assert!(Location::dummy().is_dummy());
+        assert!(Location::dummy().clone().is_dummy());
+        let real = Location::new(Path::new("").into(), 0, 0);
+        assert_eq!(real, Location::dummy());
+        assert!(!real.is_dummy());
+    }
+
+    #[test]
+    fn test_keywords() {
+        for (keyword, typ) in KEYWORDS {
+            let (tokens, errs) = get_tokens(keyword);
+            assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+            assert_eq!(
+                tokens[0].typ, *typ,
+                "`{keyword}` was tokenized as {}",
+                tokens[0]
+            );
+            assert_eq!(typ.keyword(), Some(*keyword));
+        }
+        assert_token_eq("`struct`", &[tok!(IdentifierLiteral, struct)]);
+        assert_token_eq("structs", &[tok!(IdentifierLiteral, structs)]);
+        assert_eq!(TokenType::IdentifierLiteral.keyword(), None);
     }
 
     #[test]
@@ -1311,4 +1828,293 @@ mod test {
             TokenizationError::InvalidNumberError { loc: _ },
         );
     }
+
+    #[test]
+    fn test_number_suffixes() {
+        assert_token_eq(
+            "5u8; 10i64; -3isize; 2.5f64; 7f32; 300u8;",
+            &[
+                tok!(UIntLiteral, UInt(5, U8)),
+                tok!(Semicolon),
+                tok!(UIntLiteral, UInt(10, I64)),
+                tok!(Semicolon),
+                tok!(SIntLiteral, SInt(-3, Isize)),
+                tok!(Semicolon),
+                tok!(FloatLiteral, Float(2.5, F64)),
+                tok!(Semicolon),
+                tok!(FloatLiteral, Float(7.0, F32)),
+                tok!(Semicolon),
+                // the range is checked by the typechecker
+                tok!(UIntLiteral, UInt(300, U8)),
+                tok!(Semicolon),
+            ],
+        );
+
+        match_errs!("1u7; -1u8";
+            TokenizationError::InvalidNumberType(_),
+            TokenizationError::InvalidNumberType(_),
+        );
+    }
+
+    #[test]
+    fn test_error_snippets() {
+        let source = "fn main() {\n    let \\a = 12;\n}";
+        let (_, errs) = get_tokens(source);
+        assert_eq!(errs.len(), 1, "unexpected errors: {errs:?}");
+        assert!(matches!(
+            errs[0],
+            TokenizationError::UnknownTokenError {
+                character: '\\',
+                ..
+            }
+        ));
+        assert_eq!(
+            errs[0].with_source(source).to_string(),
+            "test:1:8: Unknown token `\\`\n  |\n  |     let \\a = 12;\n  |         ^"
+        );
+
+        let (_, errs) = get_tokens("\\");
+        assert_eq!(
+            errs[0].with_source("\\").to_string(),
+            "test:0:0: Unknown token `\\`\n  |\n  | \\\n  | ^"
+        );
+    }
+
+    #[test]
+    fn test_format_strings() {
+        assert_token_eq(
+            r#"f"x = {x}, y = {{{y + 1}}}""#,
+            &[
+                tok!(FormatStringStart),
+                tok!(FormatStringFragment, String("x = ")),
+                tok!(CurlyLeft),
+                tok!(IdentifierLiteral, x),
+                tok!(CurlyRight),
+                tok!(FormatStringFragment, String(", y = {")),
+                tok!(CurlyLeft),
+                tok!(IdentifierLiteral, y),
+                tok!(Plus),
+                tok!(UIntLiteral, UInt(1, _)),
+                tok!(CurlyRight),
+                tok!(FormatStringFragment, String("}")),
+                tok!(FormatStringEnd),
+            ],
+        );
+        // embedded expressions can contain strings and blocks
+        assert_token_eq(
+            r#"f"{a}{f(.{ b: "}" })}""#,
+            &[
+                tok!(FormatStringStart),
+                tok!(CurlyLeft),
+                tok!(IdentifierLiteral, a),
+                tok!(CurlyRight),
+                tok!(CurlyLeft),
+                tok!(IdentifierLiteral, f),
+                tok!(ParenLeft),
+                tok!(Dot),
+                tok!(CurlyLeft),
+                tok!(IdentifierLiteral, b),
+                tok!(Colon),
+                tok!(StringLiteral, String("}")),
+                tok!(CurlyRight),
+                tok!(ParenRight),
+                tok!(CurlyRight),
+                tok!(FormatStringEnd),
+            ],
+        );
+        assert_token_eq(
+            r#"f "a""#,
+            &[tok!(IdentifierLiteral, f), tok!(StringLiteral, String("a"))],
+        );
+
+        match_errs!(r#"f"a } b"#; TokenizationError::UnmatchedFormatBrace { .. });
+        match_errs!(r#"f"{a"#; TokenizationError::UnterminatedString { .. });
+        match_errs!("f\"a\nb\"c"; TokenizationError::UnterminatedString { .. }, TokenizationError::UnterminatedString { .. });
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut tokenizer = Tokenizer::new("let a = 12;\nlet b = a;", Path::new("a").into());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        let capacity = tokenizer.tokens.capacity();
+
+        tokenizer.reset("fn main", Path::new("b").into());
+        assert!(tokenizer.tokens().is_empty());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        assert_eq!(tokenizer.tokens.capacity(), capacity);
+        let tokens = tokenizer.tokens();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].typ, TokenType::Fn);
+        assert_eq!(tokens[1].literal, Some(Literal::String("main".into())));
+        assert_eq!(
+            tokens[1].location,
+            Location::new(Path::new("b").into(), 0, 3)
+        );
+        assert_eq!(tokens[2].typ, TokenType::Eof);
+    }
+
+    /// applies the edit and checks that the tokens match the ones of tokenizing the edited source
+    fn check_edit(
+        source: &str,
+        range: Range<usize>,
+        text: &str,
+    ) -> (Retokenization, Vec<TokenizationError>) {
+        let mut tokenizer = Tokenizer::new(source, Path::new("test").into());
+        _ = tokenizer.scan_tokens();
+        let result = tokenizer.apply_edit(range.clone(), text);
+
+        let mut edited = source.to_string();
+        edited.replace_range(range, text);
+        let (tokens, errs) = get_tokens(&edited);
+        let actual = tokenizer.tokens();
+        assert_eq!(actual.len(), tokens.len(), "{actual:?}\n{tokens:?}");
+        for (actual, expected) in actual.iter().zip(tokens.iter()) {
+            assert_eq!(actual.typ, expected.typ);
+            assert_eq!(actual.literal, expected.literal);
+            assert_eq!(actual.location, expected.location, "{actual}");
+        }
+        match result {
+            Ok(kind) => {
+                assert!(errs.is_empty());
+                (kind, Vec::new())
+            }
+            Err(e) => {
+                assert_eq!(e.len(), errs.len());
+                (Retokenization::Full, e)
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_edits() {
+        let source = "fn main() {\n    let a = 12;\n    let b = a;\n}\n";
+        let edit = |range, text| check_edit(source, range, text);
+
+        // change `12` to `1234`
+        assert_eq!(edit(24..26, "1234").0, Retokenization::Incremental);
+        // insert a line
+        assert_eq!(
+            edit(28..28, "    let c = 3;\n").0,
+            Retokenization::Incremental
+        );
+        // join the `let` lines
+        assert_eq!(edit(27..32, " ").0, Retokenization::Incremental);
+        // remove everything after the first line
+        assert_eq!(edit(11..source.len(), "").0, Retokenization::Incremental);
+
+        // opens a string that is never closed
+        let (kind, errs) = edit(24..24, "\"");
+        assert_eq!(kind, Retokenization::Full);
+        assert!(matches!(
+            errs[..],
+            [TokenizationError::UnterminatedString { .. }]
+        ));
+        // comments can span lines
+        assert_eq!(edit(16..16, "/*").0, Retokenization::Full);
+        assert_eq!(
+            check_edit("/* a */\nlet a = 1;", 16..17, "2").0,
+            Retokenization::Full
+        );
+        // so can strings with an escaped newline
+        assert_eq!(
+            check_edit("let a = \"a\\\nb\";\nlet b = 1;", 24..25, "2").0,
+            Retokenization::Full
+        );
+
+        // invalid ranges don't change the tokens
+        let source = "let a = \"ä\";";
+        for range in [10..10, 0..100, 4..2] {
+            let mut tokenizer = Tokenizer::new(source, Path::new("test").into());
+            tokenizer.scan_tokens().expect("failed to tokenize");
+            let errs = tokenizer.apply_edit(range.clone(), "").unwrap_err();
+            assert!(
+                matches!(errs[..], [TokenizationError::InvalidEdit { start, end, .. }] if start == range.start && end == range.end),
+                "{range:?}: {errs:?}"
+            );
+            assert_eq!(tokenizer.tokens().len(), 6);
+            assert_eq!(tokenizer.source.iter().collect::<String>(), source);
+        }
+    }
+
+    #[test]
+    fn test_consecutive_edits() {
+        let mut source = "fn main() {\n    let a = \"ä\";\n    let b = 2;\n}\n".to_string();
+        let mut tokenizer = Tokenizer::new(&source, Path::new("test").into());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        // each edit relies on the lines the previous ones left behind
+        for (range, text) in [
+            (27..27, "ö"),
+            (32..32, "    let c = \"ü\";\n"),
+            (20..44, "x = 1;\n    let y = "),
+            (0..12, ""),
+            (48..48, "let z = 3;\n"),
+        ] {
+            let kind = tokenizer
+                .apply_edit(range.clone(), text)
+                .unwrap_or_else(|e| panic!("unexpected errors: {e:?}"));
+            assert_eq!(kind, Retokenization::Incremental, "{range:?} {text:?}");
+            source.replace_range(range, text);
+            assert_eq!(tokenizer.source.iter().collect::<String>(), source);
+            let (tokens, errs) = get_tokens(&source);
+            assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+            let actual = tokenizer.tokens();
+            assert_eq!(actual.len(), tokens.len(), "{actual:?}\n{tokens:?}");
+            for (actual, expected) in actual.iter().zip(tokens.iter()) {
+                assert_eq!(actual.typ, expected.typ);
+                assert_eq!(actual.literal, expected.literal);
+                assert_eq!(actual.location, expected.location, "{actual}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut tokenizer = Tokenizer::new("let a = 1;", Path::new("test").into());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        let types = tokenizer.tokens().iter().map(|v| v.typ).collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                TokenType::Let,
+                TokenType::IdentifierLiteral,
+                TokenType::Equal,
+                TokenType::UIntLiteral,
+                TokenType::Semicolon,
+                TokenType::Eof,
+            ]
+        );
+        // the tokens are still there after inspecting them
+        assert_eq!(tokenizer.tokens().len(), 6);
+    }
+
+    #[test]
+    fn test_single_eof() {
+        let (tokens, errs) = get_tokens("");
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].typ, TokenType::Eof);
+
+        let mut tokenizer = Tokenizer::new("fn", Path::new("test").into());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        let types = tokenizer.tokens().iter().map(|v| v.typ).collect::<Vec<_>>();
+        assert_eq!(types, [TokenType::Fn, TokenType::Eof]);
+    }
+
+    #[test]
+    fn test_max_errors() {
+        let source = "\\".repeat(1000);
+        let (tokens, errs) = get_tokens(&source);
+        assert_eq!(errs.len(), DEFAULT_MAX_ERRORS + 1);
+        assert!(matches!(
+            errs.last(),
+            Some(TokenizationError::TooManyErrors { .. })
+        ));
+        assert_eq!(tokens.last().map(|v| v.typ), Some(TokenType::Eof));
+
+        let mut tokenizer = Tokenizer::new(&source, Path::new("test").into()).with_max_errors(5);
+        let errs = tokenizer.scan_tokens().expect_err("expected errors");
+        assert_eq!(errs.len(), 6);
+        assert!(matches!(errs[5], TokenizationError::TooManyErrors { .. }));
+    }
 }
@@ -0,0 +1,713 @@
+//! Compile time evaluation of statics and array lengths, which can call `const fn`s. This is a
+//! small interpreter over typechecked function bodies, which supports arithmetic, comparisons,
+//! casts between numbers, branches, loops and local variables. Everything else, like calls to
+//! functions that aren't `const fn`s, can't be evaluated.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{module::FunctionId, std_annotations::const_fn::ConstFn, tokenizer::Location};
+
+use super::{
+    expression::{TypecheckedExpression, TypedLiteral},
+    intrinsics::Intrinsic,
+    typechecking::ScopeValueId,
+    visitor::{walk_expression, walk_literal, Visitor},
+    Type, TypecheckedFunctions, TypecheckingContext, TypecheckingError, DUMMY_LOCATION,
+};
+
+/// how many expressions an evaluation can run, so endless loops fail instead of hanging
+const MAX_STEPS: usize = 1_000_000;
+/// how deep `const fn`s can call each other
+const MAX_DEPTH: usize = 128;
+
+pub fn is_const_fn(context: &TypecheckingContext, id: FunctionId) -> bool {
+    context.functions.read()[id]
+        .0
+        .annotations
+        .get_first_annotation::<ConstFn>()
+        .is_some()
+}
+
+/// Checks that the typechecked `body` of a `const fn` only contains operations that can be
+/// evaluated at compile time
+pub fn check_const_fn(
+    context: &TypecheckingContext,
+    body: &[TypecheckedExpression],
+) -> Result<(), Vec<TypecheckingError>> {
+    let functions = context.functions.read();
+    let mut checker = ConstChecker {
+        functions: &functions,
+        location: DUMMY_LOCATION.clone(),
+        errors: Vec::new(),
+    };
+    checker.visit_body(body);
+    if checker.errors.len() > 0 {
+        return Err(checker.errors);
+    }
+    Ok(())
+}
+
+struct ConstChecker<'a> {
+    functions: &'a TypecheckedFunctions,
+    /// the location of the expression that is being visited
+    location: Location,
+    errors: Vec<TypecheckingError>,
+}
+
+impl Visitor for ConstChecker<'_> {
+    fn visit_expression(&mut self, expression: &TypecheckedExpression) {
+        let is_const = match expression {
+            TypecheckedExpression::DirectCall(_, _, id, _) => self.functions[*id]
+                .0
+                .annotations
+                .get_first_annotation::<ConstFn>()
+                .is_some(),
+            TypecheckedExpression::IntrinsicCall(_, _, intrinsic, _) => {
+                matches!(intrinsic, Intrinsic::Assert | Intrinsic::DebugAssert)
+            }
+            TypecheckedExpression::Call(..)
+            | TypecheckedExpression::DirectExternCall(..)
+            | TypecheckedExpression::DynCall(..)
+            | TypecheckedExpression::Asm { .. }
+            | TypecheckedExpression::Range { .. }
+            | TypecheckedExpression::Offset(..)
+            | TypecheckedExpression::BoundsCheck(..)
+            | TypecheckedExpression::Bitcast(..)
+            | TypecheckedExpression::PtrToInt(..)
+            | TypecheckedExpression::IntToPtr(..)
+            | TypecheckedExpression::StripMetadata(..)
+            | TypecheckedExpression::MakeUnsizedSlice(..)
+            | TypecheckedExpression::MakeSlice(..)
+            | TypecheckedExpression::AttachVtable(..) => false,
+            _ => true,
+        };
+        if !is_const {
+            self.errors.push(TypecheckingError::NonConstOperation {
+                location: expression.location().clone(),
+            });
+            return;
+        }
+        if !matches!(expression, TypecheckedExpression::None) {
+            self.location = expression.location().clone();
+        }
+        walk_expression(self, expression);
+    }
+
+    fn visit_literal(&mut self, literal: &TypedLiteral) {
+        match literal {
+            TypedLiteral::Static(_) => {
+                self.errors
+                    .push(TypecheckingError::StaticInConstExpression {
+                        location: self.location.clone(),
+                    })
+            }
+            TypedLiteral::Intrinsic(_) => self.errors.push(TypecheckingError::NonConstOperation {
+                location: self.location.clone(),
+            }),
+            _ => walk_literal(self, literal),
+        }
+    }
+}
+
+/// Evaluates `body` and returns the value of `result` afterwards. `types` are the types of the
+/// values in `body`. The bodies of the called `const fn`s have to be typechecked already.
+pub fn evaluate(
+    context: &TypecheckingContext,
+    body: &[TypecheckedExpression],
+    types: &[Type],
+    result: &TypedLiteral,
+    location: &Location,
+) -> Result<TypedLiteral, TypecheckingError> {
+    let functions = context.functions.read();
+    let scopes = context.const_fn_scopes.read();
+    let mut evaluator = ConstEvaluator {
+        functions: &functions,
+        scopes: &scopes,
+        ptr_bits: context.ptr_size() as u32 * 8,
+        steps: 0,
+        depth: 0,
+    };
+    let mut frame = Frame::new(types);
+    if let Some(value) = evaluator.run(&mut frame, body)? {
+        return Ok(value);
+    }
+    frame.value(result, location)
+}
+
+fn error(location: &Location, reason: &'static str) -> TypecheckingError {
+    TypecheckingError::ConstEvaluation {
+        location: location.clone(),
+        reason,
+    }
+}
+
+/// The values of a function that is being evaluated
+struct Frame<'a> {
+    types: &'a [Type],
+    values: HashMap<ScopeValueId, TypedLiteral>,
+    /// the variables the references in `values` point to, references to anything else can't be
+    /// evaluated
+    references: HashMap<ScopeValueId, ScopeValueId>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(types: &'a [Type]) -> Self {
+        Self {
+            types,
+            values: HashMap::new(),
+            references: HashMap::new(),
+        }
+    }
+
+    fn value(
+        &self,
+        literal: &TypedLiteral,
+        loc: &Location,
+    ) -> Result<TypedLiteral, TypecheckingError> {
+        let elements = |elements: &[TypedLiteral]| {
+            elements
+                .iter()
+                .map(|element| self.value(element, loc))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match literal {
+            TypedLiteral::Dynamic(id) => self
+                .values
+                .get(id)
+                .cloned()
+                .ok_or_else(|| error(loc, "the value is not known at compile time")),
+            TypedLiteral::Array(typ, values) => {
+                Ok(TypedLiteral::Array(typ.clone(), elements(values)?))
+            }
+            TypedLiteral::Struct(id, values) => Ok(TypedLiteral::Struct(*id, elements(values)?)),
+            TypedLiteral::Tuple(values) => Ok(TypedLiteral::Tuple(elements(values)?)),
+            TypedLiteral::Static(_) => Err(TypecheckingError::StaticInConstExpression {
+                location: loc.clone(),
+            }),
+            TypedLiteral::Intrinsic(_) => Err(TypecheckingError::NonConstOperation {
+                location: loc.clone(),
+            }),
+            literal => Ok(literal.clone()),
+        }
+    }
+
+    fn bool(&self, literal: &TypedLiteral, loc: &Location) -> Result<bool, TypecheckingError> {
+        match self.value(literal, loc)? {
+            TypedLiteral::Bool(v) => Ok(v),
+            _ => Err(error(loc, "expected a bool")),
+        }
+    }
+
+    /// Copies `literal` into `dst`, which can either be a value or a reference
+    fn assign(
+        &mut self,
+        dst: ScopeValueId,
+        literal: &TypedLiteral,
+        loc: &Location,
+    ) -> Result<(), TypecheckingError> {
+        if let TypedLiteral::Dynamic(id) = literal {
+            if let Some(&variable) = self.references.get(id) {
+                self.values.remove(&dst);
+                self.references.insert(dst, variable);
+                return Ok(());
+            }
+        }
+        let value = self.value(literal, loc)?;
+        self.references.remove(&dst);
+        self.values.insert(dst, value);
+        Ok(())
+    }
+
+    /// The variable the reference `literal` points to
+    fn variable(
+        &self,
+        literal: &TypedLiteral,
+        loc: &Location,
+    ) -> Result<ScopeValueId, TypecheckingError> {
+        match literal {
+            TypedLiteral::Dynamic(id) => self.references.get(id).copied(),
+            _ => None,
+        }
+        .ok_or_else(|| error(loc, "only references to local variables can be evaluated"))
+    }
+}
+
+struct ConstEvaluator<'a> {
+    functions: &'a TypecheckedFunctions,
+    /// the types of the values of the `const fn`s, see [`TypecheckingContext::const_fn_scopes`]
+    scopes: &'a HashMap<FunctionId, Box<[Type]>>,
+    /// the width of `usize` and `isize` on the target
+    ptr_bits: u32,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'a> ConstEvaluator<'a> {
+    /// Runs `body`, returns the value of the function if it returned
+    fn run(
+        &mut self,
+        frame: &mut Frame<'_>,
+        body: &[TypecheckedExpression],
+    ) -> Result<Option<TypedLiteral>, TypecheckingError> {
+        for expression in body {
+            if let Some(value) = self.run_expression(frame, expression)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    fn step(&mut self, loc: &Location) -> Result<(), TypecheckingError> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(error(loc, "the evaluation takes too many steps"));
+        }
+        Ok(())
+    }
+
+    fn run_expression(
+        &mut self,
+        frame: &mut Frame<'_>,
+        expression: &TypecheckedExpression,
+    ) -> Result<Option<TypedLiteral>, TypecheckingError> {
+        if let TypecheckedExpression::None = expression {
+            return Ok(None);
+        }
+        self.step(expression.location())?;
+        match expression {
+            TypecheckedExpression::Return(loc, value) => return frame.value(value, loc).map(Some),
+            TypecheckedExpression::Block(_, body, _) => return self.run(frame, body),
+            TypecheckedExpression::If {
+                loc,
+                cond,
+                if_block,
+                else_block,
+                ..
+            } => {
+                if frame.bool(cond, loc)? {
+                    return self.run(frame, &if_block.0);
+                } else if let Some((else_block, _)) = else_block {
+                    return self.run(frame, else_block);
+                }
+            }
+            TypecheckedExpression::While {
+                loc,
+                cond_block,
+                cond,
+                body,
+            } => loop {
+                self.step(loc)?;
+                if let Some(value) = self.run(frame, cond_block)? {
+                    return Ok(Some(value));
+                }
+                if !frame.bool(cond, loc)? {
+                    break;
+                }
+                if let Some(value) = self.run(frame, &body.0)? {
+                    return Ok(Some(value));
+                }
+            },
+            TypecheckedExpression::Literal(loc, dst, value)
+            | TypecheckedExpression::Alias(loc, dst, value)
+            | TypecheckedExpression::Pos(loc, dst, value) => frame.assign(*dst, value, loc)?,
+            TypecheckedExpression::DeclareVariable(..)
+            | TypecheckedExpression::Empty(_)
+            | TypecheckedExpression::None => (),
+            TypecheckedExpression::Unreachable(loc) => {
+                return Err(error(loc, "reached unreachable code"))
+            }
+            TypecheckedExpression::Reference(loc, dst, value) => match value {
+                TypedLiteral::Dynamic(id) => {
+                    frame.values.remove(dst);
+                    frame.references.insert(*dst, *id);
+                }
+                _ => {
+                    return Err(error(
+                        loc,
+                        "only references to local variables can be evaluated",
+                    ))
+                }
+            },
+            TypecheckedExpression::Dereference(loc, dst, value) => {
+                let variable = frame.variable(value, loc)?;
+                frame.assign(*dst, &TypedLiteral::Dynamic(variable), loc)?;
+            }
+            TypecheckedExpression::StoreAssignment(loc, dst, value) => {
+                let variable = frame.variable(dst, loc)?;
+                frame.assign(variable, value, loc)?;
+            }
+            TypecheckedExpression::Neg(loc, dst, value) => {
+                let value = frame.value(value, loc)?;
+                let value = if let Some(v) = int_value(&value) {
+                    v.checked_neg()
+                        .and_then(|v| checked_int(&value, v, self.ptr_bits))
+                        .ok_or_else(|| error(loc, "arithmetic overflow"))?
+                } else if let Some(v) = float_value(&value) {
+                    with_float_value(&value, -v)
+                } else {
+                    return Err(error(loc, "unsupported operand"));
+                };
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::LNot(loc, dst, value)
+            | TypecheckedExpression::BNot(loc, dst, value) => {
+                let value = match frame.value(value, loc)? {
+                    TypedLiteral::Bool(v) => TypedLiteral::Bool(!v),
+                    value => match int_value(&value) {
+                        Some(v) => wrap_int(&value, !v, self.ptr_bits),
+                        None => return Err(error(loc, "unsupported operand")),
+                    },
+                };
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::Add(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Sub(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Mul(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Div(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Mod(loc, dst, lhs, rhs)
+            | TypecheckedExpression::BAnd(loc, dst, lhs, rhs)
+            | TypecheckedExpression::BOr(loc, dst, lhs, rhs)
+            | TypecheckedExpression::BXor(loc, dst, lhs, rhs)
+            | TypecheckedExpression::GreaterThan(loc, dst, lhs, rhs)
+            | TypecheckedExpression::LessThan(loc, dst, lhs, rhs)
+            | TypecheckedExpression::GreaterThanEq(loc, dst, lhs, rhs)
+            | TypecheckedExpression::LessThanEq(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Eq(loc, dst, lhs, rhs)
+            | TypecheckedExpression::Neq(loc, dst, lhs, rhs)
+            | TypecheckedExpression::LShift(loc, dst, lhs, rhs)
+            | TypecheckedExpression::RShift(loc, dst, lhs, rhs) => {
+                let lhs = frame.value(lhs, loc)?;
+                let rhs = frame.value(rhs, loc)?;
+                let value = binary_operation(expression, &lhs, &rhs, self.ptr_bits)
+                    .map_err(|reason| error(loc, reason))?;
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::IntCast(loc, dst, value) => {
+                let value = frame.value(value, loc)?;
+                let value = cast(&value, &frame.types[*dst], self.ptr_bits)
+                    .ok_or_else(|| error(loc, "unsupported cast"))?;
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::OffsetNonPointer(loc, dst, value, index) => {
+                let value = match frame.value(value, loc)? {
+                    TypedLiteral::Array(_, mut elements)
+                    | TypedLiteral::Struct(_, mut elements)
+                    | TypedLiteral::Tuple(mut elements)
+                        if *index < elements.len() =>
+                    {
+                        elements.swap_remove(*index)
+                    }
+                    _ => return Err(error(loc, "unsupported operand")),
+                };
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::DirectCall(loc, dst, id, arguments) => {
+                let value = self.call(frame, loc, *id, arguments)?;
+                frame.values.insert(*dst, value);
+            }
+            TypecheckedExpression::IntrinsicCall(
+                loc,
+                dst,
+                Intrinsic::Assert | Intrinsic::DebugAssert,
+                arguments,
+            ) => {
+                let [cond] = &arguments[..] else {
+                    return Err(error(loc, "unsupported operand"));
+                };
+                if !frame.bool(cond, loc)? {
+                    return Err(error(loc, "assertion failed"));
+                }
+                frame.values.insert(*dst, TypedLiteral::Void);
+            }
+            _ => {
+                return Err(TypecheckingError::NonConstOperation {
+                    location: expression.location().clone(),
+                })
+            }
+        }
+        Ok(None)
+    }
+
+    fn call(
+        &mut self,
+        frame: &Frame<'_>,
+        loc: &Location,
+        id: FunctionId,
+        arguments: &[TypedLiteral],
+    ) -> Result<TypedLiteral, TypecheckingError> {
+        let functions = self.functions;
+        let (contract, body) = &functions[id];
+        if contract
+            .annotations
+            .get_first_annotation::<ConstFn>()
+            .is_none()
+        {
+            return Err(TypecheckingError::NonConstOperation {
+                location: loc.clone(),
+            });
+        }
+        let scopes = self.scopes;
+        let Some(types) = scopes.get(&id) else {
+            return Err(error(loc, "the called `const fn` could not be typechecked"));
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(error(loc, "too many nested calls"));
+        }
+
+        let mut callee = Frame::new(types);
+        for (id, argument) in arguments.iter().enumerate() {
+            callee.values.insert(id, frame.value(argument, loc)?);
+        }
+        self.depth += 1;
+        let value = self.run(&mut callee, body);
+        self.depth -= 1;
+        Ok(value?.unwrap_or(TypedLiteral::Void))
+    }
+}
+
+/// `ptr_bits` is the width of `usize` and `isize` on the target
+fn binary_operation(
+    expression: &TypecheckedExpression,
+    lhs: &TypedLiteral,
+    rhs: &TypedLiteral,
+    ptr_bits: u32,
+) -> Result<TypedLiteral, &'static str> {
+    if let (Some(a), Some(b)) = (int_value(lhs), int_value(rhs)) {
+        if let Some(v) = comparison(expression, Some(a.cmp(&b))) {
+            return Ok(TypedLiteral::Bool(v));
+        }
+        let value = match expression {
+            TypecheckedExpression::Add(..) => a.checked_add(b),
+            TypecheckedExpression::Sub(..) => a.checked_sub(b),
+            TypecheckedExpression::Mul(..) => a.checked_mul(b),
+            TypecheckedExpression::Div(..) | TypecheckedExpression::Mod(..) if b == 0 => {
+                return Err("division by zero")
+            }
+            TypecheckedExpression::Div(..) => a.checked_div(b),
+            TypecheckedExpression::Mod(..) => a.checked_rem(b),
+            TypecheckedExpression::BAnd(..) => return Ok(wrap_int(lhs, a & b, ptr_bits)),
+            TypecheckedExpression::BOr(..) => return Ok(wrap_int(lhs, a | b, ptr_bits)),
+            TypecheckedExpression::BXor(..) => return Ok(wrap_int(lhs, a ^ b, ptr_bits)),
+            TypecheckedExpression::LShift(..) | TypecheckedExpression::RShift(..)
+                if b < 0 || b >= int_bits(lhs, ptr_bits) as i128 =>
+            {
+                return Err("shift amount is too large")
+            }
+            TypecheckedExpression::LShift(..) => return Ok(wrap_int(lhs, a << b, ptr_bits)),
+            TypecheckedExpression::RShift(..) => return Ok(wrap_int(lhs, a >> b, ptr_bits)),
+            _ => return Err("unsupported operands"),
+        };
+        return value
+            .and_then(|v| checked_int(lhs, v, ptr_bits))
+            .ok_or("arithmetic overflow");
+    }
+    if let (Some(a), Some(b)) = (float_value(lhs), float_value(rhs)) {
+        if let Some(v) = comparison(expression, a.partial_cmp(&b)) {
+            return Ok(TypedLiteral::Bool(v));
+        }
+        let value = match expression {
+            TypecheckedExpression::Add(..) => a + b,
+            TypecheckedExpression::Sub(..) => a - b,
+            TypecheckedExpression::Mul(..) => a * b,
+            TypecheckedExpression::Div(..) => a / b,
+            TypecheckedExpression::Mod(..) => a % b,
+            _ => return Err("unsupported operands"),
+        };
+        return Ok(with_float_value(lhs, value));
+    }
+    if let (TypedLiteral::Bool(a), TypedLiteral::Bool(b)) = (lhs, rhs) {
+        if let Some(v) = comparison(expression, Some(a.cmp(b))) {
+            return Ok(TypedLiteral::Bool(v));
+        }
+        return match expression {
+            TypecheckedExpression::BAnd(..) => Ok(TypedLiteral::Bool(*a & *b)),
+            TypecheckedExpression::BOr(..) => Ok(TypedLiteral::Bool(*a | *b)),
+            TypecheckedExpression::BXor(..) => Ok(TypedLiteral::Bool(*a ^ *b)),
+            _ => Err("unsupported operands"),
+        };
+    }
+    Err("unsupported operands")
+}
+
+/// Returns the result of `expression` if it's a comparison, `ordering` is `None` for
+/// floats that can't be compared
+fn comparison(expression: &TypecheckedExpression, ordering: Option<Ordering>) -> Option<bool> {
+    match expression {
+        TypecheckedExpression::Eq(..) => Some(ordering == Some(Ordering::Equal)),
+        TypecheckedExpression::Neq(..) => Some(ordering != Some(Ordering::Equal)),
+        TypecheckedExpression::GreaterThan(..) => Some(ordering == Some(Ordering::Greater)),
+        TypecheckedExpression::LessThan(..) => Some(ordering == Some(Ordering::Less)),
+        TypecheckedExpression::GreaterThanEq(..) => Some(matches!(
+            ordering,
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        TypecheckedExpression::LessThanEq(..) => {
+            Some(matches!(ordering, Some(Ordering::Less | Ordering::Equal)))
+        }
+        _ => None,
+    }
+}
+
+fn int_value(literal: &TypedLiteral) -> Option<i128> {
+    match *literal {
+        TypedLiteral::U8(v) => Some(v as i128),
+        TypedLiteral::U16(v) => Some(v as i128),
+        TypedLiteral::U32(v) => Some(v as i128),
+        TypedLiteral::U64(v) => Some(v as i128),
+        TypedLiteral::USize(v) => Some(v as i128),
+        TypedLiteral::I8(v) => Some(v as i128),
+        TypedLiteral::I16(v) => Some(v as i128),
+        TypedLiteral::I32(v) => Some(v as i128),
+        TypedLiteral::I64(v) => Some(v as i128),
+        TypedLiteral::ISize(v) => Some(v as i128),
+        _ => None,
+    }
+}
+
+/// The width of the integer `literal`, `usize` and `isize` are `ptr_bits` wide
+fn int_bits(literal: &TypedLiteral, ptr_bits: u32) -> u32 {
+    match literal {
+        TypedLiteral::U8(_) | TypedLiteral::I8(_) => 8,
+        TypedLiteral::U16(_) | TypedLiteral::I16(_) => 16,
+        TypedLiteral::U32(_) | TypedLiteral::I32(_) => 32,
+        TypedLiteral::U64(_) | TypedLiteral::I64(_) => 64,
+        _ => ptr_bits,
+    }
+}
+
+/// The smallest and largest value of the integer type of `literal` on the target
+fn int_range(literal: &TypedLiteral, ptr_bits: u32) -> (i128, i128) {
+    let bits = int_bits(literal, ptr_bits);
+    match literal {
+        TypedLiteral::I8(_)
+        | TypedLiteral::I16(_)
+        | TypedLiteral::I32(_)
+        | TypedLiteral::I64(_)
+        | TypedLiteral::ISize(_) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+        _ => (0, (1 << bits) - 1),
+    }
+}
+
+macro_rules! int_literal {
+    ($like:expr, $convert:expr) => {
+        match $like {
+            TypedLiteral::U8(_) => TypedLiteral::U8({
+                type T = u8;
+                $convert
+            }),
+            TypedLiteral::U16(_) => TypedLiteral::U16({
+                type T = u16;
+                $convert
+            }),
+            TypedLiteral::U32(_) => TypedLiteral::U32({
+                type T = u32;
+                $convert
+            }),
+            TypedLiteral::U64(_) => TypedLiteral::U64({
+                type T = u64;
+                $convert
+            }),
+            TypedLiteral::USize(_) => TypedLiteral::USize({
+                type T = usize;
+                $convert
+            }),
+            TypedLiteral::I8(_) => TypedLiteral::I8({
+                type T = i8;
+                $convert
+            }),
+            TypedLiteral::I16(_) => TypedLiteral::I16({
+                type T = i16;
+                $convert
+            }),
+            TypedLiteral::I32(_) => TypedLiteral::I32({
+                type T = i32;
+                $convert
+            }),
+            TypedLiteral::I64(_) => TypedLiteral::I64({
+                type T = i64;
+                $convert
+            }),
+            TypedLiteral::ISize(_) => TypedLiteral::ISize({
+                type T = isize;
+                $convert
+            }),
+            _ => unreachable!("not an integer"),
+        }
+    };
+}
+
+/// Converts `value` into an integer of the same type as `like`, truncating it if it doesn't fit
+fn wrap_int(like: &TypedLiteral, value: i128, ptr_bits: u32) -> TypedLiteral {
+    let (min, max) = int_range(like, ptr_bits);
+    let value = (value - min).rem_euclid(max - min + 1) + min;
+    int_literal!(like, value as T)
+}
+
+/// Converts `value` into an integer of the same type as `like`, if it fits
+fn checked_int(like: &TypedLiteral, value: i128, ptr_bits: u32) -> Option<TypedLiteral> {
+    let (min, max) = int_range(like, ptr_bits);
+    (min..=max)
+        .contains(&value)
+        .then(|| int_literal!(like, value as T))
+}
+
+fn float_value(literal: &TypedLiteral) -> Option<f64> {
+    match *literal {
+        TypedLiteral::F16(v) | TypedLiteral::F32(v) => Some(v as f64),
+        TypedLiteral::F64(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Converts `value` into a float of the same type as `like`
+fn with_float_value(like: &TypedLiteral, value: f64) -> TypedLiteral {
+    match like {
+        TypedLiteral::F16(_) => TypedLiteral::F16(value as f32),
+        TypedLiteral::F32(_) => TypedLiteral::F32(value as f32),
+        _ => TypedLiteral::F64(value),
+    }
+}
+
+/// Casts a number or bool like [`TypecheckedExpression::IntCast`]. Integers are truncated or
+/// extended and floats saturate when they are cast to an integer.
+fn cast(value: &TypedLiteral, typ: &Type, ptr_bits: u32) -> Option<TypedLiteral> {
+    let int = match *value {
+        TypedLiteral::Bool(v) => Some(v as i128),
+        ref value => int_value(value),
+    };
+    let float = float_value(value);
+    macro_rules! convert {
+        ($variant:ident, $typ:ty) => {
+            TypedLiteral::$variant(match int {
+                Some(v) => v as $typ,
+                None => float? as $typ,
+            })
+        };
+    }
+    Some(match typ {
+        Type::PrimitiveBool(0) => TypedLiteral::Bool(int? & 1 != 0),
+        Type::PrimitiveU8(0) => convert!(U8, u8),
+        Type::PrimitiveU16(0) => convert!(U16, u16),
+        Type::PrimitiveU32(0) => convert!(U32, u32),
+        Type::PrimitiveU64(0) => convert!(U64, u64),
+        Type::PrimitiveUSize(0) | Type::PrimitiveISize(0) => {
+            let like = match typ {
+                Type::PrimitiveUSize(_) => TypedLiteral::USize(0),
+                _ => TypedLiteral::ISize(0),
+            };
+            let (min, max) = int_range(&like, ptr_bits);
+            match int {
+                Some(v) => wrap_int(&like, v, ptr_bits),
+                None => wrap_int(&like, (float? as i128).clamp(min, max), ptr_bits),
+            }
+        }
+        Type::PrimitiveI8(0) => convert!(I8, i8),
+        Type::PrimitiveI16(0) => convert!(I16, i16),
+        Type::PrimitiveI32(0) => convert!(I32, i32),
+        Type::PrimitiveI64(0) => convert!(I64, i64),
+        Type::PrimitiveF16(0) => convert!(F16, f32),
+        Type::PrimitiveF32(0) => convert!(F32, f32),
+        Type::PrimitiveF64(0) => convert!(F64, f64),
+        _ => return None,
+    })
+}
@@ -1,11 +1,14 @@
+use std::{path::Path as FsPath, sync::Arc};
+
 use thiserror::Error;
 
 use crate::{
-    error::FunctionList,
+    error::{FunctionList, ModuleCycle},
     globals::GlobalStr,
     lang_items::{LangItemAssignmentError, LangItemError},
     parser::{Path, PathWithoutGenerics},
-    tokenizer::Location,
+    target::Target,
+    tokenizer::{Location, NumberType},
 };
 
 use super::{types::Type, ScopeKind};
@@ -38,6 +41,22 @@ pub enum TypecheckingError {
     MismatchingGenericCount(Location, usize, usize),
     #[error("{0}: The size of {1} needs to be known at compiletime")]
     NonSizedType(Location, Type),
+    #[error("{location}: values of the unsized type `{typ}` have to be behind a reference")]
+    UnsizedValue { location: Location, typ: Type },
+    #[error("{location}: the literal `{value}` does not fit into `{typ}`")]
+    LiteralOutOfRange {
+        location: Location,
+        value: i128,
+        typ: NumberType,
+    },
+    #[error("{location}: cannot transmute `{from}` ({from_size} bytes) into `{to}` ({to_size} bytes), the types have different sizes")]
+    TransmuteSizeMismatch {
+        location: Location,
+        from: Type,
+        from_size: u64,
+        to: Type,
+        to_size: u64,
+    },
     #[error("{0}: Intrinsics accepts only integers, supplied: {1}")]
     IntOnlyIntrinsic(Location, Type),
     #[error("{0}: Assembly instructions can only accept numeric types (i_, u_, f_ and bool). Specified Type: `{1}`")]
@@ -46,12 +65,27 @@ pub enum TypecheckingError {
     AsmNonNumericType(Location, GlobalStr),
     #[error("{0}: Tuple only has {1} fields, but tried to get field {2}")]
     TupleIndexOutOfBounds(Location, usize, usize),
+    #[error("{0}: Array only has {1} elements, but the slice ends at {2}")]
+    SliceOutOfBounds(Location, usize, usize),
+    #[error("{0}: The slice starts at {1}, after its end at {2}")]
+    InvertedSliceRange(Location, usize, usize),
     #[error("{0}: Cannot index a tuple with a dynamic value")]
     TupleDynamicIndex(Location),
     #[error("{0}: Cannot infer type for anonymous struct")]
     CannotInferAnonStructType(Location),
+    #[error("{0}: Cannot infer the generic `{1}`, specify the type of the struct")]
+    CannotInferGeneric(Location, GlobalStr),
     #[error("{0}: Statics can only have literal values")]
     StaticsNeedToBeLiteral(Location),
+    #[error("{location}: this operation cannot be evaluated at compile time")]
+    NonConstOperation { location: Location },
+    #[error("{location}: statics cannot be used at compile time, their values are only known after all functions are typechecked")]
+    StaticInConstExpression { location: Location },
+    #[error("{location}: compile time evaluation failed: {reason}")]
+    ConstEvaluation {
+        location: Location,
+        reason: &'static str,
+    },
     #[error(
         "{0}: Cannot infer array type. Use `[] as [<type>;0]` to explicitly specify the expected array type"
     )]
@@ -66,12 +100,21 @@ pub enum TypecheckingError {
     AccessNonStructValue(Location, Type),
     #[error("{0}: Tried to index into non-array type `{1}`")]
     IndexNonArrayElem(Location, Type),
+    #[error("{location}: This needs the lang item `{name}`, but it isn't defined")]
+    MissingLangItem {
+        location: Location,
+        name: &'static str,
+    },
+    #[error("{0}: Tried to slice non-array type `{1}`")]
+    SliceNonArray(Location, Type),
     #[error("{0}: Could not find field `{2}` on `{1}`")]
     FieldNotFound(Location, Type, GlobalStr),
     #[error("{0}: Invalid cast (lhs: `{1}`, rhs: `{2}`)")]
-    DisallowedCast(Location, Type, Type),
+    InvalidCast(Location, Type, Type),
     #[error("{0}: Cannot assign to this expression")]
     CannotAssign(Location),
+    #[error("{location}: cannot assign to the immutable variable `{name}`, declare it with `var` to make it mutable")]
+    AssignToImmutable { location: Location, name: GlobalStr },
     #[error("{0}: Cannot shift by a non-uint value (found `{1}`)")]
     CannotShiftByNonUInt(Location, Type),
     #[error("{0}: Cannot add `{1}`")]
@@ -84,6 +127,8 @@ pub enum TypecheckingError {
     CannotDiv(Location, Type),
     #[error("{0}: Cannot take the remainder of `{1}`")]
     CannotMod(Location, Type),
+    #[error("{0}: Division by zero")]
+    DivisionByZero(Location),
     #[error("{0}: Cannot binary and `{1}`")]
     CannotBAnd(Location, Type),
     #[error("{0}: Cannot binary or `{1}`")]
@@ -96,8 +141,8 @@ pub enum TypecheckingError {
     CannotLOr(Location, Type),
     #[error("{0}: Cannot compare `{1}`")]
     CannotCompare(Location, Type),
-    #[error("{0}: Cannot compare the equality of `{1}`")]
-    CannotEq(Location, Type),
+    #[error("{0}: `{1}` is not comparable, `==` and `!=` need a primitive or a struct implementing `Eq`")]
+    TypeNotComparable(Location, Type),
     #[error("{0}: Cannot shift `{1}` left")]
     CannotShl(Location, Type),
     #[error("{0}: Cannot shift `{1}` right")]
@@ -118,6 +163,11 @@ pub enum TypecheckingError {
     ExportNotFound { location: Location, name: GlobalStr },
     #[error("{location}: cyclic dependency detected")]
     CyclicDependency { location: Location },
+    #[error("{location}: cyclic import: {}", ModuleCycle(.modules))]
+    ImportCycle {
+        location: Location,
+        modules: Vec<Arc<FsPath>>,
+    },
     #[error("{location}: Unbound identifier `{name}`")]
     UnboundIdent { location: Location, name: GlobalStr },
     #[error("{location}: Expected a {expected:?}, but found a {found:?}")]
@@ -136,6 +186,20 @@ pub enum TypecheckingError {
         found: Type,
         location: Location,
     },
+    #[error(
+        "{location}: Expected {expected}, but found {found}, consider taking a reference with `&`"
+    )]
+    ExpectedReference {
+        expected: Type,
+        found: Type,
+        location: Location,
+    },
+    #[error("{location}: `{typ}` is not supported on the target {target}")]
+    UnsupportedFloatWidth {
+        location: Location,
+        typ: Type,
+        target: Target,
+    },
     #[error("{location}: Function pointers can't have generics")]
     GenericFunctionPointer { location: Location },
     #[error("{location}: `{name}` is not a struct type")]
@@ -152,22 +216,178 @@ pub enum TypecheckingError {
     TooManyArguments { location: Location },
     #[error("{location}: Did not expected a generic here.")]
     UnexpectedGenerics { location: Location },
-    #[error("{location}: `{name}` is not a member of the trait.")]
-    IsNotTraitMember { location: Location, name: GlobalStr },
-    #[error("{location}: missing trait item `{name}`")]
-    MissingTraitItem { location: Location, name: GlobalStr },
+    #[error("{location}: `{name}` is not a method of the trait `{trait_name}`")]
+    UnknownTraitMethod {
+        location: Location,
+        name: GlobalStr,
+        trait_name: GlobalStr,
+    },
+    #[error("{location}: `{name}` is not an associated const of the trait `{trait_name}`")]
+    UnknownTraitConst {
+        location: Location,
+        name: GlobalStr,
+        trait_name: GlobalStr,
+    },
+    #[error("{location}: missing associated const `{name}` of the trait `{trait_name}`")]
+    MissingTraitConst {
+        location: Location,
+        name: GlobalStr,
+        trait_name: GlobalStr,
+    },
+    #[error("{location}: `Self` can only be used in the methods of a struct")]
+    SelfOutsideImpl { location: Location },
+    #[error("{location}: conflicting implementations of the trait `{trait_name}`")]
+    ConflictingImpl {
+        location: Location,
+        trait_name: GlobalStr,
+    },
+    #[error(
+        "{location}: cannot derive `{trait_name}`, the field `{field_name}` doesn't support it"
+    )]
+    CannotDerive {
+        location: Location,
+        trait_name: GlobalStr,
+        field_name: GlobalStr,
+    },
+    #[error("{location}: missing method `{name}` of the trait `{trait_name}`")]
+    MissingTraitMethod {
+        location: Location,
+        name: GlobalStr,
+        trait_name: GlobalStr,
+    },
     #[error("{0}: Type {1} is expected to implement the traits {2:?}")]
     MismatchingTraits(Location, Type, Vec<GlobalStr>),
-    #[error("{location}: Expected {}, but found {}", FunctionList(.expected), FunctionList(.found))]
-    MismatchingArguments {
+    #[error(
+        "{location}: The trait declares `{name}` as {} -> {expected_return}, but it is implemented as {} -> {found_return}",
+        FunctionList(.expected),
+        FunctionList(.found)
+    )]
+    TraitMethodSignatureMismatch {
         location: Location,
+        name: GlobalStr,
         expected: Vec<Type>,
+        expected_return: Type,
         found: Vec<Type>,
+        found_return: Type,
     },
-    #[error("{location}: Expected fn(...) -> {expected} but fund fn(...) -> {found}")]
-    MismatchingReturnType {
+}
+
+impl TypecheckingError {
+    /// The location the error occurred at. Lang item errors are about the whole program and
+    /// have no location.
+    pub fn get_loc(&self) -> Option<&Location> {
+        match self {
+            Self::InvalidExternReturnType(location, ..)
+            | Self::UnsizedReturnType(location, ..)
+            | Self::UnsizedArgument(location, ..)
+            | Self::InvalidDynTypeFunc(location, ..)
+            | Self::CannotFindTrait(location, ..)
+            | Self::MismatchingGenericCount(location, ..)
+            | Self::NonSizedType(location, ..)
+            | Self::IntOnlyIntrinsic(location, ..)
+            | Self::AsmNonNumericTypeResolved(location, ..)
+            | Self::AsmNonNumericType(location, ..)
+            | Self::TupleIndexOutOfBounds(location, ..)
+            | Self::SliceOutOfBounds(location, ..)
+            | Self::InvertedSliceRange(location, ..)
+            | Self::TupleDynamicIndex(location, ..)
+            | Self::CannotInferAnonStructType(location, ..)
+            | Self::CannotInferGeneric(location, ..)
+            | Self::StaticsNeedToBeLiteral(location, ..)
+            | Self::CannotInferArrayType(location, ..)
+            | Self::NonMemberFunction(location, ..)
+            | Self::CannotFindFunctionOnType(location, ..)
+            | Self::CannotFindValue(location, ..)
+            | Self::AccessNonStructValue(location, ..)
+            | Self::IndexNonArrayElem(location, ..)
+            | Self::SliceNonArray(location, ..)
+            | Self::FieldNotFound(location, ..)
+            | Self::InvalidCast(location, ..)
+            | Self::CannotAssign(location, ..)
+            | Self::CannotShiftByNonUInt(location, ..)
+            | Self::CannotAdd(location, ..)
+            | Self::CannotSub(location, ..)
+            | Self::CannotMul(location, ..)
+            | Self::CannotDiv(location, ..)
+            | Self::CannotMod(location, ..)
+            | Self::DivisionByZero(location, ..)
+            | Self::CannotBAnd(location, ..)
+            | Self::CannotBOr(location, ..)
+            | Self::CannotBXor(location, ..)
+            | Self::CannotLAnd(location, ..)
+            | Self::CannotLOr(location, ..)
+            | Self::CannotCompare(location, ..)
+            | Self::TypeNotComparable(location, ..)
+            | Self::CannotShl(location, ..)
+            | Self::CannotShr(location, ..)
+            | Self::LhsNotRhs(location, ..)
+            | Self::CannotNeg(location, ..)
+            | Self::CannotPos(location, ..)
+            | Self::CannotLNot(location, ..)
+            | Self::CannotBNot(location, ..)
+            | Self::CannotDeref(location, ..)
+            | Self::MismatchingTraits(location, ..)
+            | Self::ExportNotFound { location, .. }
+            | Self::CyclicDependency { location, .. }
+            | Self::ImportCycle { location, .. }
+            | Self::UnboundIdent { location, .. }
+            | Self::UnsizedValue { location, .. }
+            | Self::LiteralOutOfRange { location, .. }
+            | Self::TransmuteSizeMismatch { location, .. }
+            | Self::MissingLangItem { location, .. }
+            | Self::AssignToImmutable { location, .. }
+            | Self::MismatchingScopeType { location, .. }
+            | Self::RecursiveTypeDetected { location, .. }
+            | Self::BodyDoesNotAlwaysReturn { location, .. }
+            | Self::MismatchingType { location, .. }
+            | Self::ExpectedReference { location, .. }
+            | Self::UnsupportedFloatWidth { location, .. }
+            | Self::GenericFunctionPointer { location, .. }
+            | Self::IdentifierIsNotStruct { location, .. }
+            | Self::NoSuchFieldFound { location, .. }
+            | Self::MissingField { location, .. }
+            | Self::TypeIsNotAFunction { location, .. }
+            | Self::MissingArguments { location, .. }
+            | Self::TooManyArguments { location, .. }
+            | Self::UnexpectedGenerics { location, .. }
+            | Self::UnknownTraitMethod { location, .. }
+            | Self::UnknownTraitConst { location, .. }
+            | Self::MissingTraitConst { location, .. }
+            | Self::MissingTraitMethod { location, .. }
+            | Self::ConflictingImpl { location, .. }
+            | Self::CannotDerive { location, .. }
+            | Self::SelfOutsideImpl { location }
+            | Self::NonConstOperation { location }
+            | Self::StaticInConstExpression { location }
+            | Self::ConstEvaluation { location, .. }
+            | Self::TraitMethodSignatureMismatch { location, .. } => Some(location),
+            Self::LangItemAssignment(
+                LangItemAssignmentError::InvalidLangItemError { loc, .. }
+                | LangItemAssignmentError::Redefinition(_, loc),
+            ) => Some(loc),
+            Self::LangItemError(_) => None,
+        }
+    }
+
+    /// Sorts `errors` into source order. Errors without a location come first, and errors at the
+    /// same location keep their order.
+    pub fn sort(errors: &mut [Self]) {
+        errors.sort_by(|a, b| a.get_loc().cmp(&b.get_loc()));
+    }
+}
+
+/// Non-fatal diagnostics. These are reported separately from [`TypecheckingError`]s and never
+/// fail the compilation.
+#[derive(Clone, Debug, Error)]
+pub enum TypecheckingWarning {
+    #[error("{location}: unused import `{name}`")]
+    UnusedImport { location: Location, name: GlobalStr },
+    #[error("{location}: unreachable code")]
+    UnreachableCode { location: Location },
+    #[error("{location}: `{name}` shadows the variable declared at {previous_location} in the same scope")]
+    Shadowed {
         location: Location,
-        expected: Type,
-        found: Type,
+        name: GlobalStr,
+        previous_location: Location,
     },
 }
@@ -30,6 +30,8 @@ pub enum TypedLiteral {
     Struct(StructId, Vec<TypedLiteral>),
     Tuple(Vec<TypedLiteral>),
     F64(f64),
+    /// stored as an f32, as rust has no stable f16 type
+    F16(f32),
     F32(f32),
     U8(u8),
     U16(u16),
@@ -46,6 +48,39 @@ pub enum TypedLiteral {
 }
 
 impl TypedLiteral {
+    /// The literal `0` of the integer type `typ`, or None if `typ` is not an integer
+    pub fn int_zero(typ: &Type) -> Option<Self> {
+        Some(match typ {
+            Type::PrimitiveU8(0) => Self::U8(0),
+            Type::PrimitiveU16(0) => Self::U16(0),
+            Type::PrimitiveU32(0) => Self::U32(0),
+            Type::PrimitiveU64(0) => Self::U64(0),
+            Type::PrimitiveUSize(0) => Self::USize(0),
+            Type::PrimitiveI8(0) => Self::I8(0),
+            Type::PrimitiveI16(0) => Self::I16(0),
+            Type::PrimitiveI32(0) => Self::I32(0),
+            Type::PrimitiveI64(0) => Self::I64(0),
+            Type::PrimitiveISize(0) => Self::ISize(0),
+            _ => return None,
+        })
+    }
+
+    pub fn is_int_zero(&self) -> bool {
+        matches!(
+            self,
+            Self::U8(0)
+                | Self::U16(0)
+                | Self::U32(0)
+                | Self::U64(0)
+                | Self::USize(0)
+                | Self::I8(0)
+                | Self::I16(0)
+                | Self::I32(0)
+                | Self::I64(0)
+                | Self::ISize(0)
+        )
+    }
+
     pub fn to_type<'a>(
         &self,
         scope: &'a [(Type, ScopeTypeMetadata)],
@@ -78,6 +113,7 @@ impl TypedLiteral {
             TypedLiteral::Struct(struct_id, _) => Cow::Owned(Type::Struct {
                 struct_id: *struct_id,
                 name: ctx.structs.read()[*struct_id].name.clone(),
+                generics: Vec::new(),
                 num_references: 0,
             }),
             TypedLiteral::Tuple(elems) => Cow::Owned(Type::Tuple {
@@ -88,6 +124,7 @@ impl TypedLiteral {
                 num_references: 0,
             }),
             TypedLiteral::F64(_) => Cow::Owned(Type::PrimitiveF64(0)),
+            TypedLiteral::F16(_) => Cow::Owned(Type::PrimitiveF16(0)),
             TypedLiteral::F32(_) => Cow::Owned(Type::PrimitiveF32(0)),
             TypedLiteral::U8(_) => Cow::Owned(Type::PrimitiveU8(0)),
             TypedLiteral::U16(_) => Cow::Owned(Type::PrimitiveU16(0)),
@@ -113,6 +150,7 @@ impl TypedLiteral {
             TypedLiteral::Void => Some(Type::PrimitiveVoid(0)),
             TypedLiteral::String(_) => Some(Type::PrimitiveStr(1)),
             TypedLiteral::F64(_) => Some(Type::PrimitiveF64(0)),
+            TypedLiteral::F16(_) => Some(Type::PrimitiveF16(0)),
             TypedLiteral::F32(_) => Some(Type::PrimitiveF32(0)),
             TypedLiteral::U8(_) => Some(Type::PrimitiveU8(0)),
             TypedLiteral::U16(_) => Some(Type::PrimitiveU16(0)),
@@ -158,6 +196,7 @@ impl TypedLiteral {
             | TypedLiteral::ExternalFunction(_)
             | TypedLiteral::Void
             | TypedLiteral::F64(_)
+            | TypedLiteral::F16(_)
             | TypedLiteral::F32(_)
             | TypedLiteral::U8(_)
             | TypedLiteral::U16(_)
@@ -193,6 +232,12 @@ impl Display for OffsetValue {
     }
 }
 
+/// An instruction of a typechecked function body. Every node (except for the [`None`]
+/// placeholder) keeps the location of the source construct it was lowered from, which codegen
+/// turns into debug locations and which runtime checks like [`BoundsCheck`] report.
+///
+/// [`None`]: TypecheckedExpression::None
+/// [`BoundsCheck`]: TypecheckedExpression::BoundsCheck
 #[derive(Debug)]
 pub enum TypecheckedExpression {
     Return(Location, TypedLiteral),
@@ -303,6 +348,9 @@ pub enum TypecheckedExpression {
     // e.g. on a struct { a: i32, b: i32 }, a `.a` will be turned into 0 and a `.b` into a 1.
     // _1 = _2.a.b.c.d
     OffsetNonPointer(Location, ScopeValueId, TypedLiteral, usize),
+    // _1: &[T; N] or &[T]
+    // bounds_check(_1, _2); traps if _2 is not smaller than the length of _1
+    BoundsCheck(Location, TypedLiteral, OffsetValue),
     // Eq::val(&dyn Eq, ...)
     // The last value is the offset into the function pointer part of the vtable.
     DynCall(Location, ScopeValueId, Vec<TypedLiteral>, u32),
@@ -334,6 +382,9 @@ pub enum TypecheckedExpression {
     // _2: &[_; _3]
     // let _1 = attach_metadata(_2, _3)
     MakeUnsizedSlice(Location, ScopeValueId, TypedLiteral, usize),
+    // _2: &T, _3: usize
+    // let _1 = attach_size_metadata(_2, _3)
+    MakeSlice(Location, ScopeValueId, TypedLiteral, TypedLiteral),
     // _2: &<value>
     // let _1 = attach_vtable(_2, trait_1, trait_2)
     AttachVtable(Location, ScopeValueId, TypedLiteral, (Type, Vec<TraitId>)),
@@ -355,7 +406,9 @@ impl TypecheckedExpression {
             | TypecheckedExpression::DynCall(location, ..)
             | TypecheckedExpression::StoreAssignment(location, ..)
             | TypecheckedExpression::OffsetNonPointer(location, ..)
+            | TypecheckedExpression::BoundsCheck(location, ..)
             | TypecheckedExpression::MakeUnsizedSlice(location, ..)
+            | TypecheckedExpression::MakeSlice(location, ..)
             | TypecheckedExpression::StripMetadata(location, ..)
             | TypecheckedExpression::Bitcast(location, ..)
             | TypecheckedExpression::IntCast(location, ..)
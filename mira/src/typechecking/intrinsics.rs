@@ -7,7 +7,7 @@ use crate::error::ParsingError;
 use crate::tokenizer::{Literal, Location, Token, TokenType};
 use crate::tokenstream::TokenStream;
 
-use super::{Type, TypecheckingContext, TypecheckingError};
+use super::{types::FunctionType, Type, TypecheckingContext, TypecheckingError};
 
 macro_rules! intrinsics {
     ($($name:ident => $value:ident),* $(,)? ) => {
@@ -58,8 +58,18 @@ intrinsics! {
     // that was previously there
     ReturnAddress => return_address, // (level: i32) -> usize, returns the address a "return" would
     Select => select, // <T>(cond: bool, a: T, b: T) -> T, equivalent to cond ? a : b
-    VolatileRead => volatile_reade, // <T>(ptr: &T) -> T
+    VolatileRead => volatile_read, // <T>(ptr: &T) -> T
     VolatileWrite => volatile_write, // <T>(ptr: &T, val: T);
+    PtrAdd => ptr_add, // <T>(ptr: &T, n: usize) -> &T, advances ptr by n elements
+    PtrOffset => ptr_offset, // <T>(ptr: &T, n: isize) -> &T, moves ptr by n elements, backwards if
+    // n is negative
+    Null => null, // <T>() -> &T, returns a null reference, T has to be a reference
+    IsNull => is_null, // <T>(ptr: &T) -> bool, returns if ptr is a null reference
+    Assert => assert, // (cond: bool), calls the panic lang item if cond is false
+    DebugAssert => debug_assert, // (cond: bool), like assert, but compiles to nothing in release
+    // builds
+    Transmute => transmute, // <A, B>(v: A) -> B, reinterprets the bytes of v as a B. A and B
+    // have to be of the same size
     // jump to
     // ### INTEGER INTRINSICS ###
     // The following are *only* valid for ints
@@ -92,7 +102,10 @@ impl Intrinsic {
             | Intrinsic::Trap
             | Intrinsic::Location
             | Intrinsic::Unreachable
-            | Intrinsic::ReturnAddress => 0,
+            | Intrinsic::ReturnAddress
+            | Intrinsic::Assert
+            | Intrinsic::DebugAssert => 0,
+            Intrinsic::Transmute => 2,
             _ => 1,
         }
     }
@@ -118,10 +131,16 @@ impl Intrinsic {
             | Intrinsic::Select
             | Intrinsic::VolatileRead
             | Intrinsic::VolatileWrite
+            | Intrinsic::PtrAdd
+            | Intrinsic::PtrOffset
             | Intrinsic::Forget => generics[0]
                 .is_sized()
                 .then_some(())
                 .ok_or_else(|| TypecheckingError::NonSizedType(loc, generics[0].clone())),
+            Intrinsic::Transmute => match generics.iter().find(|v| !v.is_sized()) {
+                Some(typ) => Err(TypecheckingError::NonSizedType(loc, typ.clone())),
+                None => Ok(()),
+            },
             // ----------------------
             // - integer intrinsics -
             // ----------------------
@@ -155,7 +174,9 @@ impl Intrinsic {
             | Intrinsic::Breakpoint
             | Intrinsic::Trap
             | Intrinsic::Location
-            | Intrinsic::ReturnAddress => Ok(()),
+            | Intrinsic::ReturnAddress
+            | Intrinsic::Assert
+            | Intrinsic::DebugAssert => Ok(()),
             // ------------------------
             // - all types intrinsics -
             // ------------------------
@@ -164,9 +185,180 @@ impl Intrinsic {
             | Intrinsic::Offset
             | Intrinsic::GetMetadata
             | Intrinsic::WithMetadata
-            | Intrinsic::TypeName => Ok(()),
+            | Intrinsic::TypeName
+            | Intrinsic::Null
+            | Intrinsic::IsNull => Ok(()),
         }
     }
+
+    /// Checks the signature an intrinsic was declared with at a call to it, for the intrinsics
+    /// whose requirements can't be expressed through their declaration.
+    pub fn check_call(
+        &self,
+        context: &TypecheckingContext,
+        loc: &Location,
+        function_type: &FunctionType,
+    ) -> Result<(), TypecheckingError> {
+        match self {
+            Intrinsic::Transmute => {
+                let Some(from) = function_type.arguments.first() else {
+                    return Err(TypecheckingError::MissingArguments {
+                        location: loc.clone(),
+                    });
+                };
+                transmute(context, loc, from, &function_type.return_type)
+            }
+            Intrinsic::VolatileRead | Intrinsic::VolatileWrite => {
+                let Some(ptr) = function_type.arguments.first() else {
+                    return Err(TypecheckingError::MissingArguments {
+                        location: loc.clone(),
+                    });
+                };
+                let pointee =
+                    ptr.clone()
+                        .deref()
+                        .map_err(|ptr| TypecheckingError::ExpectedReference {
+                            expected: ptr.clone().take_ref(),
+                            found: ptr,
+                            location: loc.clone(),
+                        })?;
+                // the value that is read or written has to be the type the pointer points to
+                let value = match self {
+                    Intrinsic::VolatileRead => &function_type.return_type,
+                    _ => function_type.arguments.get(1).ok_or_else(|| {
+                        TypecheckingError::MissingArguments {
+                            location: loc.clone(),
+                        }
+                    })?,
+                };
+                if *value != pointee {
+                    return Err(TypecheckingError::MismatchingType {
+                        expected: pointee,
+                        found: value.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                Ok(())
+            }
+            Intrinsic::PtrAdd | Intrinsic::PtrOffset => {
+                let [ptr, offset, ..] = &function_type.arguments[..] else {
+                    return Err(TypecheckingError::MissingArguments {
+                        location: loc.clone(),
+                    });
+                };
+                let element =
+                    ptr.clone()
+                        .deref()
+                        .map_err(|ptr| TypecheckingError::ExpectedReference {
+                            expected: ptr.clone().take_ref(),
+                            found: ptr,
+                            location: loc.clone(),
+                        })?;
+                self.is_valid_for(loc.clone(), &[element])?;
+                // the offset is extended to an isize during codegen, which has to know whether to
+                // sign extend it
+                let (valid_offset, expected) = match self {
+                    Intrinsic::PtrAdd => (offset.is_unsigned(), Type::PrimitiveUSize(0)),
+                    _ => (offset.is_signed(), Type::PrimitiveISize(0)),
+                };
+                if !valid_offset {
+                    return Err(TypecheckingError::MismatchingType {
+                        expected,
+                        found: offset.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                if function_type.return_type != *ptr {
+                    return Err(TypecheckingError::MismatchingType {
+                        expected: ptr.clone(),
+                        found: function_type.return_type.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                Ok(())
+            }
+            Intrinsic::Null => {
+                let typ = &function_type.return_type;
+                if typ.refcount() < 1 {
+                    return Err(TypecheckingError::ExpectedReference {
+                        expected: typ.clone().take_ref(),
+                        found: typ.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                Ok(())
+            }
+            Intrinsic::IsNull => {
+                let Some(ptr) = function_type.arguments.first() else {
+                    return Err(TypecheckingError::MissingArguments {
+                        location: loc.clone(),
+                    });
+                };
+                if ptr.refcount() < 1 {
+                    return Err(TypecheckingError::ExpectedReference {
+                        expected: ptr.clone().take_ref(),
+                        found: ptr.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                if !function_type.return_type.is_bool() {
+                    return Err(TypecheckingError::MismatchingType {
+                        expected: Type::PrimitiveBool(0),
+                        found: function_type.return_type.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                Ok(())
+            }
+            Intrinsic::Assert | Intrinsic::DebugAssert => {
+                let Some(cond) = function_type.arguments.first() else {
+                    return Err(TypecheckingError::MissingArguments {
+                        location: loc.clone(),
+                    });
+                };
+                if !cond.is_bool() {
+                    return Err(TypecheckingError::MismatchingType {
+                        expected: Type::PrimitiveBool(0),
+                        found: cond.clone(),
+                        location: loc.clone(),
+                    });
+                }
+                // a failing assertion calls the `panic` lang item, even if a debug_assert might
+                // end up being compiled out
+                if context.lang_items.read().panic.is_none() {
+                    return Err(TypecheckingError::MissingLangItem {
+                        location: loc.clone(),
+                        name: "panic",
+                    });
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that a value of type `from` can be transmuted into a `to`, which requires both types to
+/// have the same size on the target that is being compiled for.
+pub fn transmute(
+    context: &TypecheckingContext,
+    loc: &Location,
+    from: &Type,
+    to: &Type,
+) -> Result<(), TypecheckingError> {
+    Intrinsic::Transmute.is_valid_for(loc.clone(), &[from.clone(), to.clone()])?;
+    let (from_size, _) = context.size_and_alignment(from);
+    let (to_size, _) = context.size_and_alignment(to);
+    if from_size != to_size {
+        return Err(TypecheckingError::TransmuteSizeMismatch {
+            location: loc.clone(),
+            from: from.clone(),
+            from_size,
+            to: to.clone(),
+            to_size,
+        });
+    }
+    Ok(())
 }
 
 #[repr(transparent)]
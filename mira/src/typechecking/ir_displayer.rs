@@ -4,7 +4,10 @@ use super::{
     expression::{TypecheckedExpression, TypedLiteral},
     Type, TypecheckedFunctionContract, TypecheckedModule, TypecheckingContext,
 };
-use std::fmt::{Debug, Display, Formatter, Result, Write};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter, Result, Write},
+};
 
 macro_rules! display_to_debug {
     ($t:ty) => {
@@ -29,6 +32,22 @@ struct FuncDisplay<'a>(&'a TypecheckedFunctionContract);
 #[repr(transparent)]
 struct ArgList<'a>(&'a [(GlobalStr, Type)]);
 //struct Display<'a>(&'a TypecheckingContext);
+/// Formats a map sorted by its keys, as the iteration order of a `HashMap` isn't deterministic
+struct SortedMap<'a, K, V>(Vec<(&'a K, &'a V)>);
+
+impl<'a, K, V> SortedMap<'a, K, V> {
+    fn new<S: Ord>(map: &'a HashMap<K, V>, key: impl FnMut(&(&'a K, &'a V)) -> S) -> Self {
+        let mut entries = map.iter().collect::<Vec<_>>();
+        entries.sort_by_key(key);
+        Self(entries)
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SortedMap<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map().entries(self.0.iter().copied()).finish()
+    }
+}
 
 macro_rules! format_tlds {
     ($f:ident $fmt:tt, $first: expr, $($value:expr),* $(,)?) => {
@@ -66,8 +85,14 @@ impl Display for TCContextDisplay<'_> {
             f.debug_struct("struct")
                 .field("name", &structure.name)
                 .field("annotations", &structure.annotations)
-                .field("global_impl", &structure.global_impl)
-                .field("trait_impl", &structure.trait_impl)
+                .field(
+                    "global_impl",
+                    &SortedMap::new(&structure.global_impl, |(k, _)| k.to_string()),
+                )
+                .field(
+                    "trait_impl",
+                    &SortedMap::new(&structure.trait_impl, |(k, _)| **k),
+                )
                 .field("elements", &structure.elements)
                 .field("module_id", &structure.module_id)
                 .finish()?;
@@ -113,8 +138,11 @@ impl Display for ArgList<'_> {
 impl Display for ModuleDisplay<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         let mut dbg_struct = f.debug_struct("mod ");
-        dbg_struct.field("%exports", &self.0.exports);
-        for (k, v) in self.0.scope.iter() {
+        dbg_struct.field(
+            "%exports",
+            &SortedMap::new(&self.0.exports, |(k, _)| k.to_string()),
+        );
+        for (k, v) in SortedMap::new(&self.0.scope, |(k, _)| k.to_string()).0 {
             k.with(|k| _ = dbg_struct.field(k, v));
         }
         dbg_struct.finish()
@@ -343,6 +371,9 @@ impl Display for ExpressionDisplay<'_> {
             TypecheckedExpression::OffsetNonPointer(_, lhs, rhs, offset_value) => f.write_fmt(
                 format_args!("_{} = offset_non_ptr({}, {offset_value})", lhs, TLD(rhs)),
             ),
+            TypecheckedExpression::BoundsCheck(_, array, offset) => {
+                f.write_fmt(format_args!("bounds_check({}, {offset})", TLD(array)))
+            }
             TypecheckedExpression::DynCall(_, dst, args, offset) => {
                 f.write_char('_')?;
                 Display::fmt(dst, f)?;
@@ -371,6 +402,9 @@ impl Display for ExpressionDisplay<'_> {
             TypecheckedExpression::MakeUnsizedSlice(_, lhs, rhs, size) => f.write_fmt(
                 format_args!("_{lhs} = attach_size_metadata({}, {size})", TLD(rhs)),
             ),
+            TypecheckedExpression::MakeSlice(_, dst, ptr, size) => {
+                format_tlds!(f "_{} = attach_size_metadata({}, {})", dst, ptr, size)
+            }
             TypecheckedExpression::Empty(_) => f.write_str("<removed>"),
             TypecheckedExpression::None => f.write_str("<none>"),
         }
@@ -398,6 +432,7 @@ impl Display for TLD<'_> {
                 f.debug_list().entries(vec.iter().map(TLD)).finish()
             }
             TypedLiteral::F64(v) => Display::fmt(v, f),
+            TypedLiteral::F16(v) => Display::fmt(v, f),
             TypedLiteral::F32(v) => Display::fmt(v, f),
             TypedLiteral::U8(v) => Display::fmt(v, f),
             TypedLiteral::U16(v) => Display::fmt(v, f),
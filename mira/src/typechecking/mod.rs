@@ -1,10 +1,13 @@
-use parking_lot::RwLock;
+use parking_lot::{RwLock, RwLockReadGuard};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
-    hash::{Hash, Hasher},
-    path::{Path, PathBuf},
-    sync::{Arc, LazyLock},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
 };
 
 use expression::{TypecheckedExpression, TypedLiteral};
@@ -14,11 +17,15 @@ use crate::{
     annotations::Annotations,
     globals::GlobalStr,
     lang_items::LangItems,
-    module::{FunctionId, ModuleContext, ModuleId, ModuleScopeValue, StructId, TraitId},
-    parser::TypeRef,
+    module::{
+        ContextStats, FunctionId, ModuleContext, ModuleId, ModuleScopeValue, StructId, TraitId,
+    },
+    parser::{ArrayLength, TypeRef},
+    target::Target,
     tokenizer::Location,
 };
 
+mod const_eval;
 mod error;
 pub mod expression;
 pub mod intrinsics;
@@ -26,14 +33,13 @@ pub mod ir_displayer;
 mod type_resolution;
 pub mod typechecking;
 mod types;
-pub use error::TypecheckingError;
+pub mod visitor;
+mod vtable;
+pub use error::{TypecheckingError, TypecheckingWarning};
 pub use types::Type;
+pub use vtable::VTable;
 
-pub static DUMMY_LOCATION: LazyLock<Location> = LazyLock::new(|| Location {
-    line: 0,
-    column: 0,
-    file: PathBuf::from("").into(), // a file should never be a folder :3
-});
+pub static DUMMY_LOCATION: LazyLock<Location> = LazyLock::new(Location::dummy);
 
 #[derive(Debug)]
 pub struct TypecheckedFunctionContract {
@@ -67,6 +73,7 @@ pub struct TypedTrait {
         Annotations,
         Location,
     )>,
+    pub consts: Vec<(GlobalStr, Type, Location)>,
     pub location: Location,
     pub module_id: ModuleId,
     pub id: TraitId,
@@ -80,6 +87,8 @@ pub struct TypedStruct {
     pub location: Location,
     pub global_impl: HashMap<GlobalStr, FunctionId>,
     pub trait_impl: HashMap<TraitId, Vec<FunctionId>>,
+    /// the associated consts of each implemented trait
+    pub trait_consts: HashMap<TraitId, Vec<(GlobalStr, Type, TypedLiteral)>>,
     pub annotations: Annotations,
     pub module_id: ModuleId,
     pub id: StructId,
@@ -94,10 +103,56 @@ impl Hash for TypedStruct {
     }
 }
 
+impl TypedStruct {
+    /// Hashes the shape of the struct (its name and elements) without the module or location it
+    /// was defined in, so structurally identical structs from different modules hash the same.
+    /// Use this for deduplication keys (e.g. generic instantiations); the `Hash` impl stays an
+    /// identity hash.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.name.hash(&mut hasher);
+        self.elements.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The type of `Self` in the methods of this struct, where every generic of the struct stands
+    /// in for itself (`Foo<T>` for `struct Foo<T>`)
+    pub fn self_type(&self) -> Type {
+        Type::Struct {
+            struct_id: self.id,
+            name: self.name.clone(),
+            generics: self.generic_params(),
+            num_references: 0,
+        }
+    }
+
+    /// The generics of this struct, each standing in for itself
+    pub fn generic_params(&self) -> Vec<Type> {
+        self.generics
+            .iter()
+            .map(|(name, _)| Type::Generic(name.clone(), 0))
+            .collect()
+    }
+
+    /// Replaces the generics of this struct in `typ`, the type of one of its fields or methods,
+    /// with `generics`, the type arguments of a value of this struct (`i32` for `Foo<i32>`)
+    pub fn substitute_generics(&self, typ: &mut Type, generics: &[Type]) {
+        if self.generics.is_empty() || self.generics.len() != generics.len() {
+            return;
+        }
+        let names = self
+            .generics
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        typ.substitute_generics(&names, generics);
+    }
+}
+
 #[derive(Debug)]
 pub struct TypecheckingContext {
     pub modules: RwLock<Vec<TypecheckedModule>>,
-    pub functions: RwLock<Vec<(TypecheckedFunctionContract, Box<[TypecheckedExpression]>)>>,
+    pub functions: RwLock<TypecheckedFunctions>,
     pub external_functions: RwLock<
         Vec<(
             TypecheckedFunctionContract,
@@ -114,13 +169,71 @@ pub struct TypecheckingContext {
         )>,
     >,
     pub structs: RwLock<Vec<TypedStruct>>,
+    /// how far the resolution of each struct in `structs` got
+    struct_resolution: RwLock<Vec<ResolutionState>>,
     pub traits: RwLock<Vec<TypedTrait>>,
     pub lang_items: RwLock<LangItems>,
+    pub target: Target,
+    /// imports that were already resolved, keyed by the module they were resolved in. The module
+    /// context doesn't change anymore once the typechecking context was created, so entries never
+    /// have to be invalidated.
+    import_cache: RwLock<HashMap<(ModuleId, Box<[GlobalStr]>), ModuleScopeValue>>,
+    /// the names that were looked up in the scope of a module, used to find unused imports
+    used_names: RwLock<HashSet<(ModuleId, GlobalStr)>>,
+    /// the types of the values of every typechecked `const fn`, which evaluating calls to them
+    /// needs for casts
+    const_fn_scopes: RwLock<HashMap<FunctionId, Box<[Type]>>>,
+    /// if the bodies of all `const fn`s are typechecked, array lengths can only call them after
+    /// that, see [`typechecking::typecheck_functions`]
+    const_fns_typechecked: AtomicBool,
+}
+
+/// The state of the type resolution of a struct, see [`TypecheckingContext::resolve_struct`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionState {
+    Unresolved,
+    /// the fields of the struct are being resolved, encountering the struct again means it
+    /// contains itself
+    InProgress,
+    Resolved,
+}
+
+type TypecheckedFunctions = Vec<(TypecheckedFunctionContract, Box<[TypecheckedExpression]>)>;
+
+/// A read guard on the functions of a [`TypecheckingContext`], see
+/// [`TypecheckingContext::functions_iter`]. It isn't an iterator itself, iterate over it through
+/// [`FunctionsGuard::iter`] or a reference to it. Holds the read lock on the functions until it is
+/// dropped.
+pub struct FunctionsGuard<'a>(RwLockReadGuard<'a, TypecheckedFunctions>);
+
+impl FunctionsGuard<'_> {
+    pub fn iter(&self) -> impl Iterator<Item = (FunctionId, &TypecheckedFunctionContract)> {
+        self.0.iter().map(|(contract, _)| contract).enumerate()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a FunctionsGuard<'_> {
+    type Item = (FunctionId, &'a TypecheckedFunctionContract);
+    type IntoIter = Box<dyn Iterator<Item = Self::Item> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
 }
 
 pub struct TypecheckedModule {
     scope: HashMap<GlobalStr, ModuleScopeValue>,
     exports: HashMap<GlobalStr, GlobalStr>,
+    /// the modules this module imports from
+    pub dependencies: HashSet<ModuleId>,
     pub path: Arc<Path>,
     pub root: Arc<Path>,
     pub assembly: Vec<(Location, String)>,
@@ -135,7 +248,7 @@ impl Debug for TypecheckedModule {
 }
 
 impl TypecheckingContext {
-    pub fn new(context: Arc<ModuleContext>) -> Arc<Self> {
+    pub fn new(context: Arc<ModuleContext>, target: Target) -> Arc<Self> {
         let modules = RwLock::new(Vec::new());
         let traits_reader = context.traits.read();
         let structs_reader = context.structs.read();
@@ -161,6 +274,7 @@ impl TypecheckingContext {
                 location: DUMMY_LOCATION.clone(),
                 global_impl: HashMap::new(),
                 trait_impl: HashMap::new(),
+                trait_consts: HashMap::new(),
                 annotations: Annotations::default(),
                 module_id: 0,
                 generics: Vec::new(),
@@ -210,6 +324,7 @@ impl TypecheckingContext {
             traits.push(TypedTrait {
                 name: GlobalStr::ZERO,
                 functions: Vec::new(),
+                consts: Vec::new(),
                 location: DUMMY_LOCATION.clone(),
                 module_id: 0,
                 id: 0,
@@ -219,12 +334,18 @@ impl TypecheckingContext {
 
         let me = Arc::new(Self {
             structs: structs.into(),
+            struct_resolution: RwLock::new(vec![ResolutionState::Unresolved; num_structs]),
             statics: statics.into(),
             functions: functions.into(),
             traits: traits.into(),
             external_functions: external_functions.into(),
             modules,
             lang_items: RwLock::new(LangItems::default()),
+            target,
+            import_cache: RwLock::new(HashMap::new()),
+            used_names: RwLock::new(HashSet::new()),
+            const_fn_scopes: RwLock::new(HashMap::new()),
+            const_fns_typechecked: AtomicBool::new(false),
         });
 
         let mut typechecked_module_writer = me.modules.write();
@@ -233,9 +354,17 @@ impl TypecheckingContext {
         for module_id in 0..module_writer.len() {
             let scope = module_writer[module_id].scope.clone();
 
+            let dependencies = module_writer[module_id]
+                .imports
+                .values()
+                .map(|(_, dependency, _)| *dependency)
+                .filter(|dependency| *dependency != module_id)
+                .collect();
+
             typechecked_module_writer.push(TypecheckedModule {
                 scope,
                 exports: module_writer[module_id].exports.clone(),
+                dependencies,
                 path: module_writer[module_id].path.clone(),
                 root: module_writer[module_id].root.clone(),
                 assembly: std::mem::take(&mut module_writer[module_id].assembly),
@@ -248,6 +377,119 @@ impl TypecheckingContext {
         me
     }
 
+    pub fn stats(&self) -> ContextStats {
+        let (interned_strings, interned_string_bytes) = GlobalStr::interned_totals();
+        ContextStats {
+            modules: self.modules.read().len(),
+            functions: self.functions.read().len(),
+            external_functions: self.external_functions.read().len(),
+            structs: self.structs.read().len(),
+            traits: self.traits.read().len(),
+            statics: self.statics.read().len(),
+            interned_strings,
+            interned_string_bytes,
+        }
+    }
+
+    /// Returns the modules each module imports from, keyed by the id of the importing module.
+    pub fn module_dependencies(&self) -> HashMap<ModuleId, HashSet<ModuleId>> {
+        self.modules
+            .read()
+            .iter()
+            .enumerate()
+            .map(|(id, module)| (id, module.dependencies.clone()))
+            .collect()
+    }
+
+    /// Finds a cycle in the imports between modules. Returns the modules that are part of it, in
+    /// the order they import each other, starting and ending with the same module (e.g. `[a, b,
+    /// a]` if `a` imports `b` and `b` imports `a`).
+    pub fn find_import_cycle(&self) -> Option<Vec<ModuleId>> {
+        let modules = self.modules.read();
+        let mut state = vec![ResolutionState::Unresolved; modules.len()];
+        let mut path = Vec::new();
+
+        fn visit(
+            modules: &[TypecheckedModule],
+            module: ModuleId,
+            state: &mut [ResolutionState],
+            path: &mut Vec<ModuleId>,
+        ) -> bool {
+            match state[module] {
+                ResolutionState::Resolved => return false,
+                ResolutionState::InProgress => {
+                    // the cycle starts where the module was first entered
+                    let start = path.iter().position(|v| *v == module).unwrap();
+                    path.drain(..start);
+                    path.push(module);
+                    return true;
+                }
+                ResolutionState::Unresolved => (),
+            }
+            state[module] = ResolutionState::InProgress;
+            path.push(module);
+            // sorted, so the same cycle is found every time
+            let mut dependencies = modules[module].dependencies.iter().collect::<Vec<_>>();
+            dependencies.sort();
+            for &dependency in dependencies {
+                if visit(modules, dependency, state, path) {
+                    return true;
+                }
+            }
+            path.pop();
+            state[module] = ResolutionState::Resolved;
+            false
+        }
+
+        (0..modules.len())
+            .any(|module| visit(&modules, module, &mut state, &mut path))
+            .then_some(path)
+    }
+
+    /// Fails with an `ImportCycle` error if modules import each other, see
+    /// [`Self::find_import_cycle`]. The error points at the import that starts the cycle.
+    ///
+    /// Cyclic imports are allowed, this is for callers that want to reject them up front.
+    pub fn check_import_cycles(&self, context: &ModuleContext) -> Result<(), TypecheckingError> {
+        let Some(cycle) = self.find_import_cycle() else {
+            return Ok(());
+        };
+        let location = context.modules.read()[cycle[0]]
+            .imports
+            .values()
+            .filter(|(_, module, _)| *module == cycle[1])
+            .map(|(location, ..)| location)
+            .min_by_key(|location| (location.line, location.column))
+            .expect("the module should import the next module of the cycle")
+            .clone();
+        let modules = self.modules.read();
+        Err(TypecheckingError::ImportCycle {
+            location,
+            modules: cycle.iter().map(|&id| modules[id].path.clone()).collect(),
+        })
+    }
+
+    /// Returns the `changed` modules and every module that depends on one of them, directly or
+    /// through other modules. These are the modules that have to be typechecked again after the
+    /// `changed` modules were edited.
+    pub fn invalidated_modules(&self, changed: &[ModuleId]) -> HashSet<ModuleId> {
+        let modules = self.modules.read();
+        let mut invalidated = changed.iter().copied().collect::<HashSet<_>>();
+        // modules only know their dependencies, so this goes over all modules until no new
+        // dependent was found
+        loop {
+            let num_invalidated = invalidated.len();
+            for (id, module) in modules.iter().enumerate() {
+                if module.dependencies.iter().any(|v| invalidated.contains(v)) {
+                    invalidated.insert(id);
+                }
+            }
+            if num_invalidated == invalidated.len() {
+                return invalidated;
+            }
+        }
+    }
+
     pub fn resolve_imports(&self, context: Arc<ModuleContext>) -> Vec<TypecheckingError> {
         let mut errors = vec![];
         let mut typechecked_module_writer = self.modules.write();
@@ -269,9 +511,148 @@ impl TypecheckingContext {
             }
         }
 
+        TypecheckingError::sort(&mut errors);
         errors
     }
 
+    /// Resolves `import` in `module` like [`resolve_import`], but only walks the import graph the
+    /// first time a path is resolved in a module.
+    fn resolve_import_cached(
+        &self,
+        context: &ModuleContext,
+        module: ModuleId,
+        import: &[GlobalStr],
+        location: &Location,
+    ) -> Result<ModuleScopeValue, TypecheckingError> {
+        if let Some(name) = import.first() {
+            self.mark_used(module, name);
+        }
+        if let Some(value) = self.import_cache.read().get(&(module, import.into())) {
+            return Ok(*value);
+        }
+        let value = resolve_import(context, module, import, location, &mut Vec::new())?;
+        self.import_cache
+            .write()
+            .insert((module, import.into()), value);
+        Ok(value)
+    }
+
+    fn mark_used(&self, module: ModuleId, name: &GlobalStr) {
+        let key = (module, name.clone());
+        if !self.used_names.read().contains(&key) {
+            self.used_names.write().insert(key);
+        }
+    }
+
+    /// Returns a warning for every import that was never referred to. This should be ran *after*
+    /// the function bodies were typechecked.
+    pub fn unused_imports(&self, context: &ModuleContext) -> Vec<TypecheckingWarning> {
+        let used_names = self.used_names.read();
+        let mut unused = Vec::new();
+        for (id, module) in context.modules.read().iter().enumerate() {
+            for (name, (location, ..)) in module.imports.iter() {
+                if used_names.contains(&(id, name.clone())) {
+                    continue;
+                }
+                unused.push((location.clone(), name.clone()));
+            }
+        }
+        // the imports are stored in a hashmap, sort them to make the order deterministic
+        unused.sort_by_cached_key(|(location, name)| {
+            (
+                location.file.clone(),
+                location.line,
+                location.column,
+                name.to_string(),
+            )
+        });
+        unused
+            .into_iter()
+            .map(|(location, name)| TypecheckingWarning::UnusedImport { location, name })
+            .collect()
+    }
+
+    /// Returns the contracts of all functions together with their ids. The functions stay
+    /// read-locked for as long as the returned value is alive, so don't hold on to it while
+    /// typechecking function bodies.
+    ///
+    /// ```ignore
+    /// for (id, contract) in &context.functions_iter() { .. }
+    /// ```
+    pub fn functions_iter(&self) -> FunctionsGuard<'_> {
+        FunctionsGuard(self.functions.read())
+    }
+
+    /// Returns the `eq` method of the struct's implementation of `eq_trait`, the `eq_trait` lang
+    /// item, which `==` and `!=` call on values of the struct.
+    pub fn struct_eq_function(
+        &self,
+        struct_id: StructId,
+        eq_trait: Option<TraitId>,
+    ) -> Option<FunctionId> {
+        let eq_trait = eq_trait?;
+        let index = self.traits.read()[eq_trait]
+            .functions
+            .iter()
+            .position(|(name, ..)| *name == "eq")?;
+        self.structs.read()[struct_id]
+            .trait_impl
+            .get(&eq_trait)?
+            .get(index)
+            .copied()
+    }
+
+    /// Whether `==` and `!=` work on values of `typ`: numbers, bools and structs implementing
+    /// `eq_trait`. The lang item is passed in as the lang items may be locked by the caller.
+    pub fn is_comparable(&self, typ: &Type, eq_trait: Option<TraitId>) -> bool {
+        match typ {
+            Type::Struct {
+                struct_id,
+                num_references: 0,
+                ..
+            } => self.struct_eq_function(*struct_id, eq_trait).is_some(),
+            _ => typ.is_int_like() || typ.is_float() || typ.is_bool(),
+        }
+    }
+
+    /// Returns the struct that `name` refers to in the scope of `module`, including imported
+    /// structs once the imports are resolved. Returns `None` if the name is unknown or isn't a
+    /// struct.
+    pub fn lookup_struct_by_name(&self, module: ModuleId, name: &GlobalStr) -> Option<StructId> {
+        match self.modules.read().get(module)?.scope.get(name)? {
+            ModuleScopeValue::Struct(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The size of a pointer, `usize` and `isize` on the target in bytes
+    pub fn ptr_size(&self) -> u64 {
+        self.target.arch.ptr_size()
+    }
+
+    /// The size and alignment of `typ` on the target
+    pub fn size_and_alignment(&self, typ: &Type) -> (u64, u32) {
+        typ.size_and_alignment(self.ptr_size(), &self.structs.read())
+    }
+
+    /// Rejects primitive types the target can't lower
+    pub fn check_primitive_supported(
+        &self,
+        typ: &Type,
+        location: &Location,
+    ) -> Result<(), TypecheckingError> {
+        match typ {
+            Type::PrimitiveF16(_) if !self.target.arch.supports_f16() => {
+                Err(TypecheckingError::UnsupportedFloatWidth {
+                    location: location.clone(),
+                    typ: typ.clone(),
+                    target: self.target,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn resolve_type(
         &self,
         module_id: ModuleId,
@@ -279,6 +660,7 @@ impl TypecheckingContext {
         generics: &[GlobalStr],
     ) -> Result<Type, TypecheckingError> {
         if let Some(primitive) = resolve_primitive_type(typ) {
+            self.check_primitive_supported(&primitive, typ.loc())?;
             return Ok(primitive);
         }
 
@@ -348,20 +730,44 @@ impl TypecheckingContext {
                     .collect::<Vec<_>>();
                 // NOTE: this should only have a generic at the end as this is a type
                 // (std::vec::Vec, can never be std::vec::Vec<u32>::Vec.)
-                for (_, generics) in type_name.entries.iter() {
-                    if generics.len() > 0 {
-                        return Err(TypecheckingError::UnexpectedGenerics {
-                            location: loc.clone(),
-                        });
-                    }
+                let (last, entries) = type_name
+                    .entries
+                    .split_last()
+                    .expect("a type path always has at least one entry");
+                if entries.iter().any(|(_, generics)| generics.len() > 0) {
+                    return Err(TypecheckingError::UnexpectedGenerics {
+                        location: loc.clone(),
+                    });
                 }
 
                 match typed_resolve_import(self, module_id, &path, loc, &mut Vec::new())? {
-                    ModuleScopeValue::Struct(id) => Ok(Type::Struct {
-                        struct_id: id,
-                        name: self.structs.read()[id].name.clone(),
-                        num_references: *num_references,
-                    }),
+                    ModuleScopeValue::Struct(id) => {
+                        let expected_generics = self.structs.read()[id].generics.len();
+                        // a generic struct without type arguments (`Foo` for `Foo<T>`) stays
+                        // generic over all of them
+                        let struct_generics = if last.1.is_empty() {
+                            self.structs.read()[id].generic_params()
+                        } else if last.1.len() != expected_generics {
+                            return Err(TypecheckingError::MismatchingGenericCount(
+                                loc.clone(),
+                                expected_generics,
+                                last.1.len(),
+                            ));
+                        } else {
+                            let mut struct_generics = Vec::with_capacity(last.1.len());
+                            for generic in &last.1 {
+                                struct_generics
+                                    .push(self.resolve_type(module_id, generic, generics)?);
+                            }
+                            struct_generics
+                        };
+                        Ok(Type::Struct {
+                            struct_id: id,
+                            name: self.structs.read()[id].name.clone(),
+                            generics: struct_generics,
+                            num_references: *num_references,
+                        })
+                    }
                     v => Err(TypecheckingError::MismatchingScopeType {
                         location: loc.clone(),
                         expected: ScopeKind::Type,
@@ -369,7 +775,8 @@ impl TypecheckingContext {
                     }),
                 }
             }
-            TypeRef::Void(..) | TypeRef::Never(_) => unreachable!(),
+            TypeRef::Void(_, num_references) => Ok(Type::PrimitiveVoid(*num_references)),
+            TypeRef::Never(_) => Ok(Type::PrimitiveNever),
             TypeRef::UnsizedArray {
                 num_references,
                 child,
@@ -382,11 +789,11 @@ impl TypecheckingContext {
                 num_references,
                 child,
                 number_elements,
-                ..
+                loc,
             } => Ok(Type::SizedArray {
                 typ: Box::new(self.resolve_type(module_id, &**child, generics)?),
                 num_references: *num_references,
-                number_elements: *number_elements,
+                number_elements: self.array_length(module_id, number_elements, loc)?,
             }),
             TypeRef::Tuple {
                 num_references,
@@ -405,6 +812,32 @@ impl TypecheckingContext {
         }
     }
 
+    /// Evaluates the length of a sized array type. Lengths that aren't a literal have to call
+    /// `const fn`s, so they can only be evaluated once all of them are typechecked.
+    fn array_length(
+        &self,
+        module_id: ModuleId,
+        length: &ArrayLength,
+        loc: &Location,
+    ) -> Result<usize, TypecheckingError> {
+        let expr = match length {
+            ArrayLength::Literal(v) => return Ok(*v),
+            ArrayLength::Const(expr) => expr,
+        };
+        if !self.const_fns_typechecked.load(Ordering::Acquire) {
+            return Err(const_array_length_error(loc));
+        }
+        match typechecking::evaluate_const_expression(
+            self,
+            module_id,
+            expr,
+            &Type::PrimitiveUSize(0),
+        )? {
+            TypedLiteral::USize(v) => Ok(v),
+            _ => unreachable!("the length was typechecked to be a usize"),
+        }
+    }
+
     /// returns if a recursive field was detected
     fn resolve_struct(
         &self,
@@ -413,14 +846,16 @@ impl TypecheckingContext {
         module_id: ModuleId,
         errors: &mut Vec<TypecheckingError>,
     ) -> bool {
-        if DUMMY_LOCATION.ne(&self.structs.read()[id].location) {
-            return false;
+        {
+            let mut states = self.struct_resolution.write();
+            match states[id] {
+                ResolutionState::Resolved => return false,
+                ResolutionState::InProgress => return true,
+                ResolutionState::Unresolved => states[id] = ResolutionState::InProgress,
+            }
         }
 
         let mut writer = context.structs.write();
-        if writer[id].location == *DUMMY_LOCATION {
-            return true;
-        }
 
         let global_impl = std::mem::take(&mut writer[id].global_impl);
         let annotations = std::mem::take(&mut writer[id].annotations);
@@ -431,7 +866,7 @@ impl TypecheckingContext {
             let mut bounds = Vec::new();
 
             for (bound, loc) in &generic.bounds {
-                match resolve_import(&context, module_id, &bound.entries, loc, &mut Vec::new()) {
+                match self.resolve_import_cached(&context, module_id, &bound.entries, loc) {
                     Err(e) => errors.push(e),
                     Ok(ModuleScopeValue::Trait(trait_id)) => bounds.push(trait_id),
                     Ok(_) => errors.push(TypecheckingError::UnboundIdent {
@@ -454,6 +889,7 @@ impl TypecheckingContext {
             id,
             generics,
             trait_impl: HashMap::new(),
+            trait_consts: HashMap::new(),
         };
         drop(writer);
 
@@ -484,10 +920,19 @@ impl TypecheckingContext {
                     }
                     t => t,
                 };
+                // `[T]` and `dyn Trait` fields have to be behind a reference
+                if !matches!(typ, Type::Generic(..) | Type::Trait { .. }) && !typ.is_sized() {
+                    errors.push(TypecheckingError::UnsizedValue {
+                        location: element.1.loc().clone(),
+                        typ,
+                    });
+                    continue;
+                }
                 typed_struct.elements.push((element.0, typ));
             }
         }
         self.structs.write()[id] = typed_struct;
+        self.struct_resolution.write()[id] = ResolutionState::Resolved;
 
         false
     }
@@ -500,8 +945,12 @@ impl TypecheckingContext {
         context: Arc<ModuleContext>,
         errors: &mut Vec<TypecheckingError>,
     ) -> Option<Type> {
-        if let Some(typ) = resolve_primitive_type(typ) {
-            return Some(typ);
+        if let Some(primitive) = resolve_primitive_type(typ) {
+            if let Err(e) = self.check_primitive_supported(&primitive, typ.loc()) {
+                errors.push(e);
+                return None;
+            }
+            return Some(primitive);
         }
         match typ {
             TypeRef::DynReference { .. } => todo!(),
@@ -561,8 +1010,7 @@ impl TypecheckingContext {
                     }
                 }
 
-                let Ok(value) = resolve_import(&context, module, &path, loc, &mut Vec::new())
-                else {
+                let Ok(value) = self.resolve_import_cached(&context, module, &path, loc) else {
                     errors.push(TypecheckingError::UnboundIdent {
                         location: loc.clone(),
                         name: path[path.len() - 1].clone(),
@@ -579,15 +1027,14 @@ impl TypecheckingContext {
                     return None;
                 };
 
-                {
+                if self.struct_resolution.read()[id] == ResolutionState::Resolved {
                     let typechecked_struct = &self.structs.read()[id];
-                    if typechecked_struct.location != *DUMMY_LOCATION {
-                        return Some(Type::Struct {
-                            struct_id: typechecked_struct.id,
-                            name: typechecked_struct.name.clone(),
-                            num_references: *num_references,
-                        });
-                    }
+                    return Some(Type::Struct {
+                        struct_id: typechecked_struct.id,
+                        name: typechecked_struct.name.clone(),
+                        generics: Vec::new(),
+                        num_references: *num_references,
+                    });
                 }
 
                 let module = context.structs.read()[id].module_id;
@@ -598,17 +1045,15 @@ impl TypecheckingContext {
                     return None;
                 }
                 let typechecked_struct = &self.structs.read()[id];
-                if typechecked_struct.location != *DUMMY_LOCATION {
-                    return Some(Type::Struct {
-                        struct_id: typechecked_struct.id,
-                        num_references: *num_references,
-                        name: typechecked_struct.name.clone(),
-                    });
-                }
-                unreachable!("struct should be resolved by here")
+                Some(Type::Struct {
+                    struct_id: typechecked_struct.id,
+                    num_references: *num_references,
+                    name: typechecked_struct.name.clone(),
+                    generics: Vec::new(),
+                })
             }
-            TypeRef::Void(..) => unreachable!(),
-            TypeRef::Never(_) => unreachable!(),
+            TypeRef::Void(_, num_references) => Some(Type::PrimitiveVoid(*num_references)),
+            TypeRef::Never(_) => Some(Type::PrimitiveNever),
             TypeRef::UnsizedArray {
                 num_references,
                 child,
@@ -627,18 +1072,37 @@ impl TypecheckingContext {
                 num_references,
                 child,
                 number_elements,
-                loc: _,
-            } => Some(Type::SizedArray {
-                typ: Box::new(self.type_resolution_resolve_type(
-                    child,
-                    is_generic_name,
-                    module,
-                    context,
-                    errors,
-                )?),
-                num_references: *num_references,
-                number_elements: *number_elements,
-            }),
+                loc,
+            } => {
+                let number_elements = match number_elements {
+                    ArrayLength::Literal(v) => *v,
+                    ArrayLength::Const(_) => {
+                        errors.push(const_array_length_error(loc));
+                        return None;
+                    }
+                };
+                // [T; 0] has no size, so it's fine for it to contain the struct that is currently
+                // being resolved (struct A { x: [A; 0] })
+                let in_resolution = match number_elements {
+                    0 => self.struct_in_resolution(child, is_generic_name, module, &context),
+                    _ => None,
+                };
+                let typ = match in_resolution {
+                    Some(typ) => typ,
+                    None => self.type_resolution_resolve_type(
+                        child,
+                        is_generic_name,
+                        module,
+                        context,
+                        errors,
+                    )?,
+                };
+                Some(Type::SizedArray {
+                    typ: Box::new(typ),
+                    num_references: *num_references,
+                    number_elements,
+                })
+            }
             TypeRef::Tuple {
                 num_references,
                 elements,
@@ -661,6 +1125,62 @@ impl TypecheckingContext {
             }
         }
     }
+
+    /// returns the struct `typ` refers to if its resolution was already started (and might still
+    /// be in progress)
+    fn struct_in_resolution<F: Fn(&GlobalStr) -> bool>(
+        &self,
+        typ: &TypeRef,
+        is_generic_name: F,
+        module: ModuleId,
+        context: &ModuleContext,
+    ) -> Option<Type> {
+        let TypeRef::Reference {
+            num_references,
+            type_name,
+            loc,
+        } = typ
+        else {
+            return None;
+        };
+        if type_name
+            .entries
+            .iter()
+            .any(|(_, generics)| generics.len() > 0)
+        {
+            return None;
+        }
+        if type_name.entries.len() == 1 && is_generic_name(&type_name.entries[0].0) {
+            return None;
+        }
+        let path = type_name
+            .entries
+            .iter()
+            .map(|v| v.0.clone())
+            .collect::<Vec<_>>();
+        let Ok(ModuleScopeValue::Struct(id)) =
+            self.resolve_import_cached(context, module, &path, loc)
+        else {
+            return None;
+        };
+        if self.struct_resolution.read()[id] == ResolutionState::Unresolved {
+            return None;
+        }
+        let structure = &context.structs.read()[id];
+        Some(Type::Struct {
+            struct_id: id,
+            name: structure.name.clone(),
+            generics: Vec::new(),
+            num_references: *num_references,
+        })
+    }
+}
+
+fn const_array_length_error(loc: &Location) -> TypecheckingError {
+    TypecheckingError::ConstEvaluation {
+        location: loc.clone(),
+        reason: "array lengths can only call `const fn`s inside of function bodies",
+    }
 }
 
 fn typed_resolve_import(
@@ -682,6 +1202,9 @@ fn typed_resolve_import(
             location: location.clone(),
         });
     }
+    if already_included.is_empty() {
+        context.mark_used(module, &import[0]);
+    }
     already_included.push((module, import[0].clone()));
 
     let reader = context.modules.read();
@@ -863,3 +1386,2095 @@ pub enum ScopeKind {
     Static,
     Module,
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::{
+        lang_items::LangItemAssignmentError, module::Module, parser::ParserQueueEntry,
+        tokenizer::Tokenizer,
+    };
+    use intrinsics::Intrinsic;
+    use typechecking::typecheck_function;
+
+    const TARGET: &str = "x86_64-linux";
+    /// the panic lang item, which runtime checks like bounds checks need. Append it to the source
+    /// to keep the ids of the other functions.
+    const PANIC: &str = "\n@lang(\"panic\") fn panic() -> ! { while (true) {} }";
+
+    /// Tokenizes, parses and forms the module of `source` and resolves its imports. The types are
+    /// not resolved yet. Shared with the tests of later stages.
+    pub(crate) fn get_context(
+        source: &str,
+        target: &str,
+    ) -> (Arc<ModuleContext>, Arc<TypecheckingContext>) {
+        get_context_with_imports(source, target, &[])
+    }
+
+    /// `imports` are (name, path) pairs that are imported from the module itself
+    fn get_context_with_imports(
+        source: &str,
+        target: &str,
+        imports: &[(&str, &str)],
+    ) -> (Arc<ModuleContext>, Arc<TypecheckingContext>) {
+        let file: Arc<Path> = Path::new("test").into();
+        let mut tokenizer = Tokenizer::new(source, file.clone());
+        tokenizer.scan_tokens().expect("failed to tokenize");
+        let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
+            file: file.clone(),
+            root: file.clone(),
+        }]));
+        let mut parser = tokenizer.to_parser(modules, file.clone());
+        let (statements, errs) = parser.parse_all();
+        assert_eq!(errs.len(), 0, "unexpected errors: {errs:?}");
+        for (name, path) in imports {
+            let location = Location::new(file.clone(), 1, 1);
+            let path = path.split("::").map(GlobalStr::new).collect();
+            parser
+                .imports
+                .insert(GlobalStr::new(name), (location, 0, path));
+        }
+
+        let module_context = Arc::new(ModuleContext::default());
+        let mut module = Module::new(module_context.clone(), parser.imports, file.clone(), file);
+        if let Err(errs) = module.push_all(statements, 0) {
+            panic!("unexpected errors: {errs:?}");
+        }
+        module_context.modules.write().push(module);
+
+        let context = TypecheckingContext::new(
+            module_context.clone(),
+            Target::parse(target).expect("invalid target"),
+        );
+        let errs = context.resolve_imports(module_context.clone());
+        assert_eq!(errs.len(), 0, "unexpected errors: {errs:?}");
+        (module_context, context)
+    }
+
+    fn resolve_types(source: &str) -> Vec<TypecheckingError> {
+        let (module_context, context) = get_context(source, TARGET);
+        context.resolve_types(module_context)
+    }
+
+    fn typecheck(source: &str) -> Vec<TypecheckingError> {
+        typecheck_for(source, TARGET)
+    }
+
+    fn typecheck_for(source: &str, target: &str) -> Vec<TypecheckingError> {
+        let (module_context, context) = get_context(source, target);
+        // the test sources don't define the lang items
+        let errs = context
+            .resolve_types(module_context.clone())
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(errs.len(), 0, "unexpected errors: {errs:?}");
+
+        let num_functions = context.functions.read().len();
+        (0..num_functions)
+            .filter_map(|i| {
+                typecheck_function(&context, &module_context, i, false, &mut Vec::new()).err()
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn has_recursive_type(errs: &[TypecheckingError]) -> bool {
+        errs.iter()
+            .any(|v| matches!(v, TypecheckingError::RecursiveTypeDetected { .. }))
+    }
+
+    #[test]
+    fn test_recursive_types() {
+        assert!(has_recursive_type(&resolve_types("struct B { x: B }")));
+        // the struct that is still in progress is found through another struct
+        let (module_context, context) = get_context("struct A { b: B }\nstruct B { a: A }", TARGET);
+        assert!(has_recursive_type(&context.resolve_types(module_context)));
+        assert!(context
+            .struct_resolution
+            .read()
+            .iter()
+            .all(|state| *state == ResolutionState::Resolved));
+        assert!(has_recursive_type(&resolve_types("struct C { x: [C; 1] }")));
+        assert!(!has_recursive_type(&resolve_types(
+            "struct A { x: [A; 0] }"
+        )));
+    }
+
+    #[test]
+    fn test_shift_operands() {
+        assert_eq!(typecheck("fn main() { let a = 1 << 2; }").len(), 0);
+        assert!(matches!(
+            typecheck("fn main() { let a = 1.0 << 2; }")[..],
+            [TypecheckingError::CannotShl(..)]
+        ));
+        assert!(matches!(
+            typecheck("fn main() { let a = 1 >> 2.0; }")[..],
+            [TypecheckingError::CannotShiftByNonUInt(..)]
+        ));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(
+            typecheck("fn main() { var a = 1; a += 2; a *= 3; a <<= 1; a ^= 4; }").len(),
+            0
+        );
+        // `s += 1` is rejected like `s = s + 1`
+        assert!(matches!(
+            typecheck("struct S { x: i32 }\nfn main() { var s = S { x: 1 }; s += 1; }")[..],
+            [TypecheckingError::LhsNotRhs(..)]
+        ));
+        assert!(matches!(
+            typecheck("struct S { x: i32 }\nfn main() { var s = S { x: 1 }; s += s; }")[..],
+            [TypecheckingError::CannotAdd(..)]
+        ));
+        assert!(matches!(
+            typecheck("fn main() { var b = true; b ^= false; b += true; }")[..],
+            [TypecheckingError::CannotAdd(..)]
+        ));
+        assert!(matches!(
+            typecheck("fn main() { let a = 1; a += 2; }")[..],
+            [TypecheckingError::AssignToImmutable { .. }]
+        ));
+
+        // the place is only evaluated once
+        let (module_context, context) = get_context(
+            &format!(
+                "fn f() -> usize = 0;
+fn main() {{ var a = [1, 2]; a[f()] += 1; var b = &a; (*b)[f()] <<= 1; }}{PANIC}"
+            ),
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        typecheck_function(&context, &module_context, 1, false, &mut Vec::new())
+            .expect("failed to typecheck");
+        struct Calls(usize);
+        impl visitor::Visitor for Calls {
+            fn visit_expression(&mut self, expression: &TypecheckedExpression) {
+                if let TypecheckedExpression::DirectCall(_, _, 0, _) = expression {
+                    self.0 += 1;
+                }
+                visitor::walk_expression(self, expression);
+            }
+        }
+        let mut calls = Calls(0);
+        visitor::Visitor::visit_body(&mut calls, &context.functions.read()[1].1);
+        assert_eq!(calls.0, 2);
+    }
+
+    #[test]
+    fn test_unsized_values() {
+        let lang_item_errors = |errs: Vec<TypecheckingError>| {
+            errs.into_iter()
+                .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+                .collect::<Vec<_>>()
+        };
+        let errs = lang_item_errors(resolve_types("struct A { a: &[i32], b: &str }"));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = lang_item_errors(resolve_types("struct A { a: [i32] }"));
+        assert!(
+            matches!(errs[..], [TypecheckingError::UnsizedValue { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+
+        assert_eq!(typecheck("fn f(s: &[i32]) { let a = s; }").len(), 0);
+        assert!(matches!(
+            typecheck("fn f(s: &str) { let a = *s; }")[..],
+            [TypecheckingError::UnsizedValue { .. }]
+        ));
+        assert!(matches!(
+            typecheck("fn f(s: &[i32]) { let a: [i32] = *s; }")[..],
+            [TypecheckingError::UnsizedValue { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_mutability() {
+        assert_eq!(typecheck("fn main() { var x = 1; x = 2; }").len(), 0);
+        match &typecheck("fn main() { let x = 1; x = 2; }")[..] {
+            [TypecheckingError::AssignToImmutable { location, name }] => {
+                assert_eq!(*name, GlobalStr::new("x"));
+                assert_eq!(location.to_string(), "test:0:25");
+            }
+            errs => panic!("unexpected errors: {errs:?}"),
+        }
+        assert!(matches!(
+            typecheck("fn main() { let x = 1; x += 2; }")[..],
+            [TypecheckingError::AssignToImmutable { .. }]
+        ));
+        // fields of immutable structs can't be assigned, but the value behind a reference can
+        let src = "struct S { x: i32 }\nfn f(s: &S) { s.x = 2; }\n";
+        assert_eq!(
+            typecheck(&format!(
+                "{src}fn main() {{ var s = S {{ x: 1 }}; s.x = 2; }}"
+            ))
+            .len(),
+            0
+        );
+        assert!(matches!(
+            typecheck(&format!(
+                "{src}fn main() {{ let s = S {{ x: 1 }}; s.x = 2; }}"
+            ))[..],
+            [TypecheckingError::AssignToImmutable { .. }]
+        ));
+        // only the fields up to the first reference belong to the variable
+        let src = "struct T { x: i32 }\nstruct S { r: &T, t: T }\n";
+        assert_eq!(
+            typecheck(&format!(
+                "{src}fn f(r: &T, t: T) {{ let s = S {{ r: r, t: t }}; s.r.x = 2; }}"
+            ))
+            .len(),
+            0
+        );
+        for assignment in ["s.t.x = 2", "s.r = r"] {
+            let errs = typecheck(&format!(
+                "{src}fn f(r: &T, t: T) {{ let s = S {{ r: r, t: t }}; {assignment}; }}"
+            ));
+            assert!(
+                matches!(errs[..], [TypecheckingError::AssignToImmutable { .. }]),
+                "{assignment}: {errs:?}"
+            );
+        }
+        // the copy of an immutable variable is mutable on its own
+        assert_eq!(
+            typecheck("fn main() { let x = 1; var y = x; y = 2; }").len(),
+            0
+        );
+        // function arguments are mutable
+        assert_eq!(typecheck("fn f(x: i32) { x = 2; }").len(), 0);
+    }
+
+    #[test]
+    fn test_reference_coercion() {
+        assert_eq!(
+            typecheck("fn f(x: i32) {}\nfn main() { let x = 12; f(&x); }").len(),
+            0
+        );
+        assert_eq!(
+            typecheck("fn main() { let x = 12; let y: i32 = &x; }").len(),
+            0
+        );
+        assert!(matches!(
+            typecheck("fn f(x: &i32) {}\nfn main() { let x = 12; f(x); }")[..],
+            [TypecheckingError::ExpectedReference { .. }]
+        ));
+        assert!(matches!(
+            typecheck("fn f(x: i32) {}\nfn main() { let x = 12; f(&&x); }")[..],
+            [TypecheckingError::MismatchingType { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_f16_support() {
+        let source = "fn main() { let a: f16 = 1.5; let b = 2.5f16; let c = a as f32; }";
+        assert_eq!(typecheck_for(source, "x86_64-linux").len(), 0);
+        assert!(matches!(
+            typecheck_for("fn main() { let a: f16 = 1.5; }", "x86-linux")[..],
+            [TypecheckingError::UnsupportedFloatWidth { .. }]
+        ));
+        assert!(matches!(
+            typecheck_for("fn main() { let b = 2.5f16; }", "x86-linux")[..],
+            [TypecheckingError::UnsupportedFloatWidth { .. }]
+        ));
+
+        let (module_context, context) = get_context("struct A { x: f16 }", "x86-linux");
+        assert!(context
+            .resolve_types(module_context)
+            .iter()
+            .any(|v| matches!(v, TypecheckingError::UnsupportedFloatWidth { .. })));
+    }
+
+    #[test]
+    fn test_pointer_sized_types() {
+        let source = "struct A { a: u8, b: usize }";
+        let a = Type::Struct {
+            struct_id: 0,
+            name: "A".into(),
+            generics: Vec::new(),
+            num_references: 0,
+        };
+        let (module_context, context) = get_context(source, "x86_64-linux");
+        context.resolve_types(module_context);
+        assert_eq!(context.size_and_alignment(&Type::PrimitiveUSize(0)), (8, 8));
+        assert_eq!(context.size_and_alignment(&Type::PrimitiveI32(1)), (8, 8));
+        assert_eq!(context.size_and_alignment(&a), (16, 8));
+
+        let (module_context, context) = get_context(source, "x86-linux");
+        context.resolve_types(module_context);
+        assert_eq!(context.size_and_alignment(&Type::PrimitiveUSize(0)), (4, 4));
+        assert_eq!(context.size_and_alignment(&Type::PrimitiveI32(1)), (4, 4));
+        assert_eq!(context.size_and_alignment(&a), (8, 4));
+    }
+
+    #[test]
+    fn test_zero_length_array_alignment() {
+        let source = "struct A { a: u8, b: [i64; 0] }\nstruct B { x: [B; 0], y: i32 }";
+        let (module_context, context) = get_context(source, TARGET);
+        context.resolve_types(module_context);
+        let structure = |struct_id: usize, name: &str| Type::Struct {
+            struct_id,
+            name: name.into(),
+            generics: Vec::new(),
+            num_references: 0,
+        };
+        let empty_array = Type::SizedArray {
+            typ: Box::new(Type::PrimitiveI64(0)),
+            num_references: 0,
+            number_elements: 0,
+        };
+        assert_eq!(context.size_and_alignment(&empty_array), (0, 8));
+        assert_eq!(context.size_and_alignment(&structure(0, "A")), (8, 8));
+        // the recursive [B; 0] doesn't add to the alignment
+        assert_eq!(context.size_and_alignment(&structure(1, "B")), (4, 4));
+        let structs = context.structs.read();
+        assert_eq!(structure(0, "A").alignment(8, &structs), 8);
+        assert_eq!(structure(1, "B").alignment(8, &structs), 4);
+    }
+
+    #[test]
+    fn test_never_coercion() {
+        const ABORT: &str = "fn abort() -> ! { while (true) {} }\n";
+        assert_eq!(
+            typecheck(&format!("{ABORT}fn main() {{ let x: i32 = abort(); }}")).len(),
+            0
+        );
+        assert_eq!(
+            typecheck(&format!(
+                "{ABORT}fn f(c: bool) -> i32 {{ let x: i32 = abort(); return x; }}"
+            ))
+            .len(),
+            0
+        );
+        assert_eq!(
+            typecheck(&format!(
+                "{ABORT}fn f(c: bool) -> i32 {{ if (c) {{ return 1; }} else {{ abort(); }} }}"
+            ))
+            .len(),
+            0
+        );
+        assert!(matches!(
+            typecheck("fn f(c: bool) -> i32 { if (c) { return 1; } }")[..],
+            [TypecheckingError::BodyDoesNotAlwaysReturn { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_diverging_return() {
+        const ABORT: &str = "fn abort() -> ! { while (true) {} }\n";
+        // `!` satisfies every return type
+        for body in ["{ abort(); }", "{ return abort(); }", "= abort();"] {
+            let errs = typecheck(&format!("{ABORT}fn f() -> i32 {body}"));
+            assert!(errs.is_empty(), "unexpected errors for {body}: {errs:?}");
+        }
+        // `void` doesn't
+        assert!(matches!(
+            typecheck("fn f() -> i32 {}")[..],
+            [TypecheckingError::BodyDoesNotAlwaysReturn { .. }]
+        ));
+        assert!(matches!(
+            typecheck("fn g() {}\nfn f() -> i32 = g();")[..],
+            [TypecheckingError::MismatchingType { .. }]
+        ));
+        assert!(!typecheck("fn f() -> i32 { return; }").is_empty());
+    }
+
+    #[test]
+    fn test_transmute() {
+        let transmute = |from: &str, to: &str| {
+            typecheck(&format!(
+                "@intrinsic(\"transmute\")\nfn transmute(v: {from}) -> {to} {{ while (true) {{}} }}\nfn f(v: {from}) -> {to} = transmute(v);"
+            ))
+        };
+        assert!(transmute("i32", "u32").is_empty());
+        assert!(transmute("&i32", "usize").is_empty());
+        assert!(matches!(
+            transmute("i32", "i64")[..],
+            [TypecheckingError::TransmuteSizeMismatch {
+                from_size: 4,
+                to_size: 8,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_volatile() {
+        const INTRINSICS: &str = "@intrinsic(\"volatile_read\")
+fn volatile_read(ptr: &u32) -> u32 { while (true) {} }
+@intrinsic(\"volatile_write\")
+fn volatile_write(ptr: &u32, value: u32) { while (true) {} }
+";
+        let errs = typecheck(&format!(
+            "{INTRINSICS}fn f(mmio: &u32) -> u32 {{ volatile_write(mmio, 1); return volatile_read(mmio); }}"
+        ));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let errs = typecheck(
+            "@intrinsic(\"volatile_read\")
+fn volatile_read(ptr: u32) -> u32 { while (true) {} }
+fn f(mmio: u32) -> u32 = volatile_read(mmio);",
+        );
+        assert!(matches!(
+            errs[..],
+            [TypecheckingError::ExpectedReference { .. }]
+        ));
+
+        // the value has to be the type behind the pointer
+        for source in [
+            "@intrinsic(\"volatile_write\")
+fn volatile_write(ptr: &u32, value: u8) { while (true) {} }
+fn f(mmio: &u32) { volatile_write(mmio, 1); }",
+            "@intrinsic(\"volatile_read\")
+fn volatile_read(ptr: &u32) -> u64 { while (true) {} }
+fn f(mmio: &u32) -> u64 = volatile_read(mmio);",
+        ] {
+            let errs = typecheck(source);
+            assert!(
+                matches!(
+                    &errs[..],
+                    [TypecheckingError::MismatchingType {
+                        expected: Type::PrimitiveU32(0),
+                        ..
+                    }]
+                ),
+                "unexpected errors for {source:?}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pointer_arithmetic() {
+        const INTRINSICS: &str = "@intrinsic(\"ptr_add\")
+fn ptr_add(ptr: &u32, n: usize) -> &u32 { while (true) {} }
+@intrinsic(\"ptr_offset\")
+fn ptr_offset(ptr: &u32, n: isize) -> &u32 { while (true) {} }
+";
+        let errs = typecheck(&format!(
+            "{INTRINSICS}fn f(p: &u32) -> u32 {{ let q = ptr_add(p, 4); return *ptr_offset(q, -2); }}"
+        ));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let errs = typecheck(
+            "@intrinsic(\"ptr_add\")
+fn ptr_add(ptr: usize, n: usize) -> usize { while (true) {} }
+fn f(p: usize) -> usize = ptr_add(p, 1);",
+        );
+        assert!(matches!(
+            errs[..],
+            [TypecheckingError::ExpectedReference { .. }]
+        ));
+
+        // ptr_offset takes a signed offset
+        let errs = typecheck(
+            "@intrinsic(\"ptr_offset\")
+fn ptr_offset(ptr: &u32, n: usize) -> &u32 { while (true) {} }
+fn f(p: &u32) -> &u32 = ptr_offset(p, 1);",
+        );
+        assert!(matches!(
+            &errs[..],
+            [TypecheckingError::MismatchingType {
+                expected: Type::PrimitiveISize(0),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_null() {
+        const INTRINSICS: &str = "@intrinsic(\"null\")
+fn null() -> &i32 { while (true) {} }
+@intrinsic(\"is_null\")
+fn is_null(ptr: &i32) -> bool { while (true) {} }
+";
+        let errs = typecheck(&format!(
+            "{INTRINSICS}fn f() -> bool {{ let p = null(); return is_null(p); }}"
+        ));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let errs = typecheck(
+            "@intrinsic(\"null\")
+fn null() -> i32 { while (true) {} }
+fn f() -> i32 = null();",
+        );
+        assert!(matches!(
+            &errs[..],
+            [TypecheckingError::ExpectedReference {
+                found: Type::PrimitiveI32(0),
+                ..
+            }]
+        ));
+
+        let errs = typecheck(
+            "@intrinsic(\"is_null\")
+fn is_null(ptr: i32) -> bool { while (true) {} }
+fn f() -> bool = is_null(0);",
+        );
+        assert!(matches!(
+            errs[..],
+            [TypecheckingError::ExpectedReference { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_lang_items() {
+        let (module_context, context) = get_context(
+            "@lang(\"copy_trait\") trait Copy {}\n@lang(\"u8\") struct U8 {}\n@lang(\"not_a_lang_item\") struct A {}",
+            TARGET,
+        );
+        let errs = context
+            .resolve_types(module_context)
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect::<Vec<_>>();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let lang_items = context.lang_items.read();
+        assert_eq!(lang_items.get_trait("copy_trait"), Some(0));
+        assert_eq!(lang_items.get_struct("u8"), Some(0));
+        assert_eq!(lang_items.get_struct("copy_trait"), None);
+        assert_eq!(lang_items.get_struct("not_a_lang_item"), None);
+        assert_eq!(lang_items.get_trait("clone_trait"), None);
+
+        let errs =
+            resolve_types("@lang(\"copy_trait\") trait A {}\n@lang(\"copy_trait\") trait B {}");
+        assert!(
+            errs.iter().any(|v| matches!(
+                v,
+                TypecheckingError::LangItemAssignment(LangItemAssignmentError::Redefinition(
+                    "copy_trait",
+                    _
+                ))
+            )),
+            "unexpected errors: {errs:?}"
+        );
+        let errs = resolve_types("@lang(\"copy_trait\") struct A {}");
+        assert!(
+            errs.iter().any(|v| matches!(
+                v,
+                TypecheckingError::LangItemAssignment(
+                    LangItemAssignmentError::InvalidLangItemError { .. }
+                )
+            )),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_varargs() {
+        const PRINTF: &str = "extern fn printf(fmt: &u8, ...) -> i32;\n";
+        let errs = typecheck(&format!(
+            "{PRINTF}fn f(fmt: &u8) -> i32 {{ printf(fmt); return printf(fmt, 1, 2.5, fmt); }}"
+        ));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let errs = typecheck(&format!("{PRINTF}fn f() -> i32 = printf();"));
+        assert!(
+            matches!(errs[..], [TypecheckingError::MissingArguments { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+        let errs = typecheck(&format!("{PRINTF}fn f(a: i32) -> i32 = printf(a);"));
+        assert!(
+            matches!(errs[..], [TypecheckingError::MismatchingType { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+        let errs = typecheck("extern fn puts(s: &u8) -> i32;\nfn f(s: &u8) -> i32 = puts(s, 1);");
+        assert!(
+            matches!(errs[..], [TypecheckingError::TooManyArguments { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_indexing() {
+        assert!(typecheck(&format!(
+            "fn f(a: [i32; 4], i: usize) -> i32 = a[i];{PANIC}"
+        ))
+        .is_empty());
+        assert!(matches!(
+            typecheck("fn f(a: [i32; 4], i: usize) -> i32 = a[i];")[..],
+            [TypecheckingError::MissingLangItem { name: "panic", .. }]
+        ));
+        assert!(typecheck("@unchecked()\nfn f(a: [i32; 4], i: usize) -> i32 = a[i];").is_empty());
+        for index in ["1.0", "1i32", "true"] {
+            let errs = typecheck(&format!("fn f(a: [i32; 4]) -> i32 = a[{index}];"));
+            assert!(
+                matches!(
+                    errs[..],
+                    [TypecheckingError::MismatchingType {
+                        expected: Type::PrimitiveUSize(0),
+                        ..
+                    }]
+                ),
+                "unexpected errors for {index}: {errs:?}"
+            );
+        }
+
+        let has_bounds_check = |source: &str| {
+            let (module_context, context) = get_context(&format!("{source}{PANIC}"), TARGET);
+            let errs = context.resolve_types(module_context.clone());
+            assert!(
+                errs.iter()
+                    .all(|v| matches!(v, TypecheckingError::LangItemError(_))),
+                "unexpected errors: {errs:?}"
+            );
+            typecheck_function(&context, &module_context, 0, false, &mut Vec::new())
+                .expect("failed to typecheck");
+            let has_bounds_check = context.functions.read()[0]
+                .1
+                .iter()
+                .any(|v| matches!(v, TypecheckedExpression::BoundsCheck(..)));
+            has_bounds_check
+        };
+        assert!(has_bounds_check("fn f(a: &[i32], i: usize) -> i32 = a[i];"));
+        assert!(!has_bounds_check(
+            "@unchecked()\nfn f(a: &[i32], i: usize) -> i32 = a[i];"
+        ));
+    }
+
+    #[test]
+    fn test_slicing() {
+        for source in [
+            "fn f(a: [i32; 4], i: usize) -> &[i32] = a[i..4];",
+            "fn f(a: &[i32; 4]) -> &[i32] = a[1..=2];",
+            "fn f(a: &[i32], i: usize) -> i32 = a[0..i][1];",
+        ] {
+            let errs = typecheck(&format!("{source}{PANIC}"));
+            assert!(errs.is_empty(), "unexpected errors for {source}: {errs:?}");
+        }
+        assert!(matches!(
+            typecheck("fn f(a: [i32; 4]) -> &[i32] = a[0..2i32];")[..],
+            [TypecheckingError::MismatchingType {
+                expected: Type::PrimitiveUSize(0),
+                ..
+            }]
+        ));
+        assert!(matches!(
+            typecheck("fn f(a: (i32, i32)) -> &[i32] = a[0..1];")[..],
+            [TypecheckingError::SliceNonArray(..)]
+        ));
+
+        let errs = typecheck(&format!("fn f(a: &[i32; 4]) -> &[i32] = a[3..1];{PANIC}"));
+        assert!(
+            matches!(errs[..], [TypecheckingError::InvertedSliceRange(_, 3, 1)]),
+            "unexpected errors: {errs:?}"
+        );
+        for (source, len, end) in [("a[0..100]", 4, 100), ("a[2..=4]", 4, 5)] {
+            let errs = typecheck(&format!("fn f(a: &[i32; 4]) -> &[i32] = {source};{PANIC}"));
+            assert!(
+                matches!(errs[..], [TypecheckingError::SliceOutOfBounds(_, l, e)] if l == len && e == end),
+                "unexpected errors for {source}: {errs:?}"
+            );
+        }
+
+        // slicing with runtime bounds is checked unless the function opts out
+        let asserts = |source: &str| {
+            let (module_context, context) = get_context(&format!("{source}{PANIC}"), TARGET);
+            context.resolve_types(module_context.clone());
+            typecheck_function(&context, &module_context, 0, false, &mut Vec::new())
+                .expect("failed to typecheck");
+            let asserts = context.functions.read()[0]
+                .1
+                .iter()
+                .filter(|v| {
+                    matches!(
+                        v,
+                        TypecheckedExpression::IntrinsicCall(_, _, Intrinsic::Assert, _)
+                    )
+                })
+                .count();
+            asserts
+        };
+        assert_eq!(asserts("fn f(a: &[i32; 4]) -> &[i32] = a[1..3];"), 0);
+        assert_eq!(
+            asserts("fn f(a: &[i32; 4], i: usize) -> &[i32] = a[1..i];"),
+            2
+        );
+        assert_eq!(asserts("fn f(a: &[i32], i: usize) -> &[i32] = a[i..2];"), 2);
+        assert_eq!(asserts("fn f(a: &[i32]) -> &[i32] = a[0..2];"), 1);
+        assert_eq!(
+            asserts("@unchecked()\nfn f(a: &[i32], i: usize) -> &[i32] = a[i..2];"),
+            0
+        );
+    }
+
+    #[test]
+    fn test_casts() {
+        for source in [
+            "fn f(a: i32) -> i64 = a as i64;",
+            "fn f(a: u64) -> u8 = a as u8;",
+            "fn f(a: i32) -> f64 = a as f64;",
+            "fn f(a: f32) -> f64 = a as f64;",
+            "fn f(a: f64) -> u16 = a as u16;",
+            "fn f(a: &&i32) -> &i32 = a as &i32;",
+            "fn f(a: &i32) -> &&&i32 = a as &&&i32;",
+        ] {
+            let errs = typecheck(source);
+            assert!(errs.is_empty(), "unexpected errors for {source}: {errs:?}");
+        }
+        for source in [
+            "struct S { a: i32 }\nfn f(a: S) -> i32 = a as i32;",
+            "fn f(a: i32) -> S = a as S;\nstruct S { a: i32 }",
+            "fn f(a: &i32) -> &i64 = a as &i64;",
+            "fn f(a: &[i32]) -> &&[i32] = a as &&[i32];",
+        ] {
+            let errs = typecheck(source);
+            assert!(
+                matches!(errs[..], [TypecheckingError::InvalidCast(..)]),
+                "unexpected errors for {source}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_literal_range() {
+        for literal in [
+            "255u8",
+            "-128i8",
+            "127i8",
+            "65535u16",
+            "2147483647i32",
+            "300",
+        ] {
+            let errs = typecheck(&format!("fn f() {{ let a = {literal}; }}"));
+            assert!(errs.is_empty(), "unexpected errors for {literal}: {errs:?}");
+        }
+        for (literal, value) in [("300u8", 300), ("-129i8", -129), ("128i8", 128)] {
+            let errs = typecheck(&format!("fn f() {{ let a = {literal}; }}"));
+            assert!(
+                matches!(
+                    errs[..],
+                    [TypecheckingError::LiteralOutOfRange { value: v, .. }] if v == value
+                ),
+                "unexpected errors for {literal}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_typechecking() {
+        let mut source = String::new();
+        for i in 0..32 {
+            source.push_str(&format!("fn ok_{i}(a: i32) -> i32 {{ return a * {i}; }}\n"));
+            if i % 3 == 0 {
+                source.push_str(&format!("fn err_{i}() -> i32 {{ return 1.0; }}\n"));
+            }
+            if i % 5 == 0 {
+                source.push_str(&format!("fn missing_{i}() {{ let x = y_{i}; }}\n"));
+            }
+        }
+
+        let run = |num_threads: usize| {
+            let (module_context, context) = get_context(&source, TARGET);
+            context.resolve_types(module_context.clone());
+            let Err(errs) = typechecking::typecheck_functions(
+                &context,
+                &module_context,
+                num_threads,
+                &mut Vec::new(),
+            ) else {
+                panic!("expected errors");
+            };
+            errs.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+
+        let sequential = run(1);
+        assert_eq!(sequential.len(), 11 + 7);
+        assert_eq!(run(4), sequential);
+        assert_eq!(run(64), sequential);
+
+        let (module_context, context) =
+            get_context("fn a() -> i32 { return 1; }\nfn b() {}", TARGET);
+        context.resolve_types(module_context.clone());
+        let Ok((scopes, _)) =
+            typechecking::typecheck_functions(&context, &module_context, 2, &mut Vec::new())
+        else {
+            panic!("unexpected errors");
+        };
+        assert_eq!(scopes.len(), 2);
+        assert!(context.functions.read().iter().all(|v| v.1.len() > 0));
+    }
+
+    #[test]
+    fn test_import_cache() {
+        let (module_context, context) = get_context(
+            "struct A { a: i32 }\ntrait T { fn f(self: &Self); }\nfn g() {}\nlet S: i32 = 1;",
+            TARGET,
+        );
+        let loc = DUMMY_LOCATION.clone();
+        for name in ["A", "T", "g", "S", "missing"] {
+            let path = [GlobalStr::new(name)];
+            let uncached = resolve_import(&module_context, 0, &path, &loc, &mut Vec::new());
+            for _ in 0..2 {
+                let cached = context.resolve_import_cached(&module_context, 0, &path, &loc);
+                match (&uncached, cached) {
+                    (Ok(a), Ok(b)) => assert_eq!(*a, b, "{name}"),
+                    (Err(a), Err(b)) => assert_eq!(a.to_string(), b.to_string(), "{name}"),
+                    (a, b) => panic!("{name}: {a:?} != {b:?}"),
+                }
+            }
+        }
+        assert_eq!(context.import_cache.read().len(), 4);
+    }
+
+    /// Measures how much faster cached import resolution is on a module with many imports, run
+    /// it with `cargo test --release -- --ignored --nocapture bench_import_cache`
+    #[test]
+    #[ignore]
+    fn bench_import_cache() {
+        const NUM_IMPORTS: usize = 500;
+        const ROUNDS: usize = 200;
+        let source = (0..NUM_IMPORTS)
+            .map(|i| format!("struct S{i} {{}}\nexport S{i};\n"))
+            .collect::<String>();
+        let names = (0..NUM_IMPORTS)
+            .map(|i| (format!("I{i}"), format!("S{i}")))
+            .collect::<Vec<_>>();
+        let imports = names
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+            .collect::<Vec<_>>();
+        let (module_context, context) = get_context_with_imports(&source, TARGET, &imports);
+        let paths = names
+            .iter()
+            .map(|(name, _)| [GlobalStr::new(name)])
+            .collect::<Vec<_>>();
+        let loc = DUMMY_LOCATION.clone();
+
+        let now = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            for path in &paths {
+                resolve_import(&module_context, 0, path, &loc, &mut Vec::new())
+                    .expect("failed to resolve");
+            }
+        }
+        let uncached = now.elapsed();
+        let now = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            for path in &paths {
+                context
+                    .resolve_import_cached(&module_context, 0, path, &loc)
+                    .expect("failed to resolve");
+            }
+        }
+        let cached = now.elapsed();
+        let lookups = (NUM_IMPORTS * ROUNDS) as u32;
+        println!(
+            "{lookups} lookups: {:?} per uncached, {:?} per cached lookup",
+            uncached / lookups,
+            cached / lookups
+        );
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let (module_context, context) = get_context_with_imports(
+            "fn g() {}\nexport g;\nstruct A { a: i32 }\nexport A;\nfn main() { used(); }",
+            TARGET,
+            &[("used", "g"), ("unused", "A")],
+        );
+        let errs = context.resolve_types(module_context.clone());
+        assert!(errs
+            .iter()
+            .all(|v| matches!(v, TypecheckingError::LangItemError(_))));
+        typechecking::typecheck_functions(&context, &module_context, 1, &mut Vec::new())
+            .unwrap_or_else(|e| panic!("unexpected errors: {e:?}"));
+
+        let warnings = context.unused_imports(&module_context);
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(matches!(
+            &warnings[0],
+            TypecheckingWarning::UnusedImport { name, .. } if *name == "unused"
+        ));
+    }
+
+    #[test]
+    fn test_warnings_are_not_errors() {
+        let (module_context, context) = get_context_with_imports(
+            "fn g() -> i32 { return 1; }\nexport g;\nfn main() { let x: i32 = g(); }",
+            TARGET,
+            &[("unused", "g")],
+        );
+        context.resolve_types(module_context.clone());
+        let mut warnings = Vec::new();
+        let result = typechecking::typecheck_functions(&context, &module_context, 1, &mut warnings);
+        assert!(result.is_ok());
+        warnings.extend(context.unused_imports(&module_context));
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+    }
+
+    fn typecheck_warnings(source: &str) -> Vec<TypecheckingWarning> {
+        let (module_context, context) = get_context(source, TARGET);
+        context.resolve_types(module_context.clone());
+        let mut warnings = Vec::new();
+        typechecking::typecheck_functions(&context, &module_context, 1, &mut warnings)
+            .unwrap_or_else(|e| panic!("unexpected errors: {e:?}"));
+        warnings
+    }
+
+    fn shadowing_warnings(source: &str) -> usize {
+        typecheck_warnings(source)
+            .iter()
+            .filter(|v| matches!(v, TypecheckingWarning::Shadowed { .. }))
+            .count()
+    }
+
+    #[test]
+    fn test_shadowing() {
+        assert_eq!(
+            shadowing_warnings("fn f() -> i32 { let a = 1; let a = 2; return a; }"),
+            1
+        );
+        assert_eq!(
+            shadowing_warnings("fn f() -> i32 { let a = 1; { let a = 2; } return a; }"),
+            0
+        );
+        assert_eq!(
+            shadowing_warnings("fn f(a: i32) -> i32 { let a = 2; return a; }"),
+            0
+        );
+        assert_eq!(
+            shadowing_warnings("fn f() { let a = 1; { let a = 2; let a = 3; } let b = 1; }"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let warnings = typecheck_warnings("fn f() -> i32 { return 1; let a = 2; let b = 3; }");
+        assert!(matches!(
+            warnings[..],
+            [TypecheckingWarning::UnreachableCode { .. }]
+        ));
+        assert_eq!(
+            typecheck_warnings("fn f(c: bool) -> i32 { if (c) { return 1; } return 2; }").len(),
+            0
+        );
+        assert_eq!(
+            typecheck_warnings(
+                "fn f(c: bool) -> i32 { if (c) { return 1; } else { return 2; } return 3; }"
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let typed_struct = |module_id: ModuleId, line: u32| TypedStruct {
+            name: GlobalStr::new("Point"),
+            elements: vec![
+                (GlobalStr::new("x"), Type::PrimitiveI32(0)),
+                (GlobalStr::new("y"), Type::PrimitiveI32(0)),
+            ],
+            location: Location::new(Path::new(&format!("module{module_id}")).into(), line, 1),
+            global_impl: HashMap::new(),
+            trait_impl: HashMap::new(),
+            trait_consts: HashMap::new(),
+            annotations: Annotations::default(),
+            module_id,
+            id: module_id,
+            generics: Vec::new(),
+        };
+        let identity_hash = |v: &TypedStruct| {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let a = typed_struct(0, 1);
+        let b = typed_struct(1, 5);
+        assert_eq!(a.structural_hash(), b.structural_hash());
+        assert_ne!(identity_hash(&a), identity_hash(&b));
+
+        let mut c = typed_struct(0, 1);
+        c.elements.pop();
+        assert_ne!(a.structural_hash(), c.structural_hash());
+    }
+
+    #[test]
+    fn test_lookup_struct_by_name() {
+        let (module_context, context) = get_context(
+            "struct Point { x: i32, y: i32 } struct Line { a: Point, b: Point } fn point() {}",
+            TARGET,
+        );
+        let line = context
+            .lookup_struct_by_name(0, &GlobalStr::new("Line"))
+            .expect("`Line` should be found");
+        assert_eq!(
+            module_context.structs.read()[line].name,
+            GlobalStr::new("Line")
+        );
+        assert!(context
+            .lookup_struct_by_name(0, &GlobalStr::new("Point"))
+            .is_some());
+        assert_eq!(
+            context.lookup_struct_by_name(0, &GlobalStr::new("Circle")),
+            None
+        );
+        assert_eq!(
+            context.lookup_struct_by_name(0, &GlobalStr::new("point")),
+            None
+        );
+        assert_eq!(
+            context.lookup_struct_by_name(1, &GlobalStr::new("Point")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_functions_iter() {
+        let (module_context, context) = get_context(
+            "fn add(a: i32, b: i32) -> i32 { return a + b; } fn main() {}",
+            TARGET,
+        );
+        context.resolve_types(module_context);
+
+        let functions = context.functions_iter();
+        assert_eq!(functions.len(), 2);
+        let mut names = functions
+            .iter()
+            .map(|(_, contract)| contract.name.clone().unwrap().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["add", "main"]);
+        for (id, contract) in &functions {
+            assert_eq!(contract.name, context.functions.read_recursive()[id].0.name);
+        }
+    }
+
+    #[test]
+    fn test_vtable() {
+        let (module_context, context) = get_context(
+            "
+trait Shape {
+    fn area(self: &Self) -> i32;
+    fn scale(self: &Self, factor: i32);
+}
+trait Named { fn name(self: &Self) -> &str; }
+struct Square {
+    size: i32;
+    impl Shape {
+        fn scale(self: &Self, factor: i32) {}
+        fn area(self: &Self) -> i32 { return self.size * self.size; }
+    }
+    impl Named { fn name(self: &Self) -> &str { return \"square\"; } }
+}
+struct Empty {}
+",
+            TARGET,
+        );
+        context.resolve_types(module_context);
+        let lookup = |name: &str| match context.modules.read()[0].scope[&GlobalStr::new(name)] {
+            ModuleScopeValue::Struct(id) | ModuleScopeValue::Trait(id) => id,
+            _ => unreachable!(),
+        };
+        let square = Type::Struct {
+            struct_id: lookup("Square"),
+            name: GlobalStr::new("Square"),
+            generics: Vec::new(),
+            num_references: 0,
+        };
+        let function_names = |vtable: VTable| {
+            vtable
+                .functions
+                .into_iter()
+                .map(|id| context.functions.read()[id].0.name.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let vtable = context
+            .vtable(&square, &[lookup("Shape")])
+            .expect("Square implements Shape");
+        assert_eq!(
+            function_names(vtable),
+            [GlobalStr::new("area"), GlobalStr::new("scale")]
+        );
+        let vtable = context
+            .vtable(&square, &[lookup("Named"), lookup("Shape")])
+            .expect("Square implements Named and Shape");
+        assert_eq!(
+            function_names(vtable),
+            [
+                GlobalStr::new("name"),
+                GlobalStr::new("area"),
+                GlobalStr::new("scale")
+            ]
+        );
+
+        let empty = Type::Struct {
+            struct_id: lookup("Empty"),
+            name: GlobalStr::new("Empty"),
+            generics: Vec::new(),
+            num_references: 0,
+        };
+        assert_eq!(context.vtable(&empty, &[lookup("Shape")]), None);
+        assert_eq!(
+            context.vtable(&Type::PrimitiveI32(0), &[lookup("Shape")]),
+            None
+        );
+    }
+
+    fn trait_impl_errors(implementation: &str) -> Vec<TypecheckingError> {
+        let source = format!(
+            "trait Shape {{ fn area(self: &Self) -> i32; fn scale(self: &Self, factor: i32); }}
+struct Square {{ size: i32; impl Shape {{ {implementation} }} }}"
+        );
+        resolve_types(&source)
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect()
+    }
+
+    #[test]
+    fn test_trait_impl_coverage() {
+        let errs = trait_impl_errors(
+            "fn area(self: &Self) -> i32 { return 1; } fn scale(self: &Self, factor: i32) {}",
+        );
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let errs = trait_impl_errors("fn area(self: &Self) -> i32 { return 1; }");
+        assert!(
+            matches!(&errs[..], [TypecheckingError::MissingTraitMethod { name, .. }] if *name == GlobalStr::new("scale")),
+            "{errs:?}"
+        );
+
+        let errs = trait_impl_errors(
+            "fn area(self: &Self) -> i32 { return 1; } fn scale(self: &Self, factor: i32) {} fn rotate(self: &Self) {}",
+        );
+        assert!(
+            matches!(&errs[..], [TypecheckingError::UnknownTraitMethod { name, .. }] if *name == GlobalStr::new("rotate")),
+            "{errs:?}"
+        );
+
+        // the argument count, argument types and the return type all have to match
+        for implementation in [
+            "fn area(self: &Self) -> i32 { return 1; } fn scale(self: &Self) {}",
+            "fn area(self: &Self) -> i32 { return 1; } fn scale(self: &Self, factor: i64) {}",
+            "fn area(self: &Self) -> u32 { return 1; } fn scale(self: &Self, factor: i32) {}",
+        ] {
+            let errs = trait_impl_errors(implementation);
+            assert!(
+                matches!(
+                    &errs[..],
+                    [TypecheckingError::TraitMethodSignatureMismatch { .. }]
+                ),
+                "{implementation}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_associated_consts() {
+        let errs = resolve_types(
+            "trait Shape { const SIDES: usize; const NAME: &str; }
+struct Square { size: i32; impl Shape { const SIDES: usize = 4; } }",
+        )
+        .into_iter()
+        .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+        .collect::<Vec<_>>();
+        assert!(
+            matches!(&errs[..], [TypecheckingError::MissingTraitConst { name, .. }] if *name == GlobalStr::new("NAME")),
+            "{errs:?}"
+        );
+
+        let errs = resolve_types(
+            "trait Shape { const SIDES: usize; }
+struct Square { size: i32; impl Shape { const SIDES: u8 = 4; } }",
+        )
+        .into_iter()
+        .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+        .collect::<Vec<_>>();
+        assert!(
+            matches!(&errs[..], [TypecheckingError::MismatchingType { .. }]),
+            "{errs:?}"
+        );
+
+        let errs = trait_impl_errors(
+            "const SIDES: u8 = 4; fn area(self: &Self) -> i32 { return 1; } fn scale(self: &Self, factor: i32) {}",
+        );
+        assert!(
+            matches!(&errs[..], [TypecheckingError::UnknownTraitConst { name, .. }] if *name == GlobalStr::new("SIDES")),
+            "{errs:?}"
+        );
+
+        let (module_context, context) = get_context(
+            "trait Shape { const SIDES: usize; }
+struct Square { size: i32; impl Shape { const SIDES: usize = 4; } }",
+            TARGET,
+        );
+        context.resolve_types(module_context);
+        let structs = context.structs.read();
+        let consts = structs[0]
+            .trait_consts
+            .values()
+            .next()
+            .expect("Shape should be implemented");
+        assert!(
+            matches!(
+                &consts[..],
+                [(name, Type::PrimitiveUSize(0), TypedLiteral::USize(4))] if *name == GlobalStr::new("SIDES")
+            ),
+            "{consts:?}"
+        );
+    }
+
+    #[test]
+    fn test_standalone_impl() {
+        let (module_context, context) = get_context(
+            "
+impl Shape for Square {
+    const SIDES: usize = 4;
+    fn area(self: &Self) -> i32 { return self.size * self.size; }
+}
+trait Shape { const SIDES: usize; fn area(self: &Self) -> i32; }
+struct Square { size: i32 }
+",
+            TARGET,
+        );
+        let errs = context
+            .resolve_types(module_context)
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect::<Vec<_>>();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+
+        let square = context
+            .lookup_struct_by_name(0, &GlobalStr::new("Square"))
+            .expect("`Square` should be defined");
+        let structs = context.structs.read();
+        assert_eq!(structs[square].trait_impl.len(), 1);
+        assert_eq!(structs[square].trait_consts.len(), 1);
+        let area = structs[square].trait_impl.values().next().unwrap()[0];
+        assert_eq!(
+            context.functions.read()[area].0.name,
+            Some(GlobalStr::new("area"))
+        );
+    }
+
+    #[test]
+    fn test_field_access() {
+        const SOURCE: &str = "struct B { c: u8 }\nstruct A { a: i32, b: B }\n";
+        for (args, body) in [
+            ("a: A", "return a.a;"),
+            ("a: &A", "return a.a;"),
+            ("a: &&A", "return a.b.c as i32;"),
+            ("a: A", "var b = a; b.a = 2; b.b.c = 1; return b.a;"),
+        ] {
+            let errs = typecheck(&format!("{SOURCE}fn f({args}) -> i32 {{ {body} }}"));
+            assert!(errs.is_empty(), "unexpected errors for {body}: {errs:?}");
+        }
+
+        let typecheck_errs = |args: &str, body: &str| {
+            let (module_context, context) =
+                get_context(&format!("{SOURCE}fn f({args}) -> i32 = {body};"), TARGET);
+            context.resolve_types(module_context.clone());
+            match typecheck_function(&context, &module_context, 0, false, &mut Vec::new()) {
+                Ok(_) => panic!("{body} should not typecheck"),
+                Err(errs) => errs,
+            }
+        };
+        let errs = typecheck_errs("a: &A", "a.d");
+        assert!(
+            matches!(&errs[..], [TypecheckingError::FieldNotFound(_, Type::Struct { .. }, name)] if *name == "d"),
+            "unexpected errors: {errs:?}"
+        );
+        for (args, body) in [
+            ("a: i32", "a.a"),
+            ("a: (i32, i32)", "a.a"),
+            ("a: A", "a.a.b"),
+        ] {
+            let errs = typecheck_errs(args, body);
+            assert!(
+                matches!(errs[..], [TypecheckingError::AccessNonStructValue(..)]),
+                "unexpected errors for {body}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_method_calls() {
+        const SOURCE: &str = "
+trait Area { fn area(self: &Self) -> i32; }
+struct A {
+    a: i32;
+    fn get(self: &Self) -> i32 = self.a;
+    fn new(a: i32) -> A = A { a: a };
+    impl Area { fn area(self: &Self) -> i32 = self.a * self.a; }
+}
+";
+        for body in ["a.get()", "a.area()", "(&a).get() + (&&a).area()"] {
+            let errs = typecheck(&format!("{SOURCE}fn f(a: A) -> i32 = {body};"));
+            assert!(errs.is_empty(), "unexpected errors for {body}: {errs:?}");
+        }
+
+        let (module_context, context) =
+            get_context(&format!("{SOURCE}fn f(a: A) -> i32 = a.get();"), TARGET);
+        context.resolve_types(module_context.clone());
+        let get = context
+            .lookup_struct_by_name(0, &GlobalStr::new("A"))
+            .map(|id| context.structs.read()[id].global_impl[&GlobalStr::new("get")])
+            .expect("`A` should be defined");
+        let f = context.functions.read().len() - 1;
+        typecheck_function(&context, &module_context, f, false, &mut Vec::new())
+            .unwrap_or_else(|errs| panic!("unexpected errors: {errs:?}"));
+        assert!(
+            context.functions.read()[f]
+                .1
+                .iter()
+                .any(|v| matches!(v, TypecheckedExpression::DirectCall(_, _, id, _) if *id == get)),
+            "`a.get()` should call A::get"
+        );
+
+        for (body, matches_err) in [
+            (
+                "a.missing()",
+                (|v| matches!(v, [TypecheckingError::CannotFindFunctionOnType(..)]))
+                    as fn(&[TypecheckingError]) -> bool,
+            ),
+            ("a.new()", |v| {
+                matches!(v, [TypecheckingError::NonMemberFunction(..)])
+            }),
+            ("a.get(1)", |v| {
+                matches!(v, [TypecheckingError::TooManyArguments { .. }])
+            }),
+            ("1.get()", |v| {
+                matches!(v, [TypecheckingError::CannotFindFunctionOnType(..)])
+            }),
+        ] {
+            let (module_context, context) =
+                get_context(&format!("{SOURCE}fn f(a: A) -> i32 = {body};"), TARGET);
+            context.resolve_types(module_context.clone());
+            let f = context.functions.read().len() - 1;
+            let Err(errs) =
+                typecheck_function(&context, &module_context, f, false, &mut Vec::new())
+            else {
+                panic!("{body} should not typecheck");
+            };
+            assert!(matches_err(&errs), "unexpected errors for {body}: {errs:?}");
+        }
+    }
+
+    #[test]
+    fn test_self_type() {
+        let (module_context, context) = get_context(
+            "struct A {
+    a: i32;
+    fn new(a: i32) -> Self { let v: Self = A { a: a }; return v; }
+    fn pair(self: &Self, other: [Self; 2]) -> (Self, i32) { while (true) {} }
+}",
+            TARGET,
+        );
+        let errs = context
+            .resolve_types(module_context.clone())
+            .into_iter()
+            .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+            .collect::<Vec<_>>();
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        for id in 0..2 {
+            typecheck_function(&context, &module_context, id, false, &mut Vec::new())
+                .unwrap_or_else(|errs| panic!("unexpected errors: {errs:?}"));
+        }
+        let functions = context.functions.read();
+        assert_eq!(functions[0].0.return_type.to_string(), "A");
+        let (_, other) = &functions[1].0.arguments[1];
+        assert_eq!(other.to_string(), "[A; 2]");
+        assert_eq!(functions[1].0.return_type.to_string(), "(A, i32)");
+        drop(functions);
+
+        let errs = resolve_types("fn f() -> Self { while (true) {} }");
+        assert!(
+            errs.iter()
+                .any(|v| matches!(v, TypecheckingError::SelfOutsideImpl { .. })),
+            "unexpected errors: {errs:?}"
+        );
+        let (module_context, context) =
+            get_context("fn f(a: i32) -> i32 { let b: Self = a; return 1; }", TARGET);
+        context.resolve_types(module_context.clone());
+        let Err(errs) = typecheck_function(&context, &module_context, 0, false, &mut Vec::new())
+        else {
+            panic!("`Self` should not be valid in a function");
+        };
+        assert!(
+            matches!(errs[..], [TypecheckingError::SelfOutsideImpl { .. }]),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_conflicting_impls() {
+        const SOURCE: &str = "
+trait Foo { fn foo(self: &Self) -> i32; }
+struct A { a: i32; impl Foo { fn foo(self: &Self) -> i32 = 1; } }
+";
+        for implementation in [
+            "impl Foo for A { fn foo(self: &Self) -> i32 = 2; }",
+            // the first impl counts, even if the second one is broken
+            "impl Foo for A {}",
+        ] {
+            let errs = resolve_types(&format!("{SOURCE}{implementation}"))
+                .into_iter()
+                .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+                .collect::<Vec<_>>();
+            assert!(
+                matches!(&errs[..], [TypecheckingError::ConflictingImpl { trait_name, .. }] if *trait_name == "Foo"),
+                "{implementation}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_void_references() {
+        let errs = typecheck(
+            "fn f(a: void, b: &void, c: &&void) -> &void { let d: &i32 = b as &i32; let e = *d; let f: &void = *c; return b; }",
+        );
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let (module_context, context) = get_context("fn f(a: &void) -> void {}", TARGET);
+        context.resolve_types(module_context);
+        assert_eq!(
+            context.functions.read()[0].0.arguments[0].1,
+            Type::PrimitiveVoid(1)
+        );
+
+        for source in [
+            "fn f(a: &void) { let b = *a; }",
+            "fn f(a: &void) { *a = void; }",
+        ] {
+            let errs = typecheck(source);
+            assert!(
+                matches!(
+                    &errs[..],
+                    [TypecheckingError::CannotDeref(_, Type::PrimitiveVoid(1))]
+                ),
+                "{source}: {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_derive() {
+        const TRAITS: &str = "
+trait Eq { fn eq(self: &Self, other: &Self) -> bool; }
+trait Clone { fn clone(self: &Self) -> Self; }
+";
+        let source = format!(
+            "{TRAITS}
+@derive(Eq, Clone)
+struct Point {{ x: i32, y: i32, visible: bool }}
+@derive(Eq)
+struct Empty {{}}
+fn f(a: &Point, b: &Point) -> bool {{ let c: Point = a.clone(); return c.eq(b); }}"
+        );
+        let errs = typecheck(&source);
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let (module_context, context) = get_context(&source, TARGET);
+        context.resolve_types(module_context);
+        let structs = context.structs.read();
+        assert_eq!(structs[0].trait_impl.len(), 2);
+        assert_eq!(structs[1].trait_impl.len(), 1);
+        drop(structs);
+
+        for (source, trait_name, field_name) in [
+            ("@derive(Eq) struct A { a: i32, b: &str }", "Eq", "b"),
+            (
+                "struct B {}\n@derive(Clone) struct A { b: B }",
+                "Clone",
+                "b",
+            ),
+        ] {
+            let errs = resolve_types(&format!("{TRAITS}{source}"))
+                .into_iter()
+                .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+                .collect::<Vec<_>>();
+            assert!(
+                matches!(
+                    &errs[..],
+                    [TypecheckingError::CannotDerive { trait_name: t, field_name: f, .. }]
+                        if *t == trait_name && *f == field_name
+                ),
+                "{source}: {errs:?}"
+            );
+        }
+
+        // fields can be compared with `==` if they are floats or structs implementing Eq
+        let source = "@lang(\"eq_trait\") trait Eq { fn eq(self: &Self, other: &Self) -> bool; }
+@derive(Eq) struct Inner { x: f32 }
+@derive(Eq) struct Outer { inner: Inner, y: f64 }
+fn f(a: Outer, b: Outer) -> bool = a == b;";
+        let errs = typecheck(source);
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = resolve_types(&format!(
+            "{TRAITS}struct B {{}}\n@derive(Eq) struct A {{ b: B }}"
+        ))
+        .into_iter()
+        .filter(|v| !matches!(v, TypecheckingError::LangItemError(_)))
+        .collect::<Vec<_>>();
+        assert!(
+            matches!(
+                &errs[..],
+                [TypecheckingError::CannotDerive { trait_name: t, field_name: f, .. }]
+                    if *t == "Eq" && *f == "b"
+            ),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_ir_display_is_deterministic() {
+        const SOURCE: &str = "
+trait Shape { fn area(self: &Self) -> i32; }
+trait Named { fn name(self: &Self) -> &str; }
+struct Square {
+    size: i32;
+    fn new(size: i32) -> Square { return Square { size: size }; }
+    fn size(self: &Self) -> i32 { return self.size; }
+    impl Named { fn name(self: &Self) -> &str { return \"square\"; } }
+    impl Shape { fn area(self: &Self) -> i32 { return self.size * self.size; } }
+}
+fn a() {} fn b() {} fn c() {}
+";
+        let render = || {
+            let (module_context, context) = get_context(SOURCE, TARGET);
+            context.resolve_types(module_context);
+            ir_displayer::TCContextDisplay(&context).to_string()
+        };
+        let output = render();
+        for _ in 0..4 {
+            assert_eq!(render(), output);
+        }
+        // impls are sorted by trait id, and `Shape` is declared before `Named`
+        let trait_impl = &output[output.find("trait_impl").unwrap()..];
+        let shape = trait_impl.find("0: [").unwrap();
+        let named = trait_impl.find("1: [").unwrap();
+        assert!(shape < named, "{trait_impl}");
+    }
+
+    #[test]
+    fn test_visitor() {
+        use visitor::{walk_literal, walk_literal_mut, Visitor, VisitorMut};
+
+        #[derive(Default)]
+        struct Literals(Vec<u32>, usize);
+        impl Visitor for Literals {
+            fn visit_literal(&mut self, literal: &TypedLiteral) {
+                self.1 += 1;
+                if let TypedLiteral::U32(v) = literal {
+                    self.0.push(*v);
+                }
+                walk_literal(self, literal);
+            }
+        }
+        struct Increment;
+        impl VisitorMut for Increment {
+            fn visit_literal(&mut self, literal: &mut TypedLiteral) {
+                if let TypedLiteral::U32(v) = literal {
+                    *v += 1;
+                }
+                walk_literal_mut(self, literal);
+            }
+        }
+
+        let (module_context, context) = get_context(
+            "fn f(a: u32) -> u32 { let b = [a, 1, 2]; if (a > 3) { return a + 4; } return 5; }",
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        typecheck_function(&context, &module_context, 0, false, &mut Vec::new())
+            .expect("failed to typecheck");
+        let mut functions = context.functions.write();
+        let body = &mut functions[0].1;
+
+        let mut literals = Literals::default();
+        literals.visit_body(body);
+        // the literals in the nested array and in the if block are visited as well
+        literals.0.sort();
+        assert_eq!(literals.0, [1, 2, 3, 4, 5]);
+        let count = literals.1;
+        assert!(count > 5, "{count}");
+
+        Increment.visit_body(body);
+        let mut literals = Literals::default();
+        literals.visit_body(body);
+        literals.0.sort();
+        assert_eq!(literals.0, [2, 3, 4, 5, 6]);
+        assert_eq!(literals.1, count);
+    }
+
+    #[test]
+    fn test_expression_locations() {
+        let (module_context, context) = get_context(
+            "fn f(a: i32, b: i32) -> i32 {\n    if (a > 0) {\n        return a\n            * b;\n    }\n    return a - b;\n}",
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        typecheck_function(&context, &module_context, 0, false, &mut Vec::new())
+            .expect("failed to typecheck");
+        // finds the locations of the binary expressions, which are nested in blocks
+        struct BinaryLocations(Vec<Location>);
+        impl visitor::Visitor for BinaryLocations {
+            fn visit_expression(&mut self, expression: &TypecheckedExpression) {
+                if let TypecheckedExpression::Mul(loc, ..) | TypecheckedExpression::Sub(loc, ..) =
+                    expression
+                {
+                    self.0.push(loc.clone());
+                }
+                visitor::walk_expression(self, expression);
+            }
+        }
+        let mut locations = BinaryLocations(Vec::new());
+        visitor::Visitor::visit_body(&mut locations, &context.functions.read()[0].1);
+        // lines and columns start at 0 and point at the operator
+        let locations = locations
+            .0
+            .iter()
+            .map(|loc| (&*loc.file, loc.line, loc.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [(Path::new("test"), 3, 12), (Path::new("test"), 5, 13)]
+        );
+    }
+
+    #[test]
+    fn test_sort_errors() {
+        let loc = |file: &str, line, column| Location::new(Path::new(file).into(), line, column);
+        let mut errs = vec![
+            TypecheckingError::CannotAssign(loc("b", 0, 0)),
+            TypecheckingError::CannotAssign(loc("a", 2, 1)),
+            TypecheckingError::LangItemError(crate::lang_items::LangItemError::MissingItem {
+                langitem: "panic",
+                ty: crate::lang_items::LangItemType::Function,
+            }),
+            TypecheckingError::TupleDynamicIndex(loc("a", 1, 8)),
+            TypecheckingError::CannotAssign(loc("a", 2, 0)),
+            TypecheckingError::TupleDynamicIndex(loc("a", 1, 8)),
+        ];
+        TypecheckingError::sort(&mut errs);
+        let locations = errs
+            .iter()
+            .map(|v| v.get_loc().map(|loc| loc.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                None,
+                Some("a:1:8"),
+                Some("a:1:8"),
+                Some("a:2:0"),
+                Some("a:2:1"),
+                Some("b:0:0")
+            ]
+            .map(|v| v.map(str::to_string))
+        );
+
+        // the functions are split up between the threads
+        let (module_context, context) = get_context(
+            "fn a() { let b = 1; b = 2; }\nfn c() -> i32 = d;\nfn d() { let b = .(1); let i = 0usize; b[i]; }",
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        let errs = typechecking::typecheck_functions(&context, &module_context, 2, &mut Vec::new())
+            .err()
+            .expect("expected errors");
+        let lines = errs
+            .iter()
+            .map(|v| v.get_loc().expect("expected a location").line)
+            .collect::<Vec<_>>();
+        assert!(lines.is_sorted(), "{lines:?}");
+        assert!(lines.len() >= 2, "{errs:?}");
+    }
+
+    #[test]
+    fn test_const_fn() {
+        const CONST_FNS: &str = "const fn square(x: usize) -> usize = x * x;
+const fn size() -> usize {
+    var n: usize = 1;
+    while (n < 10) { n = n * 2; }
+    if (n == 16) { return square(n) / 64; }
+    return 0;
+}
+let N: usize = size() + 1;
+";
+        let (module_context, context) = get_context(
+            &format!("{CONST_FNS}fn f() {{ let a: [u8; size()] = [1, 2, 3, 4]; }}"),
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        if let Err(errs) =
+            typechecking::typecheck_functions(&context, &module_context, 2, &mut Vec::new())
+        {
+            panic!("unexpected errors: {errs:?}");
+        }
+        let mut errs = Vec::new();
+        typechecking::typecheck_static(&context, &module_context, 0, &mut errs);
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        assert!(matches!(
+            context.statics.read()[0].1,
+            TypedLiteral::USize(5)
+        ));
+
+        // the length is evaluated to 4
+        let (module_context, context) = get_context(
+            &format!("{CONST_FNS}fn f() {{ let a: [u8; size()] = [1, 2, 3]; }}"),
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        let errs = typechecking::typecheck_functions(&context, &module_context, 1, &mut Vec::new())
+            .err()
+            .expect("expected errors");
+        assert!(matches!(
+            &errs[..],
+            [TypecheckingError::MismatchingType {
+                expected: Type::SizedArray {
+                    number_elements: 4,
+                    ..
+                },
+                ..
+            }]
+        ));
+
+        let errs = typecheck("fn g() -> usize = 2;\nconst fn h() -> usize = g() + 1;");
+        assert!(matches!(
+            &errs[..],
+            [TypecheckingError::NonConstOperation { location }] if location.line == 1
+        ));
+        // statics are only evaluated after all functions are typechecked
+        for source in [
+            "let N: usize = 4;\nfn f() { let a: [u8; N] = [1, 2, 3, 4]; }",
+            "let N: usize = 4;\nconst fn h() -> usize = N + 1;",
+        ] {
+            let (module_context, context) = get_context(source, TARGET);
+            context.resolve_types(module_context.clone());
+            let errs =
+                typechecking::typecheck_functions(&context, &module_context, 1, &mut Vec::new())
+                    .err()
+                    .expect("expected errors");
+            assert!(
+                matches!(
+                    &errs[..],
+                    [TypecheckingError::StaticInConstExpression { location }] if location.line == 1
+                ),
+                "unexpected errors for {source:?}: {errs:?}"
+            );
+        }
+        assert!(
+            resolve_types(&format!("{CONST_FNS}struct S {{ a: [u8; size()] }}"))
+                .iter()
+                .any(|err| matches!(err, TypecheckingError::ConstEvaluation { .. }))
+        );
+
+        // usize is as wide as a pointer on the target
+        for (target, fits) in [("x86_64-linux", true), ("x86-linux", false)] {
+            let (module_context, context) = get_context(
+                "const fn inc(x: usize) -> usize = x + 1;\nlet N: usize = inc(4294967295);",
+                target,
+            );
+            context.resolve_types(module_context.clone());
+            if let Err(errs) =
+                typechecking::typecheck_functions(&context, &module_context, 1, &mut Vec::new())
+            {
+                panic!("unexpected errors: {errs:?}");
+            }
+            let mut errs = Vec::new();
+            typechecking::typecheck_static(&context, &module_context, 0, &mut errs);
+            if fits {
+                assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+                assert!(matches!(
+                    context.statics.read()[0].1,
+                    TypedLiteral::USize(4294967296)
+                ));
+            } else {
+                assert!(
+                    matches!(
+                        &errs[..],
+                        [TypecheckingError::ConstEvaluation {
+                            reason: "arithmetic overflow",
+                            ..
+                        }]
+                    ),
+                    "unexpected errors: {errs:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let (module_context, context) = get_context(
+            "fn side_effect() -> bool = true;
+fn f(a: bool) -> bool = a && side_effect();
+fn g(a: bool) -> bool = a || side_effect();",
+            TARGET,
+        );
+        context.resolve_types(module_context.clone());
+        // how many branches each call to `side_effect` is nested in
+        struct CallBranches(usize, Vec<usize>);
+        impl visitor::Visitor for CallBranches {
+            fn visit_expression(&mut self, expression: &TypecheckedExpression) {
+                match expression {
+                    TypecheckedExpression::If { .. } => {
+                        self.0 += 1;
+                        visitor::walk_expression(self, expression);
+                        self.0 -= 1;
+                        return;
+                    }
+                    TypecheckedExpression::DirectCall(_, _, 0, _) => self.1.push(self.0),
+                    _ => (),
+                }
+                visitor::walk_expression(self, expression);
+            }
+        }
+        for id in [1, 2] {
+            typecheck_function(&context, &module_context, id, false, &mut Vec::new())
+                .expect("failed to typecheck");
+            let mut branches = CallBranches(0, Vec::new());
+            visitor::Visitor::visit_body(&mut branches, &context.functions.read()[id].1);
+            assert_eq!(
+                branches.1,
+                [1],
+                "the right side of function {id} isn't guarded"
+            );
+        }
+
+        assert!(matches!(
+            &typecheck("fn f() -> bool = 1 && 2;")[..],
+            [TypecheckingError::CannotLAnd(_, Type::PrimitiveI32(0))]
+        ));
+        assert!(matches!(
+            &typecheck("fn f() -> bool = true || 2;")[..],
+            [TypecheckingError::LhsNotRhs(..)]
+        ));
+    }
+
+    #[test]
+    fn test_assert() {
+        let assert = "@intrinsic(\"assert\") fn assert(cond: bool) { while (true) {} }\n";
+        assert!(typecheck(&format!("{assert}fn f(a: i32) {{ assert(a > 2); }}{PANIC}")).is_empty());
+        assert!(matches!(
+            typecheck(&format!("{assert}fn f(a: bool) {{ assert(a); }}"))[..],
+            [TypecheckingError::MissingLangItem { name: "panic", .. }]
+        ));
+        let errs = typecheck(&format!(
+            "@intrinsic(\"debug_assert\") fn debug_assert(cond: i32) {{ while (true) {{}} }}
+fn f(a: i32) {{ debug_assert(a); }}{PANIC}"
+        ));
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::MismatchingType {
+                    expected: Type::PrimitiveBool(0),
+                    ..
+                }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        for source in ["fn f() -> i32 = 1 / 0;", "fn f(a: u8) -> u8 = a % 0u8;"] {
+            let errs = typecheck(&format!("{source}{PANIC}"));
+            assert!(
+                matches!(errs[..], [TypecheckingError::DivisionByZero(_)]),
+                "unexpected errors: {errs:?}"
+            );
+        }
+        assert!(typecheck(&format!("fn f(x: i32) -> i32 = 1 / x;{PANIC}")).is_empty());
+        assert!(typecheck("fn f(x: i32) -> i32 = x % 3;").is_empty());
+        assert!(matches!(
+            typecheck("fn f(x: i32) -> i32 = 1 / x;")[..],
+            [TypecheckingError::MissingLangItem { name: "panic", .. }]
+        ));
+        assert!(typecheck("@unchecked()\nfn f(x: i32) -> i32 = 1 / x;").is_empty());
+        assert!(typecheck("fn f(a: f32, b: f32) -> f32 = a / b % 2.0;").is_empty());
+    }
+
+    #[test]
+    fn test_comparison() {
+        for source in [
+            "fn f(a: i32, b: i32) -> bool = a <= b;",
+            "fn f(a: u8, b: u8) -> bool = a == b;",
+            "fn f(a: f32, b: f32) -> bool = a > b;",
+            "fn f(a: bool, b: bool) -> bool = a != b;",
+            "fn f(a: i32, b: i32, c: bool) -> bool = (a < b) == c;",
+        ] {
+            let errs = typecheck(source);
+            assert!(errs.is_empty(), "unexpected errors in `{source}`: {errs:?}");
+        }
+        assert!(matches!(
+            typecheck("fn f(a: bool, b: bool) -> bool = a < b;")[..],
+            [TypecheckingError::CannotCompare(_, Type::PrimitiveBool(0))]
+        ));
+        let errs = typecheck("struct S { a: i32 }\nfn f(a: S, b: S) -> bool = a == b;");
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::TypeNotComparable(_, Type::Struct { .. })]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+        let errs = typecheck(
+            "@lang(\"eq_trait\") trait Eq { fn eq(self: &Self, other: &Self) -> bool; }
+@derive(Eq) struct S { a: i32 }
+fn f(a: S, b: S) -> bool = a != b;",
+        );
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+    }
+
+    #[test]
+    fn test_generic_method() {
+        // only `f` is typechecked, as the bodies of generic functions can't be typechecked yet
+        fn typecheck_f(source: &str) -> Vec<TypecheckingError> {
+            let (module_context, context) = get_context(source, TARGET);
+            let errs = context.resolve_types(module_context.clone());
+            assert!(
+                errs.iter()
+                    .all(|v| matches!(v, TypecheckingError::LangItemError(_))),
+                "unexpected errors: {errs:?}"
+            );
+            let functions = context.functions_iter();
+            let (f, _) = functions
+                .iter()
+                .find(|(_, contract)| contract.name == Some(GlobalStr::new("f")))
+                .expect("there should be a function named f");
+            drop(functions);
+            typecheck_function(&context, &module_context, f, false, &mut Vec::new())
+                .err()
+                .unwrap_or_default()
+        }
+
+        const FOO: &str = "struct Foo<T> { x: T; fn get(self: &Self) -> T = self.x; }";
+        let errs = typecheck_f(&format!("{FOO}\nfn f(foo: Foo<i32>) -> i32 = foo.get();"));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = typecheck_f(&format!("{FOO}\nfn f(foo: &Foo<u8>) -> i32 = foo.get();"));
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::MismatchingType {
+                    found: Type::PrimitiveU8(0),
+                    ..
+                }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+
+        // without type arguments the struct stays generic
+        let errs = typecheck_f(&format!("{FOO}\nfn f(foo: Foo) {{}}"));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let (module_context, context) =
+            get_context(&format!("{FOO}\nfn f(foo: Foo<i32, u8>) {{}}"), TARGET);
+        let errs = context.resolve_types(module_context);
+        assert!(
+            errs.iter()
+                .any(|v| matches!(v, TypecheckingError::MismatchingGenericCount(_, 1, 2))),
+            "unexpected errors: {errs:?}"
+        );
+
+        // the methods of trait impls are substituted the same way
+        const BAR: &str = "trait Dup { fn dup(self: &Self) -> Self; }
+struct Bar<T> { x: T; impl Dup { fn dup(self: &Self) -> Self = .{ x: self.x }; } }";
+        let errs = typecheck_f(&format!("{BAR}\nfn f(bar: Bar<i32>) -> i32 = bar.dup().x;"));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = typecheck_f(&format!("{BAR}\nfn f(bar: Bar<u8>) -> i32 = bar.dup().x;"));
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::MismatchingType {
+                    found: Type::PrimitiveU8(0),
+                    ..
+                }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+        // and can name the generics of the struct, even though that can't match a trait
+        let (module_context, context) = get_context(
+            "trait Get { fn get(self: &Self) -> i32; }
+struct Bar<T> { x: T; impl Get { fn get(self: &Self) -> T = self.x; } }",
+            TARGET,
+        );
+        let errs = context.resolve_types(module_context);
+        assert!(
+            errs.iter().all(|v| matches!(
+                v,
+                TypecheckingError::LangItemError(_)
+                    | TypecheckingError::TraitMethodSignatureMismatch { .. }
+            )),
+            "unexpected errors: {errs:?}"
+        );
+        assert!(matches!(
+            &context.functions.read()[0].0.return_type,
+            Type::Generic(name, 0) if *name == "T"
+        ));
+    }
+
+    #[test]
+    fn test_generic_struct_fields() {
+        const FOO: &str = "struct Foo<T> { x: T, y: i32 }";
+        let errs = typecheck(&format!("{FOO}\nfn f(foo: Foo<i32>) -> i32 = foo.x;"));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = typecheck(&format!("{FOO}\nfn f(foo: &Foo<u8>) -> i32 = foo.x;"));
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::MismatchingType {
+                    found: Type::PrimitiveU8(0),
+                    ..
+                }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+
+        // the generics of a literal come from the expected type...
+        for source in [
+            "fn f() -> Foo<i32> = Foo { x: 1, y: 2 };",
+            "fn f() -> Foo<i32> = .{ x: 1, y: 2 };",
+            "fn f() { let foo: Foo<u8> = .{ x: 1, y: 2 }; let x: u8 = foo.x; }",
+        ] {
+            let errs = typecheck(&format!("{FOO}\n{source}"));
+            assert!(errs.is_empty(), "unexpected errors in {source}: {errs:?}");
+        }
+        let errs = typecheck(&format!(
+            "{FOO}\nfn f() -> Foo<i32> = Foo {{ x: true, y: 2 }};"
+        ));
+        assert!(
+            matches!(
+                errs[..],
+                [TypecheckingError::MismatchingType {
+                    expected: Type::PrimitiveI32(0),
+                    found: Type::PrimitiveBool(0),
+                    ..
+                }]
+            ),
+            "unexpected errors: {errs:?}"
+        );
+
+        // ...or from the fields of that generic
+        let errs = typecheck(&format!(
+            "{FOO}\nfn f(v: &u8) -> &u8 {{ let foo = Foo {{ x: v, y: 2 }}; return foo.x; }}"
+        ));
+        assert!(errs.is_empty(), "unexpected errors: {errs:?}");
+        let errs = typecheck("struct Bar<T> { x: i32; }\nfn f() { let bar = Bar { x: 1 }; }");
+        assert!(
+            matches!(
+                &errs[..],
+                [TypecheckingError::CannotInferGeneric(_, name)] if *name == GlobalStr::new("T")
+            ),
+            "unexpected errors: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_max_depth_fits_the_stack() {
+        // the typechecking threads have to fit the deepest expression the parser accepts
+        let depth = crate::parser::DEFAULT_MAX_DEPTH - 1;
+        let source = format!(
+            "fn g(a: i32) -> i32 = a;\nfn f() -> i32 = {}1{};",
+            "g(".repeat(depth),
+            ")".repeat(depth)
+        );
+        let (module_context, context) = get_context(&source, TARGET);
+        context.resolve_types(module_context.clone());
+        let result =
+            typechecking::typecheck_functions(&context, &module_context, 1, &mut Vec::new());
+        assert!(result.is_ok(), "unexpected errors: {:?}", result.err());
+    }
+}
@@ -1,17 +1,23 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
+    globals::GlobalStr,
     lang_items::{LangItemAnnotation, LangItemErrors},
     module::{
         ExternalFunctionId, FunctionId, ModuleContext, ModuleScopeValue, StaticId, StructId,
         TraitId,
     },
     parser::TypeRef,
+    std_annotations::derive::{DerivableTrait, Derive},
 };
 
 use super::{
-    expression::TypedLiteral, resolve_import, types::Type, TypecheckedFunctionContract,
-    TypecheckingContext, TypecheckingError, TypedTrait, DUMMY_LOCATION,
+    expression::TypedLiteral, typechecking::typecheck_const_value, types::Type,
+    TypecheckedFunctionContract, TypecheckingContext, TypecheckingError, TypedTrait,
+    DUMMY_LOCATION,
 };
 
 impl TypecheckingContext {
@@ -60,10 +66,39 @@ impl TypecheckingContext {
         // +-----------+
         // | Functions |
         // +-----------+
+        // methods can refer to the generics of their struct (`struct Foo<T> { fn get(..) -> T }`)
+        // the trait impls are only checked once the functions are resolved, so their functions
+        // are taken from the impls of the module's struct
+        let mut struct_generics = HashMap::new();
+        let module_structs = context.structs.read();
+        for (struct_id, structure) in self.structs.read().iter().enumerate() {
+            if structure.generics.is_empty() {
+                continue;
+            }
+            let generics = structure
+                .generics
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            let trait_impl = module_structs[struct_id]
+                .impls
+                .iter()
+                .flat_map(|(_, implementation, ..)| implementation.values());
+            for function_id in structure.global_impl.values().chain(trait_impl) {
+                struct_generics.insert(*function_id, generics.clone());
+            }
+        }
+
+        drop(module_structs);
+
         let num_functions = context.functions.read().len();
         for function_id in 0..num_functions {
             let error_count = errors.len();
-            self.resolve_function(function_id, &context, &mut errors);
+            let struct_generics = struct_generics
+                .get(&function_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            self.resolve_function(function_id, struct_generics, &context, &mut errors);
             if error_count != errors.len() {
                 continue;
             }
@@ -162,6 +197,33 @@ impl TypecheckingContext {
         for struct_id in 0..num_structs {
             self.resolve_struct_impls(struct_id, &context, &mut errors);
         }
+        // fields of derived structs may be structs deriving `Eq` themselves, so every impl has to
+        // be resolved before the derives can be checked
+        for struct_id in 0..num_structs {
+            self.check_derives(struct_id, lang_items_writer.eq_trait, &mut errors);
+        }
+
+        // every method had its `Self` replaced by now, so the remaining ones aren't in an impl
+        let function_reader = self.functions.read();
+        let ext_function_reader = self.external_functions.read();
+        for contract in function_reader
+            .iter()
+            .map(|v| &v.0)
+            .chain(ext_function_reader.iter().map(|v| &v.0))
+        {
+            if contract.return_type.contains_self()
+                || contract
+                    .arguments
+                    .iter()
+                    .any(|(_, typ)| typ.contains_self())
+            {
+                errors.push(TypecheckingError::SelfOutsideImpl {
+                    location: contract.location.clone(),
+                });
+            }
+        }
+        drop(ext_function_reader);
+        drop(function_reader);
 
         {
             let mut lang_item_check_errors = LangItemErrors::new();
@@ -169,9 +231,47 @@ impl TypecheckingContext {
             errors.extend(lang_item_check_errors.0.into_iter().map(Into::into));
         }
 
+        TypecheckingError::sort(&mut errors);
         return errors;
     }
 
+    /// Checks that every field of the struct supports the traits it derives. The implementations
+    /// themselves were generated when the struct was added to its module.
+    fn check_derives(
+        &self,
+        struct_id: StructId,
+        eq_trait: Option<TraitId>,
+        errors: &mut Vec<TypecheckingError>,
+    ) {
+        let struct_reader = self.structs.read();
+        let typed_struct = &struct_reader[struct_id];
+        let derives = typed_struct
+            .annotations
+            .get_annotations::<Derive>()
+            .flat_map(|derive| derive.0.iter().cloned())
+            .collect::<Vec<_>>();
+        let elements = typed_struct.elements.clone();
+        drop(struct_reader);
+        for (derived_trait, location) in &derives {
+            for (field_name, typ) in &elements {
+                let supported = match derived_trait {
+                    // the derived `eq` compares the fields with `==`
+                    DerivableTrait::Eq => self.is_comparable(typ, eq_trait),
+                    DerivableTrait::Clone => {
+                        typ.refcount() > 0 || typ.is_int_like() || typ.is_float() || typ.is_bool()
+                    }
+                };
+                if !supported {
+                    errors.push(TypecheckingError::CannotDerive {
+                        location: location.clone(),
+                        trait_name: GlobalStr::new(derived_trait.name()),
+                        field_name: field_name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
     fn resolve_struct_impls(
         &self,
         struct_id: StructId,
@@ -180,98 +280,145 @@ impl TypecheckingContext {
     ) {
         let mut writer = context.structs.write();
         let trait_impl = std::mem::take(&mut writer[struct_id].impls);
+        let module = writer[struct_id].module_id;
         drop(writer);
 
+        let trait_impl = trait_impl
+            .into_iter()
+            .map(|(name, implementation, consts, loc)| {
+                let consts = consts
+                    .into_iter()
+                    .filter_map(|(name, typ, value, location)| {
+                        match self.resolve_type(module, &typ, &[]) {
+                            Ok(typ) => Some((name, typ, value, location)),
+                            Err(e) => {
+                                errors.push(e);
+                                None
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                (name, implementation, consts, loc)
+            })
+            .collect::<Vec<_>>();
+        // (trait, consts), the values are typechecked once the locks are released
+        let mut impl_consts = Vec::new();
+
         // it is *okay* to hold the lock here between resolve_type calls as all structs have
         // already been resolved.
         let mut struct_writer = self.structs.write();
         let trait_reader = self.traits.read();
         let function_reader = self.functions.read();
-        let module = struct_writer[struct_id].module_id;
-
-        for (name, implementation, loc) in trait_impl {
-            let trait_id =
-                match resolve_import(context, module, &[name.clone()], &loc, &mut Vec::new()) {
-                    Err(e) => {
-                        errors.push(e);
-                        continue;
-                    }
-                    Ok(ModuleScopeValue::Trait(trait_id)) => trait_id,
-                    Ok(_) => {
-                        errors.push(TypecheckingError::UnboundIdent {
-                            location: loc,
-                            name,
-                        });
-                        continue;
-                    }
-                };
+        let mut implemented_traits = HashSet::new();
+        // the methods of every impl, even the invalid ones, so their `Self` gets resolved
+        let mut impl_functions = Vec::new();
+
+        for (name, implementation, consts, loc) in trait_impl {
+            impl_functions.extend(implementation.values().copied());
+            let trait_id = match self.resolve_import_cached(context, module, &[name.clone()], &loc)
+            {
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+                Ok(ModuleScopeValue::Trait(trait_id)) => trait_id,
+                Ok(_) => {
+                    errors.push(TypecheckingError::UnboundIdent {
+                        location: loc,
+                        name,
+                    });
+                    continue;
+                }
+            };
 
             let typed_trait = &trait_reader[trait_id];
-            if typed_trait.functions.len() != implementation.len() {
-                for (name, func_id) in &implementation {
-                    if typed_trait
-                        .functions
-                        .iter()
-                        .find(|(v, ..)| v == name)
-                        .is_none()
-                    {
-                        errors.push(TypecheckingError::IsNotTraitMember {
-                            location: function_reader[*func_id].0.location.clone(),
-                            name: name.clone(),
-                        })
-                    }
+            if !implemented_traits.insert(trait_id) {
+                errors.push(TypecheckingError::ConflictingImpl {
+                    location: loc,
+                    trait_name: typed_trait.name.clone(),
+                });
+                continue;
+            }
+            for (name, func_id) in &implementation {
+                if !typed_trait.functions.iter().any(|(v, ..)| v == name) {
+                    errors.push(TypecheckingError::UnknownTraitMethod {
+                        location: function_reader[*func_id].0.location.clone(),
+                        name: name.clone(),
+                        trait_name: typed_trait.name.clone(),
+                    })
                 }
             }
 
             let mut trait_impl = Vec::new();
             for (name, args, return_type, ..) in &typed_trait.functions {
                 let Some(&func_id) = implementation.get(name) else {
-                    errors.push(TypecheckingError::MissingTraitItem {
+                    errors.push(TypecheckingError::MissingTraitMethod {
                         location: loc.clone(),
                         name: name.clone(),
+                        trait_name: typed_trait.name.clone(),
                     });
                     continue;
                 };
 
-                let mut with_errs = false;
-
                 let function_contract = &function_reader[func_id].0;
-                if !function_contract
-                    .arguments
-                    .iter()
-                    .zip(args)
-                    .map(|((_, typ_a), (_, typ_b))| *typ_a == *typ_b)
-                    .fold(true, |acc, v| acc && v)
-                {
-                    let expected = args.iter().map(|(_, v)| v.clone()).collect::<Vec<_>>();
-                    let found = function_contract
+                let arguments_match = function_contract.arguments.len() == args.len()
+                    && function_contract
                         .arguments
                         .iter()
-                        .map(|(_, v)| v.clone())
-                        .collect::<Vec<_>>();
-                    errors.push(TypecheckingError::MismatchingArguments {
+                        .zip(args)
+                        .all(|((_, typ_a), (_, typ_b))| *typ_a == *typ_b);
+                if !arguments_match || *return_type != function_contract.return_type {
+                    errors.push(TypecheckingError::TraitMethodSignatureMismatch {
                         location: function_contract.location.clone(),
-                        expected,
-                        found,
+                        name: name.clone(),
+                        expected: args.iter().map(|(_, v)| v.clone()).collect(),
+                        expected_return: return_type.clone(),
+                        found: function_contract
+                            .arguments
+                            .iter()
+                            .map(|(_, v)| v.clone())
+                            .collect(),
+                        found_return: function_contract.return_type.clone(),
                     });
-                    with_errs = true;
+                    continue;
                 }
+                trait_impl.push(func_id);
+            }
 
-                if *return_type != function_contract.return_type {
-                    errors.push(TypecheckingError::MismatchingReturnType {
-                        location: function_contract.location.clone(),
-                        expected: return_type.clone(),
-                        found: function_contract.return_type.clone(),
+            let mut consts_valid = true;
+            for (name, _, _, location) in &consts {
+                if !typed_trait.consts.iter().any(|(v, ..)| v == name) {
+                    errors.push(TypecheckingError::UnknownTraitConst {
+                        location: location.clone(),
+                        name: name.clone(),
+                        trait_name: typed_trait.name.clone(),
                     });
-                    with_errs = true
+                    consts_valid = false;
                 }
-                if !with_errs {
-                    trait_impl.push(func_id);
+            }
+            for (name, typ, _) in &typed_trait.consts {
+                match consts.iter().find(|(v, ..)| v == name) {
+                    None => errors.push(TypecheckingError::MissingTraitConst {
+                        location: loc.clone(),
+                        name: name.clone(),
+                        trait_name: typed_trait.name.clone(),
+                    }),
+                    Some((_, found, _, location)) if found != typ => {
+                        errors.push(TypecheckingError::MismatchingType {
+                            expected: typ.clone(),
+                            found: found.clone(),
+                            location: location.clone(),
+                        })
+                    }
+                    Some(_) => continue,
                 }
+                consts_valid = false;
             }
-            if trait_impl.len() != typed_trait.functions.len() {
+
+            if trait_impl.len() != typed_trait.functions.len() || !consts_valid {
                 continue;
             }
+            impl_consts.push((trait_id, consts));
             struct_writer[struct_id]
                 .trait_impl
                 .insert(trait_id, trait_impl);
@@ -286,38 +433,36 @@ impl TypecheckingContext {
             .global_impl
             .values()
             .copied()
-            .chain(
-                struct_reader[struct_id]
-                    .trait_impl
-                    .values()
-                    .map(|v| v.iter())
-                    .flatten()
-                    .copied(),
-            )
+            .chain(impl_functions)
         {
             let contract = &mut function_writer[fn_id].0;
-            if let Type::PrimitiveSelf(num_references) = contract.return_type {
-                contract.return_type = Type::Struct {
-                    struct_id,
-                    name: struct_reader[struct_id].name.clone(),
-                    num_references,
-                }
+            let self_type = struct_reader[struct_id].self_type();
+            contract.return_type.replace_self(&self_type);
+            for (_, typ) in contract.arguments.iter_mut() {
+                typ.replace_self(&self_type);
             }
-            for t in contract.arguments.iter_mut() {
-                if let Type::PrimitiveSelf(num_references) = t.1 {
-                    t.1 = Type::Struct {
-                        struct_id,
-                        name: struct_reader[struct_id].name.clone(),
-                        num_references,
-                    }
+        }
+        drop(function_writer);
+        drop(struct_reader);
+
+        for (trait_id, consts) in impl_consts {
+            let mut typed_consts = Vec::with_capacity(consts.len());
+            for (name, typ, value, location) in consts {
+                match typecheck_const_value(self, module, &typ, value, &location) {
+                    Ok(value) => typed_consts.push((name, typ, value)),
+                    Err(e) => errors.push(e),
                 }
             }
+            self.structs.write()[struct_id]
+                .trait_consts
+                .insert(trait_id, typed_consts);
         }
     }
 
     fn resolve_function(
         &self,
         function_id: FunctionId,
+        struct_generics: &[GlobalStr],
         context: &ModuleContext,
         errors: &mut Vec<TypecheckingError>,
     ) {
@@ -331,6 +476,7 @@ impl TypecheckingContext {
         let generics = std::mem::take(&mut writer[function_id].0.generics)
             .into_iter()
             .map(|generic| generic.name)
+            .chain(struct_generics.iter().cloned())
             .collect::<Vec<_>>();
         let mut resolved_function_contract = TypecheckedFunctionContract {
             module_id,
@@ -446,6 +592,7 @@ impl TypecheckingContext {
         let name = writer[trait_id].name.clone();
         let annotations = std::mem::take(&mut writer[trait_id].annotations);
         let functions = std::mem::take(&mut writer[trait_id].functions);
+        let consts = std::mem::take(&mut writer[trait_id].consts);
         let module_id = writer[trait_id].module_id;
         drop(writer);
 
@@ -479,6 +626,14 @@ impl TypecheckingContext {
             ));
         }
 
+        let mut typed_consts = Vec::with_capacity(consts.len());
+        for (name, typ, location) in consts {
+            match self.resolve_type(module_id, &typ, &[]) {
+                Ok(typ) => typed_consts.push((name, typ, location)),
+                Err(e) => errors.push(e),
+            }
+        }
+
         if errors.len() == error_count {
             self.traits.write()[trait_id] = TypedTrait {
                 name,
@@ -487,6 +642,7 @@ impl TypecheckingContext {
                 module_id,
                 annotations,
                 functions: typed_functions,
+                consts: typed_consts,
             };
         }
     }
@@ -1,19 +1,24 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc},
+};
 
 use crate::{
+    annotations::Annotations,
     globals::GlobalStr,
-    module::{ModuleContext, ModuleId, ModuleScopeValue, StaticId},
-    parser::{BinaryOp, Expression, LiteralValue, Path, Statement, UnaryOp},
-    std_annotations::ext_vararg::ExternVarArg,
+    module::{FunctionId, ModuleContext, ModuleId, ModuleScopeValue, StaticId, StructId},
+    parser::{BinaryOp, Expression, LiteralValue, Path, Statement, TypeRef, UnaryOp},
+    std_annotations::{const_fn::ConstFn, ext_vararg::ExternVarArg, unchecked::Unchecked},
     tokenizer::{Location, NumberType},
     typechecking::typed_resolve_import,
 };
 
 use super::{
+    const_eval,
     expression::{OffsetValue, TypecheckedExpression, TypedLiteral},
-    intrinsics::IntrinsicAnnotation,
+    intrinsics::{Intrinsic, IntrinsicAnnotation},
     types::{FunctionType, Type, TypeSuggestion},
-    TypecheckingContext, TypecheckingError,
+    TypecheckingContext, TypecheckingError, TypecheckingWarning,
 };
 
 pub type ScopeValueId = usize;
@@ -24,15 +29,180 @@ pub struct ScopeTypeMetadata {
 
 pub struct Scopes {
     entries: Vec<HashMap<GlobalStr, ScopeValueId>>,
+    /// where the variables of each scope in `entries` were declared and if they're mutable
+    declarations: Vec<HashMap<GlobalStr, (Location, bool)>>,
     values: Vec<(Type, ScopeTypeMetadata)>,
+    warnings: Vec<TypecheckingWarning>,
+    /// if indexing into arrays is bounds checked and divisors are checked for zero, which
+    /// functions can opt out of with `@unchecked`
+    bounds_checks: bool,
+    /// the struct the function is a method of, which `Self` refers to
+    self_type: Option<Type>,
 }
 
 impl Scopes {
     pub fn new() -> Self {
         Self {
             entries: vec![HashMap::new()],
+            declarations: vec![HashMap::new()],
             values: Vec::new(),
+            warnings: Vec::new(),
+            bounds_checks: true,
+            self_type: None,
+        }
+    }
+
+    /// Resolves a type in the body of the function, with `Self` referring to the struct the
+    /// function is a method of
+    fn resolve_type(
+        &self,
+        context: &TypecheckingContext,
+        module: ModuleId,
+        typ: &TypeRef,
+    ) -> Result<Type, TypecheckingError> {
+        let mut resolved = context.resolve_type(module, typ, &[])?;
+        if resolved.contains_self() {
+            let Some(self_type) = &self.self_type else {
+                return Err(TypecheckingError::SelfOutsideImpl {
+                    location: typ.loc().clone(),
+                });
+            };
+            resolved.replace_self(self_type);
+        }
+        Ok(resolved)
+    }
+
+    /// Emits a bounds check before indexing into `array`, a `&[T; N]` or `&[T]`, unless the
+    /// function opted out of them. A failing bounds check calls the `panic` lang item, so it has
+    /// to be defined.
+    fn bounds_check(
+        &self,
+        context: &TypecheckingContext,
+        exprs: &mut Vec<TypecheckedExpression>,
+        loc: &Location,
+        array: &TypedLiteral,
+        offset: OffsetValue,
+    ) -> Result<(), TypecheckingError> {
+        if !self.bounds_checks {
+            return Ok(());
+        }
+        require_panic_lang_item(context, loc)?;
+        exprs.push(TypecheckedExpression::BoundsCheck(
+            loc.clone(),
+            array.clone(),
+            offset,
+        ));
+        Ok(())
+    }
+
+    /// Rejects integer divisions by a literal zero and checks other divisors at runtime, unless
+    /// the function opted out of runtime checks. The check asserts that the divisor is not zero,
+    /// so it calls the `panic` lang item if it is. Float divisions are never checked.
+    fn division_by_zero_check(
+        &mut self,
+        context: &TypecheckingContext,
+        exprs: &mut Vec<TypecheckedExpression>,
+        loc: &Location,
+        divisor: &TypedLiteral,
+        typ: &Type,
+    ) -> Result<(), TypecheckingError> {
+        let Some(zero) = TypedLiteral::int_zero(typ) else {
+            return Ok(());
+        };
+        if divisor.is_int_zero() {
+            return Err(TypecheckingError::DivisionByZero(loc.clone()));
+        }
+        if !self.bounds_checks
+            || !matches!(divisor, TypedLiteral::Dynamic(_) | TypedLiteral::Static(_))
+        {
+            return Ok(());
         }
+        require_panic_lang_item(context, loc)?;
+        let is_nonzero = self.push(Type::PrimitiveBool(0));
+        exprs.push(TypecheckedExpression::Neq(
+            loc.clone(),
+            is_nonzero,
+            divisor.clone(),
+            zero,
+        ));
+        self.push_assert(exprs, loc, is_nonzero);
+        Ok(())
+    }
+
+    /// Checks that `start <= end <= len` when slicing an array at runtime, unless the function
+    /// opted out of runtime checks. `len` is `None` for slices, whose length is read from their
+    /// metadata. Parts that are already known to hold at compile time aren't checked again.
+    fn slice_bounds_check(
+        &mut self,
+        context: &TypecheckingContext,
+        exprs: &mut Vec<TypecheckedExpression>,
+        loc: &Location,
+        array: &TypedLiteral,
+        (start, end): (&TypedLiteral, &TypedLiteral),
+        len: Option<usize>,
+    ) -> Result<(), TypecheckingError> {
+        let is_static = |v: &TypedLiteral| matches!(v, TypedLiteral::USize(_));
+        let check_order = !is_static(start) || !is_static(end);
+        let check_end = !is_static(end) || len.is_none();
+        if !self.bounds_checks || (!check_order && !check_end) {
+            return Ok(());
+        }
+        require_panic_lang_item(context, loc)?;
+        if check_order {
+            let is_ordered = self.push(Type::PrimitiveBool(0));
+            exprs.push(TypecheckedExpression::LessThanEq(
+                loc.clone(),
+                is_ordered,
+                start.clone(),
+                end.clone(),
+            ));
+            self.push_assert(exprs, loc, is_ordered);
+        }
+        if check_end {
+            let len = match len {
+                Some(len) => TypedLiteral::USize(len),
+                None => {
+                    let len = self.push(Type::PrimitiveUSize(0));
+                    exprs.push(TypecheckedExpression::IntrinsicCall(
+                        loc.clone(),
+                        len,
+                        Intrinsic::GetMetadata,
+                        vec![array.clone()],
+                    ));
+                    TypedLiteral::Dynamic(len)
+                }
+            };
+            let in_bounds = self.push(Type::PrimitiveBool(0));
+            exprs.push(TypecheckedExpression::LessThanEq(
+                loc.clone(),
+                in_bounds,
+                end.clone(),
+                len,
+            ));
+            self.push_assert(exprs, loc, in_bounds);
+        }
+        Ok(())
+    }
+
+    /// Asserts that the bool `cond` is true, calling the `panic` lang item if it isn't
+    fn push_assert(
+        &mut self,
+        exprs: &mut Vec<TypecheckedExpression>,
+        loc: &Location,
+        cond: ScopeValueId,
+    ) {
+        let dst = self.push(Type::PrimitiveVoid(0));
+        exprs.push(TypecheckedExpression::IntrinsicCall(
+            loc.clone(),
+            dst,
+            Intrinsic::Assert,
+            vec![TypedLiteral::Dynamic(cond)],
+        ));
+    }
+
+    /// Reports a warning for the function this scope belongs to
+    pub fn warn(&mut self, warning: TypecheckingWarning) {
+        self.warnings.push(warning);
     }
 
     pub fn get(&self, key: &GlobalStr) -> Option<(&(Type, ScopeTypeMetadata), ScopeValueId)> {
@@ -66,6 +236,35 @@ impl Scopes {
         self.entries[idx].insert(key, value)
     }
 
+    /// Inserts a variable declared at `location`. Returns where the previous variable of the same
+    /// name was declared if it is shadowed in the same scope.
+    pub fn declare(
+        &mut self,
+        key: GlobalStr,
+        value: ScopeValueId,
+        location: Location,
+        mutable: bool,
+    ) -> Option<Location> {
+        self.insert(key.clone(), value);
+        let idx = self.declarations.len() - 1;
+        self.declarations[idx]
+            .insert(key, (location, mutable))
+            .map(|(location, _)| location)
+    }
+
+    /// Returns if the variable `key` can be assigned to. Only variables declared with `let` are
+    /// immutable, function arguments and loop variables can always be assigned to.
+    pub fn is_mutable(&self, key: &GlobalStr) -> bool {
+        for i in (0..self.entries.len()).rev() {
+            if self.entries[i].contains_key(key) {
+                return self.declarations[i]
+                    .get(key)
+                    .map_or(true, |(_, mutable)| *mutable);
+            }
+        }
+        true
+    }
+
     pub fn push(&mut self, value: Type) -> ScopeValueId {
         if !value.is_sized() {
             panic!("unsized type: {value:?}");
@@ -84,6 +283,7 @@ impl Scopes {
 
     pub fn push_scope(&mut self) {
         self.entries.push(HashMap::new());
+        self.declarations.push(HashMap::new());
     }
 
     /// Returns if it could pop a scope or not.
@@ -92,10 +292,13 @@ impl Scopes {
             return false;
         }
         assert!(self.entries.pop().is_some(), "a scope should always exist");
+        self.declarations.pop();
         true
     }
 }
 
+/// Typechecks the value of a static. Values that aren't a literal are evaluated at compile time,
+/// so this has to run after the functions were typechecked, see [`typecheck_functions`].
 pub fn typecheck_static(
     context: &TypecheckingContext,
     module_context: &ModuleContext,
@@ -104,19 +307,22 @@ pub fn typecheck_static(
 ) -> bool {
     let tc_module_reader = context.statics.read();
     let typ = &tc_module_reader[static_id].0;
+    let location = &tc_module_reader[static_id].3;
     let expr = {
         std::mem::replace(
             &mut module_context.statics.write()[static_id].1,
-            LiteralValue::Void,
+            Expression::Literal(LiteralValue::Void, location.clone()),
         )
     };
 
+    let mut scope = Scopes::new();
+    let mut exprs = Vec::new();
     match typecheck_expression(
         context,
         tc_module_reader[static_id].2,
-        &mut Scopes::new(),
-        &Expression::Literal(expr, tc_module_reader[static_id].3.clone()),
-        &mut Vec::new(),
+        &mut scope,
+        &expr,
+        &mut exprs,
         TypeSuggestion::from_type(typ),
     ) {
         Err(e) => {
@@ -132,24 +338,393 @@ pub fn typecheck_static(
                 });
                 return false;
             }
-            if !expr.is_entirely_literal() {
-                errs.push(TypecheckingError::StaticsNeedToBeLiteral(
-                    tc_module_reader[static_id].3.clone(),
-                ));
+            let value = if expr.is_entirely_literal() || exprs.is_empty() {
+                expr
+            } else {
+                let types = scope
+                    .values
+                    .into_iter()
+                    .map(|(typ, _)| typ)
+                    .collect::<Vec<_>>();
+                match const_eval::evaluate(context, &exprs, &types, &expr, location) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        errs.push(e);
+                        return false;
+                    }
+                }
+            };
+            if !value.is_entirely_literal() {
+                errs.push(TypecheckingError::StaticsNeedToBeLiteral(location.clone()));
             }
             drop(tc_module_reader);
-            context.statics.write()[static_id].1 = expr;
+            context.statics.write()[static_id].1 = value;
         }
     }
     true
 }
 
+/// Typechecks the value of an associated const, which has to be a literal of type `typ`
+pub(super) fn typecheck_const_value(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    typ: &Type,
+    value: LiteralValue,
+    location: &Location,
+) -> Result<TypedLiteral, TypecheckingError> {
+    let (value_typ, value) = typecheck_expression(
+        context,
+        module,
+        &mut Scopes::new(),
+        &Expression::Literal(value, location.clone()),
+        &mut Vec::new(),
+        TypeSuggestion::from_type(typ),
+    )?;
+    if *typ != value_typ {
+        return Err(TypecheckingError::MismatchingType {
+            expected: typ.clone(),
+            found: value_typ,
+            location: location.clone(),
+        });
+    }
+    if !value.is_entirely_literal() {
+        return Err(TypecheckingError::StaticsNeedToBeLiteral(location.clone()));
+    }
+    Ok(value)
+}
+
+/// Typechecks `expr` as a value of type `typ` and evaluates it at compile time, see
+/// [`const_eval::evaluate`]. The only functions it can call are `const fn`s, whose bodies have to
+/// be typechecked already.
+pub(super) fn evaluate_const_expression(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    expr: &Expression,
+    typ: &Type,
+) -> Result<TypedLiteral, TypecheckingError> {
+    let mut scope = Scopes::new();
+    let mut exprs = Vec::new();
+    let (value_typ, value) = typecheck_expression(
+        context,
+        module,
+        &mut scope,
+        expr,
+        &mut exprs,
+        TypeSuggestion::from_type(typ),
+    )?;
+    if *typ != value_typ {
+        return Err(TypecheckingError::MismatchingType {
+            expected: typ.clone(),
+            found: value_typ,
+            location: expr.loc().clone(),
+        });
+    }
+    let types = scope
+        .values
+        .into_iter()
+        .map(|(typ, _)| typ)
+        .collect::<Vec<_>>();
+    const_eval::evaluate(context, &exprs, &types, &value, expr.loc())
+}
+
+pub type FunctionScope = Vec<(Type, ScopeTypeMetadata)>;
+/// The scopes of every function and every external function, indexed by their ids
+pub type FunctionScopes = (Vec<FunctionScope>, Vec<FunctionScope>);
+
 pub fn typecheck_function(
     context: &TypecheckingContext,
     module_context: &ModuleContext,
     function_id: usize,
     is_external: bool,
-) -> Result<Vec<(Type, ScopeTypeMetadata)>, Vec<TypecheckingError>> {
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<FunctionScope, Vec<TypecheckingError>> {
+    let (scope, body, function_warnings) =
+        typecheck_function_body(context, module_context, function_id, is_external).map_err(
+            |mut errs| {
+                TypecheckingError::sort(&mut errs);
+                errs
+            },
+        )?;
+    warnings.extend(function_warnings);
+    if let Some(body) = body {
+        store_function_body(context, function_id, is_external, body, &scope);
+    }
+    Ok(scope)
+}
+
+/// Typechecks the bodies of all functions and external functions on up to `num_threads` threads.
+///
+/// Once the types are resolved, function bodies only read from the contexts, so they can be
+/// checked independently of each other. The typechecked bodies are stored after all threads
+/// finished, which means no thread ever waits for a write lock while another one holds a read
+/// guard. The errors are sorted by their location, so the result does not depend on the amount of
+/// threads. Warnings are appended to `warnings` in the order of the function ids, with the ones of
+/// `const fn`s first.
+///
+/// The `const fn`s are typechecked before all other functions, as array lengths can call them.
+pub fn typecheck_functions(
+    context: &TypecheckingContext,
+    module_context: &ModuleContext,
+    num_threads: usize,
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<FunctionScopes, Vec<TypecheckingError>> {
+    let num_functions = context.functions.read().len();
+    let num_ext_functions = context.external_functions.read().len();
+    let jobs = (0..num_functions)
+        .map(|id| (id, false))
+        .chain((0..num_ext_functions).map(|id| (id, true)))
+        .collect::<Vec<_>>();
+
+    let mut scopes = (0..num_functions).map(|_| Vec::new()).collect::<Vec<_>>();
+    let mut ext_scopes = (0..num_ext_functions)
+        .map(|_| Vec::new())
+        .collect::<Vec<_>>();
+    for ((id, is_external), scope) in
+        typecheck_const_fns_first(context, module_context, jobs, num_threads, warnings)?
+    {
+        if is_external {
+            ext_scopes[id] = scope;
+        } else {
+            scopes[id] = scope;
+        }
+    }
+    Ok((scopes, ext_scopes))
+}
+
+/// Replaces the bodies of the functions in `edits` with the new statements and typechecks the
+/// bodies of every function in the edited modules and in every module that depends on them
+/// again. `scopes` are the scopes returned by [`typecheck_functions`], the scopes of every other
+/// function are kept as they are. Returns how many functions were typechecked again.
+///
+/// Only the function bodies can change, the types and signatures have to be unchanged. If the
+/// new bodies fail to typecheck, the errors are returned and the edits are undone, leaving the
+/// statements, typechecked bodies and scopes as they were before.
+pub fn retypecheck_modules(
+    context: &TypecheckingContext,
+    module_context: &ModuleContext,
+    edits: Vec<(FunctionId, Statement)>,
+    scopes: &mut FunctionScopes,
+    num_threads: usize,
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<usize, Vec<TypecheckingError>> {
+    let mut changed = Vec::with_capacity(edits.len());
+    let mut old_statements = Vec::with_capacity(edits.len());
+    let mut statement_writer = module_context.functions.write();
+    for (id, statement) in edits {
+        changed.push(statement_writer[id].2);
+        old_statements.push((
+            id,
+            std::mem::replace(&mut statement_writer[id].1, statement),
+        ));
+    }
+    drop(statement_writer);
+
+    let invalidated = context.invalidated_modules(&changed);
+    let mut jobs = Vec::new();
+    let mut old_bodies = Vec::new();
+    let mut old_const_fn_scopes = Vec::new();
+    for (id, (contract, body)) in context.functions.write().iter_mut().enumerate() {
+        if invalidated.contains(&contract.module_id) {
+            old_bodies.push((id, std::mem::take(body)));
+            old_const_fn_scopes.extend(
+                context
+                    .const_fn_scopes
+                    .write()
+                    .remove(&id)
+                    .map(|scope| (id, scope)),
+            );
+            jobs.push((id, false));
+        }
+    }
+    let mut old_ext_bodies = Vec::new();
+    for (id, (contract, body)) in context.external_functions.write().iter_mut().enumerate() {
+        if invalidated.contains(&contract.module_id) {
+            old_ext_bodies.push((id, body.take()));
+            jobs.push((id, true));
+        }
+    }
+
+    let num_jobs = jobs.len();
+    let new_scopes =
+        match typecheck_const_fns_first(context, module_context, jobs, num_threads, warnings) {
+            Ok(new_scopes) => new_scopes,
+            Err(errs) => {
+                let mut statement_writer = module_context.functions.write();
+                for (id, statement) in old_statements {
+                    statement_writer[id].1 = statement;
+                }
+                // the bodies that did typecheck were already stored, along with their scopes
+                let mut function_writer = context.functions.write();
+                let mut const_fn_scopes = context.const_fn_scopes.write();
+                for (id, body) in old_bodies {
+                    function_writer[id].1 = body;
+                    const_fn_scopes.remove(&id);
+                }
+                const_fn_scopes.extend(old_const_fn_scopes);
+                let mut ext_function_writer = context.external_functions.write();
+                for (id, body) in old_ext_bodies {
+                    ext_function_writer[id].1 = body;
+                }
+                return Err(errs);
+            }
+        };
+    for ((id, is_external), scope) in new_scopes {
+        if is_external {
+            scopes.1[id] = scope;
+        } else {
+            scopes.0[id] = scope;
+        }
+    }
+    Ok(num_jobs)
+}
+
+/// Runs [`typecheck_jobs`] for the `const fn`s among the `jobs` and then for all other jobs, which
+/// can evaluate calls to the `const fn`s in array lengths. The errors of both are returned
+/// together.
+fn typecheck_const_fns_first(
+    context: &TypecheckingContext,
+    module_context: &ModuleContext,
+    jobs: Vec<(usize, bool)>,
+    num_threads: usize,
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<Vec<((usize, bool), FunctionScope)>, Vec<TypecheckingError>> {
+    let (const_jobs, jobs) = jobs
+        .into_iter()
+        .partition::<Vec<_>, _>(|&(id, is_external)| {
+            !is_external && const_eval::is_const_fn(context, id)
+        });
+
+    context
+        .const_fns_typechecked
+        .store(false, Ordering::Release);
+    let const_result = typecheck_jobs(context, module_context, const_jobs, num_threads, warnings);
+    context.const_fns_typechecked.store(true, Ordering::Release);
+    let result = typecheck_jobs(context, module_context, jobs, num_threads, warnings);
+
+    match (const_result, result) {
+        (Ok(mut scopes), Ok(other_scopes)) => {
+            scopes.extend(other_scopes);
+            Ok(scopes)
+        }
+        (Err(mut errs), Err(other_errs)) => {
+            errs.extend(other_errs);
+            TypecheckingError::sort(&mut errs);
+            Err(errs)
+        }
+        (Err(errs), Ok(_)) | (Ok(_), Err(errs)) => Err(errs),
+    }
+}
+
+/// The stack size of the typechecking threads. Typechecking an expression recurses into its
+/// operands, which takes ~68 KiB per level of nesting in debug builds. This leaves room for the
+/// nesting the parser allows by default (see [`crate::parser::DEFAULT_MAX_DEPTH`]).
+pub const TYPECHECKING_STACK_SIZE: usize = 8 << 20;
+
+/// Typechecks the bodies of the (function id, is external) `jobs` on `num_threads` threads and
+/// stores them, see [`typecheck_functions`]. Returns the scope of every job.
+fn typecheck_jobs(
+    context: &TypecheckingContext,
+    module_context: &ModuleContext,
+    jobs: Vec<(usize, bool)>,
+    num_threads: usize,
+    warnings: &mut Vec<TypecheckingWarning>,
+) -> Result<Vec<((usize, bool), FunctionScope)>, Vec<TypecheckingError>> {
+    let chunk_size = jobs.len().div_ceil(num_threads.max(1)).max(1);
+
+    let results = std::thread::scope(|s| {
+        let handles = jobs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                std::thread::Builder::new()
+                    .stack_size(TYPECHECKING_STACK_SIZE)
+                    .spawn_scoped(s, move || {
+                        chunk
+                            .iter()
+                            .map(|&(id, is_external)| {
+                                typecheck_function_body(context, module_context, id, is_external)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .expect("failed to spawn a typechecking thread")
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut errs = Vec::new();
+    let mut scopes = Vec::with_capacity(jobs.len());
+    for ((id, is_external), result) in jobs.into_iter().zip(results) {
+        match result {
+            Ok((scope, body, function_warnings)) => {
+                warnings.extend(function_warnings);
+                if let Some(body) = body {
+                    store_function_body(context, id, is_external, body, &scope);
+                }
+                scopes.push(((id, is_external), scope));
+            }
+            Err(e) => errs.extend(e),
+        }
+    }
+
+    if errs.len() > 0 {
+        TypecheckingError::sort(&mut errs);
+        return Err(errs);
+    }
+    Ok(scopes)
+}
+
+fn store_function_body(
+    context: &TypecheckingContext,
+    function_id: usize,
+    is_external: bool,
+    body: Box<[TypecheckedExpression]>,
+    scope: &FunctionScope,
+) {
+    if is_external {
+        let mut exprs = Some(body);
+        std::mem::swap(
+            &mut exprs,
+            &mut context.external_functions.write()[function_id].1,
+        );
+        assert!(exprs.is_none());
+    } else {
+        let mut boxed_slice = body;
+        let mut writer = context.functions.write();
+        std::mem::swap(&mut boxed_slice, &mut writer[function_id].1);
+        assert_eq!(boxed_slice.len(), 0);
+        if writer[function_id]
+            .0
+            .annotations
+            .get_first_annotation::<ConstFn>()
+            .is_some()
+        {
+            let types = scope.iter().map(|(typ, _)| typ.clone()).collect();
+            context.const_fn_scopes.write().insert(function_id, types);
+        }
+    }
+}
+
+type FunctionBody = (
+    FunctionScope,
+    Option<Box<[TypecheckedExpression]>>,
+    Vec<TypecheckingWarning>,
+);
+
+/// Typechecks the body of a function without storing it in the context. Returns `None` as the body
+/// for external functions that are only declared.
+fn typecheck_function_body(
+    context: &TypecheckingContext,
+    module_context: &ModuleContext,
+    function_id: usize,
+    is_external: bool,
+) -> Result<FunctionBody, Vec<TypecheckingError>> {
     let ext_fn_reader = module_context.external_functions.read();
     let fn_reader = module_context.functions.read();
     let (statement, module_id) = if is_external {
@@ -157,7 +732,7 @@ pub fn typecheck_function(
         if let Some(statement) = statement {
             (statement, module_id)
         } else {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), None, Vec::new()));
         }
     } else {
         let (_, ref statement, module_id) = fn_reader[function_id];
@@ -168,13 +743,34 @@ pub fn typecheck_function(
 
     let (return_type, args, loc) = if is_external {
         let contract = &context.external_functions.read()[function_id].0;
+        scope.bounds_checks = contract
+            .annotations
+            .get_first_annotation::<Unchecked>()
+            .is_none();
         (
             contract.return_type.clone(),
             contract.arguments.clone(),
             contract.location.clone(),
         )
     } else {
+        scope.self_type = context
+            .structs
+            .read()
+            .iter()
+            .enumerate()
+            .find(|(_, structure)| {
+                structure
+                    .global_impl
+                    .values()
+                    .chain(structure.trait_impl.values().flatten())
+                    .any(|id| *id == function_id)
+            })
+            .map(|(_, structure)| structure.self_type());
         let contract = &context.functions.read()[function_id].0;
+        scope.bounds_checks = contract
+            .annotations
+            .get_first_annotation::<Unchecked>()
+            .is_none();
         (
             contract.return_type.clone(),
             contract.arguments.clone(),
@@ -236,25 +832,13 @@ pub fn typecheck_function(
                 //    TypedLiteral::Void,
                 //));
             }
-            if is_external {
-                let mut exprs = Some(exprs.into_boxed_slice());
-                std::mem::swap(
-                    &mut exprs,
-                    &mut context.external_functions.write()[function_id].1,
-                );
-                assert!(exprs.is_none());
-            } else {
-                let mut boxed_slice = exprs.into_boxed_slice();
-                std::mem::swap(
-                    &mut boxed_slice,
-                    &mut context.functions.write()[function_id].1,
-                );
-                assert_eq!(boxed_slice.len(), 0);
-            }
         }
         Err(e) => return Err(e),
     }
-    Ok(scope.values)
+    if !is_external && const_eval::is_const_fn(context, function_id) {
+        const_eval::check_const_fn(context, &exprs)?;
+    }
+    Ok((scope.values, Some(exprs.into_boxed_slice()), scope.warnings))
 }
 
 /// Returns if the statement and if it always returns
@@ -408,6 +992,12 @@ fn typecheck_statement(
                 TypeSuggestion::from_type(return_type),
             )
             .map_err(|e| vec![e])?;
+            // `!` satisfies every return type: the expression never produces a value to return,
+            // so the return is unreachable.
+            if typ == Type::PrimitiveNever {
+                exprs.push(TypecheckedExpression::Unreachable(location.clone()));
+                return Ok(true);
+            }
             if typ != *return_type {
                 return Err(vec![TypecheckingError::MismatchingType {
                     expected: return_type.clone(),
@@ -427,7 +1017,7 @@ fn typecheck_statement(
             let mut always_returns = false;
             scope.push_scope();
 
-            for statement in statements.iter() {
+            for (i, statement) in statements.iter().enumerate() {
                 match typecheck_statement(
                     context,
                     scope,
@@ -438,6 +1028,12 @@ fn typecheck_statement(
                 ) {
                     Ok(true) => {
                         always_returns = true;
+                        // the statements after a diverging one are never typechecked
+                        if let Some(next) = statements.get(i + 1) {
+                            scope.warn(TypecheckingWarning::UnreachableCode {
+                                location: next.loc().clone(),
+                            });
+                        }
                         if !matches!(statement, Statement::Return(..)) {
                             block_exprs
                                 .push(TypecheckedExpression::Unreachable(statement.loc().clone()));
@@ -464,14 +1060,20 @@ fn typecheck_statement(
                 Ok(always_returns)
             }
         }
-        Statement::Var(name, expression, type_ref, location, _) => {
+        Statement::Var(name, expression, type_ref, location, _, mutable) => {
             let expected_typ = type_ref
                 .as_ref()
-                .map(|v| context.resolve_type(module, v, &[]))
+                .map(|v| scope.resolve_type(context, module, v))
                 .transpose()
                 .map_err(|v| vec![v])?;
+            if let Some(typ) = expected_typ.as_ref().filter(|typ| !typ.is_sized()) {
+                return Err(vec![TypecheckingError::UnsizedValue {
+                    location: location.clone(),
+                    typ: typ.clone(),
+                }]);
+            }
 
-            let (typ, expr) = typecheck_expression(
+            let (mut typ, mut expr) = typecheck_expression(
                 context,
                 module,
                 scope,
@@ -484,18 +1086,23 @@ fn typecheck_statement(
             )
             .map_err(|e| vec![e])?;
 
+            // `!` coerces to every type: as the value never exists, the variable doesn't need to
+            // be declared and nothing after it is reachable.
+            if typ == Type::PrimitiveNever {
+                return Ok(true);
+            }
+
             if let Some(expected_typ) = expected_typ {
-                if expected_typ != typ {
-                    return Err(vec![TypecheckingError::MismatchingType {
-                        expected: expected_typ,
-                        found: typ,
-                        location: location.clone(),
-                    }]);
-                }
+                expr = coerce(scope, exprs, typ, expr, &expected_typ, location)
+                    .map_err(|e| vec![e])?;
+                typ = expected_typ;
             }
 
+            // variables are copied, so that assigning to the new variable doesn't change the old one
+            let is_variable =
+                matches!(expression, Expression::Literal(LiteralValue::Dynamic(_), _));
             let id = match expr {
-                TypedLiteral::Dynamic(id) => id,
+                TypedLiteral::Dynamic(id) if !is_variable => id,
                 _ => {
                     let id = scope.push(typ.clone());
                     exprs.push(TypecheckedExpression::Literal(
@@ -506,7 +1113,15 @@ fn typecheck_statement(
                     id
                 }
             };
-            scope.insert(name.clone(), id);
+            if let Some(previous_location) =
+                scope.declare(name.clone(), id, location.clone(), *mutable)
+            {
+                scope.warn(TypecheckingWarning::Shadowed {
+                    location: location.clone(),
+                    name: name.clone(),
+                    previous_location,
+                });
+            }
             exprs.push(TypecheckedExpression::DeclareVariable(
                 location.clone(),
                 id,
@@ -532,6 +1147,7 @@ fn typecheck_statement(
         | Statement::BakedStruct(..)
         | Statement::BakedStatic(..)
         | Statement::Struct { .. }
+        | Statement::Impl { .. }
         | Statement::Export(..)
         | Statement::ModuleAsm(..)
         | Statement::Trait(_)
@@ -563,6 +1179,40 @@ macro_rules! tc_res {
     }};
 }
 
+/// Rejects integer literals whose suffix can't represent them, e.g. `300u8`. Literals without a
+/// suffix are not checked.
+fn check_literal_range(
+    context: &TypecheckingContext,
+    value: i128,
+    number_type: NumberType,
+    location: &Location,
+) -> Result<(), TypecheckingError> {
+    let bits = match number_type {
+        NumberType::I8 | NumberType::U8 => 8,
+        NumberType::I16 | NumberType::U16 => 16,
+        NumberType::I32 | NumberType::U32 => 32,
+        NumberType::I64 | NumberType::U64 => 64,
+        NumberType::Isize | NumberType::Usize => context.ptr_size() as u32 * 8,
+        _ => return Ok(()),
+    };
+    let range = match number_type {
+        NumberType::U8
+        | NumberType::U16
+        | NumberType::U32
+        | NumberType::U64
+        | NumberType::Usize => 0..=(1i128 << bits) - 1,
+        _ => -(1i128 << (bits - 1))..=(1i128 << (bits - 1)) - 1,
+    };
+    if !range.contains(&value) {
+        return Err(TypecheckingError::LiteralOutOfRange {
+            location: location.clone(),
+            value,
+            typ: number_type,
+        });
+    }
+    Ok(())
+}
+
 fn signed_number_to_literal(
     v: i64,
     number_type: NumberType,
@@ -605,7 +1255,7 @@ fn unsigned_number_to_literal(
         NumberType::I64 => (Type::PrimitiveI64(0), TypedLiteral::I64(v as i64)),
         NumberType::Isize => (Type::PrimitiveISize(0), TypedLiteral::ISize(v as isize)),
         NumberType::None => match expected {
-            TypeSuggestion::Number(NumberType::F32 | NumberType::F64) => {
+            TypeSuggestion::Number(NumberType::F16 | NumberType::F32 | NumberType::F64) => {
                 (Type::PrimitiveI32(0), TypedLiteral::I32(v as i32))
             }
             TypeSuggestion::Number(number_typ) => {
@@ -623,18 +1273,128 @@ fn float_number_to_literal(
     expected: TypeSuggestion,
 ) -> (Type, TypedLiteral) {
     match number_type {
+        NumberType::F16 => (Type::PrimitiveF16(0), TypedLiteral::F16(v as f32)),
         NumberType::F32 => (Type::PrimitiveF32(0), TypedLiteral::F32(v as f32)),
         NumberType::F64 => (Type::PrimitiveF64(0), TypedLiteral::F64(v)),
         NumberType::None => match expected {
-            TypeSuggestion::Number(number_typ @ (NumberType::F32 | NumberType::F64)) => {
-                float_number_to_literal(v, number_typ, TypeSuggestion::Unknown)
-            }
+            TypeSuggestion::Number(
+                number_typ @ (NumberType::F16 | NumberType::F32 | NumberType::F64),
+            ) => float_number_to_literal(v, number_typ, TypeSuggestion::Unknown),
             _ => (Type::PrimitiveF32(0), TypedLiteral::F32(v as f32)),
         },
         _ => unreachable!("this should never be a signed or unsigned number"),
     }
 }
 
+/// Typechecks the values of a struct literal against the fields of the struct. The generics of
+/// the struct are taken from `suggested_generics` (the type the literal is expected to have), and
+/// the ones not known from that are inferred from fields that are of that generic type.
+fn typecheck_struct_literal(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    scope: &mut Scopes,
+    exprs: &mut Vec<TypecheckedExpression>,
+    location: &Location,
+    (struct_id, suggested_generics): (StructId, &[Type]),
+    values: &HashMap<GlobalStr, (Location, Expression)>,
+) -> Result<(Type, TypedLiteral), TypecheckingError> {
+    let (name, fields, generic_names) = {
+        let structure = &context.structs.read()[struct_id];
+        (
+            structure.name.clone(),
+            structure.elements.clone(),
+            structure
+                .generics
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>(),
+        )
+    };
+    // ensure there are no excessive values in the struct initialization
+    for k in values.keys() {
+        if !fields.iter().any(|v| v.0 == *k) {
+            return Err(TypecheckingError::NoSuchFieldFound {
+                location: values[k].0.clone(),
+                name: k.clone(),
+            });
+        }
+    }
+
+    let mut generics = if suggested_generics.len() == generic_names.len() {
+        suggested_generics.iter().cloned().map(Some).collect()
+    } else {
+        vec![None; generic_names.len()]
+    };
+    let mut elements = Vec::with_capacity(fields.len());
+    for (key, typ) in fields.iter() {
+        let Some((loc, expr)) = values.get(key) else {
+            return Err(TypecheckingError::MissingField {
+                location: location.clone(),
+                name: key.clone(),
+            });
+        };
+        let (known_names, known_generics): (Vec<_>, Vec<_>) = generic_names
+            .iter()
+            .zip(&generics)
+            .filter_map(|(name, generic)| Some((name.clone(), generic.clone()?)))
+            .unzip();
+        let mut typ = typ.clone();
+        typ.substitute_generics(&known_names, &known_generics);
+
+        // a field of the type of a generic that is not known yet (`x: T`) infers that generic
+        let unknown_generic = match &typ {
+            Type::Generic(name, num_references)
+            | Type::Trait {
+                real_name: name,
+                num_references,
+                ..
+            } => generic_names
+                .iter()
+                .position(|v| v == name)
+                .filter(|&idx| generics[idx].is_none())
+                .map(|idx| (idx, *num_references)),
+            _ => None,
+        };
+        let suggestion = match unknown_generic {
+            Some(_) => TypeSuggestion::Unknown,
+            None => TypeSuggestion::from_type(&typ),
+        };
+        let (expr_typ, expr_lit) =
+            typecheck_expression(context, module, scope, expr, exprs, suggestion)?;
+        match unknown_generic {
+            Some((idx, num_references)) if expr_typ.refcount() >= num_references => {
+                let num_references = expr_typ.refcount() - num_references;
+                generics[idx] = Some(expr_typ.with_num_refs(num_references));
+            }
+            _ if typ != expr_typ => {
+                return Err(TypecheckingError::MismatchingType {
+                    expected: typ,
+                    found: expr_typ,
+                    location: loc.clone(),
+                })
+            }
+            _ => {}
+        }
+        elements.push(expr_lit);
+    }
+    let generics = generics
+        .into_iter()
+        .zip(generic_names)
+        .map(|(generic, name)| {
+            generic.ok_or_else(|| TypecheckingError::CannotInferGeneric(location.clone(), name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((
+        Type::Struct {
+            struct_id,
+            name,
+            generics,
+            num_references: 0,
+        },
+        TypedLiteral::Struct(struct_id, elements),
+    ))
+}
+
 fn typecheck_expression(
     context: &TypecheckingContext,
     module: ModuleId,
@@ -721,58 +1481,20 @@ fn typecheck_expression(
                 ))
             }
             LiteralValue::AnonymousStruct(values) => {
-                let struct_id = if let TypeSuggestion::Struct(id) = type_suggestion {
-                    id
-                } else {
+                let TypeSuggestion::Struct(struct_id, generics) = type_suggestion else {
                     return Err(TypecheckingError::CannotInferAnonStructType(
                         location.clone(),
                     ));
                 };
-
-                let structure = &context.structs.read()[struct_id];
-                // ensure there are no excessive values in the struct initialization
-                for k in values.keys() {
-                    if structure.elements.iter().find(|v| v.0 == *k).is_none() {
-                        return Err(TypecheckingError::NoSuchFieldFound {
-                            location: values[k].0.clone(),
-                            name: k.clone(),
-                        });
-                    }
-                }
-
-                let mut elements = Vec::with_capacity(structure.elements.len());
-                for (key, typ) in structure.elements.iter() {
-                    let Some((loc, expr)) = values.get(key) else {
-                        return Err(TypecheckingError::MissingField {
-                            location: location.clone(),
-                            name: key.clone(),
-                        });
-                    };
-                    let (expr_typ, expr_lit) = typecheck_expression(
-                        context,
-                        module,
-                        scope,
-                        expr,
-                        exprs,
-                        TypeSuggestion::from_type(typ),
-                    )?;
-                    if *typ != expr_typ {
-                        return Err(TypecheckingError::MismatchingType {
-                            expected: typ.clone(),
-                            found: expr_typ,
-                            location: loc.clone(),
-                        });
-                    }
-                    elements.push(expr_lit);
-                }
-                Ok((
-                    Type::Struct {
-                        struct_id,
-                        name: structure.name.clone(),
-                        num_references: 0,
-                    },
-                    TypedLiteral::Struct(struct_id, elements),
-                ))
+                typecheck_struct_literal(
+                    context,
+                    module,
+                    scope,
+                    exprs,
+                    location,
+                    (struct_id, &generics),
+                    values,
+                )
             }
             LiteralValue::Struct(values, path) => {
                 let value = typed_resolve_import(
@@ -793,62 +1515,38 @@ fn typecheck_expression(
                         path.clone(),
                     ));
                 };
-                let structure = &context.structs.read()[struct_id];
-                // ensure there are no excessive values in the struct initialization
-                for k in values.keys() {
-                    if structure.elements.iter().find(|v| v.0 == *k).is_none() {
-                        return Err(TypecheckingError::NoSuchFieldFound {
-                            location: values[k].0.clone(),
-                            name: k.clone(),
-                        });
-                    }
-                }
-
-                let mut elements = Vec::with_capacity(structure.elements.len());
-                for (key, typ) in structure.elements.iter() {
-                    let Some((loc, expr)) = values.get(key) else {
-                        return Err(TypecheckingError::MissingField {
-                            location: location.clone(),
-                            name: key.clone(),
-                        });
-                    };
-                    let (expr_typ, expr_lit) = typecheck_expression(
-                        context,
-                        module,
-                        scope,
-                        expr,
-                        exprs,
-                        TypeSuggestion::from_type(typ),
-                    )?;
-                    if *typ != expr_typ {
-                        return Err(TypecheckingError::MismatchingType {
-                            expected: typ.clone(),
-                            found: expr_typ,
-                            location: loc.clone(),
-                        });
-                    }
-                    elements.push(expr_lit);
-                }
-                Ok((
-                    Type::Struct {
-                        struct_id,
-                        name: structure.name.clone(),
-                        num_references: 0,
-                    },
-                    TypedLiteral::Struct(struct_id, elements),
-                ))
+                // the generics can only be taken from the suggestion if it is about this struct
+                let generics = match type_suggestion {
+                    TypeSuggestion::Struct(id, generics) if id == struct_id => generics,
+                    _ => Vec::new(),
+                };
+                typecheck_struct_literal(
+                    context,
+                    module,
+                    scope,
+                    exprs,
+                    location,
+                    (struct_id, &generics),
+                    values,
+                )
             }
             LiteralValue::Float(v, number_type) => {
-                Ok(float_number_to_literal(*v, *number_type, type_suggestion))
+                let (typ, literal) = float_number_to_literal(*v, *number_type, type_suggestion);
+                context.check_primitive_supported(&typ, location)?;
+                Ok((typ, literal))
             }
             LiteralValue::SInt(v, number_type) => {
+                check_literal_range(context, *v as i128, *number_type, location)?;
                 Ok(signed_number_to_literal(*v, *number_type, type_suggestion))
             }
-            LiteralValue::UInt(v, number_type) => Ok(unsigned_number_to_literal(
-                *v,
-                *number_type,
-                type_suggestion,
-            )),
+            LiteralValue::UInt(v, number_type) => {
+                check_literal_range(context, *v as i128, *number_type, location)?;
+                Ok(unsigned_number_to_literal(
+                    *v,
+                    *number_type,
+                    type_suggestion,
+                ))
+            }
             LiteralValue::Bool(v) => Ok((Type::PrimitiveBool(0), TypedLiteral::Bool(*v))),
             LiteralValue::Dynamic(path) => {
                 if path.entries.len() == 1 && path.entries[0].1.len() == 0 {
@@ -936,8 +1634,8 @@ fn typecheck_expression(
                 _ => None,
             };
             // this should never fail unless this is an incompatible type (non-primitive)
-            let output = context
-                .resolve_type(module, output, &[])
+            let output = scope
+                .resolve_type(context, module, output)
                 .ok()
                 .filter(|v| {
                     // we have to do this match because output is either TypeRef::Reference or
@@ -1019,6 +1717,15 @@ fn typecheck_expression(
                 }
                 UnaryOp::BitwiseNot => Err(TypecheckingError::CannotBNot(loc.clone(), typ)),
                 UnaryOp::Dereference => match typ.deref() {
+                    // &void is an opaque pointer, there's no value behind it
+                    Ok(Type::PrimitiveVoid(0)) => Err(TypecheckingError::CannotDeref(
+                        loc.clone(),
+                        Type::PrimitiveVoid(1),
+                    )),
+                    Ok(typ) if !typ.is_sized() => Err(TypecheckingError::UnsizedValue {
+                        location: loc.clone(),
+                        typ,
+                    }),
                     Ok(typ) => {
                         tc_res!(unary scope, exprs; Dereference(loc.clone(), right_side, typ))
                     }
@@ -1033,131 +1740,23 @@ fn typecheck_expression(
             left_side,
             loc,
         } => {
-            if matches!(operator, BinaryOp::LShift | BinaryOp::RShift) {
-                let (typ, left_side) = typecheck_expression(
+            if let BinaryOp::LogicalAnd | BinaryOp::LogicalOr = operator {
+                return typecheck_short_circuit(
                     context,
                     module,
                     scope,
-                    left_side,
                     exprs,
-                    type_suggestion,
-                )?;
-                let (typ_right, right_side) = typecheck_expression(
-                    context,
-                    module,
-                    scope,
+                    matches!(operator, BinaryOp::LogicalOr),
+                    left_side,
                     right_side,
-                    exprs,
-                    TypeSuggestion::Number(NumberType::Usize),
-                )?;
-                if !typ_right.is_int_like() || !typ_right.is_unsigned() {
-                    return Err(TypecheckingError::CannotShiftByNonUInt(
-                        loc.clone(),
-                        typ_right,
-                    ));
-                }
-
-                return match operator {
-                    BinaryOp::LShift if typ.is_int_like() => {
-                        tc_res!(binary scope, exprs; LShift(loc.clone(), left_side, right_side, typ))
-                    }
-                    BinaryOp::RShift if typ.is_int_like() => {
-                        tc_res!(binary scope, exprs; RShift(loc.clone(), left_side, right_side, typ))
-                    }
-
-                    BinaryOp::LShift => Err(TypecheckingError::CannotShl(loc.clone(), typ)),
-                    BinaryOp::RShift => Err(TypecheckingError::CannotShr(loc.clone(), typ)),
-                    _ => unreachable!(),
-                };
-            }
-            let (typ_left, left_side) =
-                typecheck_expression(context, module, scope, left_side, exprs, type_suggestion)?;
-            let (typ_right, right_side) = typecheck_expression(
-                context,
-                module,
-                scope,
-                right_side,
-                exprs,
-                TypeSuggestion::from_type(&typ_left),
-            )?;
-            if typ_left != typ_right {
-                return Err(TypecheckingError::LhsNotRhs(
-                    loc.clone(),
-                    typ_left,
-                    typ_right,
-                ));
-            }
-            let typ = typ_left;
-            let loc = loc.clone();
-            match operator {
-                BinaryOp::Plus if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; Add(loc, left_side, right_side, typ))
-                }
-                BinaryOp::Minus if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; Sub(loc, left_side, right_side, typ))
-                }
-                BinaryOp::Multiply if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; Mul(loc, left_side, right_side, typ))
-                }
-                BinaryOp::Divide if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; Div(loc, left_side, right_side, typ))
-                }
-                BinaryOp::Modulo if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; Mod(loc, left_side, right_side, typ))
-                }
-                BinaryOp::BitwiseAnd if typ.is_int_like() || typ.is_bool() => {
-                    tc_res!(binary scope, exprs; BAnd(loc, left_side, right_side, typ))
-                }
-                BinaryOp::BitwiseOr if typ.is_int_like() || typ.is_bool() => {
-                    tc_res!(binary scope, exprs; BOr(loc, left_side, right_side, typ))
-                }
-                BinaryOp::BitwiseXor if typ.is_int_like() || typ.is_bool() => {
-                    tc_res!(binary scope, exprs; BXor(loc, left_side, right_side, typ))
-                }
-                BinaryOp::LogicalOr if typ.is_bool() => {
-                    tc_res!(binary scope, exprs; LOr(loc, left_side, right_side, typ))
-                }
-                BinaryOp::LogicalAnd if typ.is_bool() => {
-                    tc_res!(binary scope, exprs; LAnd(loc, left_side, right_side, typ))
-                }
-                BinaryOp::GreaterThan if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; GreaterThan(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-                BinaryOp::GreaterThanEq if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; GreaterThanEq(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-                BinaryOp::LessThan if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; LessThan(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-                BinaryOp::LessThanEq if typ.is_int_like() => {
-                    tc_res!(binary scope, exprs; LessThanEq(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-                BinaryOp::Equals if typ.is_int_like() || typ.is_bool() => {
-                    tc_res!(binary scope, exprs; Eq(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-                BinaryOp::NotEquals if typ.is_int_like() || typ.is_bool() => {
-                    tc_res!(binary scope, exprs; Neq(loc, left_side, right_side, Type::PrimitiveBool(0)))
-                }
-
-                BinaryOp::Plus => Err(TypecheckingError::CannotAdd(loc, typ)),
-                BinaryOp::Minus => Err(TypecheckingError::CannotSub(loc, typ)),
-                BinaryOp::Multiply => Err(TypecheckingError::CannotMul(loc, typ)),
-                BinaryOp::Divide => Err(TypecheckingError::CannotDiv(loc, typ)),
-                BinaryOp::Modulo => Err(TypecheckingError::CannotMod(loc, typ)),
-                BinaryOp::BitwiseAnd => Err(TypecheckingError::CannotBAnd(loc, typ)),
-                BinaryOp::BitwiseOr => Err(TypecheckingError::CannotBOr(loc, typ)),
-                BinaryOp::BitwiseXor => Err(TypecheckingError::CannotBXor(loc, typ)),
-                BinaryOp::LogicalOr => Err(TypecheckingError::CannotLOr(loc, typ)),
-                BinaryOp::LogicalAnd => Err(TypecheckingError::CannotLAnd(loc, typ)),
-                BinaryOp::GreaterThan
-                | BinaryOp::GreaterThanEq
-                | BinaryOp::LessThan
-                | BinaryOp::LessThanEq => Err(TypecheckingError::CannotCompare(loc, typ)),
-                BinaryOp::Equals | BinaryOp::NotEquals => {
-                    Err(TypecheckingError::CannotEq(loc, typ))
-                }
-                BinaryOp::RShift | BinaryOp::LShift => unreachable!(),
+                    loc,
+                );
             }
+            let left_side =
+                typecheck_expression(context, module, scope, left_side, exprs, type_suggestion)?;
+            typecheck_binary(
+                context, module, scope, exprs, *operator, left_side, right_side, loc,
+            )
         }
         Expression::FunctionCall {
             identifier,
@@ -1193,11 +1792,11 @@ fn typecheck_expression(
             }
             if arguments.len() > function_type.arguments.len() && !has_vararg {
                 return Err(TypecheckingError::TooManyArguments {
-                    location: arguments[function_type.arguments.len() - 1].loc().clone(),
+                    location: arguments[function_type.arguments.len()].loc().clone(),
                 });
             }
             for i in 0..arguments.len() {
-                let (typ, expr) = typecheck_expression(
+                let (typ, mut expr) = typecheck_expression(
                     context,
                     module,
                     scope,
@@ -1213,17 +1812,21 @@ fn typecheck_expression(
                 // type of varargs. This is of course incredibly unsafe and as such safety
                 // precautions have to be taken when calling a function with varargs, but the
                 // compiler cannot help with those.
-                if i < function_type.arguments.len() && typ != function_type.arguments[i] {
-                    return Err(TypecheckingError::MismatchingType {
-                        expected: function_type.arguments[i].clone(),
-                        found: typ,
-                        location: arguments[i].loc().clone(),
-                    });
+                if i < function_type.arguments.len() {
+                    expr = coerce(
+                        scope,
+                        exprs,
+                        typ,
+                        expr,
+                        &function_type.arguments[i],
+                        arguments[i].loc(),
+                    )?;
                 }
                 typed_arguments.push(expr);
             }
 
             if let TypedLiteral::Intrinsic(intrinsic) = function_expr {
+                intrinsic.check_call(context, identifier.loc(), &function_type)?;
                 tc_res!(binary scope, exprs; IntrinsicCall(identifier.loc().clone(), intrinsic, typed_arguments, function_type.return_type.clone()))
             } else if let TypedLiteral::Function(fn_id) = function_expr {
                 tc_res!(binary scope, exprs; DirectCall(identifier.loc().clone(), fn_id, typed_arguments, function_type.return_type.clone()))
@@ -1244,6 +1847,7 @@ fn typecheck_expression(
         Expression::Assignment {
             left_side,
             right_side,
+            operator,
             loc,
         } => {
             let (typ_lhs, lhs) = match &**left_side {
@@ -1260,13 +1864,21 @@ fn typecheck_expression(
                         exprs,
                         TypeSuggestion::Unknown,
                     )?;
-                    let typ = typ.deref().map_err(|typ| {
-                        TypecheckingError::CannotDeref(right_side.loc().clone(), typ)
-                    })?;
+                    let typ = match typ.deref() {
+                        Ok(Type::PrimitiveVoid(0)) => Err(Type::PrimitiveVoid(1)),
+                        v => v,
+                    }
+                    .map_err(|typ| TypecheckingError::CannotDeref(right_side.loc().clone(), typ))?;
 
                     (typ, lhs)
                 }
                 _ => {
+                    if let Some(name) = assigned_variable(context, scope, left_side) {
+                        return Err(TypecheckingError::AssignToImmutable {
+                            location: loc.clone(),
+                            name: name.clone(),
+                        });
+                    }
                     let (typ, lhs) = typecheck_expression(
                         context,
                         module,
@@ -1285,22 +1897,38 @@ fn typecheck_expression(
                     )
                 }
             };
-            let (typ_rhs, rhs) = typecheck_expression(
-                context,
-                module,
-                scope,
-                right_side,
-                exprs,
-                TypeSuggestion::from_type(&typ_lhs),
-            )?;
+            let (typ_rhs, rhs) = match operator {
+                // `a += b` loads `a` through the reference to its place, so the place is only
+                // evaluated once
+                Some(operator) => {
+                    let value = scope.push(typ_lhs.clone());
+                    exprs.push(TypecheckedExpression::Dereference(
+                        loc.clone(),
+                        value,
+                        lhs.clone(),
+                    ));
+                    typecheck_binary(
+                        context,
+                        module,
+                        scope,
+                        exprs,
+                        *operator,
+                        (typ_lhs.clone(), TypedLiteral::Dynamic(value)),
+                        right_side,
+                        loc,
+                    )?
+                }
+                None => typecheck_expression(
+                    context,
+                    module,
+                    scope,
+                    right_side,
+                    exprs,
+                    TypeSuggestion::from_type(&typ_lhs),
+                )?,
+            };
 
-            if typ_lhs != typ_rhs {
-                return Err(TypecheckingError::MismatchingType {
-                    expected: typ_lhs,
-                    found: typ_rhs,
-                    location: loc.clone(),
-                });
-            }
+            let rhs = coerce(scope, exprs, typ_rhs, rhs, &typ_lhs, loc)?;
             exprs.push(TypecheckedExpression::StoreAssignment(
                 loc.clone(),
                 lhs,
@@ -1340,12 +1968,247 @@ fn typecheck_expression(
         } => {
             let (typ, lhs) =
                 typecheck_expression(context, module, scope, &**left_side, exprs, type_suggestion)?;
-            let new_type = context.resolve_type(module, new_type, &[])?;
+            let new_type = scope.resolve_type(context, module, new_type)?;
             typecheck_cast(scope, exprs, typ, new_type, lhs, loc.clone(), context)
         }
     }
 }
 
+/// Typechecks `left_side <operator> right_side` with an already typechecked left side, which lets
+/// compound assignments use the value they loaded from their place. `&&` and `||` are handled by
+/// [`typecheck_short_circuit`].
+fn typecheck_binary(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    scope: &mut Scopes,
+    exprs: &mut Vec<TypecheckedExpression>,
+    operator: BinaryOp,
+    (typ_left, left_side): (Type, TypedLiteral),
+    right_side: &Expression,
+    loc: &Location,
+) -> Result<(Type, TypedLiteral), TypecheckingError> {
+    if matches!(operator, BinaryOp::LShift | BinaryOp::RShift) {
+        let (typ_right, right_side) = typecheck_expression(
+            context,
+            module,
+            scope,
+            right_side,
+            exprs,
+            TypeSuggestion::Number(NumberType::Usize),
+        )?;
+        if !typ_right.is_int_like() || !typ_right.is_unsigned() {
+            return Err(TypecheckingError::CannotShiftByNonUInt(
+                loc.clone(),
+                typ_right,
+            ));
+        }
+
+        return match operator {
+            BinaryOp::LShift if typ_left.is_int_like() => {
+                tc_res!(binary scope, exprs; LShift(loc.clone(), left_side, right_side, typ_left))
+            }
+            BinaryOp::RShift if typ_left.is_int_like() => {
+                tc_res!(binary scope, exprs; RShift(loc.clone(), left_side, right_side, typ_left))
+            }
+
+            BinaryOp::LShift => Err(TypecheckingError::CannotShl(loc.clone(), typ_left)),
+            BinaryOp::RShift => Err(TypecheckingError::CannotShr(loc.clone(), typ_left)),
+            _ => unreachable!(),
+        };
+    }
+    let (typ_right, right_side) = typecheck_expression(
+        context,
+        module,
+        scope,
+        right_side,
+        exprs,
+        TypeSuggestion::from_type(&typ_left),
+    )?;
+    if typ_left != typ_right {
+        return Err(TypecheckingError::LhsNotRhs(
+            loc.clone(),
+            typ_left,
+            typ_right,
+        ));
+    }
+    let typ = typ_left;
+    let loc = loc.clone();
+    if let (
+        BinaryOp::Equals | BinaryOp::NotEquals,
+        Type::Struct {
+            num_references: 0, ..
+        },
+    ) = (operator, &typ)
+    {
+        let (typ, result) = typecheck_struct_eq(
+            context,
+            scope,
+            exprs,
+            typ,
+            left_side,
+            right_side,
+            loc.clone(),
+        )?;
+        if let BinaryOp::Equals = operator {
+            return Ok((typ, result));
+        }
+        return tc_res!(unary scope, exprs; LNot(loc, result, typ));
+    }
+    let is_number = typ.is_int_like() || typ.is_float();
+    match operator {
+        BinaryOp::Plus if typ.is_int_like() => {
+            tc_res!(binary scope, exprs; Add(loc, left_side, right_side, typ))
+        }
+        BinaryOp::Minus if typ.is_int_like() => {
+            tc_res!(binary scope, exprs; Sub(loc, left_side, right_side, typ))
+        }
+        BinaryOp::Multiply if typ.is_int_like() => {
+            tc_res!(binary scope, exprs; Mul(loc, left_side, right_side, typ))
+        }
+        BinaryOp::Divide if typ.is_int_like() || typ.is_float() => {
+            scope.division_by_zero_check(context, exprs, &loc, &right_side, &typ)?;
+            tc_res!(binary scope, exprs; Div(loc, left_side, right_side, typ))
+        }
+        BinaryOp::Modulo if typ.is_int_like() || typ.is_float() => {
+            scope.division_by_zero_check(context, exprs, &loc, &right_side, &typ)?;
+            tc_res!(binary scope, exprs; Mod(loc, left_side, right_side, typ))
+        }
+        BinaryOp::BitwiseAnd if typ.is_int_like() || typ.is_bool() => {
+            tc_res!(binary scope, exprs; BAnd(loc, left_side, right_side, typ))
+        }
+        BinaryOp::BitwiseOr if typ.is_int_like() || typ.is_bool() => {
+            tc_res!(binary scope, exprs; BOr(loc, left_side, right_side, typ))
+        }
+        BinaryOp::BitwiseXor if typ.is_int_like() || typ.is_bool() => {
+            tc_res!(binary scope, exprs; BXor(loc, left_side, right_side, typ))
+        }
+        BinaryOp::GreaterThan if is_number => {
+            tc_res!(binary scope, exprs; GreaterThan(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+        BinaryOp::GreaterThanEq if is_number => {
+            tc_res!(binary scope, exprs; GreaterThanEq(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+        BinaryOp::LessThan if is_number => {
+            tc_res!(binary scope, exprs; LessThan(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+        BinaryOp::LessThanEq if is_number => {
+            tc_res!(binary scope, exprs; LessThanEq(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+        BinaryOp::Equals if is_number || typ.is_bool() => {
+            tc_res!(binary scope, exprs; Eq(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+        BinaryOp::NotEquals if is_number || typ.is_bool() => {
+            tc_res!(binary scope, exprs; Neq(loc, left_side, right_side, Type::PrimitiveBool(0)))
+        }
+
+        BinaryOp::Plus => Err(TypecheckingError::CannotAdd(loc, typ)),
+        BinaryOp::Minus => Err(TypecheckingError::CannotSub(loc, typ)),
+        BinaryOp::Multiply => Err(TypecheckingError::CannotMul(loc, typ)),
+        BinaryOp::Divide => Err(TypecheckingError::CannotDiv(loc, typ)),
+        BinaryOp::Modulo => Err(TypecheckingError::CannotMod(loc, typ)),
+        BinaryOp::BitwiseAnd => Err(TypecheckingError::CannotBAnd(loc, typ)),
+        BinaryOp::BitwiseOr => Err(TypecheckingError::CannotBOr(loc, typ)),
+        BinaryOp::BitwiseXor => Err(TypecheckingError::CannotBXor(loc, typ)),
+        BinaryOp::GreaterThan
+        | BinaryOp::GreaterThanEq
+        | BinaryOp::LessThan
+        | BinaryOp::LessThanEq => Err(TypecheckingError::CannotCompare(loc, typ)),
+        BinaryOp::Equals | BinaryOp::NotEquals => {
+            Err(TypecheckingError::TypeNotComparable(loc, typ))
+        }
+        BinaryOp::RShift | BinaryOp::LShift | BinaryOp::LogicalAnd | BinaryOp::LogicalOr => {
+            unreachable!()
+        }
+    }
+}
+
+/// Lowers `lhs && rhs` and `lhs || rhs` into a branch, so the right side is only evaluated if the
+/// left side doesn't decide the result already:
+///
+/// ```text
+/// _1 = false           (true for ||)
+/// _2 = &_1
+/// if (lhs) { *_2 = rhs }  (for ||: if (lhs) {} else { *_2 = rhs })
+/// ```
+fn typecheck_short_circuit(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    scope: &mut Scopes,
+    exprs: &mut Vec<TypecheckedExpression>,
+    is_or: bool,
+    left_side: &Expression,
+    right_side: &Expression,
+    loc: &Location,
+) -> Result<(Type, TypedLiteral), TypecheckingError> {
+    let (typ_left, left_side) = typecheck_expression(
+        context,
+        module,
+        scope,
+        left_side,
+        exprs,
+        TypeSuggestion::Bool,
+    )?;
+    // the result has to be declared before the values of the right side, which are only
+    // declared in the branch
+    let result = scope.push(Type::PrimitiveBool(0));
+    scope.make_stack_allocated(result);
+    let reference = scope.push(Type::PrimitiveBool(1));
+
+    let mut rhs_exprs = Vec::new();
+    let (typ_right, right_side) = typecheck_expression(
+        context,
+        module,
+        scope,
+        right_side,
+        &mut rhs_exprs,
+        TypeSuggestion::from_type(&typ_left),
+    )?;
+    if typ_left != typ_right {
+        return Err(TypecheckingError::LhsNotRhs(
+            loc.clone(),
+            typ_left,
+            typ_right,
+        ));
+    }
+    if !typ_left.is_bool() {
+        return Err(if is_or {
+            TypecheckingError::CannotLOr(loc.clone(), typ_left)
+        } else {
+            TypecheckingError::CannotLAnd(loc.clone(), typ_left)
+        });
+    }
+
+    rhs_exprs.push(TypecheckedExpression::StoreAssignment(
+        loc.clone(),
+        TypedLiteral::Dynamic(reference),
+        right_side,
+    ));
+    let rhs_block = (rhs_exprs.into_boxed_slice(), loc.clone());
+    let (if_block, else_block) = if is_or {
+        ((Box::default(), loc.clone()), Some(rhs_block))
+    } else {
+        (rhs_block, None)
+    };
+    exprs.push(TypecheckedExpression::Literal(
+        loc.clone(),
+        result,
+        TypedLiteral::Bool(is_or),
+    ));
+    exprs.push(TypecheckedExpression::Reference(
+        loc.clone(),
+        reference,
+        TypedLiteral::Dynamic(result),
+    ));
+    exprs.push(TypecheckedExpression::If {
+        loc: loc.clone(),
+        cond: left_side,
+        if_block,
+        else_block,
+        annotations: Annotations::default(),
+    });
+    Ok((Type::PrimitiveBool(0), TypedLiteral::Dynamic(result)))
+}
+
 fn typecheck_cast(
     scope: &mut Scopes,
     exprs: &mut Vec<TypecheckedExpression>,
@@ -1493,6 +2356,17 @@ fn typecheck_cast(
             exprs.push(TypecheckedExpression::Bitcast(loc, id, lhs));
             Ok((new_typ, TypedLiteral::Dynamic(id)))
         }
+        // &&T to &T and &T to &&T
+        _ if typ.refcount() > 0
+            && new_typ.refcount() > 0
+            && typ.is_thin_ptr()
+            && new_typ.is_thin_ptr()
+            && typ.clone().with_num_refs(0) == new_typ.clone().with_num_refs(0) =>
+        {
+            let id = scope.push(new_typ.clone());
+            exprs.push(TypecheckedExpression::Bitcast(loc, id, lhs));
+            Ok((new_typ, TypedLiteral::Dynamic(id)))
+        }
         (
             Type::PrimitiveU8(0) | Type::PrimitiveBool(0),
             Type::PrimitiveU8(0) | Type::PrimitiveBool(0),
@@ -1508,6 +2382,7 @@ fn typecheck_cast(
             | Type::PrimitiveI32(0)
             | Type::PrimitiveI64(0)
             | Type::PrimitiveISize(0)
+            | Type::PrimitiveF16(0)
             | Type::PrimitiveF32(0)
             | Type::PrimitiveF64(0),
             Type::PrimitiveU8(0)
@@ -1520,6 +2395,7 @@ fn typecheck_cast(
             | Type::PrimitiveI32(0)
             | Type::PrimitiveI64(0)
             | Type::PrimitiveISize(0)
+            | Type::PrimitiveF16(0)
             | Type::PrimitiveF32(0)
             | Type::PrimitiveF64(0),
         ) => {
@@ -1527,7 +2403,7 @@ fn typecheck_cast(
             exprs.push(TypecheckedExpression::IntCast(loc, id, lhs));
             Ok((new_typ, TypedLiteral::Dynamic(id)))
         }
-        _ => Err(TypecheckingError::DisallowedCast(loc, typ, new_typ)),
+        _ => Err(TypecheckingError::InvalidCast(loc, typ, new_typ)),
     }
 }
 
@@ -1545,7 +2421,7 @@ fn typecheck_dyn_membercall(
 ) -> Result<(Type, TypedLiteral), TypecheckingError> {
     let trait_reader = context.traits.read();
     let mut offset = 0;
-    let (mut arg_typs, mut return_ty, trait_name) = 'out: {
+    let (arg_typs, mut return_ty, trait_name) = 'out: {
         for trait_id in trait_refs.iter().map(|v| v.0) {
             for func in trait_reader[trait_id].functions.iter() {
                 if func.0 == *ident {
@@ -1628,14 +2504,14 @@ fn typecheck_dyn_membercall(
             exprs,
             TypeSuggestion::from_type(&arg_typs[i + 1].1),
         )?;
-        if ty != arg_typs[i + 1].1 {
-            return Err(TypecheckingError::MismatchingType {
-                expected: arg_typs.remove(i + 1).1,
-                found: typ,
-                location: args[i].loc().clone(),
-            });
-        }
-        typed_args.push(lit);
+        typed_args.push(coerce(
+            scope,
+            exprs,
+            ty,
+            lit,
+            &arg_typs[i + 1].1,
+            args[i].loc(),
+        )?);
     }
 
     let is_void = matches!(return_ty, Type::PrimitiveNever | Type::PrimitiveVoid(0));
@@ -1695,6 +2571,7 @@ fn typecheck_membercall(
         | Type::PrimitiveVoid(_)
         | Type::PrimitiveNever
         | Type::PrimitiveSelf(_)
+        | Type::PrimitiveF16(_)
         | Type::Generic(..) => None,
         Type::Struct { struct_id, .. } => Some(*struct_id),
         Type::PrimitiveI8(_) => langitem_reader.i8,
@@ -1743,20 +2620,37 @@ fn typecheck_membercall(
     };
 
     let function = &function_reader[function_id];
-    if function
+    let mut arguments = function
         .0
         .arguments
-        .get(0)
-        .map(|v| v.1.clone().without_ref())
-        != Some(typ_lhs.clone().without_ref())
+        .iter()
+        .map(|v| v.1.clone())
+        .collect::<Vec<_>>();
+    let mut return_type = function.0.return_type.clone();
+    // the generics of the struct are replaced with the type arguments of the value the method
+    // is called on, so `get` on a `Foo<i32>` returns an `i32` rather than a `T`
+    if let Type::Struct {
+        struct_id,
+        generics,
+        ..
+    } = &typ_lhs
     {
+        let structure = &context.structs.read()[*struct_id];
+        for typ in arguments
+            .iter_mut()
+            .chain(std::iter::once(&mut return_type))
+        {
+            structure.substitute_generics(typ, generics);
+        }
+    }
+    if arguments.first().map(|v| v.clone().without_ref()) != Some(typ_lhs.clone().without_ref()) {
         return Err(TypecheckingError::NonMemberFunction(
             function.0.location.clone(),
             ident.clone(),
             typ_lhs,
         ));
     }
-    let arg_refcount = function.0.arguments[0].1.refcount();
+    let arg_refcount = arguments[0].refcount();
     while typ_lhs.refcount() != arg_refcount {
         if typ_lhs.refcount() > arg_refcount {
             typ_lhs = typ_lhs
@@ -1781,38 +2675,38 @@ fn typecheck_membercall(
         }
     }
 
-    let mut typed_arguments = Vec::with_capacity(function.0.arguments.len() + 1);
+    let mut typed_arguments = Vec::with_capacity(arguments.len() + 1);
     typed_arguments.push(typed_literal_lhs);
-    if args.len() < function.0.arguments.len() - 1 {
+    if args.len() < arguments.len() - 1 {
         return Err(TypecheckingError::MissingArguments {
             location: lhs.loc().clone(),
         });
     }
-    if args.len() > function.0.arguments.len() - 1 {
+    if args.len() > arguments.len() - 1 {
         return Err(TypecheckingError::TooManyArguments {
-            location: args[function.0.arguments.len() - 1].loc().clone(),
+            location: args[arguments.len() - 1].loc().clone(),
         });
     }
-    for i in 0..function.0.arguments.len() - 1 {
+    for i in 0..arguments.len() - 1 {
         let (typ, expr) = typecheck_expression(
             context,
             module,
             scope,
             &args[i],
             exprs,
-            TypeSuggestion::from_type(&function.0.arguments[i + 1].1),
+            TypeSuggestion::from_type(&arguments[i + 1]),
         )?;
-        if typ != function.0.arguments[i + 1].1 {
-            return Err(TypecheckingError::MismatchingType {
-                expected: function.0.arguments[i + 1].1.clone(),
-                found: typ,
-                location: args[i].loc().clone(),
-            });
-        }
-        typed_arguments.push(expr);
+        typed_arguments.push(coerce(
+            scope,
+            exprs,
+            typ,
+            expr,
+            &arguments[i + 1],
+            args[i].loc(),
+        )?);
     }
 
-    let call_id = scope.push(function.0.return_type.clone());
+    let call_id = scope.push(return_type.clone());
     exprs.push(TypecheckedExpression::DirectCall(
         lhs.loc().clone(),
         call_id,
@@ -1820,10 +2714,7 @@ fn typecheck_membercall(
         typed_arguments,
     ));
 
-    Ok((
-        function.0.return_type.clone(),
-        TypedLiteral::Dynamic(call_id),
-    ))
+    Ok((return_type, TypedLiteral::Dynamic(call_id)))
 }
 
 fn typecheck_take_ref(
@@ -1901,7 +2792,11 @@ fn ref_resolve_indexing(
                 }
                 assert_eq!(typ_lhs.refcount(), 0, "non-zero refcount after auto-deref");
                 match typ_lhs {
-                    Type::Struct { struct_id, .. } => {
+                    Type::Struct {
+                        struct_id,
+                        ref generics,
+                        ..
+                    } => {
                         let structure = &context.structs.read()[struct_id];
                         match structure
                             .elements
@@ -1911,7 +2806,9 @@ fn ref_resolve_indexing(
                             .map(|(idx, (_, typ))| (idx, typ))
                         {
                             Some((idx, typ)) => {
-                                typ_lhs = typ.clone();
+                                let mut typ = typ.clone();
+                                structure.substitute_generics(&mut typ, generics);
+                                typ_lhs = typ;
                                 let new_val = scope.push(typ_lhs.clone().take_ref());
                                 exprs.push(TypecheckedExpression::Offset(
                                     loc.clone(),
@@ -1943,7 +2840,7 @@ fn ref_resolve_indexing(
         Expression::Indexing {
             left_side,
             right_side,
-        } => {
+        } if !matches!(**right_side, Expression::Range { .. }) => {
             let (mut typ_lhs, mut typed_literal_lhs) = ref_resolve_indexing(
                 context,
                 module,
@@ -1967,6 +2864,7 @@ fn ref_resolve_indexing(
             }
             assert_eq!(typ_lhs.refcount(), 0, "non-zero refcount after auto-deref");
             let offset = indexing_resolve_rhs(context, module, scope, right_side, exprs)?;
+            let is_tuple = matches!(typ_lhs, Type::Tuple { .. });
             let typ = match typ_lhs {
                 Type::SizedArray { typ, .. } => *typ,
                 Type::UnsizedArray { typ, .. } => *typ,
@@ -1993,6 +2891,9 @@ fn ref_resolve_indexing(
                 }
             };
 
+            if !is_tuple {
+                scope.bounds_check(context, exprs, expression.loc(), &typed_literal_lhs, offset)?;
+            }
             let id = scope.push(typ.clone().take_ref());
             exprs.push(TypecheckedExpression::Offset(
                 expression.loc().clone(),
@@ -2085,6 +2986,145 @@ fn ref_resolve_indexing(
     }
 }
 
+/// Returns an error if the `panic` lang item, which failing runtime checks call, isn't defined
+fn require_panic_lang_item(
+    context: &TypecheckingContext,
+    loc: &Location,
+) -> Result<(), TypecheckingError> {
+    if context.lang_items.read().panic.is_none() {
+        return Err(TypecheckingError::MissingLangItem {
+            location: loc.clone(),
+            name: "panic",
+        });
+    }
+    Ok(())
+}
+
+/// Typechecks `array[start..end]`, which results in a `&[T]` pointing into `array`
+fn typecheck_slice(
+    context: &TypecheckingContext,
+    module: ModuleId,
+    scope: &mut Scopes,
+    loc: &Location,
+    array: &Expression,
+    range: &Expression,
+    exprs: &mut Vec<TypecheckedExpression>,
+) -> Result<(Type, TypedLiteral), TypecheckingError> {
+    let Expression::Range {
+        left_side: start,
+        right_side: end,
+        inclusive,
+        ..
+    } = range
+    else {
+        unreachable!("slices are only created from ranges")
+    };
+    let (mut typ_lhs, mut typed_literal_lhs) = ref_resolve_indexing(
+        context,
+        module,
+        scope,
+        array,
+        exprs,
+        TypeSuggestion::Unknown,
+        false,
+    )?;
+    while typ_lhs.refcount() > 0 {
+        typ_lhs = typ_lhs
+            .deref()
+            .expect("&_ should never fail to dereference");
+        let id = scope.push(typ_lhs.clone().take_ref());
+        exprs.push(TypecheckedExpression::Dereference(
+            loc.clone(),
+            id,
+            typed_literal_lhs,
+        ));
+        typed_literal_lhs = TypedLiteral::Dynamic(id);
+    }
+    let (typ, array_len) = match typ_lhs {
+        Type::SizedArray {
+            typ,
+            number_elements,
+            ..
+        } => (*typ, Some(number_elements)),
+        Type::UnsizedArray { typ, .. } => (*typ, None),
+        _ => return Err(TypecheckingError::SliceNonArray(loc.clone(), typ_lhs)),
+    };
+    let start = indexing_resolve_rhs(context, module, scope, start, exprs)?;
+    let end = indexing_resolve_rhs(context, module, scope, end, exprs)?;
+    let to_literal = |offset| match offset {
+        OffsetValue::Dynamic(id) => TypedLiteral::Dynamic(id),
+        OffsetValue::Static(v) => TypedLiteral::USize(v),
+    };
+
+    let mut end = to_literal(end);
+    if *inclusive {
+        end = match end {
+            TypedLiteral::USize(v) => TypedLiteral::USize(v + 1),
+            end => {
+                let id = scope.push(Type::PrimitiveUSize(0));
+                exprs.push(TypecheckedExpression::Add(
+                    loc.clone(),
+                    id,
+                    end,
+                    TypedLiteral::USize(1),
+                ));
+                TypedLiteral::Dynamic(id)
+            }
+        };
+    }
+    // literal bounds are checked right away, everything else at runtime
+    if let TypedLiteral::USize(end) = end {
+        if let OffsetValue::Static(start) = start {
+            if start > end {
+                return Err(TypecheckingError::InvertedSliceRange(
+                    loc.clone(),
+                    start,
+                    end,
+                ));
+            }
+        }
+        if let Some(len) = array_len.filter(|len| end > *len) {
+            return Err(TypecheckingError::SliceOutOfBounds(loc.clone(), len, end));
+        }
+    }
+    scope.slice_bounds_check(
+        context,
+        exprs,
+        loc,
+        &typed_literal_lhs,
+        (&to_literal(start), &end),
+        array_len,
+    )?;
+
+    let ptr_id = scope.push(typ.clone().take_ref());
+    exprs.push(TypecheckedExpression::Offset(
+        loc.clone(),
+        ptr_id,
+        typed_literal_lhs,
+        start,
+    ));
+    let len_id = scope.push(Type::PrimitiveUSize(0));
+    exprs.push(TypecheckedExpression::Sub(
+        loc.clone(),
+        len_id,
+        end,
+        to_literal(start),
+    ));
+
+    let typ = Type::UnsizedArray {
+        typ: Box::new(typ),
+        num_references: 1,
+    };
+    let id = scope.push(typ.clone());
+    exprs.push(TypecheckedExpression::MakeSlice(
+        loc.clone(),
+        id,
+        TypedLiteral::Dynamic(ptr_id),
+        TypedLiteral::Dynamic(len_id),
+    ));
+    Ok((typ, TypedLiteral::Dynamic(id)))
+}
+
 fn indexing_resolve_rhs(
     context: &TypecheckingContext,
     module: ModuleId,
@@ -2114,6 +3154,80 @@ fn indexing_resolve_rhs(
     }
 }
 
+/// Converts a value of type `typ` into a value of type `expected`. A reference to `expected`
+/// (`&T` where `T` is expected) is implicitly dereferenced, everything else has to match exactly.
+/// References are never taken implicitly, the user has to do that with `&`.
+fn coerce(
+    scope: &mut Scopes,
+    expressions: &mut Vec<TypecheckedExpression>,
+    typ: Type,
+    typed_literal: TypedLiteral,
+    expected: &Type,
+    loc: &Location,
+) -> Result<TypedLiteral, TypecheckingError> {
+    if typ == *expected {
+        return Ok(typed_literal);
+    }
+    if typ.refcount() == expected.refcount() + 1 && expected.is_sized() {
+        let derefed = typ
+            .clone()
+            .deref()
+            .expect("&_ should never fail to dereference");
+        if derefed == *expected {
+            let id = scope.push(derefed);
+            expressions.push(TypecheckedExpression::Dereference(
+                loc.clone(),
+                id,
+                typed_literal,
+            ));
+            return Ok(TypedLiteral::Dynamic(id));
+        }
+    }
+    if expected.refcount() == typ.refcount() + 1 && expected.clone().deref().is_ok_and(|v| v == typ)
+    {
+        return Err(TypecheckingError::ExpectedReference {
+            expected: expected.clone(),
+            found: typ,
+            location: loc.clone(),
+        });
+    }
+    Err(TypecheckingError::MismatchingType {
+        expected: expected.clone(),
+        found: typ,
+        location: loc.clone(),
+    })
+}
+
+/// Lowers `a == b` on structs into a call to their implementation of the `eq_trait` lang item.
+/// Structs that don't implement it are not comparable.
+fn typecheck_struct_eq(
+    context: &TypecheckingContext,
+    scope: &mut Scopes,
+    exprs: &mut Vec<TypecheckedExpression>,
+    typ: Type,
+    left_side: TypedLiteral,
+    right_side: TypedLiteral,
+    loc: Location,
+) -> Result<(Type, TypedLiteral), TypecheckingError> {
+    let Type::Struct { struct_id, .. } = typ else {
+        unreachable!("only structs are compared through their Eq implementation")
+    };
+    let eq_trait = context.lang_items.read().eq_trait;
+    let Some(function) = context.struct_eq_function(struct_id, eq_trait) else {
+        return Err(TypecheckingError::TypeNotComparable(loc, typ));
+    };
+    let left_side = make_reference(scope, exprs, typ.clone(), left_side, loc.clone());
+    let right_side = make_reference(scope, exprs, typ, right_side, loc.clone());
+    let result = scope.push(Type::PrimitiveBool(0));
+    exprs.push(TypecheckedExpression::DirectCall(
+        loc.clone(),
+        result,
+        function,
+        vec![left_side, right_side],
+    ));
+    Ok((Type::PrimitiveBool(0), TypedLiteral::Dynamic(result)))
+}
+
 /// typ - the type before the reference (as in, typ is the type of the typed_literal, the type of
 /// the returned type literal is typ.take_ref())
 fn make_reference(
@@ -2143,6 +3257,81 @@ fn make_reference(
     TypedLiteral::Dynamic(new_id)
 }
 
+/// Returns the immutable variable that assigning to `expression` would change, e.g. `a` for
+/// `a = 1` or `a.b[2] = 1`. Once the access goes through a reference (`s.r.x = 1` with `r: &T`),
+/// only the value behind it changes.
+fn assigned_variable<'a>(
+    context: &TypecheckingContext,
+    scope: &Scopes,
+    expression: &'a Expression,
+) -> Option<&'a GlobalStr> {
+    // the accesses from the variable outwards
+    let mut accesses = Vec::new();
+    let mut expr = expression;
+    let name = loop {
+        match expr {
+            Expression::MemberAccess { left_side, .. } | Expression::Indexing { left_side, .. } => {
+                accesses.push(expr);
+                expr = left_side;
+            }
+            Expression::Literal(LiteralValue::Dynamic(path), _)
+                if path.entries.len() == 1 && path.entries[0].1.is_empty() =>
+            {
+                break &path.entries[0].0;
+            }
+            _ => return None,
+        }
+    };
+    if scope.is_mutable(name) {
+        return None;
+    }
+
+    let mut typ = scope.get(name)?.0 .0.clone();
+    for access in accesses.into_iter().rev() {
+        match access {
+            Expression::MemberAccess { index, .. } => {
+                for field_name in index {
+                    if typ.refcount() > 0 {
+                        return None;
+                    }
+                    let Type::Struct {
+                        struct_id,
+                        generics,
+                        ..
+                    } = &typ
+                    else {
+                        return None;
+                    };
+                    let structure = &context.structs.read()[*struct_id];
+                    let mut field = structure
+                        .elements
+                        .iter()
+                        .find(|(v, _)| v == field_name)?
+                        .1
+                        .clone();
+                    structure.substitute_generics(&mut field, generics);
+                    typ = field;
+                }
+            }
+            Expression::Indexing { right_side, .. } => {
+                if typ.refcount() > 0 {
+                    return None;
+                }
+                typ = match (typ, &**right_side) {
+                    (Type::SizedArray { typ, .. } | Type::UnsizedArray { typ, .. }, _) => *typ,
+                    (
+                        Type::Tuple { mut elements, .. },
+                        Expression::Literal(LiteralValue::UInt(idx, _), _),
+                    ) if (*idx as usize) < elements.len() => elements.swap_remove(*idx as usize),
+                    _ => return None,
+                };
+            }
+            _ => unreachable!("only member accesses and indexing are collected"),
+        }
+    }
+    Some(name)
+}
+
 fn copy_resolve_indexing(
     context: &TypecheckingContext,
     module: ModuleId,
@@ -2152,6 +3341,18 @@ fn copy_resolve_indexing(
     type_suggestion: TypeSuggestion,
 ) -> Result<(Type, TypedLiteral), TypecheckingError> {
     match expression {
+        Expression::Indexing {
+            left_side,
+            right_side,
+        } if matches!(**right_side, Expression::Range { .. }) => typecheck_slice(
+            context,
+            module,
+            scope,
+            expression.loc(),
+            left_side,
+            right_side,
+            exprs,
+        ),
         Expression::Indexing {
             left_side,
             right_side,
@@ -2212,6 +3413,13 @@ fn copy_resolve_indexing(
                         }
                         let typed_lit_ref =
                             make_reference(scope, exprs, typ, lhs, expression.loc().clone());
+                        scope.bounds_check(
+                            context,
+                            exprs,
+                            expression.loc(),
+                            &typed_lit_ref,
+                            off,
+                        )?;
                         let offset_id = scope.push(new_typ.clone().take_ref());
                         let new_id = scope.push(new_typ.clone());
                         exprs.push(TypecheckedExpression::Offset(
@@ -2246,6 +3454,7 @@ fn copy_resolve_indexing(
                     Type::UnsizedArray { typ, .. } | Type::SizedArray { typ, .. } => *typ,
                     _ => unreachable!(),
                 };
+                scope.bounds_check(context, exprs, expression.loc(), &lhs, offset)?;
                 let offset_id = scope.push(typ.clone().take_ref());
                 let value_id = scope.push(typ.clone());
                 exprs.push(TypecheckedExpression::Offset(
@@ -2288,7 +3497,11 @@ fn copy_resolve_indexing(
                     typed_literal_lhs = TypedLiteral::Dynamic(new_id);
                 }
                 let offset = match typ_lhs {
-                    Type::Struct { struct_id, .. } => {
+                    Type::Struct {
+                        struct_id,
+                        ref generics,
+                        ..
+                    } => {
                         let structure = &context.structs.read()[struct_id];
                         match structure
                             .elements
@@ -2298,7 +3511,9 @@ fn copy_resolve_indexing(
                             .map(|(idx, (_, typ))| (idx, typ))
                         {
                             Some((idx, typ)) => {
-                                typ_lhs = typ.clone();
+                                let mut typ = typ.clone();
+                                structure.substitute_generics(&mut typ, generics);
+                                typ_lhs = typ;
                                 idx
                             }
                             None => {
@@ -19,6 +19,12 @@ pub struct FunctionType {
     pub return_type: Type,
 }
 
+impl FunctionType {
+    fn contains_self(&self) -> bool {
+        self.return_type.contains_self() || self.arguments.iter().any(Type::contains_self)
+    }
+}
+
 #[derive(Clone, Debug, Eq)]
 pub enum Type {
     Trait {
@@ -33,6 +39,8 @@ pub enum Type {
     Struct {
         struct_id: StructId,
         name: GlobalStr,
+        /// The type arguments of a generic struct, e.g. `i32` for `Foo<i32>`
+        generics: Vec<Type>,
         num_references: u8,
     },
     UnsizedArray {
@@ -65,6 +73,7 @@ pub enum Type {
     PrimitiveU64(u8),
     PrimitiveUSize(u8),
 
+    PrimitiveF16(u8),
     PrimitiveF32(u8),
     PrimitiveF64(u8),
 
@@ -75,6 +84,8 @@ pub enum Type {
     Generic(GlobalStr, u8),
 }
 
+/// Consistent with `PartialEq`: everything that's only kept for displaying the type, like the name
+/// of a struct or the trait names of a dyn type, isn't hashed.
 impl Hash for Type {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.refcount().hash(state);
@@ -89,7 +100,8 @@ impl Hash for Type {
             }
             Type::DynType { trait_refs, .. } => {
                 "dyn".hash(state);
-                trait_refs.hash(state);
+                trait_refs.len().hash(state);
+                trait_refs.iter().for_each(|(id, _)| id.hash(state));
             }
             Type::Struct { struct_id, .. } => struct_id.hash(state),
             Type::UnsizedArray { typ, .. } => {
@@ -126,6 +138,7 @@ impl Hash for Type {
             Type::PrimitiveU32(_) => "u32".hash(state),
             Type::PrimitiveU64(_) => "u64".hash(state),
             Type::PrimitiveUSize(_) => "usize".hash(state),
+            Type::PrimitiveF16(_) => "f16".hash(state),
             Type::PrimitiveF32(_) => "f32".hash(state),
             Type::PrimitiveF64(_) => "f64".hash(state),
             Type::PrimitiveStr(_) => "str".hash(state),
@@ -156,6 +169,7 @@ pub fn resolve_primitive_type(typ: &TypeRef) -> Option<Type> {
                 "u16" => Some(Type::PrimitiveU16(*num_references)),
                 "u32" => Some(Type::PrimitiveU32(*num_references)),
                 "u64" => Some(Type::PrimitiveU64(*num_references)),
+                "f16" => Some(Type::PrimitiveF16(*num_references)),
                 "f32" => Some(Type::PrimitiveF32(*num_references)),
                 "f64" => Some(Type::PrimitiveF64(*num_references)),
                 "bool" => Some(Type::PrimitiveBool(*num_references)),
@@ -216,6 +230,18 @@ impl Type {
     }
 
     pub fn alignment(&self, ptr_size: u64, structs: &[TypedStruct]) -> u32 {
+        self.alignment_of(ptr_size, structs, &mut Vec::new())
+    }
+
+    /// `in_progress` are the structs whose alignment is being computed. A struct can only contain
+    /// itself through a zero-length array (`struct A { x: [A; 0] }`), where it doesn't add to the
+    /// alignment of the other fields, so it counts as 1 there.
+    fn alignment_of(
+        &self,
+        ptr_size: u64,
+        structs: &[TypedStruct],
+        in_progress: &mut Vec<StructId>,
+    ) -> u32 {
         if self.refcount() > 0 {
             return ptr_size as u32;
         }
@@ -229,16 +255,22 @@ impl Type {
             | Type::UnsizedArray { .. } => {
                 unreachable!("generics, self and unsized types don't have an alignment")
             }
-            Type::Struct { struct_id, .. } => structs[*struct_id]
-                .elements
-                .iter()
-                .map(|v| v.1.alignment(ptr_size, structs))
-                .max()
-                .unwrap_or(1),
-            Type::SizedArray { typ, .. } => typ.alignment(ptr_size, structs),
+            Type::Struct { struct_id, .. } if in_progress.contains(struct_id) => 1,
+            Type::Struct { struct_id, .. } => {
+                in_progress.push(*struct_id);
+                let alignment = structs[*struct_id]
+                    .elements
+                    .iter()
+                    .map(|v| v.1.alignment_of(ptr_size, structs, in_progress))
+                    .max()
+                    .unwrap_or(1);
+                in_progress.pop();
+                alignment
+            }
+            Type::SizedArray { typ, .. } => typ.alignment_of(ptr_size, structs, in_progress),
             Type::Tuple { elements, .. } => elements
                 .iter()
-                .map(|v| v.alignment(ptr_size, structs))
+                .map(|v| v.alignment_of(ptr_size, structs, in_progress))
                 .max()
                 .unwrap_or(1),
             Type::PrimitiveVoid(_)
@@ -246,7 +278,7 @@ impl Type {
             | Type::PrimitiveBool(_)
             | Type::PrimitiveU8(_)
             | Type::PrimitiveI8(_) => 1,
-            Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => 2,
+            Type::PrimitiveF16(_) | Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => 2,
             Type::PrimitiveF32(_) | Type::PrimitiveU32(_) | Type::PrimitiveI32(_) => 4,
             Type::PrimitiveF64(_) | Type::PrimitiveU64(_) | Type::PrimitiveI64(_) => 8,
             Type::Function(..) | Type::PrimitiveUSize(_) | Type::PrimitiveISize(_) => {
@@ -283,6 +315,13 @@ impl Type {
                 }
                 (align(size, alignment), alignment)
             }
+            // [T; 0] may contain the struct it is a field of, so only the alignment of T is
+            // computed, which handles that case
+            Type::SizedArray {
+                typ,
+                number_elements: 0,
+                ..
+            } => (0, typ.alignment(ptr_size, structs)),
             Type::SizedArray {
                 typ,
                 number_elements,
@@ -306,7 +345,7 @@ impl Type {
             | Type::PrimitiveBool(_)
             | Type::PrimitiveU8(_)
             | Type::PrimitiveI8(_) => (1, 1),
-            Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => (2, 2),
+            Type::PrimitiveF16(_) | Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => (2, 2),
             Type::PrimitiveF32(_) | Type::PrimitiveU32(_) | Type::PrimitiveI32(_) => (4, 4),
             Type::PrimitiveF64(_) | Type::PrimitiveU64(_) | Type::PrimitiveI64(_) => (8, 8),
             Type::Function(..) | Type::PrimitiveUSize(_) | Type::PrimitiveISize(_) => {
@@ -315,6 +354,7 @@ impl Type {
         }
     }
 
+    /// The number of references in front of the type, 2 for `&&T`. `!` can't be referenced.
     pub fn refcount(&self) -> u8 {
         match self {
             Type::PrimitiveNever => 0,
@@ -337,6 +377,7 @@ impl Type {
             | Type::PrimitiveU32(num_references)
             | Type::PrimitiveU64(num_references)
             | Type::PrimitiveUSize(num_references)
+            | Type::PrimitiveF16(num_references)
             | Type::PrimitiveF32(num_references)
             | Type::PrimitiveF64(num_references)
             | Type::PrimitiveStr(num_references)
@@ -385,14 +426,91 @@ impl Type {
             | Type::PrimitiveU32(_)
             | Type::PrimitiveU64(_)
             | Type::PrimitiveUSize(_)
+            | Type::PrimitiveF16(_)
             | Type::PrimitiveF32(_)
             | Type::PrimitiveF64(_)
             | Type::PrimitiveBool(_) => true,
         }
     }
 
+    /// Replaces every `Self` in the type, including the ones in arrays, tuples and function types,
+    /// with `self_type`
+    pub fn replace_self(&mut self, self_type: &Type) {
+        match self {
+            Type::PrimitiveSelf(num_references) => {
+                *self = self_type.clone().with_num_refs(*num_references)
+            }
+            Type::UnsizedArray { typ, .. } | Type::SizedArray { typ, .. } => {
+                typ.replace_self(self_type)
+            }
+            Type::Tuple { elements, .. } => elements
+                .iter_mut()
+                .for_each(|typ| typ.replace_self(self_type)),
+            Type::Function(function_type, _) if function_type.contains_self() => {
+                let function_type = Arc::make_mut(function_type);
+                function_type
+                    .arguments
+                    .iter_mut()
+                    .for_each(|typ| typ.replace_self(self_type));
+                function_type.return_type.replace_self(self_type);
+            }
+            _ => {}
+        }
+    }
+
+    /// If `Self` appears anywhere in the type, see [`Type::replace_self`]
+    pub fn contains_self(&self) -> bool {
+        match self {
+            Type::PrimitiveSelf(_) => true,
+            Type::UnsizedArray { typ, .. } | Type::SizedArray { typ, .. } => typ.contains_self(),
+            Type::Tuple { elements, .. } => elements.iter().any(Type::contains_self),
+            Type::Function(function_type, _) => function_type.contains_self(),
+            _ => false,
+        }
+    }
+
+    /// Replaces every generic named in `names` with the type at the same position in `generics`,
+    /// keeping the references on the generic (`&T` becomes `&i32` for `T = i32`). Generics with
+    /// trait bounds are [`Type::Trait`]s, so these are replaced as well.
+    pub fn substitute_generics(&mut self, names: &[GlobalStr], generics: &[Type]) {
+        match self {
+            Type::Generic(name, num_references)
+            | Type::Trait {
+                real_name: name,
+                num_references,
+                ..
+            } => {
+                if let Some(idx) = names.iter().position(|v| v == name) {
+                    let num_references = *num_references + generics[idx].refcount();
+                    *self = generics[idx].clone().with_num_refs(num_references);
+                }
+            }
+            Type::UnsizedArray { typ, .. } | Type::SizedArray { typ, .. } => {
+                typ.substitute_generics(names, generics)
+            }
+            Type::Tuple { elements, .. }
+            | Type::Struct {
+                generics: elements, ..
+            } => elements
+                .iter_mut()
+                .for_each(|typ| typ.substitute_generics(names, generics)),
+            Type::Function(function_type, _) => {
+                let function_type = Arc::make_mut(function_type);
+                function_type
+                    .arguments
+                    .iter_mut()
+                    .for_each(|typ| typ.substitute_generics(names, generics));
+                function_type
+                    .return_type
+                    .substitute_generics(names, generics);
+            }
+            _ => {}
+        }
+    }
+
     pub fn from_numtype(typ: NumberType) -> Option<Self> {
         match typ {
+            NumberType::F16 => Some(Self::PrimitiveF16(0)),
             NumberType::F32 => Some(Self::PrimitiveF32(0)),
             NumberType::F64 => Some(Self::PrimitiveF64(0)),
             NumberType::I8 => Some(Self::PrimitiveI8(0)),
@@ -409,6 +527,7 @@ impl Type {
         }
     }
 
+    /// Adds a reference, `T` becomes `&T`. `!` stays `!`.
     pub fn take_ref(mut self) -> Self {
         match &mut self {
             Type::Trait { num_references, .. }
@@ -429,6 +548,7 @@ impl Type {
             | Type::PrimitiveU32(num_references)
             | Type::PrimitiveU64(num_references)
             | Type::PrimitiveUSize(num_references)
+            | Type::PrimitiveF16(num_references)
             | Type::PrimitiveF32(num_references)
             | Type::PrimitiveF64(num_references)
             | Type::PrimitiveStr(num_references)
@@ -440,6 +560,8 @@ impl Type {
         self
     }
 
+    /// Removes a reference, `&T` becomes `T`. Returns the unchanged type as the error if it isn't
+    /// a reference. `!` derefs to itself.
     pub fn deref(mut self) -> Result<Self, Self> {
         match &mut self {
             Type::Trait { num_references, .. }
@@ -460,6 +582,7 @@ impl Type {
             | Type::PrimitiveU32(num_references)
             | Type::PrimitiveU64(num_references)
             | Type::PrimitiveUSize(num_references)
+            | Type::PrimitiveF16(num_references)
             | Type::PrimitiveF32(num_references)
             | Type::PrimitiveF64(num_references)
             | Type::PrimitiveStr(num_references)
@@ -477,6 +600,7 @@ impl Type {
         }
     }
 
+    /// Removes all references, `&&T` becomes `T`
     pub fn without_ref(mut self) -> Self {
         match &mut self {
             Type::Trait { num_references, .. }
@@ -497,6 +621,7 @@ impl Type {
             | Type::PrimitiveU32(num_references)
             | Type::PrimitiveU64(num_references)
             | Type::PrimitiveUSize(num_references)
+            | Type::PrimitiveF16(num_references)
             | Type::PrimitiveF32(num_references)
             | Type::PrimitiveF64(num_references)
             | Type::PrimitiveStr(num_references)
@@ -510,6 +635,7 @@ impl Type {
         }
     }
 
+    /// Replaces the number of references, see [`Type::refcount`]
     pub fn with_num_refs(mut self, num_refs: u8) -> Self {
         match &mut self {
             Type::Trait { num_references, .. }
@@ -530,6 +656,7 @@ impl Type {
             | Type::PrimitiveU32(num_references)
             | Type::PrimitiveU64(num_references)
             | Type::PrimitiveUSize(num_references)
+            | Type::PrimitiveF16(num_references)
             | Type::PrimitiveF32(num_references)
             | Type::PrimitiveF64(num_references)
             | Type::PrimitiveStr(num_references)
@@ -556,6 +683,7 @@ impl Type {
                 | Type::PrimitiveU32(0)
                 | Type::PrimitiveU64(0)
                 | Type::PrimitiveUSize(0)
+                | Type::PrimitiveF16(0)
                 | Type::PrimitiveF32(0)
                 | Type::PrimitiveF64(0)
                 | Type::PrimitiveBool(0)
@@ -577,6 +705,7 @@ impl Type {
                 | Type::PrimitiveU32(_)
                 | Type::PrimitiveU64(_)
                 | Type::PrimitiveUSize(_)
+                | Type::PrimitiveF16(_)
                 | Type::PrimitiveF32(_)
                 | Type::PrimitiveF64(_)
                 | Type::PrimitiveStr(_)
@@ -624,7 +753,10 @@ impl Type {
     }
 
     pub fn is_float(&self) -> bool {
-        matches!(self, Self::PrimitiveF32(0) | Self::PrimitiveF64(0))
+        matches!(
+            self,
+            Self::PrimitiveF16(0) | Self::PrimitiveF32(0) | Self::PrimitiveF64(0)
+        )
     }
 
     pub fn get_bitwidth(&self, isize_bitwidth: u32) -> u32 {
@@ -643,7 +775,7 @@ impl Type {
             | Type::PrimitiveStr(_)
             | Type::PrimitiveNever => 0,
             Type::PrimitiveU8(_) | Type::PrimitiveI8(_) => 8,
-            Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => 16,
+            Type::PrimitiveF16(_) | Type::PrimitiveU16(_) | Type::PrimitiveI16(_) => 16,
             Type::PrimitiveF32(_) | Type::PrimitiveU32(_) | Type::PrimitiveI32(_) => 32,
             Type::PrimitiveF64(_) | Type::PrimitiveU64(_) | Type::PrimitiveI64(_) => 64,
             Type::PrimitiveUSize(_) | Type::PrimitiveISize(_) => isize_bitwidth,
@@ -662,7 +794,20 @@ impl Display for Type {
         }
 
         match self {
-            Type::Struct { name, .. } => Display::fmt(name, f),
+            Type::Struct { name, generics, .. } => {
+                Display::fmt(name, f)?;
+                if !generics.is_empty() {
+                    f.write_char('<')?;
+                    for (i, generic) in generics.iter().enumerate() {
+                        if i != 0 {
+                            f.write_str(", ")?;
+                        }
+                        Display::fmt(generic, f)?;
+                    }
+                    f.write_char('>')?;
+                }
+                Ok(())
+            }
             Type::Trait { real_name, .. } => Display::fmt(real_name, f),
             Type::DynType { trait_refs, .. } => {
                 f.write_str("dyn ")?;
@@ -727,6 +872,7 @@ impl Display for Type {
             Type::PrimitiveU16(_) => f.write_str("u16"),
             Type::PrimitiveU32(_) => f.write_str("u32"),
             Type::PrimitiveU64(_) => f.write_str("u64"),
+            Type::PrimitiveF16(_) => f.write_str("f16"),
             Type::PrimitiveF32(_) => f.write_str("f32"),
             Type::PrimitiveF64(_) => f.write_str("f64"),
             Type::PrimitiveBool(_) => f.write_str("bool"),
@@ -746,6 +892,18 @@ impl PartialEq for Type {
         }
 
         match (self, other) {
+            (
+                Type::Trait {
+                    trait_refs,
+                    real_name,
+                    ..
+                },
+                Type::Trait {
+                    trait_refs: other_traits,
+                    real_name: other_name,
+                    ..
+                },
+            ) => real_name == other_name && trait_refs == other_traits,
             (
                 Type::DynType {
                     trait_refs: self_traits,
@@ -766,12 +924,15 @@ impl PartialEq for Type {
             (
                 Type::Struct {
                     struct_id: structure,
+                    generics,
                     ..
                 },
                 Type::Struct {
-                    struct_id: other, ..
+                    struct_id: other,
+                    generics: other_generics,
+                    ..
                 },
-            ) => *structure == *other,
+            ) => *structure == *other && generics == other_generics,
             (Type::UnsizedArray { typ, .. }, Type::UnsizedArray { typ: other, .. }) => typ == other,
             (
                 Type::SizedArray {
@@ -797,6 +958,7 @@ impl PartialEq for Type {
             (Type::PrimitiveU32(_), Type::PrimitiveU32(_)) => true,
             (Type::PrimitiveU64(_), Type::PrimitiveU64(_)) => true,
             (Type::PrimitiveUSize(_), Type::PrimitiveUSize(_)) => true,
+            (Type::PrimitiveF16(_), Type::PrimitiveF16(_)) => true,
             (Type::PrimitiveF32(_), Type::PrimitiveF32(_)) => true,
             (Type::PrimitiveF64(_), Type::PrimitiveF64(_)) => true,
             (Type::PrimitiveStr(_), Type::PrimitiveStr(_)) => true,
@@ -818,7 +980,8 @@ impl PartialEq for Type {
 
 #[derive(Clone, Debug, Default)]
 pub enum TypeSuggestion {
-    Struct(StructId),
+    /// A struct and the type arguments of it, if it is generic
+    Struct(StructId, Vec<Type>),
     Array(Box<TypeSuggestion>),
     UnsizedArray(Box<TypeSuggestion>),
     Number(NumberType),
@@ -831,7 +994,7 @@ pub enum TypeSuggestion {
 impl Display for TypeSuggestion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TypeSuggestion::Struct(id) => f.write_fmt(format_args!("<struct {id:#x}>")),
+            TypeSuggestion::Struct(id, _) => f.write_fmt(format_args!("<struct {id:#x}>")),
             TypeSuggestion::Array(v) | TypeSuggestion::UnsizedArray(v) => {
                 f.write_fmt(format_args!("[{v}]"))
             }
@@ -855,9 +1018,10 @@ impl Display for TypeSuggestion {
 impl TypeSuggestion {
     pub fn to_type(&self, ctx: &TypecheckingContext) -> Option<Type> {
         match self {
-            TypeSuggestion::Struct(id) => Some(Type::Struct {
+            TypeSuggestion::Struct(id, generics) => Some(Type::Struct {
                 struct_id: *id,
                 name: ctx.structs.read()[*id].name.clone(),
+                generics: generics.clone(),
                 num_references: 0,
             }),
             TypeSuggestion::Array(type_suggestion)
@@ -870,6 +1034,7 @@ impl TypeSuggestion {
                     number_elements: 0,
                 }),
             TypeSuggestion::Number(number_type) => Some(match number_type {
+                NumberType::F16 => Type::PrimitiveF16(0),
                 NumberType::F32 => Type::PrimitiveF32(0),
                 NumberType::F64 => Type::PrimitiveF64(0),
                 NumberType::I8 => Type::PrimitiveI8(0),
@@ -908,7 +1073,11 @@ impl TypeSuggestion {
             | Type::PrimitiveNever
             | Type::Trait { .. }
             | Type::DynType { .. } => Self::Unknown,
-            Type::Struct { struct_id, .. } => Self::Struct(*struct_id),
+            Type::Struct {
+                struct_id,
+                generics,
+                ..
+            } => Self::Struct(*struct_id, generics.clone()),
             Type::SizedArray { typ, .. } => Self::Array(Box::new(Self::from_type(typ))),
             Type::UnsizedArray { typ, .. } => Self::UnsizedArray(Box::new(Self::from_type(typ))),
             Type::PrimitiveI8(_) => Self::Number(NumberType::I8),
@@ -921,6 +1090,7 @@ impl TypeSuggestion {
             Type::PrimitiveU32(_) => Self::Number(NumberType::U32),
             Type::PrimitiveU64(_) => Self::Number(NumberType::U64),
             Type::PrimitiveUSize(_) => Self::Number(NumberType::Usize),
+            Type::PrimitiveF16(_) => Self::Number(NumberType::F16),
             Type::PrimitiveF32(_) => Self::Number(NumberType::F32),
             Type::PrimitiveF64(_) => Self::Number(NumberType::F64),
             Type::PrimitiveBool(_) => Self::Bool,
@@ -930,3 +1100,148 @@ impl TypeSuggestion {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn structure(struct_id: StructId, name: &str, num_references: u8) -> Type {
+        Type::Struct {
+            struct_id,
+            name: name.into(),
+            generics: Vec::new(),
+            num_references,
+        }
+    }
+
+    fn array(typ: Type, number_elements: usize) -> Type {
+        Type::SizedArray {
+            typ: Box::new(typ),
+            num_references: 0,
+            number_elements,
+        }
+    }
+
+    #[test]
+    fn test_struct_equality() {
+        assert_eq!(structure(1, "A", 0), structure(1, "A", 0));
+        assert_eq!(structure(1, "A", 2), structure(1, "A", 2));
+        // the name is only kept for displaying the type
+        assert_eq!(structure(1, "A", 0), structure(1, "B", 0));
+        assert_ne!(structure(1, "A", 0), structure(2, "A", 0));
+        assert_ne!(structure(1, "A", 0), structure(1, "A", 1));
+        assert_ne!(Type::PrimitiveU8(0), Type::PrimitiveU8(1));
+    }
+
+    #[test]
+    fn test_array_equality() {
+        assert_eq!(
+            array(Type::PrimitiveU8(0), 4),
+            array(Type::PrimitiveU8(0), 4)
+        );
+        assert_ne!(
+            array(Type::PrimitiveU8(0), 4),
+            array(Type::PrimitiveU8(0), 5)
+        );
+        assert_ne!(
+            array(Type::PrimitiveU8(0), 4),
+            array(Type::PrimitiveI8(0), 4)
+        );
+        assert_ne!(
+            array(Type::PrimitiveU8(0), 4),
+            array(Type::PrimitiveU8(1), 4)
+        );
+    }
+
+    #[test]
+    fn test_generic_equality() {
+        let generic = |name: &str, trait_refs: Vec<TraitId>| Type::Trait {
+            trait_refs,
+            num_references: 0,
+            real_name: name.into(),
+        };
+        assert_eq!(generic("T", vec![1]), generic("T", vec![1]));
+        assert_ne!(generic("T", vec![1]), generic("U", vec![1]));
+        assert_ne!(generic("T", vec![1]), generic("T", vec![2]));
+        assert_eq!(Type::Generic("T".into(), 1), Type::Generic("T".into(), 1));
+        assert_ne!(Type::Generic("T".into(), 1), Type::Generic("U".into(), 1));
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::HashSet;
+
+        let dyn_type = |name: &str| Type::DynType {
+            trait_refs: vec![(1, name.into())],
+            num_references: 1,
+        };
+        let pairs = [
+            (structure(1, "A", 1), structure(1, "B", 1)),
+            (dyn_type("A"), dyn_type("B")),
+            (
+                array(structure(1, "A", 0), 4),
+                array(structure(1, "B", 0), 4),
+            ),
+            (Type::Generic("T".into(), 2), Type::Generic("T".into(), 2)),
+            (
+                Type::Tuple {
+                    elements: vec![Type::PrimitiveU8(0), dyn_type("A")],
+                    num_references: 0,
+                },
+                Type::Tuple {
+                    elements: vec![Type::PrimitiveU8(0), dyn_type("B")],
+                    num_references: 0,
+                },
+            ),
+        ];
+        for (a, b) in pairs {
+            assert_eq!(a, b);
+            let set = HashSet::from([a.clone(), b]);
+            assert_eq!(set.len(), 1, "{a} was hashed inconsistently");
+        }
+
+        let set = HashSet::from([
+            structure(1, "A", 0),
+            structure(1, "A", 1),
+            structure(2, "A", 0),
+            array(Type::PrimitiveU8(0), 4),
+            array(Type::PrimitiveU8(0), 5),
+            Type::Generic("T".into(), 0),
+            Type::Generic("U".into(), 0),
+        ]);
+        assert_eq!(set.len(), 7);
+    }
+
+    #[test]
+    fn test_references() {
+        let types = [
+            structure(1, "A", 0),
+            Type::Generic("T".into(), 0),
+            Type::Trait {
+                trait_refs: vec![1],
+                num_references: 0,
+                real_name: "T".into(),
+            },
+            Type::UnsizedArray {
+                typ: Box::new(Type::PrimitiveU8(1)),
+                num_references: 0,
+            },
+            array(Type::PrimitiveU8(1), 4),
+            Type::PrimitiveI32(0),
+        ];
+        for typ in types {
+            assert_eq!(typ.refcount(), 0);
+            let double_ref = typ.clone().take_ref().take_ref();
+            assert_eq!(double_ref.refcount(), 2);
+            assert_eq!(double_ref, typ.clone().with_num_refs(2));
+            // the references of the element type are untouched
+            assert_eq!(double_ref.clone().without_ref(), typ);
+            let single_ref = double_ref.deref().expect("&&T should deref");
+            assert_eq!(single_ref.refcount(), 1);
+            assert_eq!(single_ref.deref(), Ok(typ.clone()));
+            assert_eq!(typ.clone().deref(), Err(typ));
+        }
+        assert_eq!(Type::PrimitiveNever.take_ref(), Type::PrimitiveNever);
+        assert_eq!(Type::PrimitiveNever.deref(), Ok(Type::PrimitiveNever));
+    }
+}
@@ -0,0 +1,159 @@
+use super::expression::{TypecheckedExpression, TypedLiteral};
+
+macro_rules! visitor {
+    ($(#[$attr:meta])* $visitor:ident, $walk_body:ident, $walk_expression:ident, $walk_literal:ident $(, $mut:tt)?) => {
+        $(#[$attr])*
+        pub trait $visitor {
+            fn visit_body(&mut self, body: &$($mut)? [TypecheckedExpression]) {
+                $walk_body(self, body);
+            }
+
+            fn visit_expression(&mut self, expression: &$($mut)? TypecheckedExpression) {
+                $walk_expression(self, expression);
+            }
+
+            fn visit_literal(&mut self, literal: &$($mut)? TypedLiteral) {
+                $walk_literal(self, literal);
+            }
+        }
+
+        /// Visits every expression of `body` in order
+        pub fn $walk_body<V: $visitor + ?Sized>(visitor: &mut V, body: &$($mut)? [TypecheckedExpression]) {
+            for expression in body {
+                visitor.visit_expression(expression);
+            }
+        }
+
+        /// Visits the literals and the bodies of the blocks in `expression`, in the order they
+        /// are evaluated in
+        pub fn $walk_expression<V: $visitor + ?Sized>(
+            visitor: &mut V,
+            expression: &$($mut)? TypecheckedExpression,
+        ) {
+            match expression {
+                TypecheckedExpression::Block(_, body, _) => visitor.visit_body(body),
+                TypecheckedExpression::If {
+                    cond,
+                    if_block,
+                    else_block,
+                    ..
+                } => {
+                    visitor.visit_literal(cond);
+                    visitor.visit_body(&$($mut)? if_block.0);
+                    if let Some((else_block, _)) = else_block {
+                        visitor.visit_body(else_block);
+                    }
+                }
+                TypecheckedExpression::While {
+                    cond_block,
+                    cond,
+                    body,
+                    ..
+                } => {
+                    visitor.visit_body(cond_block);
+                    visitor.visit_literal(cond);
+                    visitor.visit_body(&$($mut)? body.0);
+                }
+                TypecheckedExpression::Call(_, _, function, arguments) => {
+                    visitor.visit_literal(function);
+                    for argument in arguments {
+                        visitor.visit_literal(argument);
+                    }
+                }
+                TypecheckedExpression::DirectCall(_, _, _, arguments)
+                | TypecheckedExpression::DirectExternCall(_, _, _, arguments)
+                | TypecheckedExpression::IntrinsicCall(_, _, _, arguments)
+                | TypecheckedExpression::DynCall(_, _, arguments, _) => {
+                    for argument in arguments {
+                        visitor.visit_literal(argument);
+                    }
+                }
+                TypecheckedExpression::Range { lhs, rhs, .. }
+                | TypecheckedExpression::StoreAssignment(_, lhs, rhs)
+                | TypecheckedExpression::Add(_, _, lhs, rhs)
+                | TypecheckedExpression::Sub(_, _, lhs, rhs)
+                | TypecheckedExpression::Mul(_, _, lhs, rhs)
+                | TypecheckedExpression::Div(_, _, lhs, rhs)
+                | TypecheckedExpression::Mod(_, _, lhs, rhs)
+                | TypecheckedExpression::BAnd(_, _, lhs, rhs)
+                | TypecheckedExpression::BOr(_, _, lhs, rhs)
+                | TypecheckedExpression::BXor(_, _, lhs, rhs)
+                | TypecheckedExpression::GreaterThan(_, _, lhs, rhs)
+                | TypecheckedExpression::LessThan(_, _, lhs, rhs)
+                | TypecheckedExpression::LAnd(_, _, lhs, rhs)
+                | TypecheckedExpression::LOr(_, _, lhs, rhs)
+                | TypecheckedExpression::GreaterThanEq(_, _, lhs, rhs)
+                | TypecheckedExpression::LessThanEq(_, _, lhs, rhs)
+                | TypecheckedExpression::Eq(_, _, lhs, rhs)
+                | TypecheckedExpression::Neq(_, _, lhs, rhs)
+                | TypecheckedExpression::LShift(_, _, lhs, rhs)
+                | TypecheckedExpression::RShift(_, _, lhs, rhs)
+                | TypecheckedExpression::MakeSlice(_, _, lhs, rhs) => {
+                    visitor.visit_literal(lhs);
+                    visitor.visit_literal(rhs);
+                }
+                TypecheckedExpression::Return(_, value)
+                | TypecheckedExpression::Pos(_, _, value)
+                | TypecheckedExpression::Neg(_, _, value)
+                | TypecheckedExpression::LNot(_, _, value)
+                | TypecheckedExpression::BNot(_, _, value)
+                | TypecheckedExpression::Reference(_, _, value)
+                | TypecheckedExpression::Dereference(_, _, value)
+                | TypecheckedExpression::Offset(_, _, value, _)
+                | TypecheckedExpression::OffsetNonPointer(_, _, value, _)
+                | TypecheckedExpression::BoundsCheck(_, value, _)
+                | TypecheckedExpression::Literal(_, _, value)
+                | TypecheckedExpression::Alias(_, _, value)
+                | TypecheckedExpression::Bitcast(_, _, value)
+                | TypecheckedExpression::IntCast(_, _, value)
+                | TypecheckedExpression::PtrToInt(_, _, value)
+                | TypecheckedExpression::IntToPtr(_, _, value)
+                | TypecheckedExpression::StripMetadata(_, _, value)
+                | TypecheckedExpression::MakeUnsizedSlice(_, _, value, _)
+                | TypecheckedExpression::AttachVtable(_, _, value, _) => {
+                    visitor.visit_literal(value)
+                }
+                TypecheckedExpression::Asm { .. }
+                | TypecheckedExpression::DeclareVariable(..)
+                | TypecheckedExpression::Empty(_)
+                | TypecheckedExpression::Unreachable(_)
+                | TypecheckedExpression::None => (),
+            }
+        }
+
+        /// Visits the elements of array, struct and tuple literals
+        pub fn $walk_literal<V: $visitor + ?Sized>(visitor: &mut V, literal: &$($mut)? TypedLiteral) {
+            match literal {
+                TypedLiteral::Array(_, elements)
+                | TypedLiteral::Struct(_, elements)
+                | TypedLiteral::Tuple(elements) => {
+                    for element in elements {
+                        visitor.visit_literal(element);
+                    }
+                }
+                _ => (),
+            }
+        }
+    };
+}
+
+visitor!(
+    /// Walks the typechecked body of a function. Every method visits the children of the node it
+    /// was called with by default, so an analysis only overrides the methods of the nodes it cares
+    /// about and calls the matching `walk_*` function if it wants to keep going into the
+    /// children.
+    Visitor,
+    walk_body,
+    walk_expression,
+    walk_literal
+);
+
+visitor!(
+    /// Like [`Visitor`], but with mutable access to the nodes, so passes can rewrite the body in
+    /// place.
+    VisitorMut,
+    walk_body_mut,
+    walk_expression_mut,
+    walk_literal_mut,
+    mut
+);
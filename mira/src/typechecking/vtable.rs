@@ -0,0 +1,44 @@
+use crate::module::{FunctionId, TraitId};
+
+use super::{Type, TypecheckingContext};
+
+/// The dispatch table of a `&dyn Trait1 + Trait2 + ...` created from a `&typ`.
+///
+/// In memory, a vtable starts with the size of `typ`, followed by a function pointer for every
+/// function in `functions`. The functions are the implementations of every function of the
+/// traits, in the order the traits were specified and the functions declared in each trait.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VTable {
+    pub typ: Type,
+    pub traits: Vec<TraitId>,
+    pub functions: Vec<FunctionId>,
+}
+
+impl TypecheckingContext {
+    /// Computes the vtable of `typ` for the traits `traits`. Returns `None` if `typ` doesn't
+    /// implement one of the traits.
+    pub fn vtable(&self, typ: &Type, traits: &[TraitId]) -> Option<VTable> {
+        let Type::Struct { struct_id, .. } = typ else {
+            return None;
+        };
+        // codegen calls this while holding on to the struct and trait readers
+        let struct_reader = self.structs.read_recursive();
+        let trait_reader = self.traits.read_recursive();
+        let mut functions = Vec::new();
+        for trait_id in traits {
+            let implementation = struct_reader[*struct_id].trait_impl.get(trait_id)?;
+            // implementations with missing or mismatching functions are rejected during type
+            // resolution, so the functions are in the same order as in the trait.
+            assert_eq!(
+                implementation.len(),
+                trait_reader[*trait_id].functions.len()
+            );
+            functions.extend_from_slice(implementation);
+        }
+        Some(VTable {
+            typ: typ.clone(),
+            traits: traits.to_vec(),
+            functions,
+        })
+    }
+}
@@ -3,7 +3,8 @@ use std::{
     error::Error,
     io::{ErrorKind, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitCode},
+    str::FromStr,
     sync::{Arc, LazyLock},
 };
 mod editor;
@@ -12,11 +13,17 @@ use editor::{get_path, run_editor};
 use repl::Repl;
 
 use mira::{
-    codegen::CodegenConfig,
+    codegen::{BuildMode, CodegenConfig, OptLevel},
+    error::{MiraError, SourceMap},
     linking::{run_full_compilation_pipeline, FullCompilationOptions},
-    target::Target,
+    parser::ParserQueueEntry,
+    target::{Os, Target},
+    timings::PassTimings,
+    tokenizer::Tokenizer,
+    typechecking::TypecheckingWarning,
     AUTHORS as MIRA_AUTHORS, VERSION as VER,
 };
+use parking_lot::RwLock;
 
 const MIRAC_VERSION: &str = env!("CARGO_PKG_VERSION");
 const MIRAC_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -70,7 +77,11 @@ fn print_help(editor_mode: bool) {
     println!("│ --exec <file>      │ emits the executable                        │");
     println!("│ --file <file>      │ set the file used in the debug info         │");
     println!("│ --nolibc           │ don't link with libc                        │");
+    println!("│ -O<0|1|2|3|s|z>    │ sets the optimization level                 │");
+    println!("│ --no-debug         │ leaves out the debug info                   │");
+    println!("│ --release          │ leaves out debug_assert checks              │");
     println!("│ --verbose          │ Output what the compiler is doing           │");
+    println!("│ --time-passes      │ Output the time spent in each pass          │");
     _ = "     └─ [ mira vN.N.N ]───┴─────────────────────────────────────────────┘";
     // prints line as shown above
     assert!(VER.len() <= 7);
@@ -96,6 +107,308 @@ struct Data {
     file: Arc<Path>,
     editor_path: Option<PathBuf>,
     editor_mode: bool,
+    target: Target,
+}
+
+#[derive(Clone, Copy)]
+enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Obj,
+}
+
+struct Args {
+    target: Target,
+    emit: Option<(Emit, PathBuf)>,
+    compile: Option<PathBuf>,
+    time_passes: bool,
+    opt_level: Option<OptLevel>,
+    debug_info: bool,
+    build_mode: BuildMode,
+}
+
+const USAGE: &str = "usage: mirac [--target <arch-os[-abi]> | --os <os>] [--time-passes] [-O<0|1|2|3|s|z>] [--debug] [--release] [--emit <tokens|ast|ir|obj> <file> | compile <file>]";
+
+/// Parses the command line arguments, returns the message to print if they are invalid.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut target = None;
+    // the flag that selected the target, only one of `--target` and `--os` can be used
+    let mut target_flag = None;
+    let mut emit = None;
+    let mut file = None;
+    let mut compile = false;
+    let mut time_passes = false;
+    let mut opt_level = None;
+    let mut debug_info = false;
+    let mut build_mode = BuildMode::Debug;
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name @ ("--target" | "--os" | "--emit"), value)) => (name, value.to_string()),
+            None if arg == "--target" || arg == "--os" || arg == "--emit" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("`{arg}` needs a value"))?;
+                (arg.as_str(), value)
+            }
+            None if arg == "--time-passes" => {
+                time_passes = true;
+                continue;
+            }
+            None if arg == "--debug" => {
+                debug_info = true;
+                continue;
+            }
+            None if arg == "--release" => {
+                build_mode = BuildMode::Release;
+                continue;
+            }
+            None if arg.starts_with("-O") => {
+                opt_level = Some(
+                    OptLevel::from_str(&arg[2..])
+                        .map_err(|_| format!("invalid optimization level `{arg}`"))?,
+                );
+                continue;
+            }
+            None if arg == "compile" && !compile && file.is_none() => {
+                compile = true;
+                continue;
+            }
+            None if !arg.starts_with('-') && file.is_none() => {
+                file = Some(PathBuf::from(arg));
+                continue;
+            }
+            _ => return Err(format!("unknown argument `{arg}`")),
+        };
+        if name == "--target" || name == "--os" {
+            match target_flag {
+                Some(flag) if flag != name => {
+                    return Err("`--target` and `--os` can't be used together".to_string())
+                }
+                _ => target_flag = Some(name.to_string()),
+            }
+        }
+        match name {
+            "--target" => {
+                target = Some(
+                    Target::from_str(&value)
+                        .map_err(|e| format!("invalid target `{value}`: {e}"))?,
+                )
+            }
+            "--os" => {
+                let os = Os::from_str(&value).map_err(|_| format!("invalid os `{value}`"))?;
+                target = Some(Target::default_for_os(os));
+            }
+            _ => {
+                emit = Some(match value.as_str() {
+                    "tokens" => Emit::Tokens,
+                    "ast" => Emit::Ast,
+                    "ir" => Emit::Ir,
+                    "obj" => Emit::Obj,
+                    _ => return Err(format!("cannot emit `{value}`")),
+                })
+            }
+        }
+    }
+    let (emit, compile) = match (emit, compile, file) {
+        (Some(_), true, _) => return Err("`--emit` can't be used with `compile`".to_string()),
+        (Some(emit), false, Some(file)) => (Some((emit, file)), None),
+        (None, true, Some(file)) => (None, Some(file)),
+        (Some(_), false, None) => return Err("`--emit` needs a file".to_string()),
+        (None, true, None) => return Err("`compile` needs a file".to_string()),
+        (None, false, Some(file)) => return Err(format!("unknown argument `{}`", file.display())),
+        (None, false, None) => (None, None),
+    };
+    Ok(Args {
+        target: match target {
+            Some(target) => target,
+            None => Target::host().ok_or_else(|| {
+                "the host isn't a supported target, pass one with `--target`".to_string()
+            })?,
+        },
+        emit,
+        compile,
+        time_passes,
+        opt_level,
+        debug_info,
+        build_mode,
+    })
+}
+
+/// Prints the warnings of a compilation to stderr, so they don't end up in the emitted output
+fn print_warnings(warnings: &[TypecheckingWarning]) {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
+
+/// Runs the compilation pipeline on `file` and every file it imports without writing an object
+/// file. Returns every error that was collected in the first stage that failed.
+fn run_file(file: &Path, args: &Args) -> Result<(), Vec<MiraError>> {
+    let file: Arc<Path> = file.into();
+    let root: Arc<Path> = file.parent().unwrap_or(Path::new(".")).into();
+    let mut warnings = Vec::new();
+    let result = run_full_compilation_pipeline(
+        FullCompilationOptions {
+            file: file.clone(),
+            root_directory: root,
+            debug_file: file,
+            source: None,
+            shared_object: false,
+            linker_script: None,
+            obj_path: None,
+            add_extension_to_exe: false,
+            exec_path: None,
+            codegen_opts: codegen_config(args.target, args.opt_level, args.build_mode),
+            link_with_crt: false,
+            additional_linker_args: &[],
+            additional_linker_directories: &[],
+            verbose: false,
+            time_passes: args.time_passes,
+            with_debug_info: args.debug_info,
+            ir_writer: None,
+            llvm_ir_writer: None,
+            llvm_bc_writer: None,
+            asm_writer: None,
+        },
+        &mut warnings,
+    );
+    print_warnings(&warnings);
+    result
+}
+
+/// Runs the compiler on `file` up until the stage `emit` stands for and prints its result, or
+/// writes the object file next to `file` for [`Emit::Obj`].
+fn emit(
+    emit: Emit,
+    file: &Path,
+    target: Target,
+    opt_level: Option<OptLevel>,
+    build_mode: BuildMode,
+    debug_info: bool,
+    time_passes: bool,
+) -> ExitCode {
+    let source = match std::fs::read_to_string(file) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: failed to read {}: {e}", file.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let file: Arc<Path> = file.into();
+    let root: Arc<Path> = file.parent().unwrap_or(Path::new(".")).into();
+
+    if let Emit::Ir | Emit::Obj = emit {
+        let obj_path = matches!(emit, Emit::Obj).then(|| file.with_extension("o"));
+        let ir_writer: Option<Box<dyn Write>> = match emit {
+            Emit::Ir => Some(Box::new(std::io::stdout())),
+            _ => None,
+        };
+        let mut warnings = Vec::new();
+        let result = run_full_compilation_pipeline(
+            FullCompilationOptions {
+                file: file.clone(),
+                root_directory: root,
+                debug_file: file.clone(),
+                source: Some(&source),
+                shared_object: false,
+                linker_script: None,
+                obj_path,
+                add_extension_to_exe: false,
+                exec_path: None,
+                codegen_opts: codegen_config(target, opt_level, build_mode),
+                link_with_crt: false,
+                additional_linker_args: &[],
+                additional_linker_directories: &[],
+                verbose: false,
+                time_passes,
+                with_debug_info: debug_info,
+                ir_writer,
+                llvm_ir_writer: None,
+                llvm_bc_writer: None,
+                asm_writer: None,
+            },
+            &mut warnings,
+        );
+        print_warnings(&warnings);
+        if let Err(errs) = result {
+            let mut sources = SourceMap::new();
+            sources.insert(file, source.into());
+            for e in render_errors(&errs, &mut sources) {
+                eprintln!("{e}");
+            }
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let mut timings = PassTimings::new();
+    let print_timings = |timings: &PassTimings| {
+        if time_passes {
+            eprint!("{timings}");
+        }
+    };
+    let mut tokenizer = Tokenizer::new(&source, file.clone());
+    if let Err(errs) = timings.time("tokenize", || tokenizer.scan_tokens()) {
+        for e in errs.iter() {
+            eprintln!("{}", e.with_source(&source));
+        }
+        print_timings(&timings);
+        return ExitCode::FAILURE;
+    }
+    if let Emit::Tokens = emit {
+        for token in tokenizer.tokens() {
+            println!("{token}");
+        }
+        print_timings(&timings);
+        return ExitCode::SUCCESS;
+    }
+
+    let modules = Arc::new(RwLock::new(vec![ParserQueueEntry {
+        file: file.clone(),
+        root: root.clone(),
+    }]));
+    let mut parser = tokenizer.to_parser(modules, root);
+    let (statements, errs) = timings.time("parse", || parser.parse_all());
+    for statement in statements.iter() {
+        println!("{statement}");
+    }
+    for e in errs.iter() {
+        eprintln!("{e}");
+    }
+    print_timings(&timings);
+    if errs.len() > 0 {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// The codegen config for `target`, with the optimization level passed on the command line if
+/// there was one. `debug_assert`s are only kept in [`BuildMode::Debug`].
+fn codegen_config(
+    target: Target,
+    opt_level: Option<OptLevel>,
+    build_mode: BuildMode,
+) -> CodegenConfig<'static> {
+    let config = CodegenConfig::new_release_safe(target).build_mode(build_mode);
+    match opt_level {
+        Some(opt_level) => config.optimizations(opt_level),
+        None => config,
+    }
+}
+
+/// Renders every error with the line of the source code it occurred at. Sources missing from
+/// `sources` are read from disk.
+fn render_errors<'a>(
+    errs: &'a [MiraError],
+    sources: &'a mut SourceMap,
+) -> impl Iterator<Item = String> + 'a {
+    errs.iter().map(move |e| {
+        if let Some(location) = e.get_loc() {
+            sources.load(&location.file);
+        }
+        e.render(sources)
+    })
 }
 
 fn parse_opts<'a>(args: &'a str) -> Vec<String> {
@@ -148,6 +461,11 @@ fn _compile_run(rest: &str, repl: &mut Repl<Data>, run: bool) {
     let mut exec_file = None;
     let mut nolibc = false;
     let mut verbose = false;
+    let mut time_passes = false;
+    let mut opt_level = None;
+    // unlike mirac, the repl emits debug info unless it's told not to
+    let mut debug_info = true;
+    let mut build_mode = BuildMode::Debug;
     let mut file = None;
     let mut i = 0;
     while i < opts.len() {
@@ -156,6 +474,22 @@ fn _compile_run(rest: &str, repl: &mut Repl<Data>, run: bool) {
                 opts.remove(i);
                 verbose = true;
             }
+            "--time-passes" => {
+                opts.remove(i);
+                time_passes = true;
+            }
+            "--debug" => {
+                opts.remove(i);
+                debug_info = true;
+            }
+            "--no-debug" => {
+                opts.remove(i);
+                debug_info = false;
+            }
+            "--release" => {
+                opts.remove(i);
+                build_mode = BuildMode::Release;
+            }
             "--llvm-ir" => {
                 opts.remove(i);
                 if let Some(_) = opts.get(i).filter(|v| !v.starts_with('-')) {
@@ -271,6 +605,13 @@ fn _compile_run(rest: &str, repl: &mut Repl<Data>, run: bool) {
                 opts.remove(i);
                 nolibc = true;
             }
+            opt if opt.starts_with("-O") => match OptLevel::from_str(&opt[2..]) {
+                Ok(v) => {
+                    opts.remove(i);
+                    opt_level = Some(v);
+                }
+                Err(()) => return println!("invalid optimization level `{opt}`"),
+            },
             _ => i += 1,
         }
     }
@@ -286,29 +627,38 @@ fn _compile_run(rest: &str, repl: &mut Repl<Data>, run: bool) {
         .map(Into::into)
         .unwrap_or_else(|| repl.data.file.clone());
 
-    if let Err(e) = run_full_compilation_pipeline(FullCompilationOptions {
-        file: repl.data.file.clone(),
-        root_directory: repl.data.current_dir.clone(),
-        debug_file,
-        source: Some(&repl.buf),
-        shared_object: false,
-        linker_script: None,
-        obj_path: obj_file,
-        add_extension_to_exe: false,
-        exec_path: exec_file.clone(),
-        codegen_opts: CodegenConfig::new_release_safe(Target::from_name("x86_64-linux")),
-        link_with_crt: !nolibc,
-        additional_linker_args: &opts,
-        additional_linker_directories: &[],
-        verbose,
-        with_debug_info: true,
-        ir_writer,
-        llvm_ir_writer,
-        llvm_bc_writer,
-        asm_writer,
-    }) {
+    let mut warnings = Vec::new();
+    let result = run_full_compilation_pipeline(
+        FullCompilationOptions {
+            file: repl.data.file.clone(),
+            root_directory: repl.data.current_dir.clone(),
+            debug_file: debug_file.clone(),
+            source: Some(&repl.buf),
+            shared_object: false,
+            linker_script: None,
+            obj_path: obj_file,
+            add_extension_to_exe: false,
+            exec_path: exec_file.clone(),
+            codegen_opts: codegen_config(repl.data.target, opt_level, build_mode),
+            link_with_crt: !nolibc,
+            additional_linker_args: &opts,
+            additional_linker_directories: &[],
+            verbose,
+            time_passes,
+            with_debug_info: debug_info,
+            ir_writer,
+            llvm_ir_writer,
+            llvm_bc_writer,
+            asm_writer,
+        },
+        &mut warnings,
+    );
+    print_warnings(&warnings);
+    if let Err(e) = result {
         println!("Failed to compile:");
-        for e in e.iter() {
+        let mut sources = SourceMap::new();
+        sources.insert(debug_file, repl.buf.as_str().into());
+        for e in render_errors(&e, &mut sources) {
             println!("{e}");
         }
         drop(e);
@@ -334,7 +684,42 @@ fn _compile_run(rest: &str, repl: &mut Repl<Data>, run: bool) {
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: {e}");
+            eprintln!("{USAGE}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if let Some((stage, file)) = &args.emit {
+        return Ok(emit(
+            *stage,
+            file,
+            args.target,
+            args.opt_level,
+            args.build_mode,
+            args.debug_info,
+            args.time_passes,
+        ));
+    }
+    if let Some(file) = &args.compile {
+        return Ok(match run_file(file, &args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(errs) => {
+                for e in render_errors(&errs, &mut SourceMap::new()) {
+                    eprintln!("{e}");
+                }
+                eprintln!(
+                    "failed to compile {}: {} errors",
+                    file.display(),
+                    errs.len()
+                );
+                ExitCode::FAILURE
+            }
+        });
+    }
     let current_dir: Arc<Path> = std::env::current_dir()?.into();
     let file: Arc<Path> = current_dir.join("stdin_buffer").into();
     let editor_path = get_path(None);
@@ -400,9 +785,11 @@ fn main() -> Result<(), Box<dyn Error>> {
             file,
             editor_path,
             editor_mode,
+            target: args.target,
         },
     );
-    repl.run()
+    repl.run()?;
+    Ok(ExitCode::SUCCESS)
     /*
     loop {
         write!(stdout, "> ")?;
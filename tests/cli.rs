@@ -0,0 +1,180 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+fn mirac() -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_mirac"));
+    cmd.stdin(Stdio::null());
+    cmd
+}
+
+#[test]
+fn test_invalid_target() {
+    let output = mirac()
+        .args(["--target", "riscv-linux"])
+        .output()
+        .expect("failed to run mirac");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("invalid target `riscv-linux`: Invalid Arch"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_missing_target() {
+    let output = mirac()
+        .arg("--target")
+        .output()
+        .expect("failed to run mirac");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`--target` needs a value"), "{stderr}");
+}
+
+#[test]
+fn test_target_and_os() {
+    let output = mirac()
+        .args(["--os", "linux", "--target", "x86-linux"])
+        .output()
+        .expect("failed to run mirac");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("`--target` and `--os` can't be used together"),
+        "{stderr}"
+    );
+}
+
+fn sample(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mirac_cli_{}_{name}.mr", std::process::id()));
+    std::fs::write(&path, source).expect("failed to write the sample");
+    path
+}
+
+#[test]
+fn test_emit_tokens() {
+    let file = sample("tokens", "fn main() { let a = 1; }");
+    let output = mirac()
+        .arg("--emit")
+        .arg("tokens")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let tokens = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(tokens[..4], ["fn", "identifier(main)", "(", ")"]);
+    assert!(tokens.contains(&"let"));
+}
+
+#[test]
+fn test_emit_ast() {
+    let file = sample("ast", "struct Point { x: i32, y: i32 }");
+    let output = mirac()
+        .arg("--emit=ast")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("struct Point"), "{stdout}");
+}
+
+#[test]
+fn test_compile_errors() {
+    let file = sample("compile", "fn a() {}\nfn a() {}\nstruct b {}\nfn b() {}\n");
+    let output = mirac()
+        .arg("compile")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`a` is already defined"), "{stderr}");
+    assert!(stderr.contains("`b` is already defined"), "{stderr}");
+    assert!(stderr.contains(": 2 errors"), "{stderr}");
+}
+
+/// The lang items every program has to define, taken from `std.mr`.
+const LANG_ITEMS: &str = "
+@lang(\"allocator_trait\")
+trait Allocator {
+    fn alloc(self: &Self, size: usize) -> &void;
+    fn realloc(self: &Self, ptr: &void, size: usize) -> &void;
+    fn free(self: &Self, ptr: &void);
+}
+
+struct CAlloc {;
+    impl Allocator {
+        fn alloc(self: &Self, size: usize) -> &void = &void;
+        fn realloc(self: &Self, ptr: &void, size: usize) -> &void = ptr;
+        fn free(self: &Self, ptr: &void) = void;
+    }
+}
+@lang(\"allocator\")
+let allocator: CAlloc = .{};
+
+@lang(\"copy_trait\")
+trait Copy {}
+
+@lang(\"clone_trait\")
+trait Clone {
+    fn clone(self: &Self) -> Self;
+}
+";
+
+#[test]
+fn test_time_passes() {
+    let source = format!("{LANG_ITEMS}\nfn main() -> i32 {{ return 12; }}\n");
+    let file = sample("time_passes", &source);
+    let output = mirac()
+        .arg("--time-passes")
+        .arg("compile")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    for pass in ["tokenize", "parse", "module-form", "typecheck", "total"] {
+        assert!(
+            stderr.lines().any(|line| line.starts_with(pass)),
+            "no timing for {pass}: {stderr}"
+        );
+    }
+
+    let file = sample("no_time_passes", &source);
+    let output = mirac()
+        .arg("compile")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.is_empty(), "{stderr}");
+}
+
+#[test]
+fn test_warnings_on_stderr() {
+    let source = format!("{LANG_ITEMS}\nfn main() -> i32 {{ let a = 1; let a = 2; return a; }}\n");
+    let file = sample("warnings", &source);
+    let output = mirac()
+        .arg("--emit=ir")
+        .arg(&file)
+        .output()
+        .expect("failed to run mirac");
+    _ = std::fs::remove_file(&file);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.contains("Warning: "), "{stderr}");
+    assert!(stderr.contains("`a` shadows the variable"), "{stderr}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Warning"), "{stdout}");
+}